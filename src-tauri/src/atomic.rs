@@ -0,0 +1,187 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::reader::FileReader;
+
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How `save_file_data` puts an edit on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SaveMode {
+    /// Writes the new bytes straight into the file.
+    InPlace,
+    /// Writes a complete new copy next to the file and renames it over the
+    /// original, so a crash leaves either the old or the new file.
+    Atomic,
+}
+
+impl SaveMode {
+    pub(crate) fn parse(mode: &str) -> Result<Self, AppError> {
+        match mode {
+            "in-place" => Ok(Self::InPlace),
+            "atomic" => Ok(Self::Atomic),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported save mode '{}', expected 'in-place' or 'atomic'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Replaces the file at `path`, currently read through `reader`, with a copy
+/// that has `bytes` written at `start`, and returns the new file opened for
+/// reading and writing. A `start` past the end is reached with zeros. The
+/// copy is staged in the same directory, synced and given the original's
+/// permissions before being renamed over it.
+/// `before_rename` runs last; if it or any earlier step fails, the staged
+/// copy is removed and the original is left as it was.
+pub(crate) fn replace_file<F>(
+    reader: &FileReader,
+    path: &Path,
+    start: u64,
+    bytes: &[u8],
+    before_rename: F,
+) -> Result<File, AppError>
+where
+    F: FnOnce() -> Result<(), AppError>,
+{
+    let io_error = |what: &str, e: std::io::Error| {
+        AppError::Io(format!("Failed to {} {}: {}", what, path.display(), e))
+    };
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut staged = tempfile::Builder::new()
+        .prefix(".byte-leviathan-")
+        .suffix(".tmp")
+        .tempfile_in(directory)
+        .map_err(|e| io_error("stage a copy of", e))?;
+
+    let end = start + bytes.len() as u64;
+    {
+        let mut stage = |chunk: &[u8]| {
+            staged
+                .write_all(chunk)
+                .map_err(|e| io_error("stage a copy of", e))
+        };
+        let kept = std::cmp::min(start, reader.file_size);
+        if kept > 0 {
+            reader.for_each_chunk(0, kept, COPY_CHUNK_SIZE, |_, chunk| stage(chunk))?;
+        }
+        let zeros = vec![0u8; std::cmp::min(start - kept, COPY_CHUNK_SIZE as u64) as usize];
+        let mut gap = start - kept;
+        while gap > 0 {
+            let len = std::cmp::min(gap, zeros.len() as u64);
+            stage(&zeros[..len as usize])?;
+            gap -= len;
+        }
+        stage(bytes)?;
+        if end < reader.file_size {
+            reader.for_each_chunk(end, reader.file_size, COPY_CHUNK_SIZE, |_, chunk| {
+                stage(chunk)
+            })?;
+        }
+    }
+    staged
+        .as_file()
+        .sync_all()
+        .map_err(|e| io_error("sync the copy of", e))?;
+
+    let permissions = fs::metadata(path)
+        .map_err(|e| io_error("read the permissions of", e))?
+        .permissions();
+    fs::set_permissions(staged.path(), permissions)
+        .map_err(|e| io_error("copy the permissions of", e))?;
+
+    before_rename()?;
+    staged
+        .persist(path)
+        .map_err(|e| io_error("replace", e.error))
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open, open_temp};
+    use crate::{get_file_data, save_file_data, AppState};
+
+    #[test]
+    fn failure_before_the_rename_leaves_the_original() {
+        let app = app();
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = tempfile::NamedTempFile::new_in(dir.path()).unwrap();
+        file.write_all(b"original contents").unwrap();
+        let path = file.path().to_path_buf();
+        let handle = open(&app, &file);
+        let reader = app
+            .state::<AppState>()
+            .lock()
+            .unwrap()
+            .get(handle)
+            .unwrap()
+            .reader();
+
+        let result = replace_file(&reader, &path, 0, b"REPLACED", || {
+            Err(AppError::Io("simulated crash".to_string()))
+        });
+        assert!(matches!(result, Err(AppError::Io(_))));
+        assert_eq!(fs::read(&path).unwrap(), b"original contents");
+        let left: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(left.len(), 1, "the staged copy should be removed");
+    }
+
+    #[test]
+    fn atomic_saves_replace_the_file() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"hello world");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(file.path(), fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        let atomic = Some("atomic".to_string());
+
+        save_file_data(
+            handle,
+            6,
+            b"WORLD".to_vec(),
+            None,
+            atomic.clone(),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(fs::read(file.path()).unwrap(), b"hello WORLD");
+        save_file_data(handle, 11, b"!!".to_vec(), Some(true), atomic, app.state()).unwrap();
+        assert_eq!(fs::read(file.path()).unwrap(), b"hello WORLD!!");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(file.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+
+        // Later reads and in-place edits go to the new file.
+        save_file_data(handle, 0, b"H".to_vec(), None, None, app.state()).unwrap();
+        let read = tauri::async_runtime::block_on(get_file_data(handle, 0, 13, None, app.state()))
+            .unwrap();
+        assert_eq!(read, b"Hello WORLD!!");
+        assert_eq!(fs::read(file.path()).unwrap(), b"Hello WORLD!!");
+
+        assert!(matches!(
+            save_file_data(
+                handle,
+                0,
+                b"x".to_vec(),
+                None,
+                Some("lazy".to_string()),
+                app.state()
+            ),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
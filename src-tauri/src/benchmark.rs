@@ -0,0 +1,142 @@
+use serde::Serialize;
+use std::time::Instant;
+use tauri::State;
+
+use crate::cancel::Operation;
+use crate::error::AppError;
+use crate::{lock_state, run_blocking, AppState};
+
+/// Largest block `benchmark_read` reads at once.
+const MAX_BENCHMARK_BLOCK: u64 = 64 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BenchmarkResult {
+    pub bytes_read: u64,
+    pub blocks: u64,
+    pub elapsed_ms: f64,
+    pub mib_per_second: f64,
+    /// Whether the reads were served from a memory mapping.
+    pub mapped: bool,
+}
+
+/// Next value of a xorshift generator; random offsets need not be good, only
+/// spread across the file.
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Times reading `total_bytes` from the file in `block_size` blocks, one
+/// after the other from the start (wrapping around at the end) or, with
+/// `random`, at random block-aligned offsets. Blocks cut short by the end of
+/// the file are topped up by the next one, so exactly `total_bytes` are
+/// read. Can be stopped through `operation_id`.
+#[tauri::command]
+pub async fn benchmark_read(
+    handle: u64,
+    total_bytes: u64,
+    block_size: u64,
+    random: bool,
+    operation_id: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<BenchmarkResult, AppError> {
+    if !(1..=MAX_BENCHMARK_BLOCK).contains(&block_size) {
+        return Err(AppError::InvalidArgument(format!(
+            "Block size must be between 1 and {} bytes",
+            MAX_BENCHMARK_BLOCK
+        )));
+    }
+    if total_bytes == 0 {
+        return Err(AppError::InvalidArgument(
+            "Benchmark must read at least one byte".to_string(),
+        ));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    if reader.file_size == 0 {
+        return Err(AppError::InvalidArgument(
+            "Cannot benchmark reads from an empty file".to_string(),
+        ));
+    }
+    let operation = Operation::register(&state, operation_id)?;
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let mapped = reader.is_mapped(0, reader.file_size.min(block_size) as usize);
+        let block_count = reader.file_size.div_ceil(block_size);
+        let mut buffer = vec![0u8; block_size as usize];
+        let mut random_state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut offset = 0;
+        let mut bytes_read = 0;
+        let mut blocks = 0;
+
+        let started = Instant::now();
+        while bytes_read < total_bytes {
+            token.check()?;
+            if random {
+                offset = next_random(&mut random_state) % block_count * block_size;
+            } else if offset >= reader.file_size {
+                offset = 0;
+            }
+            let want = std::cmp::min(block_size, total_bytes - bytes_read) as usize;
+            let read = reader.read_at(offset, &mut buffer[..want])?;
+            if read == 0 {
+                return Err(AppError::UnexpectedEof);
+            }
+            offset += read as u64;
+            bytes_read += read as u64;
+            blocks += 1;
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let mib = bytes_read as f64 / (1024.0 * 1024.0);
+        Ok(BenchmarkResult {
+            bytes_read,
+            blocks,
+            elapsed_ms: elapsed * 1000.0,
+            mib_per_second: if elapsed > 0.0 { mib / elapsed } else { 0.0 },
+            mapped,
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn reads_exactly_the_requested_total() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[7; 10_000]);
+        for random in [false, true] {
+            let result = block_on(benchmark_read(
+                handle,
+                50_000,
+                4096,
+                random,
+                None,
+                app.state(),
+            ))
+            .unwrap();
+            assert_eq!(result.bytes_read, 50_000, "random: {}", random);
+            assert!(result.blocks >= 50_000 / 4096);
+            assert!(result.mib_per_second >= 0.0);
+        }
+
+        assert!(matches!(
+            block_on(benchmark_read(handle, 1, 0, false, None, app.state())),
+            Err(AppError::InvalidArgument(_))
+        ));
+        let (_empty, empty) = open_temp(&app, &[]);
+        assert!(matches!(
+            block_on(benchmark_read(empty, 1, 1, false, None, app.state())),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::error::AppError;
+use crate::{lock_state, AppState};
+
+/// A labelled position in the file, for quick jumps.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bookmark {
+    /// Assigned by the backend when the bookmark is added.
+    #[serde(default)]
+    pub id: u64,
+    pub offset: u64,
+    pub label: String,
+}
+
+pub(crate) fn validate_bookmark(bookmark: &Bookmark, file_size: u64) -> Result<(), AppError> {
+    if bookmark.offset >= file_size {
+        return Err(AppError::OutOfBounds {
+            requested: bookmark.offset,
+            size: file_size,
+        });
+    }
+    Ok(())
+}
+
+/// Moves bookmarks to follow `removed` bytes at `at` being replaced by
+/// `inserted` new ones. Bookmarks inside the removed bytes are dropped.
+pub(crate) fn shift_bookmarks(bookmarks: &mut Vec<Bookmark>, at: u64, removed: u64, inserted: u64) {
+    let removed_end = at + removed;
+    bookmarks.retain_mut(|bookmark| {
+        if bookmark.offset >= removed_end {
+            bookmark.offset = bookmark.offset - removed + inserted;
+        } else if bookmark.offset >= at {
+            return false;
+        }
+        true
+    });
+}
+
+/// Returns the id assigned to the new bookmark.
+#[tauri::command]
+pub fn add_bookmark(
+    handle: u64,
+    offset: u64,
+    label: String,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let id = file_state.next_bookmark_id;
+    let bookmark = Bookmark { id, offset, label };
+    validate_bookmark(&bookmark, file_state.file_size)?;
+
+    file_state.next_bookmark_id += 1;
+    let index = file_state
+        .bookmarks
+        .partition_point(|existing| existing.offset <= offset);
+    file_state.bookmarks.insert(index, bookmark);
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn remove_bookmark(handle: u64, id: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let index = file_state
+        .bookmarks
+        .iter()
+        .position(|bookmark| bookmark.id == id)
+        .ok_or(AppError::BookmarkNotFound(id))?;
+    file_state.bookmarks.remove(index);
+    Ok(())
+}
+
+/// Returns all bookmarks ordered by offset.
+#[tauri::command]
+pub fn list_bookmarks(handle: u64, state: State<AppState>) -> Result<Vec<Bookmark>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(app_state.get(handle)?.bookmarks.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn add_list_and_remove() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 16]);
+
+        let late = add_bookmark(handle, 12, "late".to_string(), app.state()).unwrap();
+        let early = add_bookmark(handle, 3, "early".to_string(), app.state()).unwrap();
+        assert_ne!(late, early);
+        let listed = list_bookmarks(handle, app.state()).unwrap();
+        let summary: Vec<_> = listed.iter().map(|b| (b.id, b.offset)).collect();
+        assert_eq!(summary, vec![(early, 3), (late, 12)]);
+
+        remove_bookmark(handle, early, app.state()).unwrap();
+        assert_eq!(list_bookmarks(handle, app.state()).unwrap()[0].id, late);
+        assert_eq!(
+            remove_bookmark(handle, early, app.state()),
+            Err(AppError::BookmarkNotFound(early))
+        );
+    }
+
+    #[test]
+    fn rejects_offsets_outside_the_file() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 16]);
+
+        assert_eq!(
+            add_bookmark(handle, 16, "end".to_string(), app.state()),
+            Err(AppError::OutOfBounds {
+                requested: 16,
+                size: 16
+            })
+        );
+        assert!(list_bookmarks(handle, app.state()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use tauri::State;
+
+use crate::error::AppError;
+use crate::reader::{to_usize, FileReader};
+use crate::{lock_state, AppState};
+
+/// Size of the aligned blocks `get_file_data` reads and caches.
+pub(crate) const BLOCK_SIZE: u64 = 512;
+
+const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// Blocks read ahead when prefetching is enabled without choosing a count.
+const DEFAULT_PREFETCH_BLOCKS: u64 = 16;
+
+/// Most blocks `set_prefetch` reads ahead, as many as the default cache holds.
+const MAX_PREFETCH_BLOCKS: u64 = DEFAULT_CACHE_CAPACITY as u64;
+
+/// Requests remembered to spot sequential reading; all of them must move
+/// forward for the next blocks to be prefetched.
+const READ_AHEAD_HISTORY: usize = 3;
+
+struct CachedBlock {
+    /// Shorter than `BLOCK_SIZE` only for the last block of the file.
+    data: Vec<u8>,
+    last_used: u64,
+}
+
+/// Least-recently-used cache of file blocks, keyed by aligned start offset.
+pub(crate) struct BlockCache {
+    blocks: HashMap<u64, CachedBlock>,
+    capacity: usize,
+    clock: u64,
+    /// Bumped on every invalidation so reads that raced an edit are not
+    /// cached.
+    generation: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+    blocks: usize,
+    capacity: usize,
+}
+
+impl BlockCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            blocks: HashMap::new(),
+            capacity: DEFAULT_CACHE_CAPACITY,
+            clock: 0,
+            generation: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether every block of `[start, end)` is cached. Unlike `read`, this
+    /// neither counts as a hit or miss nor marks the blocks as used.
+    pub(crate) fn is_cached(&self, start: u64, end: u64) -> bool {
+        let mut block_start = (start / BLOCK_SIZE) * BLOCK_SIZE;
+        while block_start < end {
+            let to = std::cmp::min(end - block_start, BLOCK_SIZE) as usize;
+            if !self
+                .blocks
+                .get(&block_start)
+                .is_some_and(|block| block.data.len() >= to)
+            {
+                return false;
+            }
+            block_start += BLOCK_SIZE;
+        }
+        true
+    }
+
+    /// Assembles `[start, end)` from cached blocks. Returns `None` unless every
+    /// block of the range is cached.
+    pub(crate) fn read(&mut self, start: u64, end: u64) -> Option<Vec<u8>> {
+        self.clock += 1;
+        let mut data = Vec::with_capacity((end - start) as usize);
+        let mut block_start = (start / BLOCK_SIZE) * BLOCK_SIZE;
+        while block_start < end {
+            let from = start.saturating_sub(block_start) as usize;
+            let to = std::cmp::min(end - block_start, BLOCK_SIZE) as usize;
+            match self.blocks.get_mut(&block_start) {
+                Some(block) if block.data.len() >= to => {
+                    block.last_used = self.clock;
+                    data.extend_from_slice(&block.data[from..to]);
+                }
+                _ => {
+                    self.misses += 1;
+                    return None;
+                }
+            }
+            block_start += BLOCK_SIZE;
+        }
+        self.hits += 1;
+        Some(data)
+    }
+
+    /// Caches `data` read from the block-aligned `start`, unless the cache was
+    /// invalidated since `generation` was taken.
+    pub(crate) fn insert(&mut self, start: u64, data: &[u8], generation: u64) {
+        if generation != self.generation {
+            return;
+        }
+        let chunks = data.chunks(BLOCK_SIZE as usize);
+        // Blocks beyond the capacity would only evict each other.
+        let skip = chunks.len().saturating_sub(self.capacity);
+        for (index, chunk) in chunks.enumerate().skip(skip) {
+            self.clock += 1;
+            self.blocks.insert(
+                start + index as u64 * BLOCK_SIZE,
+                CachedBlock {
+                    data: chunk.to_vec(),
+                    last_used: self.clock,
+                },
+            );
+        }
+        self.evict();
+    }
+
+    /// Drops every block overlapping `[start, end)`.
+    pub(crate) fn invalidate(&mut self, start: u64, end: u64) {
+        self.blocks
+            .retain(|&block_start, _| block_start + BLOCK_SIZE <= start || block_start >= end);
+        self.generation += 1;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.blocks.clear();
+        self.generation += 1;
+    }
+
+    fn evict(&mut self) {
+        while self.blocks.len() > self.capacity {
+            let oldest = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, block)| block.last_used)
+                .map(|(&block_start, _)| block_start);
+            match oldest {
+                Some(block_start) => self.blocks.remove(&block_start),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Spots sequential reading in the requests `get_file_data` serves, so the
+/// blocks after them can be cached before they are asked for.
+pub(crate) struct ReadAhead {
+    enabled: bool,
+    blocks: u64,
+    /// `[start, end)` of the latest requests, oldest first.
+    recent: VecDeque<(u64, u64)>,
+}
+
+impl ReadAhead {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            blocks: DEFAULT_PREFETCH_BLOCKS,
+            recent: VecDeque::with_capacity(READ_AHEAD_HISTORY),
+        }
+    }
+
+    /// Records a request for `[start, end)` and returns the block-aligned
+    /// range to prefetch, clamped to `file_size`, if the latest requests
+    /// each began after the one before and no later than where it ended.
+    pub(crate) fn record(&mut self, start: u64, end: u64, file_size: u64) -> Option<(u64, u64)> {
+        if self.recent.len() == READ_AHEAD_HISTORY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((start, end));
+        if !self.enabled || self.recent.len() < READ_AHEAD_HISTORY {
+            return None;
+        }
+        let sequential = self.recent.iter().zip(self.recent.iter().skip(1)).all(
+            |(&(previous_start, previous_end), &(start, _))| {
+                previous_start < start && start <= previous_end
+            },
+        );
+        if !sequential {
+            return None;
+        }
+        let from = end.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        let to = std::cmp::min(from.saturating_add(self.blocks * BLOCK_SIZE), file_size);
+        (from < to).then_some((from, to))
+    }
+}
+
+/// Reads `[start, end)` of file `handle` on the blocking thread pool and
+/// caches it, unless the file was closed or edited since `generation` was
+/// taken. Failures are dropped; a later request will simply miss.
+pub(crate) fn prefetch(
+    state: AppState,
+    handle: u64,
+    reader: FileReader,
+    start: u64,
+    end: u64,
+    generation: u64,
+) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let Ok(length) = to_usize(end - start) else {
+            return;
+        };
+        let mut data = vec![0u8; length];
+        let Ok(read) = reader.read_at(start, &mut data) else {
+            return;
+        };
+        if let Ok(file_state) = lock_state(&state).get_mut(handle) {
+            file_state.cache.insert(start, &data[..read], generation);
+        }
+    });
+}
+
+/// Turns read-ahead on or off for file `handle`. While on, once
+/// `get_file_data` sees a few requests moving forward through the file, it
+/// caches the next `blocks` blocks (16 if unset, at most 256) in the
+/// background so scrolling on finds them ready.
+#[tauri::command]
+pub fn set_prefetch(
+    handle: u64,
+    enabled: bool,
+    blocks: Option<u64>,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let blocks = blocks.unwrap_or(DEFAULT_PREFETCH_BLOCKS);
+    if !(1..=MAX_PREFETCH_BLOCKS).contains(&blocks) {
+        return Err(AppError::InvalidArgument(format!(
+            "Prefetch must read between 1 and {} blocks",
+            MAX_PREFETCH_BLOCKS
+        )));
+    }
+    let mut app_state = lock_state(&state);
+    let read_ahead = &mut app_state.get_mut(handle)?.read_ahead;
+    read_ahead.enabled = enabled;
+    read_ahead.blocks = blocks;
+    read_ahead.recent.clear();
+    Ok(())
+}
+
+/// Drops all cached blocks of file `handle`.
+#[tauri::command]
+pub fn clear_cache(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    app_state.get_mut(handle)?.cache.clear();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cache_stats(handle: u64, state: State<AppState>) -> Result<CacheStats, AppError> {
+    let mut app_state = lock_state(&state);
+    let cache = &app_state.get(handle)?.cache;
+    Ok(CacheStats {
+        hits: cache.hits,
+        misses: cache.misses,
+        blocks: cache.blocks.len(),
+        capacity: cache.capacity,
+    })
+}
+
+/// Sets how many blocks are cached, evicting the least recently used beyond
+/// that. A capacity of 0 disables the cache.
+#[tauri::command]
+pub fn set_cache_capacity(
+    handle: u64,
+    capacity: usize,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let cache = &mut app_state.get_mut(handle)?.cache;
+    cache.capacity = capacity;
+    cache.evict();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+    use crate::{get_file_data, lock_state, save_file_data, AppState};
+
+    fn block(fill: u8) -> Vec<u8> {
+        vec![fill; BLOCK_SIZE as usize]
+    }
+
+    #[test]
+    fn second_read_hits_and_invalidate_misses() {
+        let mut cache = BlockCache::new();
+        assert!(cache.read(0, 16).is_none());
+
+        let generation = cache.generation();
+        cache.insert(0, &[block(1), block(2)].concat(), generation);
+        assert_eq!(
+            cache.read(500, 520).unwrap(),
+            [vec![1; 12], vec![2; 8]].concat()
+        );
+
+        cache.invalidate(600, 601);
+        assert!(cache.read(500, 520).is_none());
+        assert_eq!(cache.read(0, 4).unwrap(), vec![1; 4]);
+        assert_eq!((cache.hits, cache.misses), (2, 2));
+    }
+
+    #[test]
+    fn stale_inserts_are_dropped_and_lru_evicted() {
+        let mut cache = BlockCache::new();
+        let stale = cache.generation();
+        cache.clear();
+        cache.insert(0, &block(1), stale);
+        assert!(cache.blocks.is_empty());
+
+        cache.capacity = 2;
+        let generation = cache.generation();
+        cache.insert(0, &block(1), generation);
+        cache.insert(BLOCK_SIZE, &block(2), generation);
+        cache.read(0, 1).unwrap();
+        cache.insert(2 * BLOCK_SIZE, &block(3), generation);
+        assert!(cache.read(BLOCK_SIZE, BLOCK_SIZE + 1).is_none());
+        assert!(cache.read(0, 1).is_some());
+    }
+
+    #[test]
+    fn writes_invalidate_cached_reads() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[7; 2048]);
+        let read = || {
+            tauri::async_runtime::block_on(get_file_data(handle, 100, 200, None, app.state()))
+                .unwrap()
+        };
+
+        read();
+        assert_eq!(read(), vec![7; 100]);
+        let stats = cache_stats(handle, app.state()).unwrap();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+
+        save_file_data(handle, 150, vec![9], None, None, app.state()).unwrap();
+        assert_eq!(read()[50], 9);
+        assert_eq!(cache_stats(handle, app.state()).unwrap().misses, 2);
+    }
+
+    #[test]
+    fn sequential_reads_prefetch_the_next_blocks() {
+        let app = app();
+        let bytes: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+        let read = |start: u64| {
+            tauri::async_runtime::block_on(get_file_data(
+                handle,
+                start,
+                start + BLOCK_SIZE,
+                None,
+                app.state(),
+            ))
+            .unwrap()
+        };
+        let cached_blocks = || cache_stats(handle, app.state()).unwrap().blocks;
+
+        // Off by default.
+        for block in 0..3 {
+            read(block * BLOCK_SIZE);
+        }
+        assert_eq!(cached_blocks(), 3);
+
+        assert!(matches!(
+            set_prefetch(handle, true, Some(0), app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+        set_prefetch(handle, true, Some(8), app.state()).unwrap();
+        for block in 3..6 {
+            read(block * BLOCK_SIZE);
+        }
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while cached_blocks() < 6 + 8 && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let app_state = app.state::<AppState>();
+        {
+            let mut files = lock_state(&app_state);
+            let cache = &files.get(handle).unwrap().cache;
+            assert!(cache.is_cached(6 * BLOCK_SIZE, 14 * BLOCK_SIZE));
+            assert!(!cache.is_cached(14 * BLOCK_SIZE, 15 * BLOCK_SIZE));
+        }
+
+        let hits = cache_stats(handle, app.state()).unwrap().hits;
+        let start = 6 * BLOCK_SIZE as usize;
+        assert_eq!(
+            read(6 * BLOCK_SIZE),
+            &bytes[start..start + BLOCK_SIZE as usize]
+        );
+        assert_eq!(cache_stats(handle, app.state()).unwrap().hits, hits + 1);
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+use crate::error::AppError;
+use crate::{lock_state, AppState};
+
+/// Cancel flag handed to a long-running operation and checked between chunks.
+#[derive(Clone, Default)]
+pub(crate) struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    /// When the operation times out, and its timeout in milliseconds.
+    deadline: Option<(Instant, u64)>,
+}
+
+impl CancelToken {
+    /// Fails with `Cancelled` once `cancel_operation` has been called, or
+    /// with `TimedOut` once the deadline has passed.
+    pub(crate) fn check(&self) -> Result<(), AppError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(AppError::Cancelled);
+        }
+        if let Some((deadline, timeout_ms)) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(AppError::TimedOut { timeout_ms });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Registration of a cancellable operation, removed again when dropped.
+pub(crate) struct Operation {
+    state: AppState,
+    id: Option<u64>,
+    pub token: CancelToken,
+}
+
+impl Operation {
+    /// Registers `operation_id` so `cancel_operation` can reach it. Without an
+    /// id the operation runs uncancellable.
+    pub(crate) fn register(state: &AppState, operation_id: Option<u64>) -> Result<Self, AppError> {
+        let token = CancelToken::default();
+        if let Some(id) = operation_id {
+            let mut app_state = lock_state(state);
+            if app_state.operations.contains_key(&id) {
+                return Err(AppError::InvalidArgument(format!(
+                    "Operation {} is already running",
+                    id
+                )));
+            }
+            app_state.operations.insert(id, token.clone());
+        }
+        Ok(Self {
+            state: state.clone(),
+            id: operation_id,
+            token,
+        })
+    }
+
+    /// Makes the operation fail with `TimedOut` once it has run for
+    /// `timeout_ms` milliseconds. Without a timeout it runs to completion.
+    pub(crate) fn with_timeout(mut self, timeout_ms: Option<u64>) -> Self {
+        if let Some(timeout_ms) = timeout_ms {
+            let deadline = Instant::now().checked_add(Duration::from_millis(timeout_ms));
+            self.token.deadline = deadline.map(|deadline| (deadline, timeout_ms));
+        }
+        self
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            lock_state(&self.state).operations.remove(&id);
+        }
+    }
+}
+
+/// Asks the operation registered as `operation_id` to stop; it then fails
+/// with a `cancelled` error. Returns false if no such operation is running.
+#[tauri::command]
+pub fn cancel_operation(operation_id: u64, state: State<AppState>) -> Result<bool, AppError> {
+    let app_state = lock_state(&state);
+    match app_state.operations.get(&operation_id) {
+        Some(token) => {
+            token.cancelled.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::strings::find_strings;
+    use crate::test_support::app;
+
+    #[test]
+    fn cancelled_operation_fails_promptly() {
+        let app = app();
+        let handle = open_bytes(vec![0; 256 * 1024 * 1024], app.state())
+            .unwrap()
+            .handle;
+
+        let app_handle = app.handle().clone();
+        let scan = std::thread::spawn(move || {
+            tauri::async_runtime::block_on(find_strings(
+                handle,
+                4,
+                "ascii".to_string(),
+                Some(7),
+                None,
+                app_handle.state(),
+            ))
+        });
+        while !cancel_operation(7, app.state()).unwrap() {
+            assert!(!scan.is_finished(), "scan finished before it was cancelled");
+            std::thread::yield_now();
+        }
+        let cancelled_at = Instant::now();
+
+        assert!(matches!(scan.join().unwrap(), Err(AppError::Cancelled)));
+        assert!(cancelled_at.elapsed() < Duration::from_secs(5));
+        // The registration is gone once the operation has ended.
+        assert!(!cancel_operation(7, app.state()).unwrap());
+    }
+}
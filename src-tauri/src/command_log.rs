@@ -0,0 +1,302 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tauri::ipc::{CallbackFn, Invoke, InvokeBody, InvokeResponse, InvokeResponseBody};
+use tauri::webview::InvokeRequest;
+use tauri::{Manager, Runtime, State};
+
+use crate::error::AppError;
+use crate::tags::now_millis;
+
+/// Most invocations kept; older ones are dropped first.
+const MAX_LOGGED_COMMANDS: usize = 1000;
+
+/// Arrays longer than this and strings longer than `MAX_LOGGED_STRING` are
+/// logged as their length, so large payloads do not fill the log. Byte
+/// arrays are logged as their length whatever their size.
+const MAX_LOGGED_ARRAY: usize = 16;
+const MAX_LOGGED_STRING: usize = 256;
+
+/// Marks an invocation `logging` has re-issued with a resolver that logs its
+/// outcome, so it is dispatched as-is the second time round.
+const LOGGED_HEADER: &str = "x-command-log";
+
+/// The log's own commands, left out of it.
+const UNLOGGED_COMMANDS: [&str; 3] = ["set_command_logging", "get_command_log", "dump_command_log"];
+
+/// One logged invocation.
+#[derive(Serialize, Clone, Debug)]
+pub struct LoggedCommand {
+    /// Counts every invocation logged since the app started, so gaps show
+    /// where the ring dropped entries.
+    pub seq: u64,
+    pub command: String,
+    /// The arguments, with long arrays and strings replaced by
+    /// `{ "len": ... }`.
+    pub args: serde_json::Value,
+    /// Unix milliseconds.
+    pub timestamp: u64,
+    /// `pending` until the command answers, then `ok`, or the `code` of the
+    /// error it failed with; `error` for failures that have none, such as
+    /// an unknown command or malformed arguments.
+    pub status: String,
+}
+
+/// Opt-in ring of the commands invoked, for working out what led to a bug.
+pub struct CommandLog {
+    enabled: AtomicBool,
+    ring: Mutex<Ring>,
+}
+
+struct Ring {
+    entries: VecDeque<LoggedCommand>,
+    next_seq: u64,
+}
+
+impl CommandLog {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            ring: Mutex::new(Ring {
+                entries: VecDeque::new(),
+                next_seq: 1,
+            }),
+        }
+    }
+
+    fn ring(&self) -> MutexGuard<'_, Ring> {
+        self.ring.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether invocations of `command` are logged.
+    fn logs(&self, command: &str) -> bool {
+        self.enabled.load(Ordering::Relaxed) && !UNLOGGED_COMMANDS.contains(&command)
+    }
+
+    /// Logs one invocation of `command` as `pending` and returns its `seq`.
+    /// `args` are expected to be summarized already.
+    fn record(&self, command: &str, args: serde_json::Value) -> u64 {
+        let mut ring = self.ring();
+        let seq = ring.next_seq;
+        ring.next_seq += 1;
+        ring.entries.push_back(LoggedCommand {
+            seq,
+            command: command.to_string(),
+            args,
+            timestamp: now_millis(),
+            status: "pending".to_string(),
+        });
+        if ring.entries.len() > MAX_LOGGED_COMMANDS {
+            ring.entries.pop_front();
+        }
+        seq
+    }
+
+    /// Sets the status of invocation `seq` from its `response`, unless the
+    /// ring dropped it meanwhile.
+    fn finish(&self, seq: u64, response: &InvokeResponse) {
+        let status = match response {
+            InvokeResponse::Ok(_) => "ok",
+            InvokeResponse::Err(error) => error.0["code"].as_str().unwrap_or("error"),
+        };
+        let mut ring = self.ring();
+        if let Some(entry) = ring.entries.iter_mut().find(|entry| entry.seq == seq) {
+            entry.status = status.to_string();
+        }
+    }
+}
+
+fn length(len: usize) -> serde_json::Value {
+    serde_json::json!({ "len": len })
+}
+
+/// Whether `items` look like a byte array: numbers that each fit in a byte.
+/// Other short arrays of small numbers, such as ranges near the start of a
+/// file, are summarized along with them.
+fn is_bytes(items: &[serde_json::Value]) -> bool {
+    !items.is_empty()
+        && items
+            .iter()
+            .all(|item| item.as_u64().is_some_and(|number| number <= 0xff))
+}
+
+/// Copies `value` with byte arrays, long arrays and long strings replaced by
+/// their length.
+fn summarize(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(items) if items.len() > MAX_LOGGED_ARRAY || is_bytes(items) => {
+            length(items.len())
+        }
+        serde_json::Value::Array(items) => items.iter().map(summarize).collect(),
+        serde_json::Value::String(text) if text.len() > MAX_LOGGED_STRING => length(text.len()),
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| (key.clone(), summarize(value)))
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+/// Wraps the app's invoke handler so each invocation lands in the
+/// `CommandLog` while logging is on, with its outcome.
+///
+/// Results go straight from a command to its resolver, so a logged
+/// invocation is re-issued through `Webview::on_message` with a resolver that
+/// logs the response before passing it on to the original one.
+pub(crate) fn logging<R, F>(handler: F) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static
+where
+    R: Runtime,
+    F: Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+{
+    move |invoke| {
+        let webview = invoke.message.webview();
+        let Some(log) = webview.try_state::<CommandLog>() else {
+            return handler(invoke);
+        };
+        let command = invoke.message.command();
+        if !log.logs(command) || invoke.message.headers().contains_key(LOGGED_HEADER) {
+            return handler(invoke);
+        }
+        let Ok(url) = webview.url() else {
+            return handler(invoke);
+        };
+        let args = match invoke.message.payload() {
+            InvokeBody::Json(args) => summarize(args),
+            InvokeBody::Raw(bytes) => length(bytes.len()),
+        };
+        let seq = log.record(command, args);
+
+        let mut headers = invoke.message.headers().clone();
+        headers.insert(LOGGED_HEADER, tauri::http::HeaderValue::from_static("1"));
+        let request = InvokeRequest {
+            cmd: command.to_string(),
+            // Unused: the response goes back through the original resolver.
+            callback: CallbackFn(0),
+            error: CallbackFn(0),
+            url,
+            body: invoke.message.payload().clone(),
+            headers,
+            invoke_key: webview.app_handle().invoke_key().to_string(),
+        };
+        let resolver = invoke.resolver;
+        webview.on_message(
+            request,
+            Box::new(move |webview, _, response, _, _| {
+                if let Some(log) = webview.try_state::<CommandLog>() {
+                    log.finish(seq, &response);
+                }
+                match response {
+                    InvokeResponse::Ok(body) => resolver.respond(Ok(body)),
+                    InvokeResponse::Err(error) => {
+                        resolver.respond::<InvokeResponseBody>(Err(error))
+                    }
+                }
+            }),
+        );
+        true
+    }
+}
+
+/// Starts or stops logging invocations. Entries already logged are kept.
+#[tauri::command]
+pub fn set_command_logging(enabled: bool, log: State<CommandLog>) {
+    log.enabled.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns the logged invocations, oldest first.
+#[tauri::command]
+pub fn get_command_log(log: State<CommandLog>) -> Vec<LoggedCommand> {
+    log.ring().entries.iter().cloned().collect()
+}
+
+/// Writes the logged invocations to `path` as a JSON array, oldest first,
+/// to attach to a bug report.
+#[tauri::command]
+pub fn dump_command_log(path: String, log: State<CommandLog>) -> Result<(), AppError> {
+    let entries = get_command_log(log);
+    let json = serde_json::to_string_pretty(&entries)
+        .map_err(|e| AppError::InvalidFormat(format!("Failed to serialize command log: {}", e)))?;
+    fs::write(&path, json)
+        .map_err(|e| AppError::Io(format!("Failed to write command log to {}: {}", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tauri::ipc::InvokeError;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::app;
+
+    #[test]
+    fn logs_invocations_in_order_without_payloads() {
+        let app = app();
+        app.manage(CommandLog::new());
+        let log = || app.state::<CommandLog>();
+        let ok = || InvokeResponse::Ok(InvokeResponseBody::Json("null".to_string()));
+
+        assert!(!log().logs("file_info"));
+        set_command_logging(true, log());
+        assert!(!log().logs("get_command_log"));
+
+        let info = log().record("file_info", summarize(&json!({ "handle": 1 })));
+        let bytes = vec![0u8; 4096];
+        let save = log().record(
+            "save_file_data",
+            summarize(&json!({ "handle": 1, "start": 16, "bytes": bytes, "saveMode": "atomic" })),
+        );
+        let insert = log().record(
+            "insert_bytes",
+            summarize(&json!({ "handle": 1, "offset": 300, "bytes": [1, 2] })),
+        );
+        let unknown = log().record("no_such_command", summarize(&json!({})));
+        log().finish(info, &ok());
+        log().finish(
+            save,
+            &InvokeResponse::Err(InvokeError(
+                serde_json::to_value(AppError::ReadOnly).unwrap(),
+            )),
+        );
+        log().finish(
+            unknown,
+            &InvokeResponse::Err(InvokeError(json!("Command no_such_command not found"))),
+        );
+
+        let entries = get_command_log(log());
+        let summary: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|entry| (entry.command.as_str(), entry.status.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("file_info", "ok"),
+                ("save_file_data", "read_only"),
+                ("insert_bytes", "pending"),
+                ("no_such_command", "error"),
+            ]
+        );
+        assert!(entries.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+        assert_eq!(
+            entries[1].args,
+            json!({ "handle": 1, "start": 16, "bytes": { "len": 4096 }, "saveMode": "atomic" })
+        );
+        assert_eq!(
+            entries[2].args,
+            json!({ "handle": 1, "offset": 300, "bytes": { "len": 2 } })
+        );
+        log().finish(insert, &ok());
+        assert_eq!(get_command_log(log())[2].status, "ok");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("commands.json");
+        dump_command_log(path.to_string_lossy().into_owned(), log()).unwrap();
+        let dumped: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(dumped.as_array().unwrap().len(), 4);
+        assert_eq!(dumped[3]["command"], "no_such_command");
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::{File, Metadata};
+use std::io::{Seek, SeekFrom};
+
+use crate::error::AppError;
+
+/// Returns the size of `file` in bytes. Block devices report a length of 0
+/// in their metadata, so their size is asked of the device instead.
+pub(crate) fn file_size(file: &File) -> Result<u64, AppError> {
+    let metadata = file
+        .metadata()
+        .map_err(|e| AppError::Io(format!("Failed to read metadata: {}", e)))?;
+    if metadata.is_file() || metadata.len() > 0 {
+        return Ok(metadata.len());
+    }
+
+    if let Some(size) = block_device_size(file, &metadata) {
+        return Ok(size);
+    }
+    // Devices that can seek report their size as the end position; anything
+    // else (pipes, terminals) keeps the metadata length.
+    let mut file = file;
+    Ok(file.seek(SeekFrom::End(0)).unwrap_or(metadata.len()))
+}
+
+#[cfg(target_os = "linux")]
+fn block_device_size(file: &File, metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::io::AsRawFd;
+
+    if !metadata.file_type().is_block_device() {
+        return None;
+    }
+    // `_IOR(0x12, 114, size_t)` from <linux/fs.h>; the kernel always writes
+    // a u64.
+    const BLKGETSIZE64: u64 =
+        (2 << 30) | ((std::mem::size_of::<usize>() as u64) << 16) | (0x12 << 8) | 114;
+    let mut size: u64 = 0;
+    // SAFETY: BLKGETSIZE64 writes a single u64 through the pointer, which
+    // points at a live local.
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), BLKGETSIZE64 as _, &mut size) };
+    (result == 0).then_some(size)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn block_device_size(_file: &File, _metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::test_support::temp_file;
+
+    #[test]
+    fn regular_file_size_comes_from_metadata() {
+        let mut file = temp_file(&[0; 1234]);
+        assert_eq!(file_size(file.as_file()).unwrap(), 1234);
+        file.write_all(&[0; 10]).unwrap();
+        assert_eq!(file_size(file.as_file()).unwrap(), 1244);
+        assert_eq!(file_size(temp_file(b"").as_file()).unwrap(), 0);
+    }
+
+    /// Set `TEST_BLOCK_DEVICE` to a readable block device, e.g. a loop
+    /// device, to run this; it is skipped otherwise.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn block_device_size_is_queried() {
+        let Some(path) = std::env::var_os("TEST_BLOCK_DEVICE") else {
+            return;
+        };
+        let mut device = File::open(path).unwrap();
+        let size = file_size(&device).unwrap();
+        assert!(size > 0);
+        assert_eq!(device.seek(SeekFrom::End(0)).unwrap(), size);
+    }
+}
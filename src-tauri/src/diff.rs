@@ -0,0 +1,589 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::reader::{self, to_usize};
+use crate::tags::{insert_sorted, now_millis, Tag};
+use crate::{lock_state, resolve_path, run_blocking, AppState};
+
+const DIFF_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Changed,
+    OnlyInA,
+    OnlyInB,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DiffRange {
+    pub offset: u64,
+    pub length: u64,
+    pub kind: DiffKind,
+}
+
+/// Compares two byte sources chunk by chunk and returns coalesced ranges
+/// where they differ. Each reader fills a buffer from an offset and returns
+/// how many bytes it read, like `FileState::read_at`.
+pub(crate) fn diff_sources<A, B>(
+    mut read_a: A,
+    size_a: u64,
+    mut read_b: B,
+    size_b: u64,
+) -> Result<Vec<DiffRange>, AppError>
+where
+    A: FnMut(u64, &mut [u8]) -> Result<usize, AppError>,
+    B: FnMut(u64, &mut [u8]) -> Result<usize, AppError>,
+{
+    let common = std::cmp::min(size_a, size_b);
+    let mut ranges = Vec::new();
+    let mut run_start: Option<u64> = None;
+
+    let mut buf_a = vec![0u8; DIFF_CHUNK_SIZE];
+    let mut buf_b = vec![0u8; DIFF_CHUNK_SIZE];
+    let mut offset = 0u64;
+    while offset < common {
+        let want = std::cmp::min(DIFF_CHUNK_SIZE as u64, common - offset) as usize;
+        let read_len = std::cmp::min(
+            read_a(offset, &mut buf_a[..want])?,
+            read_b(offset, &mut buf_b[..want])?,
+        );
+        if read_len == 0 {
+            break;
+        }
+
+        let (chunk_a, chunk_b) = (&buf_a[..read_len], &buf_b[..read_len]);
+        if chunk_a == chunk_b {
+            if let Some(start) = run_start.take() {
+                ranges.push(DiffRange {
+                    offset: start,
+                    length: offset - start,
+                    kind: DiffKind::Changed,
+                });
+            }
+        } else {
+            for (i, (a, b)) in chunk_a.iter().zip(chunk_b).enumerate() {
+                let position = offset + i as u64;
+                match (a != b, run_start) {
+                    (true, None) => run_start = Some(position),
+                    (false, Some(start)) => {
+                        ranges.push(DiffRange {
+                            offset: start,
+                            length: position - start,
+                            kind: DiffKind::Changed,
+                        });
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        offset += read_len as u64;
+    }
+
+    if let Some(start) = run_start {
+        ranges.push(DiffRange {
+            offset: start,
+            length: offset - start,
+            kind: DiffKind::Changed,
+        });
+    }
+    if size_a != size_b {
+        ranges.push(DiffRange {
+            offset: common,
+            length: size_a.abs_diff(size_b),
+            kind: if size_a > size_b {
+                DiffKind::OnlyInA
+            } else {
+                DiffKind::OnlyInB
+            },
+        });
+    }
+    Ok(ranges)
+}
+
+/// Returns the ranges where two open files differ.
+#[tauri::command]
+pub async fn diff_files(
+    handle_a: u64,
+    handle_b: u64,
+    state: State<'_, AppState>,
+) -> Result<Vec<DiffRange>, AppError> {
+    let (reader_a, reader_b) = {
+        let mut app_state = lock_state(&state);
+        (
+            app_state.get(handle_a)?.reader(),
+            app_state.get(handle_b)?.reader(),
+        )
+    };
+    if handle_a == handle_b {
+        return Ok(Vec::new());
+    }
+
+    run_blocking(move || {
+        diff_sources(
+            |offset, buf| reader_a.read_at(offset, buf),
+            reader_a.file_size,
+            |offset, buf| reader_b.read_at(offset, buf),
+            reader_b.file_size,
+        )
+    })
+    .await
+}
+
+/// Returns the ranges where the open file differs from the file at
+/// `baseline_path`, a known-good copy opened read-only for the comparison.
+/// The baseline is side A, so `only_in_b` means bytes the open file gained.
+/// With `tag_changes`, every range present in the open file is also added
+/// to it as a `modified` tag, even where it overlaps existing tags. No tags
+/// are added if the file was edited while the comparison ran, as the ranges
+/// may no longer line up with its bytes.
+#[tauri::command]
+pub async fn diff_against_baseline(
+    handle: u64,
+    baseline_path: String,
+    tag_changes: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<DiffRange>, AppError> {
+    let (reader, generation) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (file_state.reader(), file_state.cache.generation())
+    };
+    let file_size = reader.file_size;
+    let path = resolve_path(Path::new(&baseline_path))?;
+    let baseline = File::open(&path)
+        .map_err(|e| AppError::Io(format!("Failed to open baseline {}: {}", path.display(), e)))?;
+    let baseline_size = baseline
+        .metadata()
+        .map_err(|e| AppError::Io(format!("Failed to read baseline metadata: {}", e)))?
+        .len();
+
+    let ranges = run_blocking(move || {
+        diff_sources(
+            |offset, buf| {
+                reader::read_file_at(&baseline, buf, offset)
+                    .map_err(|e| AppError::Io(format!("Failed to read baseline: {}", e)))
+            },
+            baseline_size,
+            |offset, buf| reader.read_at(offset, buf),
+            reader.file_size,
+        )
+    })
+    .await?;
+
+    if tag_changes.unwrap_or(false) {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get_mut(handle)?;
+        if file_state.file_size != file_size || file_state.cache.generation() != generation {
+            return Ok(ranges);
+        }
+        let created_at = now_millis();
+        for range in ranges
+            .iter()
+            .filter(|range| range.kind != DiffKind::OnlyInA)
+        {
+            let mut tag = Tag::new(
+                range.offset,
+                range.offset + range.length,
+                "modified".to_string(),
+                "Modified",
+            );
+            tag.id = file_state.next_tag_id;
+            tag.created_at = created_at;
+            file_state.next_tag_id += 1;
+            insert_sorted(&mut file_state.tags, tag);
+        }
+    }
+    Ok(ranges)
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct OffsetBytes {
+    pub handle: u64,
+    /// `None` when the file ends at or before the requested offset.
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// Returns the bytes at `offset..offset + length` in each of `handle_ids`,
+/// in the order given. Reads are cut short at end of file.
+#[tauri::command]
+pub fn compare_offset(
+    handle_ids: Vec<u64>,
+    offset: u64,
+    length: u64,
+    state: State<AppState>,
+) -> Result<Vec<OffsetBytes>, AppError> {
+    let readers = {
+        let mut app_state = lock_state(&state);
+        handle_ids
+            .iter()
+            .map(|&handle| Ok((handle, app_state.get(handle)?.reader())))
+            .collect::<Result<Vec<_>, AppError>>()?
+    };
+
+    readers
+        .into_iter()
+        .map(|(handle, reader)| {
+            if offset >= reader.file_size {
+                return Ok(OffsetBytes {
+                    handle,
+                    bytes: None,
+                });
+            }
+            let available = std::cmp::min(length, reader.file_size - offset);
+            let mut bytes = vec![0u8; to_usize(available)?];
+            let read = reader.read_at(offset, &mut bytes)?;
+            bytes.truncate(read);
+            Ok(OffsetBytes {
+                handle,
+                bytes: Some(bytes),
+            })
+        })
+        .collect()
+}
+
+/// Smallest and largest blocks `similar_blocks` matches.
+const MIN_SIMILAR_BLOCK: u64 = 16;
+const MAX_SIMILAR_BLOCK: u64 = 16 * 1024 * 1024;
+
+/// Most blocks of the open file `similar_blocks` indexes, bounding the
+/// index to a few hundred MiB.
+const MAX_INDEXED_BLOCKS: u64 = 1 << 24;
+
+/// Multiplier of the polynomial rolling hash, the 64-bit FNV prime.
+const ROLLING_BASE: u64 = 0x0100_0000_01b3;
+
+fn block_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |hash, &byte| {
+        hash.wrapping_mul(ROLLING_BASE).wrapping_add(byte as u64)
+    })
+}
+
+/// The bytes of a file from `start` on, read ahead in chunks and dropped
+/// once passed.
+struct Window {
+    file: File,
+    bytes: Vec<u8>,
+    start: u64,
+    chunk: Vec<u8>,
+}
+
+impl Window {
+    /// Reads until the window reaches `end` or the file ends, and returns
+    /// where the window ends.
+    fn fill_to(&mut self, end: u64) -> Result<u64, AppError> {
+        while self.start + (self.bytes.len() as u64) < end {
+            let offset = self.start + self.bytes.len() as u64;
+            let read = reader::read_file_at(&self.file, &mut self.chunk, offset)
+                .map_err(|e| AppError::Io(format!("Failed to read file data: {}", e)))?;
+            if read == 0 {
+                break;
+            }
+            self.bytes.extend_from_slice(&self.chunk[..read]);
+        }
+        Ok(self.start + self.bytes.len() as u64)
+    }
+
+    /// Forgets the bytes before `offset` once a chunk's worth has piled up.
+    fn pass(&mut self, offset: u64) {
+        let passed = (offset - self.start) as usize;
+        if passed >= DIFF_CHUNK_SIZE {
+            self.bytes.drain(..passed);
+            self.start = offset;
+        }
+    }
+
+    fn at(&self, offset: u64) -> u8 {
+        self.bytes[(offset - self.start) as usize]
+    }
+}
+
+/// Finds the `block_size` blocks of the open file, at multiples of
+/// `block_size`, that also appear anywhere in the file at `other_path`, and
+/// returns `(offset_in_this, offset_in_other)` pairs ordered by the offset
+/// in the other file. A rolling hash slides over the other file a byte at a
+/// time, so moved and duplicated blocks are found wherever they landed. Each
+/// candidate is compared byte for byte; after a match the scan resumes past
+/// the matched block, and a block found several times in the open file is
+/// paired with its first copy.
+#[tauri::command]
+pub async fn similar_blocks(
+    handle: u64,
+    other_path: String,
+    block_size: u64,
+    state: State<'_, AppState>,
+) -> Result<Vec<(u64, u64)>, AppError> {
+    if !(MIN_SIMILAR_BLOCK..=MAX_SIMILAR_BLOCK).contains(&block_size) {
+        return Err(AppError::InvalidArgument(format!(
+            "Block size must be between {} and {} bytes",
+            MIN_SIMILAR_BLOCK, MAX_SIMILAR_BLOCK
+        )));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let block_count = reader.file_size / block_size;
+    if block_count > MAX_INDEXED_BLOCKS {
+        return Err(AppError::InvalidArgument(format!(
+            "Blocks of {} bytes are too small for a file this large; use at least {}",
+            block_size,
+            reader.file_size.div_ceil(MAX_INDEXED_BLOCKS)
+        )));
+    }
+    let path = resolve_path(Path::new(&other_path))?;
+    let other = File::open(&path)
+        .map_err(|e| AppError::Io(format!("Failed to open {}: {}", path.display(), e)))?;
+
+    run_blocking(move || {
+        let len = block_size as usize;
+        let mut index: HashMap<u64, Vec<u64>> = HashMap::new();
+        let chunk_size = std::cmp::max(1, DIFF_CHUNK_SIZE / len) * len;
+        reader.for_each_chunk(0, block_count * block_size, chunk_size, |offset, chunk| {
+            for (i, block) in chunk.chunks_exact(len).enumerate() {
+                let block_offset = offset + (i * len) as u64;
+                index
+                    .entry(block_hash(block))
+                    .or_default()
+                    .push(block_offset);
+            }
+            Ok(())
+        })?;
+
+        // Weight of the byte leaving the window.
+        let leaving = ROLLING_BASE.wrapping_pow(block_size as u32 - 1);
+        let mut window = Window {
+            file: other,
+            bytes: Vec::new(),
+            start: 0,
+            chunk: vec![0u8; DIFF_CHUNK_SIZE],
+        };
+        let mut this_block = vec![0u8; len];
+        let mut pairs = Vec::new();
+        let mut position = 0;
+        let mut hash = None;
+        while window.fill_to(position + block_size + 1)? >= position + block_size {
+            let from = (position - window.start) as usize;
+            let block = &window.bytes[from..from + len];
+            let current = *hash.get_or_insert_with(|| block_hash(block));
+            let mut matched = None;
+            for &offset in index.get(&current).into_iter().flatten() {
+                reader.read_exact_at(offset, &mut this_block)?;
+                if this_block == block {
+                    matched = Some(offset);
+                    break;
+                }
+            }
+
+            match matched {
+                Some(offset) => {
+                    pairs.push((offset, position));
+                    position += block_size;
+                    hash = None;
+                }
+                None if window.start + (window.bytes.len() as u64) > position + block_size => {
+                    let entering = window.at(position + block_size);
+                    hash = Some(
+                        current
+                            .wrapping_sub((window.at(position) as u64).wrapping_mul(leaving))
+                            .wrapping_mul(ROLLING_BASE)
+                            .wrapping_add(entering as u64),
+                    );
+                    position += 1;
+                }
+                None => break,
+            }
+            window.pass(position);
+        }
+        Ok(pairs)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::tags::get_all_tags;
+    use crate::test_support::{app, open_temp, temp_file};
+
+    fn diff(a: &[u8], b: &[u8]) -> Vec<(u64, u64, DiffKind)> {
+        let read = |bytes: &[u8], offset: u64, buf: &mut [u8]| {
+            let offset = offset as usize;
+            let read = std::cmp::min(buf.len(), bytes.len() - offset);
+            buf[..read].copy_from_slice(&bytes[offset..offset + read]);
+            Ok(read)
+        };
+        diff_sources(
+            |offset, buf| read(a, offset, buf),
+            a.len() as u64,
+            |offset, buf| read(b, offset, buf),
+            b.len() as u64,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|range| (range.offset, range.length, range.kind))
+        .collect()
+    }
+
+    #[test]
+    fn coalesces_changed_runs() {
+        assert_eq!(
+            diff(b"abcdefghij", b"aXYdefZhiQ"),
+            vec![
+                (1, 2, DiffKind::Changed),
+                (6, 1, DiffKind::Changed),
+                (9, 1, DiffKind::Changed),
+            ]
+        );
+        assert!(diff(b"same", b"same").is_empty());
+    }
+
+    #[test]
+    fn reports_length_difference() {
+        assert_eq!(
+            diff(b"abcdef", b"abXd"),
+            vec![(2, 1, DiffKind::Changed), (4, 2, DiffKind::OnlyInA)]
+        );
+        assert_eq!(diff(b"ab", b"abcd"), vec![(2, 2, DiffKind::OnlyInB)]);
+    }
+
+    #[test]
+    fn run_spanning_chunks_stays_whole() {
+        let a = vec![0u8; DIFF_CHUNK_SIZE + 8];
+        let mut b = a.clone();
+        b[DIFF_CHUNK_SIZE - 4..DIFF_CHUNK_SIZE + 4].fill(1);
+        assert_eq!(
+            diff(&a, &b),
+            vec![(DIFF_CHUNK_SIZE as u64 - 4, 8, DiffKind::Changed)]
+        );
+    }
+
+    #[test]
+    fn compare_offset_reads_each_file_in_order() {
+        let app = app();
+        let handles: Vec<u64> = [&b"0123456789"[..], b"01234", b"0123XY"]
+            .into_iter()
+            .map(|bytes| open_bytes(bytes.to_vec(), app.state()).unwrap().handle)
+            .collect();
+        let order = vec![handles[2], handles[0], handles[1]];
+
+        let compared = compare_offset(order.clone(), 4, 3, app.state()).unwrap();
+        assert_eq!(
+            compared,
+            vec![
+                OffsetBytes {
+                    handle: order[0],
+                    bytes: Some(b"XY".to_vec())
+                },
+                OffsetBytes {
+                    handle: order[1],
+                    bytes: Some(b"456".to_vec())
+                },
+                OffsetBytes {
+                    handle: order[2],
+                    bytes: Some(b"4".to_vec())
+                },
+            ]
+        );
+        let past_end = compare_offset(handles.clone(), 5, 1, app.state()).unwrap();
+        assert_eq!(past_end[1].bytes, None);
+        assert_eq!(
+            compare_offset(vec![handles[0], 999], 0, 1, app.state()),
+            Err(AppError::FileNotOpen(999))
+        );
+    }
+
+    #[test]
+    fn diffs_against_a_baseline_file() {
+        let app = app();
+        let baseline = temp_file(b"firmware v1 checksum=0000");
+        let (_file, handle) = open_temp(&app, b"firmware v1 chEcksum=0042!!");
+        let baseline_path = baseline.path().to_string_lossy().into_owned();
+
+        let ranges = tauri::async_runtime::block_on(diff_against_baseline(
+            handle,
+            baseline_path,
+            Some(true),
+            app.state(),
+        ))
+        .unwrap();
+        let ranges: Vec<_> = ranges
+            .into_iter()
+            .map(|range| (range.offset, range.length, range.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (14, 1, DiffKind::Changed),
+                (23, 2, DiffKind::Changed),
+                (25, 2, DiffKind::OnlyInB),
+            ]
+        );
+
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        let spans: Vec<_> = tags
+            .iter()
+            .map(|tag| (tag.start, tag.end, tag.name.as_str()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (14, 15, "modified"),
+                (23, 25, "modified"),
+                (25, 27, "modified")
+            ]
+        );
+
+        assert!(matches!(
+            tauri::async_runtime::block_on(diff_against_baseline(
+                handle,
+                "/definitely/not/a/baseline".to_string(),
+                None,
+                app.state(),
+            )),
+            Err(AppError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn similar_blocks_finds_moved_and_duplicated_blocks() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut noise = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        };
+        let this = noise(4096);
+        let mut other = noise(100);
+        other.extend_from_slice(&this[1024..1536]);
+        other.extend(noise(37));
+        other.extend_from_slice(&this[..512]);
+        other.extend_from_slice(&this[..512]);
+        // Shorter than a block, so never matched.
+        other.extend_from_slice(&this[2048..2300]);
+
+        let app = app();
+        let (_file, handle) = open_temp(&app, &this);
+        let other_file = temp_file(&other);
+        let path = other_file.path().to_string_lossy().into_owned();
+        let pairs =
+            tauri::async_runtime::block_on(similar_blocks(handle, path.clone(), 512, app.state()))
+                .unwrap();
+        assert_eq!(pairs, [(1024, 100), (0, 649), (0, 1161)]);
+
+        assert!(matches!(
+            tauri::async_runtime::block_on(similar_blocks(handle, path, 4, app.state())),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
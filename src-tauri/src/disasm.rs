@@ -0,0 +1,140 @@
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::reader::to_usize;
+use crate::{lock_state, run_blocking, AppState};
+
+/// Longest range `disassemble` decodes at once.
+const MAX_DISASSEMBLY: u64 = 1024 * 1024;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Instruction {
+    pub address: u64,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub operands: String,
+}
+
+/// Decodes `code`, which starts at `address`, as machine code for `arch`.
+#[cfg(feature = "disasm")]
+fn decode(code: &[u8], address: u64, arch: &str) -> Result<Vec<Instruction>, AppError> {
+    use capstone::prelude::*;
+
+    let engine = match arch {
+        "x86" => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode32)
+            .build(),
+        "x86_64" => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode64)
+            .build(),
+        "arm" => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).build(),
+        "aarch64" => Capstone::new()
+            .arm64()
+            .mode(arch::arm64::ArchMode::Arm)
+            .build(),
+        other => {
+            return Err(AppError::InvalidArgument(format!(
+                "Unsupported architecture '{}', expected x86, x86_64, arm or aarch64",
+                other
+            )))
+        }
+    }
+    .map_err(|e| AppError::Internal(format!("Failed to start disassembler: {}", e)))?;
+
+    let instructions = engine
+        .disasm_all(code, address)
+        .map_err(|e| AppError::InvalidFormat(format!("Failed to disassemble: {}", e)))?;
+    Ok(instructions
+        .iter()
+        .map(|instruction| Instruction {
+            address: instruction.address(),
+            bytes: instruction.bytes().to_vec(),
+            mnemonic: instruction.mnemonic().unwrap_or_default().to_string(),
+            operands: instruction.op_str().unwrap_or_default().to_string(),
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "disasm"))]
+fn decode(_code: &[u8], _address: u64, _arch: &str) -> Result<Vec<Instruction>, AppError> {
+    Err(AppError::InvalidArgument(
+        "Disassembly needs the app to be built with the `disasm` feature".to_string(),
+    ))
+}
+
+/// Disassembles `[start, end)` as `x86`, `x86_64`, `arm` or `aarch64` code,
+/// using file offsets as addresses. Decoding stops at the first invalid
+/// instruction.
+#[tauri::command]
+pub async fn disassemble(
+    handle: u64,
+    start: u64,
+    end: u64,
+    arch: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<Instruction>, AppError> {
+    let reader = lock_state(&state).get(handle)?.reader();
+    let end = reader.validate_range(start, end)?;
+    if end - start > MAX_DISASSEMBLY {
+        return Err(AppError::InvalidArgument(format!(
+            "Cannot disassemble more than {} bytes at once",
+            MAX_DISASSEMBLY
+        )));
+    }
+
+    run_blocking(move || {
+        let mut code = vec![0u8; to_usize(end - start)?];
+        reader.read_exact_at(start, &mut code)?;
+        decode(&code, start, &arch)
+    })
+    .await
+}
+
+#[cfg(all(test, feature = "disasm"))]
+mod tests {
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::test_support::app;
+
+    #[test]
+    fn decodes_known_x86_64_instructions() {
+        let app = app();
+        // nop, then the disassembled push rbp; mov rbp, rsp; xor eax, eax; ret
+        let code = b"\x90\x55\x48\x89\xe5\x31\xc0\xc3".to_vec();
+        let handle = open_bytes(code, app.state()).unwrap().handle;
+
+        let instructions =
+            block_on(disassemble(handle, 1, 8, "x86_64".to_string(), app.state())).unwrap();
+        let decoded: Vec<_> = instructions
+            .iter()
+            .map(|instruction| {
+                (
+                    instruction.address,
+                    instruction.mnemonic.as_str(),
+                    instruction.operands.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            decoded,
+            [
+                (1, "push", "rbp"),
+                (2, "mov", "rbp, rsp"),
+                (5, "xor", "eax, eax"),
+                (7, "ret", ""),
+            ]
+        );
+        assert_eq!(instructions[1].bytes, [0x48, 0x89, 0xe5]);
+
+        let unknown = block_on(disassemble(handle, 0, 8, "mips".to_string(), app.state()));
+        assert!(matches!(unknown, Err(AppError::InvalidArgument(_))));
+        let past_end = block_on(disassemble(handle, 8, 9, "x86".to_string(), app.state()));
+        assert!(matches!(past_end, Err(AppError::OutOfBounds { .. })));
+    }
+}
@@ -0,0 +1,157 @@
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::{lock_state, run_blocking, AppState};
+
+/// Bytes examined from the start of the range; enough for a stable guess.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EncodingGuess {
+    /// One of `ascii`, `utf8`, `utf16le`, `utf16be` or `binary`.
+    encoding: &'static str,
+    /// How strongly the sample supports the guess, from 0.0 to 1.0.
+    confidence: f64,
+}
+
+fn is_text_byte(byte: u8) -> bool {
+    (0x20..=0x7E).contains(&byte) || matches!(byte, b'\t' | b'\n' | b'\r')
+}
+
+fn guess(encoding: &'static str, confidence: f64) -> EncodingGuess {
+    EncodingGuess {
+        encoding,
+        confidence,
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+/// Guesses the text encoding of `sample`.
+fn guess_encoding(sample: &[u8]) -> EncodingGuess {
+    if sample.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return guess("utf8", 1.0);
+    }
+    if sample.starts_with(&[0xFF, 0xFE]) {
+        return guess("utf16le", 1.0);
+    }
+    if sample.starts_with(&[0xFE, 0xFF]) {
+        return guess("utf16be", 1.0);
+    }
+
+    // Mostly-ASCII UTF-16 has a zero in every other byte.
+    let units = sample.len() / 2;
+    let even_zeros = sample
+        .iter()
+        .step_by(2)
+        .take(units)
+        .filter(|&&b| b == 0)
+        .count();
+    let odd_zeros = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+    let (even_ratio, odd_ratio) = (ratio(even_zeros, units), ratio(odd_zeros, units));
+    if odd_ratio > 0.5 && even_ratio < 0.1 {
+        return guess("utf16le", odd_ratio);
+    }
+    if even_ratio > 0.5 && odd_ratio < 0.1 {
+        return guess("utf16be", even_ratio);
+    }
+
+    let text_ratio = ratio(
+        sample.iter().filter(|&&byte| is_text_byte(byte)).count(),
+        sample.len(),
+    );
+    if text_ratio == 1.0 {
+        return guess("ascii", 1.0);
+    }
+
+    // A multi-byte sequence cut off by the end of the sample still counts.
+    let valid_utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    let non_ascii = sample.iter().filter(|&&byte| byte >= 0x80).count();
+    if valid_utf8 && non_ascii > 0 {
+        // Continuation bytes make up the rest of each multi-byte sequence.
+        let text_like = text_ratio + ratio(non_ascii, sample.len());
+        return guess("utf8", text_like.min(1.0));
+    }
+
+    guess("binary", 1.0 - text_ratio)
+}
+
+/// Guesses the encoding of the text in `[start, end)` from a byte order mark
+/// or, failing that, from the distribution of its bytes. Only the first 64 KiB
+/// of the range are examined.
+#[tauri::command]
+pub async fn detect_encoding(
+    handle: u64,
+    start: u64,
+    end: u64,
+    state: State<'_, AppState>,
+) -> Result<EncodingGuess, AppError> {
+    let reader = lock_state(&state).get(handle)?.reader();
+
+    run_blocking(move || {
+        let end = reader.validate_range(start, end)?;
+        let length = std::cmp::min(end - start, SAMPLE_SIZE as u64) as usize;
+        let mut sample = vec![0u8; length];
+        reader.read_exact_at(start, &mut sample)?;
+        Ok(guess_encoding(&sample))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoding_of(sample: &[u8]) -> &'static str {
+        guess_encoding(sample).encoding
+    }
+
+    #[test]
+    fn byte_order_marks_are_certain() {
+        for (sample, encoding) in [
+            (&b"\xEF\xBB\xBFhi"[..], "utf8"),
+            (b"\xFF\xFEh\x00", "utf16le"),
+            (b"\xFE\xFF\x00h", "utf16be"),
+        ] {
+            let found = guess_encoding(sample);
+            assert_eq!((found.encoding, found.confidence), (encoding, 1.0));
+        }
+    }
+
+    #[test]
+    fn guesses_from_byte_distribution() {
+        let utf16le: Vec<u8> = "plain text"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let utf16be: Vec<u8> = "plain text"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(encoding_of(&utf16le), "utf16le");
+        assert_eq!(encoding_of(&utf16be), "utf16be");
+        assert_eq!(encoding_of(b"plain text\r\n"), "ascii");
+        assert_eq!(encoding_of("naïve café".as_bytes()), "utf8");
+        // A sequence cut off by the end of the sample.
+        assert_eq!(encoding_of(&"café €".as_bytes()[..8]), "utf8");
+        assert_eq!(
+            encoding_of(&[0x00, 0x8F, 0xFF, 0x01, 0x02, 0x00, 0xC3]),
+            "binary"
+        );
+    }
+}
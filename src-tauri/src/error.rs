@@ -0,0 +1,201 @@
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+/// Error returned by every command.
+///
+/// Serializes as `{ "code": "...", "message": "..." }` plus any structured
+/// fields of the variant, so the frontend can branch on `code` and still show
+/// `message` as-is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    FileNotOpen(u64),
+    ReadOnly,
+    OutOfBounds {
+        requested: u64,
+        size: u64,
+    },
+    InvalidRange {
+        start: u64,
+        end: u64,
+    },
+    InvalidArgument(String),
+    InvalidFormat(String),
+    TagNotFound(u64),
+    BookmarkNotFound(u64),
+    TagOverlap {
+        id: u64,
+        name: String,
+    },
+    UnexpectedEof,
+    /// An offset or length too large to address in memory on this platform,
+    /// which only happens where `usize` is narrower than 64 bits.
+    OffsetTooLarge {
+        offset: u64,
+    },
+    /// The file was truncated by something else while open; its size has
+    /// been updated to `new_size`.
+    FileShrunk {
+        new_size: u64,
+    },
+    /// A read longer than the file's `max_read_bytes`.
+    ReadTooLarge {
+        requested: u64,
+        limit: u64,
+    },
+    /// Stopped early by `cancel_operation`.
+    Cancelled,
+    /// Stopped after running longer than its `timeout_ms`.
+    TimedOut {
+        timeout_ms: u64,
+    },
+    Io(String),
+    /// A background task failed before producing a result.
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::FileNotOpen(_) => "file_not_open",
+            Self::ReadOnly => "read_only",
+            Self::OutOfBounds { .. } => "out_of_bounds",
+            Self::InvalidRange { .. } => "invalid_range",
+            Self::InvalidArgument(_) => "invalid_argument",
+            Self::InvalidFormat(_) => "invalid_format",
+            Self::TagNotFound(_) => "tag_not_found",
+            Self::BookmarkNotFound(_) => "bookmark_not_found",
+            Self::TagOverlap { .. } => "tag_overlap",
+            Self::UnexpectedEof => "unexpected_eof",
+            Self::OffsetTooLarge { .. } => "offset_too_large",
+            Self::FileShrunk { .. } => "file_shrunk",
+            Self::ReadTooLarge { .. } => "read_too_large",
+            Self::Cancelled => "cancelled",
+            Self::TimedOut { .. } => "timed_out",
+            Self::Io(_) => "io",
+            Self::Internal(_) => "internal",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileNotOpen(handle) => write!(f, "No file is open with handle {}", handle),
+            Self::ReadOnly => write!(f, "File was opened read-only"),
+            Self::OutOfBounds { requested, size } => {
+                write!(f, "Position {} exceeds file size {}", requested, size)
+            }
+            Self::InvalidRange { start, end } => write!(
+                f,
+                "Invalid range {}..{}: end must be greater than start",
+                start, end
+            ),
+            Self::InvalidArgument(message) | Self::InvalidFormat(message) => {
+                write!(f, "{}", message)
+            }
+            Self::TagNotFound(id) => write!(f, "No tag with id {}", id),
+            Self::BookmarkNotFound(id) => write!(f, "No bookmark with id {}", id),
+            Self::TagOverlap { id, name } => {
+                write!(f, "Tag overlaps existing tag '{}' (id {})", name, id)
+            }
+            Self::UnexpectedEof => write!(f, "Unexpected EOF when reading file data"),
+            Self::OffsetTooLarge { offset } => write!(
+                f,
+                "Offset {} is too large to address in memory on this platform",
+                offset
+            ),
+            Self::FileShrunk { new_size } => {
+                write!(f, "File shrank to {} bytes while it was open", new_size)
+            }
+            Self::ReadTooLarge { requested, limit } => write!(
+                f,
+                "Read of {} bytes exceeds the limit of {} bytes",
+                requested, limit
+            ),
+            Self::Cancelled => write!(f, "Operation was cancelled"),
+            Self::TimedOut { timeout_ms } => {
+                write!(f, "Operation timed out after {} ms", timeout_ms)
+            }
+            Self::Io(message) | Self::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.to_string())
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::FileNotOpen(handle) => map.serialize_entry("handle", handle)?,
+            Self::OutOfBounds { requested, size } => {
+                map.serialize_entry("requested", requested)?;
+                map.serialize_entry("size", size)?;
+            }
+            Self::InvalidRange { start, end } => {
+                map.serialize_entry("start", start)?;
+                map.serialize_entry("end", end)?;
+            }
+            Self::TagNotFound(id) | Self::BookmarkNotFound(id) => map.serialize_entry("id", id)?,
+            Self::TagOverlap { id, name } => {
+                map.serialize_entry("id", id)?;
+                map.serialize_entry("name", name)?;
+            }
+            Self::ReadTooLarge { requested, limit } => {
+                map.serialize_entry("requested", requested)?;
+                map.serialize_entry("limit", limit)?;
+            }
+            Self::OffsetTooLarge { offset } => map.serialize_entry("offset", offset)?,
+            Self::FileShrunk { new_size } => map.serialize_entry("new_size", new_size)?,
+            Self::TimedOut { timeout_ms } => map.serialize_entry("timeout_ms", timeout_ms)?,
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::app;
+    use crate::{get_file_data, open_bytes};
+
+    #[test]
+    fn out_of_bounds_read_serializes_with_fields() {
+        let app = app();
+        let handle = open_bytes(vec![0; 16], app.state()).unwrap().handle;
+        let error = block_on(get_file_data(handle, 20, 24, None, app.state())).unwrap_err();
+
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "code": "out_of_bounds",
+                "message": "Position 20 exceeds file size 16",
+                "requested": 20,
+                "size": 16,
+            })
+        );
+    }
+
+    #[test]
+    fn unit_variants_serialize_code_and_message() {
+        assert_eq!(
+            serde_json::to_value(AppError::ReadOnly).unwrap(),
+            json!({ "code": "read_only", "message": "File was opened read-only" })
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::cancel::Operation;
+use crate::error::AppError;
+use crate::reader::{Backing, FileReader};
+use crate::{lock_state, run_blocking, AppState, FileState};
+
+const EXPORT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Paths of the files holding the open file's bytes.
+pub(crate) fn source_paths(file_state: &FileState) -> Vec<PathBuf> {
+    match &file_state.backing {
+        Backing::File(_) | Backing::Gzip(_) => file_state.file_path.iter().cloned().collect(),
+        Backing::Split(segments) => segments
+            .iter()
+            .map(|segment| segment.path.clone())
+            .collect(),
+        Backing::Memory(_) => Vec::new(),
+    }
+}
+
+/// Whether `out_path` is one of the files behind `reader`, under any name.
+#[cfg(unix)]
+pub(crate) fn is_source_file(
+    reader: &FileReader,
+    _source_paths: &[PathBuf],
+    out_path: &Path,
+) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(out) = fs::metadata(out_path) else {
+        return false;
+    };
+    let files = match &reader.backing {
+        Backing::File(file) => vec![file],
+        Backing::Gzip(index) => vec![index.file()],
+        Backing::Split(segments) => segments.iter().map(|segment| &segment.file).collect(),
+        Backing::Memory(_) => Vec::new(),
+    };
+    files.iter().any(|file| {
+        file.metadata()
+            .is_ok_and(|source| source.dev() == out.dev() && source.ino() == out.ino())
+    })
+}
+
+/// Whether `out_path` is one of the files behind `reader`, under any name.
+#[cfg(not(unix))]
+pub(crate) fn is_source_file(
+    _reader: &FileReader,
+    source_paths: &[PathBuf],
+    out_path: &Path,
+) -> bool {
+    let Ok(out) = fs::canonicalize(out_path) else {
+        return false;
+    };
+    source_paths
+        .iter()
+        .any(|path| fs::canonicalize(path).is_ok_and(|path| path == out))
+}
+
+/// Copies `[start, end)` (default: the selection) into `out_path` and returns
+/// the number of bytes written. An existing file is only replaced when
+/// `overwrite` is set. Can be stopped through `operation_id`, and gives up
+/// with `timed_out` after `timeout_ms`; either leaves a partial export behind.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn export_range(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    anchor: Option<String>,
+    out_path: String,
+    overwrite: Option<bool>,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<u64, AppError> {
+    let (reader, (start, end), source_paths) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (
+            file_state.reader(),
+            file_state.range_or_selection(start, end, anchor.as_deref())?,
+            source_paths(file_state),
+        )
+    };
+
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    run_blocking(move || {
+        let end = reader.validate_range(start, end)?;
+        // Opening the source for writing would truncate it before it is read.
+        if is_source_file(&reader, &source_paths, Path::new(&out_path)) {
+            return Err(AppError::InvalidArgument(
+                "Cannot export a range into the file it is read from".to_string(),
+            ));
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if overwrite.unwrap_or(false) {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        let mut out = options
+            .open(&out_path)
+            .map_err(|e| AppError::Io(format!("Failed to create export file: {}", e)))?;
+
+        let mut written = 0u64;
+        reader.for_each_chunk(start, end, EXPORT_CHUNK_SIZE, |_, chunk| {
+            token.check()?;
+            out.write_all(chunk)
+                .map_err(|e| AppError::Io(format!("Failed to write export file: {}", e)))?;
+            written += chunk.len() as u64;
+            Ok(())
+        })?;
+        out.flush()
+            .map_err(|e| AppError::Io(format!("Failed to flush export file: {}", e)))?;
+        Ok(written)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn exports_middle_slice() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"0123456789abcdef");
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("slice.bin");
+        let out_path = out.to_string_lossy().into_owned();
+
+        let written = block_on(export_range(
+            handle,
+            Some(4),
+            Some(10),
+            None,
+            out_path.clone(),
+            None,
+            None,
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(written, 6);
+        assert_eq!(fs::read(&out).unwrap(), b"456789");
+
+        // Existing files are kept unless `overwrite` is set.
+        let again = |overwrite| {
+            block_on(export_range(
+                handle,
+                Some(0),
+                Some(2),
+                None,
+                out_path.clone(),
+                overwrite,
+                None,
+                None,
+                app.state(),
+            ))
+        };
+        assert!(matches!(again(None), Err(AppError::Io(_))));
+        assert_eq!(again(Some(true)).unwrap(), 2);
+        assert_eq!(fs::read(&out).unwrap(), b"01");
+    }
+
+    #[test]
+    fn refuses_to_overwrite_the_source() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"precious bytes");
+        let dir = tempfile::tempdir().unwrap();
+        #[allow(unused_mut)]
+        let mut targets = vec![file.path().to_path_buf()];
+        // Other names of the same file are only detected on Unix.
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.bin");
+            fs::hard_link(file.path(), &link).unwrap();
+            targets.push(link);
+        }
+
+        for out in targets {
+            let result = block_on(export_range(
+                handle,
+                Some(0),
+                Some(4),
+                None,
+                out.to_string_lossy().into_owned(),
+                Some(true),
+                None,
+                None,
+                app.state(),
+            ));
+            assert!(matches!(result, Err(AppError::InvalidArgument(_))));
+        }
+        assert_eq!(fs::read(file.path()).unwrap(), b"precious bytes");
+    }
+}
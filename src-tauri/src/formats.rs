@@ -0,0 +1,397 @@
+//! Parsers that know a whole file format and tag its structure.
+//!
+//! Parsers live in a `ParserRegistry` managed by the app. `scan_magic` runs
+//! every parser whose `detect` accepts the start of the file, and templates
+//! hand a region to one by name with `format <name>;`. The PNG and ELF
+//! parsers below go through the same trait as any added with `register`.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::error::AppError;
+use crate::reader::FileReader;
+use crate::tags::Tag;
+
+/// Bytes of the start of a region passed to `FormatParser::detect`.
+pub(crate) const HEADER_LEN: usize = 64;
+
+/// Anything a parser can read from and seek in.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// A file format that can tag its own structure.
+pub trait FormatParser: Send + Sync {
+    /// Short identifier, used by templates and as the prefix of tag names.
+    fn name(&self) -> &str;
+
+    /// Whether `header`, the first bytes of the region (at most `HEADER_LEN`),
+    /// looks like this format.
+    fn detect(&self, header: &[u8]) -> bool;
+
+    /// Tags the structure of the `size`-byte region behind `file`, with
+    /// offsets relative to its start. Parsing stops quietly at the first
+    /// malformed or truncated part, keeping the tags found so far.
+    fn parse(&self, file: &mut dyn ReadSeek, size: u64) -> Vec<Tag>;
+}
+
+/// The format parsers known to the app.
+#[derive(Default)]
+pub struct ParserRegistry {
+    parsers: RwLock<Vec<Arc<dyn FormatParser>>>,
+}
+
+impl ParserRegistry {
+    /// A registry holding the built-in PNG and ELF parsers.
+    pub fn with_builtins() -> Self {
+        let registry = Self::default();
+        registry.register(PngParser);
+        registry.register(ElfParser);
+        registry
+    }
+
+    /// Adds `parser`, replacing any registered under the same name.
+    pub fn register(&self, parser: impl FormatParser + 'static) {
+        let mut parsers = self.parsers.write().unwrap_or_else(PoisonError::into_inner);
+        parsers.retain(|existing| existing.name() != parser.name());
+        parsers.push(Arc::new(parser));
+    }
+
+    pub(crate) fn parsers(&self) -> Vec<Arc<dyn FormatParser>> {
+        self.parsers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn find(&self, name: &str) -> Option<Arc<dyn FormatParser>> {
+        self.parsers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|parser| parser.name() == name)
+            .cloned()
+    }
+}
+
+/// Reads and seeks within `[base, base + size)` of a file, with positions
+/// relative to `base`.
+struct RegionCursor<'a> {
+    reader: &'a FileReader,
+    base: u64,
+    size: u64,
+    position: u64,
+}
+
+impl Read for RegionCursor<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.size.saturating_sub(self.position);
+        let len = std::cmp::min(buf.len() as u64, available) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let read = self
+            .reader
+            .read_at(self.base + self.position, &mut buf[..len])
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for RegionCursor<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek out of range"))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// The first bytes of the file from `start`, as passed to `detect`.
+pub(crate) fn region_header(reader: &FileReader, start: u64) -> Result<Vec<u8>, AppError> {
+    let available = reader.file_size.saturating_sub(start);
+    let mut header = vec![0u8; std::cmp::min(available, HEADER_LEN as u64) as usize];
+    reader.read_exact_at(start, &mut header)?;
+    Ok(header)
+}
+
+/// Runs `parser` over the file from `start` to its end and returns its tags
+/// with file offsets. Empty tags and tags reaching past the end are dropped.
+pub(crate) fn run_parser(parser: &dyn FormatParser, reader: &FileReader, start: u64) -> Vec<Tag> {
+    let size = reader.file_size.saturating_sub(start);
+    let mut cursor = RegionCursor {
+        reader,
+        base: start,
+        size,
+        position: 0,
+    };
+    parser
+        .parse(&mut cursor, size)
+        .into_iter()
+        .filter(|tag| tag.start < tag.end && tag.end <= size)
+        .map(|mut tag| {
+            tag.start += start;
+            tag.end += start;
+            tag
+        })
+        .collect()
+}
+
+fn read_at(file: &mut dyn ReadSeek, offset: u64, buf: &mut [u8]) -> Option<()> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(buf).ok()
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Tags the signature and each chunk of a PNG image.
+struct PngParser;
+
+impl FormatParser for PngParser {
+    fn name(&self) -> &str {
+        "png"
+    }
+
+    fn detect(&self, header: &[u8]) -> bool {
+        header.starts_with(PNG_SIGNATURE)
+    }
+
+    fn parse(&self, file: &mut dyn ReadSeek, size: u64) -> Vec<Tag> {
+        let mut tags = vec![Tag::new(
+            0,
+            PNG_SIGNATURE.len() as u64,
+            "png.signature".to_string(),
+            "PNG signature",
+        )];
+        let mut offset = PNG_SIGNATURE.len() as u64;
+        let mut chunk = [0u8; 8];
+        while read_at(file, offset, &mut chunk).is_some() {
+            let length = u32::from_be_bytes(chunk[..4].try_into().unwrap()) as u64;
+            let kind = &chunk[4..];
+            // Length, type and CRC around the data.
+            let end = offset + 12 + length;
+            if !kind.iter().all(u8::is_ascii_alphabetic) || end > size {
+                break;
+            }
+            let kind = String::from_utf8_lossy(kind).into_owned();
+            tags.push(Tag::new(offset, end, format!("png.{}", kind), &kind));
+            offset = end;
+            if kind == "IEND" {
+                break;
+            }
+        }
+        tags
+    }
+}
+
+/// Tags the identification, header and program and section header tables of
+/// an ELF file.
+struct ElfParser;
+
+impl FormatParser for ElfParser {
+    fn name(&self) -> &str {
+        "elf"
+    }
+
+    fn detect(&self, header: &[u8]) -> bool {
+        header.starts_with(b"\x7fELF")
+    }
+
+    fn parse(&self, file: &mut dyn ReadSeek, size: u64) -> Vec<Tag> {
+        let mut tags = Vec::new();
+        let mut header = [0u8; 64];
+        if read_at(file, 0, &mut header[..16]).is_none() {
+            return tags;
+        }
+        tags.push(Tag::new(
+            0,
+            16,
+            "elf.ident".to_string(),
+            "ELF identification",
+        ));
+
+        let wide = match header[4] {
+            1 => false,
+            2 => true,
+            _ => return tags,
+        };
+        let big = match header[5] {
+            1 => false,
+            2 => true,
+            _ => return tags,
+        };
+        let header_len = if wide { 64 } else { 52 };
+        if read_at(file, 16, &mut header[16..header_len]).is_none() {
+            return tags;
+        }
+        let field = |offset: usize, width: usize| {
+            let bytes = &header[offset..offset + width];
+            let fold = |value: u64, byte: &u8| value << 8 | *byte as u64;
+            if big {
+                bytes.iter().fold(0, fold)
+            } else {
+                bytes.iter().rev().fold(0, fold)
+            }
+        };
+        // Offsets of e_phoff, e_shoff and e_ehsize, then the table sizes.
+        let (phoff, shoff, rest) = if wide {
+            (field(0x20, 8), field(0x28, 8), 0x34)
+        } else {
+            (field(0x1c, 4), field(0x20, 4), 0x28)
+        };
+        let ehsize = field(rest, 2);
+        let (phentsize, phnum) = (field(rest + 2, 2), field(rest + 4, 2));
+        let (shentsize, shnum) = (field(rest + 6, 2), field(rest + 8, 2));
+
+        if (16..=size).contains(&ehsize) {
+            tags.push(Tag::new(16, ehsize, "elf.header".to_string(), "ELF header"));
+        }
+        for (name, display_name, offset, count, entry_size) in [
+            (
+                "elf.program_headers",
+                "Program headers",
+                phoff,
+                phnum,
+                phentsize,
+            ),
+            (
+                "elf.section_headers",
+                "Section headers",
+                shoff,
+                shnum,
+                shentsize,
+            ),
+        ] {
+            if offset == 0 || count == 0 {
+                continue;
+            }
+            let end = count
+                .checked_mul(entry_size)
+                .and_then(|len| offset.checked_add(len));
+            if let Some(end) = end.filter(|&end| end <= size) {
+                tags.push(Tag::new(offset, end, name.to_string(), display_name));
+            }
+        }
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::magic::scan_magic;
+    use crate::open_bytes;
+    use crate::template::apply_template;
+    use crate::test_support::{app, reader_over};
+
+    /// Tags the four bytes after a `TOY!` signature.
+    struct ToyParser;
+
+    impl FormatParser for ToyParser {
+        fn name(&self) -> &str {
+            "toy"
+        }
+
+        fn detect(&self, header: &[u8]) -> bool {
+            header.starts_with(b"TOY!")
+        }
+
+        fn parse(&self, file: &mut dyn ReadSeek, size: u64) -> Vec<Tag> {
+            let mut body = [0u8; 4];
+            if size < 8 || read_at(file, 4, &mut body).is_none() {
+                return Vec::new();
+            }
+            vec![Tag::new(4, 8, "toy.body".to_string(), "Toy body")]
+        }
+    }
+
+    fn spans(tags: &[Tag]) -> Vec<(u64, u64, &str)> {
+        tags.iter()
+            .map(|tag| (tag.start, tag.end, tag.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn registered_parsers_contribute_tags() {
+        let app = app();
+        app.state::<ParserRegistry>().register(ToyParser);
+        let handle = open_bytes(b"TOY!bodyTOY!body".to_vec(), app.state())
+            .unwrap()
+            .handle;
+
+        let scanned = tauri::async_runtime::block_on(scan_magic(
+            handle,
+            None,
+            None,
+            app.state(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(spans(&scanned), [(4, 8, "toy.body")]);
+
+        let templated = apply_template(
+            handle,
+            "u8 skip[8]; format toy;".to_string(),
+            0,
+            None,
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(spans(&templated), [(0, 8, "u8[8]"), (12, 16, "toy.body")]);
+    }
+
+    #[test]
+    fn png_parser_tags_each_chunk() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&[0; 13 + 4]);
+        png.extend_from_slice(&0u32.to_be_bytes());
+        png.extend_from_slice(b"IEND");
+        png.extend_from_slice(&[0; 4]);
+        png.extend_from_slice(b"trailing");
+
+        let tags = run_parser(&PngParser, &reader_over(&png), 0);
+        assert_eq!(
+            spans(&tags),
+            [
+                (0, 8, "png.signature"),
+                (8, 33, "png.IHDR"),
+                (33, 45, "png.IEND")
+            ]
+        );
+    }
+
+    #[test]
+    fn elf_parser_tags_header_tables() {
+        let mut elf = vec![0u8; 64 + 56];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        elf[0x34..0x36].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        elf[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes());
+        // A section header table past the end of the file is left out.
+        elf[0x28..0x30].copy_from_slice(&4096u64.to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&3u16.to_le_bytes());
+
+        let tags = run_parser(&ElfParser, &reader_over(&elf), 0);
+        assert_eq!(
+            spans(&tags),
+            [
+                (0, 16, "elf.ident"),
+                (16, 64, "elf.header"),
+                (64, 120, "elf.program_headers"),
+            ]
+        );
+    }
+}
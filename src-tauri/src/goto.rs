@@ -0,0 +1,465 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::inspect::Endian;
+use crate::{lock_state, AppState};
+
+fn invalid(expr: &str) -> AppError {
+    AppError::InvalidArgument(format!("Invalid offset expression '{}'", expr))
+}
+
+/// Parses `0x1F`, `4096` or either with a `K`/`M` (binary) suffix.
+fn parse_number(text: &str) -> Option<u64> {
+    let (digits, multiplier) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1024),
+        'm' | 'M' => (&text[..text.len() - 1], 1024 * 1024),
+        _ => (text, 1),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    value.checked_mul(multiplier)
+}
+
+/// Evaluates `expr` to an absolute offset. Supported forms are absolute
+/// (`0x1000`, `4096`, `4K`), relative to `cursor` (`+0x10`, `-32`) and
+/// relative to the end of the file (`end`, `end-16`).
+fn evaluate(expr: &str, cursor: u64, file_size: u64) -> Result<u64, AppError> {
+    let text = expr.trim();
+
+    let (base, rest) = match text.get(..3) {
+        Some(keyword) if keyword.eq_ignore_ascii_case("end") => (file_size, text[3..].trim_start()),
+        _ => match text.chars().next() {
+            Some('+') | Some('-') => (cursor, text),
+            _ => return parse_number(text).ok_or_else(|| invalid(expr)),
+        },
+    };
+    if rest.is_empty() {
+        return Ok(base);
+    }
+
+    // `rest` is not empty, and may start with any character (e.g. an
+    // autocorrected dash), so split on a char rather than a byte.
+    let sign = rest.chars().next().unwrap();
+    let amount = rest[sign.len_utf8()..].trim_start();
+    let amount = parse_number(amount).ok_or_else(|| invalid(expr))?;
+    match sign {
+        '+' => base.checked_add(amount).ok_or_else(|| invalid(expr)),
+        '-' => base.checked_sub(amount).ok_or_else(|| {
+            AppError::InvalidArgument(format!("Offset '{}' is before the start of the file", expr))
+        }),
+        _ => Err(invalid(expr)),
+    }
+}
+
+/// Resolves a goto expression to an offset within the file. Offsets past the
+/// end of the file are rejected rather than clamped.
+#[tauri::command]
+pub fn resolve_offset(
+    handle: u64,
+    expr: String,
+    cursor: u64,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    let file_size = lock_state(&state).get(handle)?.file_size;
+
+    let offset = evaluate(&expr, cursor, file_size)?;
+    if offset > file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: file_size,
+        });
+    }
+    Ok(offset)
+}
+
+/// Returns the offset `delta` bytes into tag `tag_id`, so fields can be
+/// addressed relative to a tag that edits may move. The result may be the
+/// tag's end but not lie past it.
+#[tauri::command]
+pub fn offset_in_tag(
+    handle: u64,
+    tag_id: u64,
+    delta: u64,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let tag = app_state
+        .get(handle)?
+        .tags
+        .iter()
+        .find(|tag| tag.id == tag_id)
+        .ok_or(AppError::TagNotFound(tag_id))?;
+    let length = tag.end - tag.start;
+    if delta > length {
+        return Err(AppError::InvalidArgument(format!(
+            "Offset {} is past the end of tag {}, which is {} bytes long",
+            delta, tag_id, length
+        )));
+    }
+    Ok(tag.start + delta)
+}
+
+/// Rounds `offset` to a multiple of `boundary`, `down`, `up` or to the
+/// `nearest` one (halfway rounds up), clamped to `[0, file_size]`.
+fn align(offset: u64, boundary: u64, direction: &str, file_size: u64) -> Result<u64, AppError> {
+    if boundary == 0 {
+        return Err(AppError::InvalidArgument(
+            "Boundary must not be zero".to_string(),
+        ));
+    }
+    let down = offset - offset % boundary;
+    // Past u64::MAX the next boundary is clamped like any other.
+    let up = || {
+        if down == offset {
+            down
+        } else {
+            down.saturating_add(boundary)
+        }
+    };
+    let aligned = match direction {
+        "down" => down,
+        "up" => up(),
+        "nearest" if offset - down < boundary - (offset - down) => down,
+        "nearest" => up(),
+        other => {
+            return Err(AppError::InvalidArgument(format!(
+                "Unsupported direction '{}', expected 'down', 'up' or 'nearest'",
+                other
+            )))
+        }
+    };
+    Ok(std::cmp::min(aligned, file_size))
+}
+
+/// Snaps `offset` to a `boundary`-aligned offset, rounding `down`, `up` or to
+/// the `nearest` one, for paging through the file. The result never lies past
+/// the end of the file.
+#[tauri::command]
+pub fn align_offset(
+    handle: u64,
+    offset: u64,
+    boundary: u64,
+    direction: String,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    let file_size = lock_state(&state).get(handle)?.file_size;
+    align(offset, boundary, &direction, file_size)
+}
+
+/// Reads the `pointer_size`-byte unsigned pointer in `bytes`.
+fn decode_pointer(bytes: &[u8], endian: Endian) -> u64 {
+    let mut value = [0u8; 8];
+    match endian {
+        Endian::Little => value[..bytes.len()].copy_from_slice(bytes),
+        Endian::Big => value[8 - bytes.len()..].copy_from_slice(bytes),
+    }
+    match endian {
+        Endian::Little => u64::from_le_bytes(value),
+        Endian::Big => u64::from_be_bytes(value),
+    }
+}
+
+/// Reads the 1, 2, 4 or 8-byte unsigned pointer at `offset` and returns the
+/// file offset it points to, taking `base` (the address the file is loaded
+/// at) off first. Pointers that land outside the file are rejected.
+#[tauri::command]
+pub fn follow_pointer(
+    handle: u64,
+    offset: u64,
+    pointer_size: u64,
+    endian: String,
+    base: u64,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    if ![1, 2, 4, 8].contains(&pointer_size) {
+        return Err(AppError::InvalidArgument(format!(
+            "Unsupported pointer size {}, expected 1, 2, 4 or 8",
+            pointer_size
+        )));
+    }
+    let endian = Endian::parse(&endian)?;
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let file_size = file_state.file_size;
+    if offset
+        .checked_add(pointer_size)
+        .map_or(true, |end| end > file_size)
+    {
+        return Err(AppError::OutOfBounds {
+            requested: offset.saturating_add(pointer_size),
+            size: file_size,
+        });
+    }
+    let mut bytes = vec![0u8; pointer_size as usize];
+    file_state.read_exact_at(offset, &mut bytes)?;
+
+    let pointer = decode_pointer(&bytes, endian);
+    let target = pointer.checked_sub(base).ok_or_else(|| {
+        AppError::InvalidArgument(format!(
+            "Pointer {:#x} is below the base address {:#x}",
+            pointer, base
+        ))
+    })?;
+    if target >= file_size {
+        return Err(AppError::OutOfBounds {
+            requested: target,
+            size: file_size,
+        });
+    }
+    Ok(target)
+}
+
+/// Most locations kept for `nav_back`; the oldest are forgotten first.
+const MAX_NAV_HISTORY: usize = 1000;
+
+/// Records a jump to `offset`, as from a goto, tag link or followed pointer,
+/// so `nav_back` can return to where the view was. Anything `nav_forward`
+/// could still reach is forgotten, as in a browser. Returns `offset`.
+#[tauri::command]
+pub fn push_location(handle: u64, offset: u64, state: State<AppState>) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if offset > file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: file_state.file_size,
+        });
+    }
+
+    let history = &mut file_state.nav_history;
+    history.truncate(file_state.nav_index + 1);
+    if history.last() != Some(&offset) {
+        history.push(offset);
+    }
+    if history.len() > MAX_NAV_HISTORY {
+        history.drain(..history.len() - MAX_NAV_HISTORY);
+    }
+    file_state.nav_index = history.len() - 1;
+    Ok(offset)
+}
+
+/// Steps one place back or forward through the file's history and returns
+/// the location there, clamped to the file, or `None` at either end.
+fn navigate(handle: u64, back: bool, state: State<AppState>) -> Result<Option<u64>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    let index = if back {
+        file_state.nav_index.checked_sub(1)
+    } else {
+        Some(file_state.nav_index + 1)
+    };
+    match index.and_then(|index| Some((index, *file_state.nav_history.get(index)?))) {
+        Some((index, offset)) => {
+            file_state.nav_index = index;
+            Ok(Some(std::cmp::min(offset, file_state.file_size)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Goes back to the previous location pushed with `push_location`. Returns
+/// `None`, staying put, when there is nothing further back.
+#[tauri::command]
+pub fn nav_back(handle: u64, state: State<AppState>) -> Result<Option<u64>, AppError> {
+    navigate(handle, true, state)
+}
+
+/// Returns to the location `nav_back` last left, or `None` when there is
+/// nothing to go forward to.
+#[tauri::command]
+pub fn nav_forward(handle: u64, state: State<AppState>) -> Result<Option<u64>, AppError> {
+    navigate(handle, false, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::tags::{add_tag, remove_tag, Tag};
+    use crate::test_support::app;
+
+    #[test]
+    fn evaluates_absolute_offsets() {
+        assert_eq!(evaluate("0x1F", 0, 100).unwrap(), 31);
+        assert_eq!(evaluate("0X10", 0, 100).unwrap(), 16);
+        assert_eq!(evaluate(" 4096 ", 0, 100).unwrap(), 4096);
+        assert_eq!(evaluate("4K", 0, 100).unwrap(), 4096);
+        assert_eq!(evaluate("2m", 0, 100).unwrap(), 2 * 1024 * 1024);
+    }
+
+    #[test]
+    fn evaluates_relative_offsets() {
+        assert_eq!(evaluate("+0x10", 100, 1000).unwrap(), 116);
+        assert_eq!(evaluate("-32", 100, 1000).unwrap(), 68);
+        assert!(matches!(
+            evaluate("-200", 100, 1000),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn evaluates_end_keyword() {
+        assert_eq!(evaluate("end", 0, 1000).unwrap(), 1000);
+        assert_eq!(evaluate("END - 16", 0, 1000).unwrap(), 984);
+        assert_eq!(evaluate("end+0x10", 0, 1000).unwrap(), 1016);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expr in [
+            "",
+            "0xZZ",
+            "end*2",
+            "end\u{2013}16",
+            "\u{2013}16",
+            "12Q",
+            "+",
+        ] {
+            assert!(
+                matches!(evaluate(expr, 0, 1000), Err(AppError::InvalidArgument(_))),
+                "{:?} should be rejected",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn aligns_in_each_direction() {
+        assert_eq!(align(5000, 4096, "down", 1 << 20).unwrap(), 4096);
+        assert_eq!(align(5000, 4096, "up", 1 << 20).unwrap(), 8192);
+        assert_eq!(align(5000, 4096, "nearest", 1 << 20).unwrap(), 4096);
+        assert_eq!(align(7000, 4096, "nearest", 1 << 20).unwrap(), 8192);
+        assert_eq!(align(6144, 4096, "nearest", 1 << 20).unwrap(), 8192);
+        for direction in ["down", "up", "nearest"] {
+            assert_eq!(align(8192, 4096, direction, 1 << 20).unwrap(), 8192);
+        }
+    }
+
+    #[test]
+    fn alignment_is_clamped_to_the_file_end() {
+        assert_eq!(align(9000, 4096, "up", 10_000).unwrap(), 10_000);
+        assert_eq!(align(11_000, 4096, "nearest", 10_000).unwrap(), 10_000);
+        assert_eq!(align(20_000, 4096, "down", 10_000).unwrap(), 10_000);
+        assert_eq!(align(9000, 4096, "down", 10_000).unwrap(), 8192);
+        assert_eq!(align(u64::MAX, 4096, "up", u64::MAX).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn rejects_zero_boundaries_and_unknown_directions() {
+        assert!(matches!(
+            align(10, 0, "down", 100),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            align(10, 16, "sideways", 100),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn follows_little_endian_pointers_past_a_base() {
+        let app = app();
+        let mut bytes = vec![0u8; 64];
+        bytes[0..4].copy_from_slice(&0x0040_0020u32.to_le_bytes());
+        bytes[4..8].copy_from_slice(&0x0040_0040u32.to_le_bytes());
+        bytes[8..12].copy_from_slice(&0x10u32.to_le_bytes());
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let follow = |offset, base| {
+            follow_pointer(handle, offset, 4, "little".to_string(), base, app.state())
+        };
+
+        assert_eq!(follow(0, 0x40_0000).unwrap(), 0x20);
+        assert_eq!(follow(8, 0).unwrap(), 0x10);
+        assert!(matches!(
+            follow(4, 0x40_0000),
+            Err(AppError::OutOfBounds {
+                requested: 0x40,
+                size: 64
+            })
+        ));
+        assert!(matches!(
+            follow(8, 0x40_0000),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert!(matches!(follow(62, 0), Err(AppError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn pointers_come_in_each_size_and_endianness() {
+        assert_eq!(decode_pointer(&[0x12], Endian::Big), 0x12);
+        assert_eq!(decode_pointer(&[0x12, 0x34], Endian::Big), 0x1234);
+        assert_eq!(decode_pointer(&[0x12, 0x34], Endian::Little), 0x3412);
+        assert_eq!(
+            decode_pointer(&[1, 0, 0, 0, 0, 0, 0, 0x80], Endian::Little),
+            0x8000_0000_0000_0001
+        );
+        let app = app();
+        let handle = open_bytes(vec![0; 16], app.state()).unwrap().handle;
+        assert!(matches!(
+            follow_pointer(handle, 0, 3, "little".to_string(), 0, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn navigates_back_and_forward_like_a_browser() {
+        let app = app();
+        let handle = open_bytes(vec![0; 1000], app.state()).unwrap().handle;
+        assert_eq!(nav_back(handle, app.state()).unwrap(), None);
+        for offset in [0, 100, 200, 300] {
+            push_location(handle, offset, app.state()).unwrap();
+        }
+        // Pushing where the view already is adds nothing.
+        push_location(handle, 300, app.state()).unwrap();
+
+        assert_eq!(nav_back(handle, app.state()).unwrap(), Some(200));
+        assert_eq!(nav_back(handle, app.state()).unwrap(), Some(100));
+        assert_eq!(nav_forward(handle, app.state()).unwrap(), Some(200));
+        assert_eq!(nav_back(handle, app.state()).unwrap(), Some(100));
+
+        // A new jump after going back drops 200 and 300.
+        push_location(handle, 500, app.state()).unwrap();
+        assert_eq!(nav_forward(handle, app.state()).unwrap(), None);
+        assert_eq!(nav_back(handle, app.state()).unwrap(), Some(100));
+        assert_eq!(nav_back(handle, app.state()).unwrap(), Some(0));
+        assert_eq!(nav_back(handle, app.state()).unwrap(), None);
+        assert_eq!(nav_forward(handle, app.state()).unwrap(), Some(100));
+        assert_eq!(nav_forward(handle, app.state()).unwrap(), Some(500));
+        assert_eq!(nav_forward(handle, app.state()).unwrap(), None);
+
+        assert!(matches!(
+            push_location(handle, 1001, app.state()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn offsets_relative_to_a_tag() {
+        let app = app();
+        let handle = open_bytes(vec![0; 64], app.state()).unwrap().handle;
+        let tag = Tag::new(16, 24, "header".to_string(), "Header");
+        let id = add_tag(handle, tag, None, None, None, app.state()).unwrap();
+
+        assert_eq!(offset_in_tag(handle, id, 0, app.state()).unwrap(), 16);
+        assert_eq!(offset_in_tag(handle, id, 4, app.state()).unwrap(), 20);
+        assert_eq!(offset_in_tag(handle, id, 8, app.state()).unwrap(), 24);
+        assert!(matches!(
+            offset_in_tag(handle, id, 9, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+
+        remove_tag(handle, id, app.state()).unwrap();
+        assert!(matches!(
+            offset_in_tag(handle, id, 0, app.state()),
+            Err(AppError::TagNotFound(missing)) if missing == id
+        ));
+    }
+}
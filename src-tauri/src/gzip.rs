@@ -0,0 +1,386 @@
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+use std::fs::File;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::reader;
+
+/// Deflate back-references reach at most this far into earlier output.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Decompressed bytes between two access points. Reads inflate up to this
+/// much before reaching the bytes asked for.
+const ACCESS_POINT_SPACING: u64 = 1024 * 1024;
+
+/// Compressed bytes fed to the inflater at once.
+const INPUT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Longest gzip member header accepted, file name and comment included.
+const MAX_HEADER_LEN: usize = 64 * 1024;
+
+fn corrupt(what: &str) -> AppError {
+    AppError::InvalidFormat(format!("Gzip data is corrupt: {}", what))
+}
+
+/// Reads as much of `buf` as the file holds at `offset`.
+fn read_compressed(file: &File, offset: u64, buf: &mut [u8]) -> Result<usize, AppError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader::read_file_at(file, &mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(AppError::Io(format!("Failed to read file data: {}", e))),
+        }
+    }
+    Ok(filled)
+}
+
+/// Returns where the deflate data of the gzip member starting at `offset`
+/// begins, or `None` if no member starts there.
+fn member_data_start(file: &File, offset: u64) -> Result<Option<u64>, AppError> {
+    let mut header = vec![0u8; MAX_HEADER_LEN];
+    let len = read_compressed(file, offset, &mut header)?;
+    let header = &header[..len];
+    if header.len() < 10 || header[..2] != [0x1f, 0x8b] {
+        return Ok(None);
+    }
+    if header[2] != 8 {
+        return Err(AppError::InvalidFormat(format!(
+            "Unsupported gzip compression method {}",
+            header[2]
+        )));
+    }
+    let flags = header[3];
+    let truncated = || corrupt("member header is truncated");
+    let mut pos = 10;
+    if flags & 0x04 != 0 {
+        let extra = header.get(pos..pos + 2).ok_or_else(truncated)?;
+        pos += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+    // The file name, then the comment, each ended by a zero byte.
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            let text = header.get(pos..).ok_or_else(truncated)?;
+            pos += text
+                .iter()
+                .position(|&byte| byte == 0)
+                .ok_or_else(truncated)?
+                + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        pos += 2;
+    }
+    if pos > header.len() {
+        return Err(truncated());
+    }
+    Ok(Some(offset + pos as u64))
+}
+
+/// Inflation state at some point of the decompressed stream, enough to carry
+/// on from there. Cloning one is how reads start at an access point.
+#[derive(Clone)]
+struct Inflater {
+    decompressor: Box<DecompressorOxide>,
+    /// The latest output, which back-references copy from. Used as a ring.
+    window: Vec<u8>,
+    window_pos: usize,
+    /// Offset in the compressed file of the next byte to inflate.
+    input: u64,
+    /// Offset in the decompressed stream of the next byte produced.
+    output: u64,
+    /// Where the decompressed stream stood when the current member began.
+    member_output: u64,
+    /// Past the end of the last member.
+    done: bool,
+}
+
+impl Inflater {
+    fn new(data_start: u64, output: u64) -> Self {
+        Self {
+            decompressor: Box::default(),
+            window: vec![0; WINDOW_SIZE],
+            window_pos: 0,
+            input: data_start,
+            output,
+            member_output: output,
+            done: false,
+        }
+    }
+
+    /// Inflates some more of the stream and returns where in `window` the
+    /// new bytes are; an empty range once the stream has ended.
+    fn step(
+        &mut self,
+        file: &File,
+        compressed_size: u64,
+        input: &mut [u8],
+    ) -> Result<Range<usize>, AppError> {
+        loop {
+            if self.done {
+                return Ok(0..0);
+            }
+            let read = read_compressed(file, self.input, input)?;
+            let flags = if self.input + (read as u64) < compressed_size {
+                inflate_flags::TINFL_FLAG_HAS_MORE_INPUT
+            } else {
+                0
+            };
+            let start = self.window_pos;
+            let (status, consumed, produced) = decompress(
+                &mut self.decompressor,
+                &input[..read],
+                &mut self.window,
+                start,
+                flags,
+            );
+            self.input += consumed as u64;
+            self.output += produced as u64;
+            self.window_pos = (start + produced) % WINDOW_SIZE;
+            match status {
+                TINFLStatus::Done => self.next_member(file, compressed_size)?,
+                TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput => {}
+                TINFLStatus::FailedCannotMakeProgress => {
+                    return Err(corrupt("the stream ends early"))
+                }
+                _ => return Err(corrupt("invalid deflate data")),
+            }
+            if produced > 0 {
+                return Ok(start..start + produced);
+            }
+        }
+    }
+
+    /// Checks the trailer of the member just inflated and moves on to the
+    /// next member, if any. Anything after the last member is ignored, as
+    /// `gzip -d` does.
+    fn next_member(&mut self, file: &File, compressed_size: u64) -> Result<(), AppError> {
+        let mut trailer = [0u8; 8];
+        if read_compressed(file, self.input, &mut trailer)? < trailer.len() {
+            return Err(corrupt("the stream ends early"));
+        }
+        let length = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if length != (self.output - self.member_output) as u32 {
+            return Err(corrupt("a member's length does not match its trailer"));
+        }
+        self.input += trailer.len() as u64;
+
+        let next = if self.input < compressed_size {
+            member_data_start(file, self.input)?
+        } else {
+            None
+        };
+        match next {
+            // The window is kept: it still holds the bytes just returned.
+            Some(data_start) => {
+                self.decompressor.init();
+                self.input = data_start;
+                self.member_output = self.output;
+            }
+            None => self.done = true,
+        }
+        Ok(())
+    }
+}
+
+/// Random access into the decompressed contents of a gzip file, like zlib's
+/// `zran`. Building the index inflates the whole file once and keeps the
+/// inflater's state every 1 MiB of output; a read resumes from the nearest
+/// state at or before it.
+pub(crate) struct GzipIndex {
+    file: Arc<File>,
+    compressed_size: u64,
+    /// Ordered by `output`, the first at the start of the stream.
+    points: Vec<Inflater>,
+    size: u64,
+}
+
+impl GzipIndex {
+    pub(crate) fn build(file: Arc<File>, compressed_size: u64) -> Result<Self, AppError> {
+        let data_start = member_data_start(&file, 0)?
+            .ok_or_else(|| AppError::InvalidFormat("Not a gzip file".to_string()))?;
+        let mut inflater = Inflater::new(data_start, 0);
+        let mut points = vec![inflater.clone()];
+        let mut input = vec![0u8; INPUT_CHUNK_SIZE];
+        while !inflater
+            .step(&file, compressed_size, &mut input)?
+            .is_empty()
+        {
+            let last = points.last().unwrap().output;
+            if inflater.output - last >= ACCESS_POINT_SPACING && !inflater.done {
+                points.push(inflater.clone());
+            }
+        }
+        Ok(Self {
+            file,
+            compressed_size,
+            points,
+            size: inflater.output,
+        })
+    }
+
+    /// Length of the decompressed contents.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The compressed file.
+    pub(crate) fn file(&self) -> &Arc<File> {
+        &self.file
+    }
+
+    /// Reads decompressed bytes at `offset` into `buf`, returning how many
+    /// were read. Short counts only happen at the end of the contents.
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, AppError> {
+        if offset >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.points.partition_point(|point| point.output <= offset) - 1;
+        let mut inflater = self.points[index].clone();
+        let mut input = vec![0u8; INPUT_CHUNK_SIZE];
+        let mut filled = 0;
+        while filled < buf.len() {
+            let produced_at = inflater.output;
+            let range = inflater.step(&self.file, self.compressed_size, &mut input)?;
+            if range.is_empty() {
+                break;
+            }
+            let produced_end = produced_at + range.len() as u64;
+            let wanted = offset + filled as u64;
+            if produced_end <= wanted {
+                continue;
+            }
+            let from = range.start + (wanted - produced_at) as usize;
+            let count = std::cmp::min(range.end - from, buf.len() - filled);
+            buf[filled..filled + count].copy_from_slice(&inflater.window[from..from + count]);
+            filled += count;
+        }
+        Ok(filled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, temp_file};
+    use crate::{file_info, get_file_data, open_file, save_file_data};
+
+    /// Log-like lines that compress well but do not repeat exactly.
+    fn log_lines(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut text = Vec::with_capacity(len + 64);
+        let mut line = 0;
+        while text.len() < len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            writeln!(text, "{:08} request id={:016x} status=ok", line, state).unwrap();
+            line += 1;
+        }
+        text.truncate(len);
+        text
+    }
+
+    fn gzip(plain: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(plain).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn random_reads_match_the_decompressed_contents() {
+        let plain = log_lines(3 * 1024 * 1024 + 12_345);
+        // Two members, as left by concatenating rotated logs.
+        let split = 2 * 1024 * 1024 + 77;
+        let mut compressed = gzip(&plain[..split]);
+        compressed.extend(gzip(&plain[split..]));
+        let file = temp_file(&compressed);
+        let app = app();
+        let path = file.path().to_string_lossy().into_owned();
+        let opened = open_file(
+            path,
+            None,
+            Some(true),
+            None,
+            app.handle().clone(),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(opened.file_size, plain.len() as u64);
+
+        let handle = opened.handle;
+        let size = plain.len() as u64;
+        for (start, end) in [
+            (0, 100),
+            (1024 * 1024 - 10, 1024 * 1024 + 10),
+            (split as u64 - 5, split as u64 + 5),
+            (2_500_000, 2_600_000),
+            (size - 64, size),
+            (5, 2 * 1024 * 1024 + 900),
+        ] {
+            let read = tauri::async_runtime::block_on(get_file_data(
+                handle,
+                start,
+                end,
+                None,
+                app.state(),
+            ))
+            .unwrap();
+            assert_eq!(
+                read,
+                &plain[start as usize..end as usize],
+                "{}..{}",
+                start,
+                end
+            );
+        }
+
+        assert!(file_info(handle, app.state()).unwrap().read_only);
+        assert!(matches!(
+            save_file_data(handle, 0, b"x".to_vec(), None, None, app.state()),
+            Err(AppError::ReadOnly)
+        ));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_gzip() {
+        let app = app();
+        let plain = temp_file(b"just some text");
+        let path = plain.path().to_string_lossy().into_owned();
+        assert!(matches!(
+            open_file(
+                path,
+                None,
+                Some(true),
+                None,
+                app.handle().clone(),
+                app.state()
+            ),
+            Err(AppError::InvalidFormat(_))
+        ));
+
+        let mut truncated = gzip(&log_lines(100_000));
+        truncated.truncate(truncated.len() / 2);
+        let truncated = temp_file(&truncated);
+        let path = truncated.path().to_string_lossy().into_owned();
+        assert!(matches!(
+            open_file(
+                path,
+                None,
+                Some(true),
+                None,
+                app.handle().clone(),
+                app.state()
+            ),
+            Err(AppError::InvalidFormat(_))
+        ));
+    }
+}
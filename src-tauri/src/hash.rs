@@ -0,0 +1,664 @@
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Runtime, State};
+
+use crate::cancel::Operation;
+use crate::error::AppError;
+use crate::inspect::Endian;
+use crate::reader::FileReader;
+use crate::{lock_state, run_blocking, AppState};
+
+const HASH_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How far `hash_file` advances between `hash-progress` events.
+const HASH_PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// How many bytes at each end of the file a fingerprint covers.
+const FINGERPRINT_SPAN: u64 = 64 * 1024;
+
+/// Payload of a `hash-progress` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct HashProgress {
+    handle: u64,
+    bytes_hashed: u64,
+    file_size: u64,
+}
+
+/// Incremental hasher for the algorithms exposed to the frontend.
+pub(crate) enum RangeHasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Crc32(crc32fast::Hasher),
+}
+
+impl RangeHasher {
+    pub(crate) fn new(algorithm: &str) -> Result<Self, AppError> {
+        match algorithm {
+            "md5" => Ok(Self::Md5(Md5::new())),
+            "sha1" => Ok(Self::Sha1(Sha1::new())),
+            "sha256" => Ok(Self::Sha256(Sha256::new())),
+            "crc32" => Ok(Self::Crc32(crc32fast::Hasher::new())),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported hash algorithm '{}'",
+                other
+            ))),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Returns the digest as lowercase hex.
+    pub(crate) fn finalize_hex(self) -> String {
+        let digest: Vec<u8> = match self {
+            Self::Md5(hasher) => hasher.finalize().to_vec(),
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+        };
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// Hashes `[start, end)` with `md5`, `sha1`, `sha256` or `crc32`. Without a
+/// range, hashes every selection as if they were laid end to end in order.
+/// Can be stopped through `operation_id`, and gives up with `timed_out` after
+/// `timeout_ms`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn hash_range(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    anchor: Option<String>,
+    algorithm: String,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let mut hasher = RangeHasher::new(&algorithm)?;
+    let (reader, ranges) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (
+            file_state.reader(),
+            file_state.ranges_or_selections(start, end, anchor.as_deref())?,
+        )
+    };
+
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    run_blocking(move || {
+        for (start, end) in ranges {
+            let end = reader.validate_range(start, end)?;
+            reader.for_each_chunk(start, end, HASH_CHUNK_SIZE, |_, chunk| {
+                token.check()?;
+                hasher.update(chunk);
+                Ok(())
+            })?;
+        }
+        Ok(hasher.finalize_hex())
+    })
+    .await
+}
+
+/// Hashes the whole file with `md5`, `sha1`, `sha256` or `crc32`, emitting a
+/// `hash-progress` event every 64 MiB and once more when done. Can be stopped
+/// through `operation_id`, and gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn hash_file<R: Runtime>(
+    handle: u64,
+    algorithm: String,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    app: AppHandle<R>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let mut hasher = RangeHasher::new(&algorithm)?;
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let file_size = reader.file_size;
+        let progress = |bytes_hashed| {
+            let _ = app.emit(
+                "hash-progress",
+                HashProgress {
+                    handle,
+                    bytes_hashed,
+                    file_size,
+                },
+            );
+        };
+        let mut last_reported = 0;
+        reader.for_each_chunk(0, file_size, HASH_CHUNK_SIZE, |offset, chunk| {
+            token.check()?;
+            hasher.update(chunk);
+            let bytes_hashed = offset + chunk.len() as u64;
+            if bytes_hashed - last_reported >= HASH_PROGRESS_INTERVAL {
+                last_reported = bytes_hashed;
+                progress(bytes_hashed);
+            }
+            Ok(())
+        })?;
+        if last_reported < file_size || file_size == 0 {
+            progress(file_size);
+        }
+        Ok(hasher.finalize_hex())
+    })
+    .await
+}
+
+/// Small checksums commonly stored inside file formats.
+enum Checksum {
+    /// CRC-16/ARC, the reflected 0x8005 polynomial.
+    Crc16(u16),
+    Crc32(crc32fast::Hasher),
+    Adler32 {
+        a: u32,
+        b: u32,
+    },
+    Sum32(u32),
+    Sum16(u16),
+    Xor8(u8),
+}
+
+/// Largest modulus of the Adler-32 sums.
+const ADLER_MODULUS: u32 = 65521;
+
+/// Bytes that can be added before the Adler-32 sums may overflow a `u32`.
+const ADLER_BLOCK: usize = 5552;
+
+impl Checksum {
+    fn new(algorithm: &str) -> Result<Self, AppError> {
+        match algorithm {
+            "crc16" => Ok(Self::Crc16(0)),
+            "crc32" => Ok(Self::Crc32(crc32fast::Hasher::new())),
+            "adler32" => Ok(Self::Adler32 { a: 1, b: 0 }),
+            "sum32" => Ok(Self::Sum32(0)),
+            "sum16" => Ok(Self::Sum16(0)),
+            "xor8" => Ok(Self::Xor8(0)),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported checksum algorithm '{}'",
+                other
+            ))),
+        }
+    }
+
+    /// Width of the stored checksum in bytes.
+    fn width(&self) -> usize {
+        match self {
+            Self::Crc32(_) | Self::Adler32 { .. } | Self::Sum32(_) => 4,
+            Self::Crc16(_) | Self::Sum16(_) => 2,
+            Self::Xor8(_) => 1,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc16(crc) => {
+                for &byte in data {
+                    *crc ^= byte as u16;
+                    for _ in 0..8 {
+                        *crc = if *crc & 1 == 1 {
+                            (*crc >> 1) ^ 0xa001
+                        } else {
+                            *crc >> 1
+                        };
+                    }
+                }
+            }
+            Self::Crc32(hasher) => hasher.update(data),
+            Self::Adler32 { a, b } => {
+                for block in data.chunks(ADLER_BLOCK) {
+                    for &byte in block {
+                        *a += byte as u32;
+                        *b += *a;
+                    }
+                    *a %= ADLER_MODULUS;
+                    *b %= ADLER_MODULUS;
+                }
+            }
+            Self::Sum32(sum) => {
+                *sum = data
+                    .iter()
+                    .fold(*sum, |sum, &byte| sum.wrapping_add(byte as u32))
+            }
+            Self::Sum16(sum) => {
+                *sum = data
+                    .iter()
+                    .fold(*sum, |sum, &byte| sum.wrapping_add(byte as u16))
+            }
+            Self::Xor8(xor) => *xor = data.iter().fold(*xor, |xor, &byte| xor ^ byte),
+        }
+    }
+
+    fn finalize(self) -> u64 {
+        match self {
+            Self::Crc16(crc) => crc as u64,
+            Self::Crc32(hasher) => hasher.finalize() as u64,
+            Self::Adler32 { a, b } => ((b << 16) | a) as u64,
+            Self::Sum32(sum) => sum as u64,
+            Self::Sum16(sum) => sum as u64,
+            Self::Xor8(xor) => xor as u64,
+        }
+    }
+}
+
+/// Decodes an unsigned integer of `bytes.len()` (at most 8) bytes.
+fn decode_unsigned(bytes: &[u8], endian: Endian) -> u64 {
+    let fold = |value: u64, &byte: &u8| (value << 8) | byte as u64;
+    match endian {
+        Endian::Big => bytes.iter().fold(0, fold),
+        Endian::Little => bytes.iter().rev().fold(0, fold),
+    }
+}
+
+/// Computes a `crc16`, `crc32`, `adler32`, `sum32`, `sum16` or `xor8`
+/// checksum over `[data_start, data_end)` and compares it with the value
+/// stored at `checksum_offset`.
+#[tauri::command]
+pub async fn verify_checksum(
+    handle: u64,
+    data_start: u64,
+    data_end: u64,
+    checksum_offset: u64,
+    algorithm: String,
+    endian: String,
+    state: State<'_, AppState>,
+) -> Result<bool, AppError> {
+    let mut checksum = Checksum::new(&algorithm)?;
+    let endian = Endian::parse(&endian)?;
+    let reader = lock_state(&state).get(handle)?.reader();
+
+    run_blocking(move || {
+        let width = checksum.width() as u64;
+        if checksum_offset
+            .checked_add(width)
+            .map_or(true, |end| end > reader.file_size)
+        {
+            return Err(AppError::OutOfBounds {
+                requested: checksum_offset.saturating_add(width),
+                size: reader.file_size,
+            });
+        }
+        let mut stored = vec![0u8; checksum.width()];
+        reader.read_exact_at(checksum_offset, &mut stored)?;
+
+        let data_end = reader.validate_range(data_start, data_end)?;
+        reader.for_each_chunk(data_start, data_end, HASH_CHUNK_SIZE, |_, chunk| {
+            checksum.update(chunk);
+            Ok(())
+        })?;
+        Ok(checksum.finalize() == decode_unsigned(&stored, endian))
+    })
+    .await
+}
+
+/// Checksums `locate_checksum` looks for.
+const LOCATED_CHECKSUMS: [&str; 4] = ["crc16", "crc32", "sum32", "adler32"];
+
+/// Most candidates `locate_checksum` returns; short checksums such as
+/// `crc16` turn up by chance every few kilobytes.
+const MAX_CHECKSUM_CANDIDATES: usize = 1000;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ChecksumCandidate {
+    pub algorithm: String,
+    pub endian: String,
+    pub offset: u64,
+}
+
+/// Computes each common checksum of `[data_start, data_end)` and returns every
+/// place in the file that stores one of them, little or big endian, for finding
+/// a format's integrity field. Ordered by offset, and capped at the first 1000
+/// candidates. Can be stopped through `operation_id`, and gives up with
+/// `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn locate_checksum(
+    handle: u64,
+    data_start: u64,
+    data_end: u64,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ChecksumCandidate>, AppError> {
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let data_end = reader.validate_range(data_start, data_end)?;
+        let mut checksums: Vec<Checksum> = LOCATED_CHECKSUMS
+            .iter()
+            .map(|algorithm| Checksum::new(algorithm))
+            .collect::<Result<_, _>>()?;
+        reader.for_each_chunk(data_start, data_end, HASH_CHUNK_SIZE, |_, chunk| {
+            token.check()?;
+            for checksum in &mut checksums {
+                checksum.update(chunk);
+            }
+            Ok(())
+        })?;
+
+        // The stored form of each checksum: (algorithm, endian, bytes).
+        let mut patterns = Vec::new();
+        for (algorithm, checksum) in LOCATED_CHECKSUMS.iter().zip(checksums) {
+            let width = checksum.width();
+            let value = checksum.finalize();
+            let big = value.to_be_bytes()[8 - width..].to_vec();
+            let mut little = big.clone();
+            little.reverse();
+            patterns.push((*algorithm, "little", little));
+            patterns.push((*algorithm, "big", big));
+        }
+        let longest = patterns
+            .iter()
+            .map(|(_, _, bytes)| bytes.len())
+            .max()
+            .unwrap();
+
+        let mut candidates = Vec::new();
+        let mut window: Vec<u8> = Vec::with_capacity(HASH_CHUNK_SIZE + longest);
+        let mut window_start = 0;
+        let mut chunk = vec![0u8; HASH_CHUNK_SIZE];
+        let mut read_pos = 0;
+        while read_pos < reader.file_size {
+            token.check()?;
+            let want = std::cmp::min(HASH_CHUNK_SIZE as u64, reader.file_size - read_pos) as usize;
+            let read = reader.read_at(read_pos, &mut chunk[..want])?;
+            if read == 0 {
+                break;
+            }
+            window.extend_from_slice(&chunk[..read]);
+            read_pos += read as u64;
+            let last_chunk = read_pos >= reader.file_size;
+
+            // Positions too close to the end for the longest value wait for
+            // the next chunk, unless there is none.
+            let scanned = if last_chunk {
+                window.len()
+            } else {
+                window.len().saturating_sub(longest - 1)
+            };
+            for position in 0..scanned {
+                for (algorithm, endian, bytes) in &patterns {
+                    if window[position..].starts_with(bytes) {
+                        candidates.push(ChecksumCandidate {
+                            algorithm: algorithm.to_string(),
+                            endian: endian.to_string(),
+                            offset: window_start + position as u64,
+                        });
+                        if candidates.len() == MAX_CHECKSUM_CANDIDATES {
+                            return Ok(candidates);
+                        }
+                    }
+                }
+            }
+            window.drain(..scanned);
+            window_start += scanned as u64;
+        }
+        Ok(candidates)
+    })
+    .await
+}
+
+/// Cheap identity of a file's contents that survives renames: its size and
+/// the SHA-256 of its first and last 64 KiB.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    size: u64,
+    sha256: String,
+}
+
+pub(crate) fn fingerprint(reader: &FileReader) -> Result<Fingerprint, AppError> {
+    let size = reader.file_size;
+    let mut hasher = RangeHasher::Sha256(Sha256::new());
+    let head = std::cmp::min(size, FINGERPRINT_SPAN);
+    let tail = size.saturating_sub(FINGERPRINT_SPAN);
+    for (start, end) in [(0, head), (tail, size)] {
+        reader.for_each_chunk(start, end, HASH_CHUNK_SIZE, |_, chunk| {
+            hasher.update(chunk);
+            Ok(())
+        })?;
+    }
+    Ok(Fingerprint {
+        size,
+        sha256: hasher.finalize_hex(),
+    })
+}
+
+/// Returns the fingerprint of file `handle`, hashing it outside the state
+/// lock unless one is cached from before the last edit.
+pub(crate) fn fingerprint_of(state: &AppState, handle: u64) -> Result<Fingerprint, AppError> {
+    let (reader, generation) = {
+        let mut app_state = lock_state(state);
+        let file_state = app_state.get_mut(handle)?;
+        let generation = file_state.cache.generation();
+        if let Some((cached_at, fingerprint)) = &file_state.fingerprint {
+            if *cached_at == generation {
+                return Ok(fingerprint.clone());
+            }
+        }
+        (file_state.reader(), generation)
+    };
+
+    let fingerprint = fingerprint(&reader)?;
+    let mut app_state = lock_state(state);
+    if let Ok(file_state) = app_state.get_mut(handle) {
+        // An edit while hashing leaves nothing worth caching.
+        if file_state.cache.generation() == generation {
+            file_state.fingerprint = Some((generation, fingerprint.clone()));
+        }
+    }
+    Ok(fingerprint)
+}
+
+/// Returns the file's fingerprint, for finding its tags and workspaces again
+/// after it was renamed. It describes the current contents, so edits change
+/// it.
+#[tauri::command]
+pub async fn file_fingerprint(
+    handle: u64,
+    state: State<'_, AppState>,
+) -> Result<Fingerprint, AppError> {
+    let state = Arc::clone(state.inner());
+    run_blocking(move || fingerprint_of(&state, handle)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tauri::async_runtime::block_on;
+    use tauri::{Listener, Manager};
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn hashes_match_known_digests() {
+        let app = app();
+        let handle = open_bytes(b"[abc]".to_vec(), app.state()).unwrap().handle;
+        let hash = |algorithm: &str| {
+            block_on(hash_range(
+                handle,
+                Some(1),
+                Some(4),
+                None,
+                algorithm.to_string(),
+                None,
+                None,
+                app.state(),
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(hash("md5"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hash("sha1"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            hash("sha256"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(hash("crc32"), "352441c2");
+    }
+
+    #[test]
+    fn rejects_unknown_algorithm() {
+        assert!(matches!(
+            RangeHasher::new("sha512"),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn verify_checksum_detects_corruption() {
+        let app = app();
+        let mut bytes = b"123456789".to_vec();
+        bytes.extend(0xCBF4_3926u32.to_le_bytes());
+        bytes.extend(0x01DDu16.to_be_bytes());
+        let good = open_bytes(bytes.clone(), app.state()).unwrap().handle;
+        bytes[4] ^= 0xff;
+        let corrupted = open_bytes(bytes, app.state()).unwrap().handle;
+        let verify = |handle, checksum_offset, algorithm: &str, endian: &str| {
+            block_on(verify_checksum(
+                handle,
+                0,
+                9,
+                checksum_offset,
+                algorithm.to_string(),
+                endian.to_string(),
+                app.state(),
+            ))
+            .unwrap()
+        };
+
+        assert!(verify(good, 9, "crc32", "little"));
+        assert!(verify(good, 13, "sum16", "big"));
+        assert!(!verify(good, 9, "crc32", "big"));
+        assert!(!verify(corrupted, 9, "crc32", "little"));
+        assert!(!verify(corrupted, 13, "sum16", "big"));
+    }
+
+    #[test]
+    fn range_can_be_anchored_at_the_end() {
+        let app = app();
+        let handle = open_bytes(b"xxabc".to_vec(), app.state()).unwrap().handle;
+
+        let hash = block_on(hash_range(
+            handle,
+            Some(3),
+            Some(0),
+            Some("end".to_string()),
+            "md5".to_string(),
+            None,
+            None,
+            app.state(),
+        ));
+        assert_eq!(hash.unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn new_checksums_match_known_values() {
+        for (algorithm, expected) in [
+            ("crc16", 0xbb3d),
+            ("adler32", 0x091e_01de),
+            ("sum32", 0x1dd),
+        ] {
+            let mut checksum = Checksum::new(algorithm).unwrap();
+            checksum.update(b"1234");
+            checksum.update(b"56789");
+            assert_eq!(checksum.finalize(), expected, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn locates_an_embedded_crc32() {
+        let app = app();
+        let mut bytes = vec![0u8; HASH_CHUNK_SIZE + 64];
+        bytes[..9].copy_from_slice(b"123456789");
+        bytes[100..104].copy_from_slice(&0xCBF4_3926u32.to_be_bytes());
+        // Straddles the boundary between two scanned chunks.
+        let straddling = HASH_CHUNK_SIZE - 2;
+        bytes[straddling..straddling + 4].copy_from_slice(&0xCBF4_3926u32.to_le_bytes());
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+
+        let candidates = block_on(locate_checksum(handle, 0, 9, None, None, app.state())).unwrap();
+        let candidate = |endian: &str, offset| ChecksumCandidate {
+            algorithm: "crc32".to_string(),
+            endian: endian.to_string(),
+            offset,
+        };
+        assert_eq!(
+            candidates,
+            [
+                candidate("big", 100),
+                candidate("little", straddling as u64)
+            ]
+        );
+    }
+
+    #[test]
+    fn hash_file_digests_the_whole_file_with_progress() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"abc");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        {
+            let events = Arc::clone(&events);
+            app.listen_any("hash-progress", move |e| {
+                let payload: serde_json::Value = serde_json::from_str(e.payload()).unwrap();
+                events.lock().unwrap().push(payload);
+            });
+        }
+
+        let digest = block_on(hash_file(
+            handle,
+            "sha256".to_string(),
+            None,
+            None,
+            app.handle().clone(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let events = events.lock().unwrap();
+        let last = events.last().expect("at least one progress event");
+        assert_eq!(last["bytes_hashed"], 3);
+        assert_eq!(last["file_size"], 3);
+    }
+
+    #[test]
+    fn copies_share_a_fingerprint_and_edits_change_it() {
+        let app = app();
+        let bytes: Vec<u8> = (0..200 * 1024u32).map(|i| (i % 251) as u8).collect();
+        let fingerprint = |handle| block_on(file_fingerprint(handle, app.state())).unwrap();
+
+        let (_file, handle) = open_temp(&app, &bytes);
+        let original = fingerprint(handle);
+        assert_eq!(fingerprint(open_temp(&app, &bytes).1), original);
+        let mut modified = bytes.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert_ne!(fingerprint(open_temp(&app, &modified).1), original);
+
+        let copy = open_bytes(bytes, app.state()).unwrap().handle;
+        assert_eq!(fingerprint(copy), original);
+
+        crate::save_file_data(handle, 0, vec![0xff], None, None, app.state()).unwrap();
+        assert_ne!(fingerprint(handle), original);
+    }
+}
@@ -0,0 +1,398 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use std::fmt::Write;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::reader::to_usize;
+use crate::tags::Tag;
+use crate::{lock_state, run_blocking, AppState};
+
+/// Largest dump `format_hexdump` will produce.
+const MAX_DUMP_SIZE: usize = 16 * 1024 * 1024;
+
+const MAX_BYTES_PER_LINE: usize = 256;
+
+/// Bytes per line of `format_annotated_dump`.
+const ANNOTATED_BYTES_PER_LINE: usize = 16;
+
+/// Number of hex digits needed to print every offset in a file of
+/// `file_size` bytes, but at least 8.
+fn offset_width(file_size: u64) -> usize {
+    let last = file_size.saturating_sub(1);
+    let digits = (64 - last.leading_zeros() as usize).div_ceil(4);
+    std::cmp::max(8, digits)
+}
+
+/// Length of one dump line, including the newline.
+fn line_length(bytes_per_line: usize, width: usize) -> usize {
+    width + 2 + 3 * bytes_per_line + 2 + bytes_per_line + 2
+}
+
+/// Formats `data`, which starts at `start`, as `hexdump -C` style lines.
+fn format_lines(data: &[u8], start: u64, bytes_per_line: usize, width: usize) -> String {
+    let lines = data.len().div_ceil(bytes_per_line);
+    let mut dump = String::with_capacity(lines * line_length(bytes_per_line, width));
+
+    for (index, line) in data.chunks(bytes_per_line).enumerate() {
+        let offset = start + (index * bytes_per_line) as u64;
+        let _ = write!(dump, "{:0width$x}  ", offset, width = width);
+        for byte in line {
+            let _ = write!(dump, "{:02x} ", byte);
+        }
+        for _ in line.len()..bytes_per_line {
+            dump.push_str("   ");
+        }
+        dump.push_str(" |");
+        dump.extend(line.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+/// Renders `[start, end)` (default: the selection) as a classic hex dump:
+/// offset, hex bytes and an ASCII column with non-printable bytes as `.`.
+#[tauri::command]
+pub async fn format_hexdump(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    anchor: Option<String>,
+    bytes_per_line: usize,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    if !(1..=MAX_BYTES_PER_LINE).contains(&bytes_per_line) {
+        return Err(AppError::InvalidArgument(format!(
+            "Bytes per line must be between 1 and {}",
+            MAX_BYTES_PER_LINE
+        )));
+    }
+    let (reader, (start, end)) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (
+            file_state.reader(),
+            file_state.range_or_selection(start, end, anchor.as_deref())?,
+        )
+    };
+
+    run_blocking(move || {
+        let end = reader.validate_range(start, end)?;
+        let width = offset_width(reader.file_size);
+
+        let length = end - start;
+        let lines = length.div_ceil(bytes_per_line as u64);
+        let dump_size = lines.saturating_mul(line_length(bytes_per_line, width) as u64);
+        if dump_size > MAX_DUMP_SIZE as u64 {
+            return Err(AppError::InvalidArgument(format!(
+                "Hex dump of {} bytes would exceed the {} byte output limit",
+                length, MAX_DUMP_SIZE
+            )));
+        }
+
+        let mut data = vec![0u8; to_usize(length)?];
+        reader.read_exact_at(start, &mut data)?;
+        Ok(format_lines(&data, start, bytes_per_line, width))
+    })
+    .await
+}
+
+/// Appends to each line of `dump`, which shows `len` bytes from `start`, the
+/// display names of the `tags` (sorted by start) covering its bytes. A name
+/// is preceded by `[` on the line where its tag starts and followed by `]`
+/// on the line where it ends.
+fn annotate_lines(dump: &str, start: u64, len: u64, bytes_per_line: usize, tags: &[Tag]) -> String {
+    let mut annotated = String::with_capacity(dump.len());
+    let end = start + len;
+    for (index, line) in dump.lines().enumerate() {
+        annotated.push_str(line);
+        let line_start = start + (index * bytes_per_line) as u64;
+        let line_end = std::cmp::min(line_start + bytes_per_line as u64, end);
+        let mut covering = tags
+            .iter()
+            .take_while(|tag| tag.start < line_end)
+            .filter(|tag| tag.end > line_start)
+            .peekable();
+        if covering.peek().is_some() {
+            annotated.push_str("  #");
+            for tag in covering {
+                annotated.push(' ');
+                if tag.start >= line_start {
+                    annotated.push('[');
+                }
+                annotated.push_str(&tag.display_name);
+                if tag.end <= line_end {
+                    annotated.push(']');
+                }
+            }
+        }
+        annotated.push('\n');
+    }
+    annotated
+}
+
+/// Renders `[start, end)` as a hex dump of 16 bytes per line, each line
+/// followed by the tags covering it, for sharing as a text report. See
+/// `annotate_lines` for how tags are shown.
+#[tauri::command]
+pub async fn format_annotated_dump(
+    handle: u64,
+    start: u64,
+    end: u64,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let (reader, tags) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        let tags: Vec<Tag> = file_state
+            .tags
+            .iter()
+            .filter(|tag| tag.start < end && tag.end > start)
+            .cloned()
+            .collect();
+        (file_state.reader(), tags)
+    };
+
+    run_blocking(move || {
+        let end = reader.validate_range(start, end)?;
+        let width = offset_width(reader.file_size);
+        let length = end - start;
+        let lines = length.div_ceil(ANNOTATED_BYTES_PER_LINE as u64);
+        let dump_size = lines.saturating_mul(line_length(ANNOTATED_BYTES_PER_LINE, width) as u64);
+        if dump_size > MAX_DUMP_SIZE as u64 {
+            return Err(AppError::InvalidArgument(format!(
+                "Hex dump of {} bytes would exceed the {} byte output limit",
+                length, MAX_DUMP_SIZE
+            )));
+        }
+
+        let mut data = vec![0u8; to_usize(length)?];
+        reader.read_exact_at(start, &mut data)?;
+        let dump = format_lines(&data, start, ANNOTATED_BYTES_PER_LINE, width);
+        let annotated = annotate_lines(&dump, start, length, ANNOTATED_BYTES_PER_LINE, &tags);
+        if annotated.len() > MAX_DUMP_SIZE {
+            return Err(AppError::InvalidArgument(format!(
+                "Annotated dump of {} bytes would exceed the {} byte output limit",
+                length, MAX_DUMP_SIZE
+            )));
+        }
+        Ok(annotated)
+    })
+    .await
+}
+
+/// How `copy_range` writes bytes out for pasting into other tools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CopyFormat {
+    /// `00 01 ff`
+    Hex,
+    /// `{0x00, 0x01, 0xff}`
+    CArray,
+    Base64,
+    /// `b'\x00\x01\xff'`
+    PythonBytes,
+    /// `[0x00u8, 0x01u8, 0xffu8]`
+    RustArray,
+}
+
+impl CopyFormat {
+    fn parse(format: &str) -> Result<Self, AppError> {
+        match format {
+            "hex" => Ok(Self::Hex),
+            "c-array" => Ok(Self::CArray),
+            "base64" => Ok(Self::Base64),
+            "python-bytes" => Ok(Self::PythonBytes),
+            "rust-array" => Ok(Self::RustArray),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported copy format '{}', expected 'hex', 'c-array', 'base64', \
+                 'python-bytes' or 'rust-array'",
+                other
+            ))),
+        }
+    }
+
+    /// Length of `len` bytes written in this format.
+    fn output_len(self, len: u64) -> u64 {
+        let separators = len.saturating_sub(1);
+        match self {
+            Self::Hex => 2 * len + separators,
+            Self::CArray => 2 + 4 * len + 2 * separators,
+            Self::Base64 => len.div_ceil(3) * 4,
+            Self::PythonBytes => 3 + 4 * len,
+            Self::RustArray => 2 + 6 * len + 2 * separators,
+        }
+    }
+
+    fn format(self, data: &[u8]) -> String {
+        let mut text = String::with_capacity(self.output_len(data.len() as u64) as usize);
+        let join = |text: &mut String, separator: &str, byte_format: fn(&mut String, u8)| {
+            for (i, &byte) in data.iter().enumerate() {
+                if i > 0 {
+                    text.push_str(separator);
+                }
+                byte_format(text, byte);
+            }
+        };
+        match self {
+            Self::Hex => join(&mut text, " ", |text, byte| {
+                let _ = write!(text, "{:02x}", byte);
+            }),
+            Self::CArray => {
+                text.push('{');
+                join(&mut text, ", ", |text, byte| {
+                    let _ = write!(text, "0x{:02x}", byte);
+                });
+                text.push('}');
+            }
+            Self::Base64 => text.push_str(&BASE64_STANDARD.encode(data)),
+            Self::PythonBytes => {
+                text.push_str("b'");
+                for byte in data {
+                    let _ = write!(text, "\\x{:02x}", byte);
+                }
+                text.push('\'');
+            }
+            Self::RustArray => {
+                text.push('[');
+                join(&mut text, ", ", |text, byte| {
+                    let _ = write!(text, "0x{:02x}u8", byte);
+                });
+                text.push(']');
+            }
+        }
+        text
+    }
+}
+
+/// Formats `[start, end)` for the frontend to put on the clipboard, as
+/// `hex`, `c-array`, `base64`, `python-bytes` or `rust-array`. Output over
+/// 16 MiB is refused.
+#[tauri::command]
+pub async fn copy_range(
+    handle: u64,
+    start: u64,
+    end: u64,
+    format: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let format = CopyFormat::parse(&format)?;
+    let reader = lock_state(&state).get(handle)?.reader();
+    let end = reader.validate_range(start, end)?;
+    let length = end - start;
+    if format.output_len(length) > MAX_DUMP_SIZE as u64 {
+        return Err(AppError::InvalidArgument(format!(
+            "Copying {} bytes would exceed the {} byte output limit",
+            length, MAX_DUMP_SIZE
+        )));
+    }
+
+    run_blocking(move || {
+        let mut data = vec![0u8; to_usize(length)?];
+        reader.read_exact_at(start, &mut data)?;
+        Ok(format.format(&data))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::tags::add_tag;
+    use crate::test_support::app;
+
+    #[test]
+    fn formats_classic_lines() {
+        let dump = format_lines(b"Hello, hex!\x00\x01", 0x10, 8, 8);
+        assert_eq!(
+            dump,
+            "00000010  48 65 6c 6c 6f 2c 20 68  |Hello, h|\n\
+             00000018  65 78 21 00 01           |ex!..|\n"
+        );
+        assert_eq!(dump.len(), 2 * line_length(8, 8) - 3);
+    }
+
+    #[test]
+    fn offset_column_scales_with_file_size() {
+        assert_eq!(offset_width(0), 8);
+        assert_eq!(offset_width(1 << 32), 8);
+        assert_eq!(offset_width((1 << 32) + 1), 9);
+        assert_eq!(offset_width(u64::MAX), 16);
+    }
+
+    #[test]
+    fn copies_ranges_in_every_format() {
+        let app = app();
+        let handle = open_bytes(b"\x00AB\xff\x10".to_vec(), app.state())
+            .unwrap()
+            .handle;
+        let copy = |format: &str| {
+            tauri::async_runtime::block_on(copy_range(
+                handle,
+                0,
+                4,
+                format.to_string(),
+                app.state(),
+            ))
+        };
+
+        for (format, expected) in [
+            ("hex", "00 41 42 ff"),
+            ("c-array", "{0x00, 0x41, 0x42, 0xff}"),
+            ("base64", "AEFC/w=="),
+            ("python-bytes", "b'\\x00\\x41\\x42\\xff'"),
+            ("rust-array", "[0x00u8, 0x41u8, 0x42u8, 0xffu8]"),
+        ] {
+            let text = copy(format).unwrap();
+            assert_eq!(text, expected, "{}", format);
+            let output_len = CopyFormat::parse(format).unwrap().output_len(4);
+            assert_eq!(output_len, text.len() as u64, "{}", format);
+        }
+        assert!(matches!(copy("pascal"), Err(AppError::InvalidArgument(_))));
+        assert!(matches!(
+            tauri::async_runtime::block_on(copy_range(
+                handle,
+                9,
+                12,
+                "hex".to_string(),
+                app.state()
+            )),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn annotated_dump_names_tags_on_their_lines() {
+        let app = app();
+        let bytes: Vec<u8> = (0..64).collect();
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        for tag in [
+            Tag::new(4, 8, "magic".to_string(), "Magic"),
+            Tag::new(12, 40, "body".to_string(), "Body"),
+        ] {
+            add_tag(handle, tag, None, None, None, app.state()).unwrap();
+        }
+
+        let dump =
+            tauri::async_runtime::block_on(format_annotated_dump(handle, 0, 48, app.state()))
+                .unwrap();
+        let suffixes: Vec<&str> = dump
+            .lines()
+            .map(|line| line.split_once("|  #").map_or("", |(_, tags)| tags))
+            .collect();
+        assert_eq!(suffixes, [" [Magic] [Body", " Body", " Body]"]);
+        assert!(dump.starts_with("00000000  00 01 02 03 "));
+
+        let dump =
+            tauri::async_runtime::block_on(format_annotated_dump(handle, 40, 64, app.state()))
+                .unwrap();
+        assert!(!dump.contains('#'), "{}", dump);
+    }
+}
@@ -0,0 +1,161 @@
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::Read;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::reader::to_usize;
+use crate::{lock_state, run_blocking, AppState};
+
+/// Inflates `compressed` as `format`, failing once the output would exceed
+/// `limit` bytes.
+fn inflate(compressed: &[u8], format: &str, limit: u64) -> Result<Vec<u8>, AppError> {
+    let decoder: Box<dyn Read + '_> = match format {
+        "zlib" => Box::new(ZlibDecoder::new(compressed)),
+        "gzip" => Box::new(GzDecoder::new(compressed)),
+        "raw-deflate" => Box::new(DeflateDecoder::new(compressed)),
+        other => {
+            return Err(AppError::InvalidArgument(format!(
+                "Unsupported compression format '{}', expected 'zlib', 'gzip' or 'raw-deflate'",
+                other
+            )))
+        }
+    };
+
+    let mut inflated = Vec::new();
+    decoder
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut inflated)
+        .map_err(|e| {
+            AppError::InvalidFormat(format!("Failed to inflate {} data: {}", format, e))
+        })?;
+    if inflated.len() as u64 > limit {
+        return Err(AppError::InvalidArgument(format!(
+            "Decompressed data is larger than the read limit of {} bytes",
+            limit
+        )));
+    }
+    Ok(inflated)
+}
+
+/// Inflates the `zlib`, `gzip` or `raw-deflate` stream stored in
+/// `[start, end)` and returns the decompressed bytes. Bytes after the end of
+/// the stream are ignored. Both the range and the output are limited to the
+/// file's read limit, as set with `set_max_read_bytes`.
+#[tauri::command]
+pub async fn decompress_range(
+    handle: u64,
+    start: u64,
+    end: u64,
+    format: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, AppError> {
+    let (reader, limit) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (file_state.reader(), file_state.max_read_bytes)
+    };
+    let end = reader.validate_range(start, end)?;
+    if end - start > limit {
+        return Err(AppError::ReadTooLarge {
+            requested: end - start,
+            limit,
+        });
+    }
+
+    run_blocking(move || {
+        let mut compressed = vec![0u8; to_usize(end - start)?];
+        reader.read_exact_at(start, &mut compressed)?;
+        inflate(&compressed, &format, limit)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::set_max_read_bytes;
+    use crate::test_support::app;
+
+    fn compress(format: &str, plain: &[u8]) -> Vec<u8> {
+        fn finish<W: Write>(mut encoder: W, plain: &[u8]) -> W {
+            encoder.write_all(plain).unwrap();
+            encoder
+        }
+        match format {
+            "zlib" => finish(ZlibEncoder::new(Vec::new(), Compression::default()), plain)
+                .finish()
+                .unwrap(),
+            "gzip" => finish(GzEncoder::new(Vec::new(), Compression::default()), plain)
+                .finish()
+                .unwrap(),
+            _ => finish(
+                DeflateEncoder::new(Vec::new(), Compression::default()),
+                plain,
+            )
+            .finish()
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn inflates_embedded_streams() {
+        let plain = b"the same words over and over, ".repeat(50);
+        for format in ["zlib", "gzip", "raw-deflate"] {
+            let app = app();
+            let compressed = compress(format, &plain);
+            let mut bytes = b"HEADER".to_vec();
+            bytes.extend_from_slice(&compressed);
+            bytes.extend_from_slice(b"TRAILER");
+            let handle = open_bytes(bytes, app.state()).unwrap().handle;
+            let end = 6 + compressed.len() as u64;
+
+            let inflated = block_on(decompress_range(
+                handle,
+                6,
+                end,
+                format.to_string(),
+                app.state(),
+            ))
+            .unwrap();
+            assert_eq!(inflated, plain, "{}", format);
+
+            set_max_read_bytes(handle, plain.len() as u64 - 1, app.state()).unwrap();
+            let too_large = block_on(decompress_range(
+                handle,
+                6,
+                end,
+                format.to_string(),
+                app.state(),
+            ));
+            assert!(matches!(too_large, Err(AppError::InvalidArgument(_))));
+        }
+    }
+
+    #[test]
+    fn malformed_streams_fail_clearly() {
+        let app = app();
+        let handle = open_bytes(b"definitely not zlib".to_vec(), app.state())
+            .unwrap()
+            .handle;
+        let inflate = |format: &str| {
+            block_on(decompress_range(
+                handle,
+                0,
+                19,
+                format.to_string(),
+                app.state(),
+            ))
+        };
+
+        assert!(matches!(inflate("zlib"), Err(AppError::InvalidFormat(_))));
+        assert!(matches!(inflate("gzip"), Err(AppError::InvalidFormat(_))));
+        assert!(matches!(inflate("lzma"), Err(AppError::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,619 @@
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::journal::Edit;
+use crate::{lock_state, AppState};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub(crate) fn parse(endian: &str) -> Result<Self, AppError> {
+        match endian {
+            "little" => Ok(Self::Little),
+            "big" => Ok(Self::Big),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported endianness '{}', expected 'little' or 'big'",
+                other
+            ))),
+        }
+    }
+}
+
+/// The fixed-size numeric types understood by the data inspector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ValueKind {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+macro_rules! decode_as {
+    ($ty:ty, $bytes:expr, $endian:expr) => {{
+        let array: [u8; std::mem::size_of::<$ty>()] = $bytes.try_into().unwrap();
+        match $endian {
+            Endian::Little => <$ty>::from_le_bytes(array),
+            Endian::Big => <$ty>::from_be_bytes(array),
+        }
+    }};
+}
+
+macro_rules! encode_as {
+    ($ty:ty, $value:expr, $endian:expr) => {{
+        let value: $ty = $value.parse().map_err(|_| {
+            AppError::InvalidArgument(format!("'{}' is not a valid {}", $value, stringify!($ty)))
+        })?;
+        match $endian {
+            Endian::Little => value.to_le_bytes().to_vec(),
+            Endian::Big => value.to_be_bytes().to_vec(),
+        }
+    }};
+}
+
+impl ValueKind {
+    pub(crate) fn parse(kind: &str) -> Result<Self, AppError> {
+        match kind {
+            "u8" => Ok(Self::U8),
+            "i8" => Ok(Self::I8),
+            "u16" => Ok(Self::U16),
+            "i16" => Ok(Self::I16),
+            "u32" => Ok(Self::U32),
+            "i32" => Ok(Self::I32),
+            "u64" => Ok(Self::U64),
+            "i64" => Ok(Self::I64),
+            "f32" => Ok(Self::F32),
+            "f64" => Ok(Self::F64),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported value kind '{}'",
+                other
+            ))),
+        }
+    }
+
+    /// The integer kind of the same width with the given signedness, so a
+    /// `u32` can be shown as an `i32` and back. Floats are unchanged.
+    pub(crate) fn with_signedness(self, signed: bool) -> Self {
+        match (self, signed) {
+            (Self::U8 | Self::I8, true) => Self::I8,
+            (Self::U8 | Self::I8, false) => Self::U8,
+            (Self::U16 | Self::I16, true) => Self::I16,
+            (Self::U16 | Self::I16, false) => Self::U16,
+            (Self::U32 | Self::I32, true) => Self::I32,
+            (Self::U32 | Self::I32, false) => Self::U32,
+            (Self::U64 | Self::I64, true) => Self::I64,
+            (Self::U64 | Self::I64, false) => Self::U64,
+            (kind, _) => kind,
+        }
+    }
+
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+        }
+    }
+
+    /// Formats the first `self.size()` bytes of `bytes` as a decimal string.
+    pub(crate) fn decode(self, bytes: &[u8], endian: Endian) -> String {
+        let bytes = &bytes[..self.size()];
+        match self {
+            Self::U8 => bytes[0].to_string(),
+            Self::I8 => (bytes[0] as i8).to_string(),
+            Self::U16 => decode_as!(u16, bytes, endian).to_string(),
+            Self::I16 => decode_as!(i16, bytes, endian).to_string(),
+            Self::U32 => decode_as!(u32, bytes, endian).to_string(),
+            Self::I32 => decode_as!(i32, bytes, endian).to_string(),
+            Self::U64 => decode_as!(u64, bytes, endian).to_string(),
+            Self::I64 => decode_as!(i64, bytes, endian).to_string(),
+            // `Display` gives the shortest representation that round-trips.
+            Self::F32 => decode_as!(f32, bytes, endian).to_string(),
+            Self::F64 => decode_as!(f64, bytes, endian).to_string(),
+        }
+    }
+
+    /// Parses a decimal `value` and encodes it as `self.size()` bytes. Values
+    /// that do not fit the type are rejected.
+    pub(crate) fn encode(self, value: &str, endian: Endian) -> Result<Vec<u8>, AppError> {
+        let value = value.trim();
+        Ok(match self {
+            Self::U8 => encode_as!(u8, value, endian),
+            Self::I8 => encode_as!(i8, value, endian),
+            Self::U16 => encode_as!(u16, value, endian),
+            Self::I16 => encode_as!(i16, value, endian),
+            Self::U32 => encode_as!(u32, value, endian),
+            Self::I32 => encode_as!(i32, value, endian),
+            Self::U64 => encode_as!(u64, value, endian),
+            Self::I64 => encode_as!(i64, value, endian),
+            Self::F32 => encode_as!(f32, value, endian),
+            Self::F64 => encode_as!(f64, value, endian),
+        })
+    }
+}
+
+/// Sets the endianness `inspect_value` and `write_value` use for the file
+/// when a call leaves it out; little endian until set.
+#[tauri::command]
+pub fn set_default_endian(
+    handle: u64,
+    endian: String,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let endian = Endian::parse(&endian)?;
+    lock_state(&state).get_mut(handle)?.default_endian = endian;
+    Ok(())
+}
+
+/// Decodes the value of type `kind` stored at `offset`, in the file's
+/// default endianness unless `endian` is given. With `signed`, integers are
+/// shown with that signedness whatever `kind` says, e.g. `u16` as `i16`.
+#[tauri::command]
+pub fn inspect_value(
+    handle: u64,
+    offset: u64,
+    kind: String,
+    endian: Option<String>,
+    signed: Option<bool>,
+    state: State<AppState>,
+) -> Result<String, AppError> {
+    let mut kind = ValueKind::parse(&kind)?;
+    if let Some(signed) = signed {
+        kind = kind.with_signedness(signed);
+    }
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let endian = match endian {
+        Some(endian) => Endian::parse(&endian)?,
+        None => file_state.default_endian,
+    };
+
+    let size = kind.size() as u64;
+    if offset
+        .checked_add(size)
+        .map_or(true, |end| end > file_state.file_size)
+    {
+        return Err(AppError::OutOfBounds {
+            requested: offset.saturating_add(size),
+            size: file_state.file_size,
+        });
+    }
+
+    let mut bytes = vec![0u8; kind.size()];
+    file_state.read_exact_at(offset, &mut bytes)?;
+    Ok(kind.decode(&bytes, endian))
+}
+
+/// Encodes `value` as type `kind` and writes it at `offset`, the inverse of
+/// `inspect_value`. Uses the file's default endianness unless `endian` is
+/// given.
+#[tauri::command]
+pub fn write_value(
+    handle: u64,
+    offset: u64,
+    kind: String,
+    endian: Option<String>,
+    value: String,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let kind = ValueKind::parse(&kind)?;
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    let endian = match endian {
+        Some(endian) => Endian::parse(&endian)?,
+        None => file_state.default_endian,
+    };
+    let new_bytes = kind.encode(&value, endian)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+
+    let size = kind.size() as u64;
+    if offset
+        .checked_add(size)
+        .map_or(true, |end| end > file_state.file_size)
+    {
+        return Err(AppError::OutOfBounds {
+            requested: offset.saturating_add(size),
+            size: file_state.file_size,
+        });
+    }
+
+    let mut old_bytes = vec![0u8; kind.size()];
+    file_state.read_exact_at(offset, &mut old_bytes)?;
+    if old_bytes == new_bytes {
+        return Ok(());
+    }
+
+    file_state.write_at(offset, &new_bytes)?;
+    file_state.journal.record(Edit {
+        offset,
+        old_bytes,
+        new_bytes,
+    });
+    Ok(())
+}
+
+/// Returns the bits of the byte at `offset`, most significant first.
+#[tauri::command]
+pub fn inspect_bits(
+    handle: u64,
+    offset: u64,
+    state: State<AppState>,
+) -> Result<[bool; 8], AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    if offset >= file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: file_state.file_size,
+        });
+    }
+
+    let mut byte = [0u8; 1];
+    file_state.read_exact_at(offset, &mut byte)?;
+    Ok(std::array::from_fn(|index| byte[0] & (0x80 >> index) != 0))
+}
+
+/// Sets one bit of the byte at `offset`. `index` uses the same MSB-first
+/// order as `inspect_bits`, so index 0 is the most significant bit.
+#[tauri::command]
+pub fn set_bit(
+    handle: u64,
+    offset: u64,
+    index: u8,
+    value: bool,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    if index > 7 {
+        return Err(AppError::InvalidArgument(format!(
+            "Bit index {} is out of range 0..=7",
+            index
+        )));
+    }
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    if offset >= file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: file_state.file_size,
+        });
+    }
+
+    let mut old = [0u8; 1];
+    file_state.read_exact_at(offset, &mut old)?;
+    let mask = 0x80 >> index;
+    let new = if value { old[0] | mask } else { old[0] & !mask };
+    if new == old[0] {
+        return Ok(());
+    }
+
+    file_state.write_at(offset, &[new])?;
+    file_state.journal.record(Edit {
+        offset,
+        old_bytes: old.to_vec(),
+        new_bytes: vec![new],
+    });
+    Ok(())
+}
+
+/// One plausible reading of the bytes at an offset.
+#[derive(Serialize, Clone, Debug)]
+pub struct TypeGuess {
+    /// `ascii`, a value kind such as `u32`, `length-prefix` or `pointer`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// `little` or `big` for multi-byte readings.
+    pub endian: Option<String>,
+    pub value: String,
+    /// From 0.0 to 1.0; only meaningful relative to the other guesses.
+    pub confidence: f64,
+}
+
+fn endian_name(endian: Endian) -> &'static str {
+    match endian {
+        Endian::Little => "little",
+        Endian::Big => "big",
+    }
+}
+
+/// Reads the unsigned integer of `width` bytes at the start of `bytes`.
+fn unsigned(bytes: &[u8], width: usize, endian: Endian) -> u64 {
+    let fold = |value: u64, byte: &u8| value << 8 | *byte as u64;
+    match endian {
+        Endian::Little => bytes[..width].iter().rev().fold(0, fold),
+        Endian::Big => bytes[..width].iter().fold(0, fold),
+    }
+}
+
+/// Ranks readings of `bytes`, found at `offset` in a file of `file_size`
+/// bytes, most plausible first.
+fn guess(bytes: &[u8], offset: u64, file_size: u64) -> Vec<TypeGuess> {
+    let mut guesses = Vec::new();
+    let mut push = |kind: &str, endian: Option<Endian>, value: String, confidence: f64| {
+        guesses.push(TypeGuess {
+            kind: kind.to_string(),
+            endian: endian.map(|endian| endian_name(endian).to_string()),
+            value,
+            confidence,
+        })
+    };
+
+    let printable = bytes
+        .iter()
+        .take_while(|byte| (0x20..0x7f).contains(*byte))
+        .count();
+    if printable > 0 {
+        // A run of text is far more telling than a single letter.
+        let confidence = if printable >= 4 { 0.8 } else { 0.6 };
+        push("ascii", None, (bytes[0] as char).to_string(), confidence);
+    }
+    push("u8", None, bytes[0].to_string(), 0.2);
+
+    for endian in [Endian::Little, Endian::Big] {
+        for (name, width) in [("u16", 2), ("u32", 4), ("u64", 8)] {
+            if bytes.len() < width {
+                continue;
+            }
+            let value = unsigned(bytes, width, endian);
+            // Counts, sizes and ids rarely use the upper half of a field.
+            if value != 0 && value >> (width * 4) == 0 {
+                let confidence = if width == 4 { 0.6 } else { 0.5 };
+                push(name, Some(endian), value.to_string(), confidence);
+            }
+            if width == 4 {
+                let signed = value as u32 as i32;
+                if (-0x10000..0).contains(&signed) {
+                    push("i32", Some(endian), signed.to_string(), 0.4);
+                }
+                // What follows a length prefix has to fit in the file.
+                if (1..=16 * 1024 * 1024).contains(&value)
+                    && (offset + 4)
+                        .checked_add(value)
+                        .is_some_and(|end| end <= file_size)
+                {
+                    push("length-prefix", Some(endian), value.to_string(), 0.3);
+                }
+                if (0x0040_0000..0x8000_0000).contains(&value) && value % 4 == 0 {
+                    push("pointer", Some(endian), format!("0x{:08x}", value), 0.3);
+                }
+            }
+            if width == 8 {
+                let user = (0x0000_5500_0000_0000..0x0000_8000_0000_0000).contains(&value);
+                let kernel = value >= 0xffff_8000_0000_0000;
+                if (user || kernel) && value % 8 == 0 {
+                    push("pointer", Some(endian), format!("0x{:016x}", value), 0.4);
+                }
+            }
+        }
+
+        let plausible = |value: f64| value.is_normal() && (1e-6..1e9).contains(&value.abs());
+        if bytes.len() >= 4 {
+            let value = f32::from_bits(unsigned(bytes, 4, endian) as u32);
+            if plausible(value as f64) {
+                push("f32", Some(endian), value.to_string(), 0.45);
+            }
+        }
+        if bytes.len() >= 8 {
+            let value = f64::from_bits(unsigned(bytes, 8, endian));
+            if plausible(value) {
+                push("f64", Some(endian), value.to_string(), 0.5);
+            }
+        }
+    }
+
+    guesses.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    guesses
+}
+
+/// Guesses what the up to 8 bytes at `offset` hold, for the inspector:
+/// printable text, small integers, ordinary floats, length prefixes that fit
+/// the file and pointer-like values, most plausible first.
+#[tauri::command]
+pub fn guess_types(
+    handle: u64,
+    offset: u64,
+    state: State<AppState>,
+) -> Result<Vec<TypeGuess>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    if offset >= file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: file_state.file_size,
+        });
+    }
+
+    let mut bytes = vec![0u8; std::cmp::min(8, file_state.file_size - offset) as usize];
+    file_state.read_exact_at(offset, &mut bytes)?;
+    Ok(guess(&bytes, offset, file_state.file_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn inspects_little_endian_u32_and_f64() {
+        let mut bytes = 0xdeadbeefu32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(-1.5f64).to_le_bytes());
+        let app = app();
+        let (_file, handle) = open_temp(&app, &bytes);
+
+        let inspect = |offset, kind: &str, endian: &str| {
+            inspect_value(
+                handle,
+                offset,
+                kind.to_string(),
+                Some(endian.to_string()),
+                None,
+                app.state(),
+            )
+        };
+        assert_eq!(inspect(0, "u32", "little").unwrap(), "3735928559");
+        assert_eq!(inspect(0, "u32", "big").unwrap(), "4022250974");
+        assert_eq!(inspect(4, "f64", "little").unwrap(), "-1.5");
+        assert!(matches!(
+            inspect(10, "u32", "little"),
+            Err(AppError::OutOfBounds {
+                requested: 14,
+                size: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn reads_and_sets_bits() {
+        let app = app();
+        let (file, handle) = open_temp(&app, &[0xff, 0b1010_0101]);
+
+        assert_eq!(
+            inspect_bits(handle, 1, app.state()).unwrap(),
+            [true, false, true, false, false, true, false, true]
+        );
+        set_bit(handle, 1, 0, false, app.state()).unwrap();
+        assert!(!inspect_bits(handle, 1, app.state()).unwrap()[0]);
+        assert_eq!(std::fs::read(file.path()).unwrap(), [0xff, 0b0010_0101]);
+
+        assert!(matches!(
+            set_bit(handle, 1, 8, true, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            set_bit(handle, 2, 0, true, app.state()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn writes_big_endian_i32_and_reads_it_back() {
+        let app = app();
+        let (file, handle) = open_temp(&app, &[0; 8]);
+        let write = |offset, kind: &str, value: &str| {
+            write_value(
+                handle,
+                offset,
+                kind.to_string(),
+                Some("big".to_string()),
+                value.to_string(),
+                app.state(),
+            )
+        };
+
+        write(2, "i32", "-123456").unwrap();
+        assert_eq!(
+            std::fs::read(file.path()).unwrap()[2..6],
+            (-123456i32).to_be_bytes()
+        );
+        let read_back = inspect_value(
+            handle,
+            2,
+            "i32".to_string(),
+            Some("big".to_string()),
+            None,
+            app.state(),
+        );
+        assert_eq!(read_back.unwrap(), "-123456");
+
+        assert!(matches!(
+            write(0, "u8", "300"),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            write(6, "i32", "1"),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn guesses_text_and_small_integers() {
+        let app = app();
+        let mut bytes = b"A\0\0\0".to_vec();
+        bytes.extend_from_slice(&[0xff; 100]);
+        let (_file, handle) = open_temp(&app, &bytes);
+
+        let guesses = guess_types(handle, 0, app.state()).unwrap();
+        let found = |kind: &str, endian: Option<&str>, value: &str| {
+            guesses.iter().any(|guess| {
+                guess.kind == kind && guess.endian.as_deref() == endian && guess.value == value
+            })
+        };
+        assert!(found("ascii", None, "A"));
+        assert!(found("u32", Some("little"), "65"));
+        // 65 bytes follow the prefix, so it could be one.
+        assert!(found("length-prefix", Some("little"), "65"));
+        // Read little endian, the eight bytes are a NaN.
+        assert!(!guesses
+            .iter()
+            .any(|guess| guess.kind == "f64" && guess.endian.as_deref() == Some("little")));
+        assert!(guesses
+            .windows(2)
+            .all(|pair| pair[0].confidence >= pair[1].confidence));
+
+        assert!(matches!(
+            guess_types(handle, 104, app.state()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn default_endian_applies_unless_overridden() {
+        let app = app();
+        let (file, handle) = open_temp(&app, &[0xff, 0xfe, 0, 0]);
+        let inspect = |kind: &str, endian: Option<&str>, signed| {
+            inspect_value(
+                handle,
+                0,
+                kind.to_string(),
+                endian.map(str::to_string),
+                signed,
+                app.state(),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(inspect("u16", None, None), "65279");
+        set_default_endian(handle, "big".to_string(), app.state()).unwrap();
+        assert_eq!(inspect("u16", None, None), "65534");
+        assert_eq!(inspect("u16", Some("little"), None), "65279");
+        assert_eq!(inspect("u16", None, Some(true)), "-2");
+        assert_eq!(inspect("i16", None, Some(false)), "65534");
+        assert_eq!(inspect("f32", None, Some(true)), inspect("f32", None, None));
+
+        write_value(
+            handle,
+            2,
+            "u16".to_string(),
+            None,
+            "258".to_string(),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap()[2..], [1, 2]);
+
+        assert!(matches!(
+            set_default_endian(handle, "middle".to_string(), app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,376 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::splice::splice;
+use crate::{lock_state, AppState, FileState};
+
+const DEFAULT_UNDO_DEPTH: usize = 1000;
+
+/// A single edit: `old_bytes` at `offset` were replaced by `new_bytes`.
+#[derive(Clone, Debug)]
+pub(crate) struct Edit {
+    pub offset: u64,
+    pub old_bytes: Vec<u8>,
+    pub new_bytes: Vec<u8>,
+}
+
+/// Undo and redo history for one file. Each entry is a group of edits,
+/// oldest first, that is undone and redone as one.
+pub(crate) struct EditJournal {
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    depth: usize,
+    /// How many transactions are open; the outermost one decides when
+    /// `pending` becomes an entry.
+    transactions: usize,
+    pending: Vec<Edit>,
+    /// Whether the open transaction made an edit too large to record, so it
+    /// cannot be undone as a whole.
+    poisoned: bool,
+}
+
+impl EditJournal {
+    pub(crate) fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth: DEFAULT_UNDO_DEPTH,
+            transactions: 0,
+            pending: Vec::new(),
+            poisoned: false,
+        }
+    }
+
+    /// Records a new edit, as part of the open transaction if there is one.
+    /// Any undone edits can no longer be redone.
+    pub(crate) fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        if self.transactions > 0 {
+            if !self.poisoned {
+                self.pending.push(edit);
+            }
+        } else {
+            self.push(vec![edit]);
+        }
+    }
+
+    /// Records edits, oldest first, that are undone and redone together.
+    pub(crate) fn record_group(&mut self, edits: Vec<Edit>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        if self.transactions > 0 {
+            if !self.poisoned {
+                self.pending.extend(edits);
+            }
+        } else {
+            self.push(edits);
+        }
+    }
+
+    /// Adds edits to the entry recorded last, for an edit made in steps that
+    /// is still undone as one.
+    pub(crate) fn extend_last(&mut self, edits: Vec<Edit>) {
+        if self.transactions > 0 {
+            if !self.poisoned {
+                self.pending.extend(edits);
+            }
+        } else if let Some(group) = self.undo.back_mut() {
+            group.extend(edits);
+        }
+    }
+
+    /// Forgets all history, for edits too large to record. An open
+    /// transaction stays open, but records nothing when it is committed.
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending.clear();
+        self.poisoned = self.transactions > 0;
+    }
+
+    fn push(&mut self, group: Vec<Edit>) {
+        self.undo.push_back(group);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+/// The region touched by an undo or redo, so the frontend can refresh it.
+#[derive(Serialize, Clone, Debug)]
+pub struct EditRange {
+    offset: u64,
+    length: u64,
+}
+
+/// Replaces the `current_length` bytes at `offset` with `replacement`,
+/// shifting the rest of the file when the lengths differ.
+fn apply_replacement(
+    file_state: &mut FileState,
+    offset: u64,
+    current_length: usize,
+    replacement: &[u8],
+) -> Result<(), AppError> {
+    splice(file_state, offset, current_length as u64, replacement)
+}
+
+fn revert(file_state: &mut FileState, edit: &Edit) -> Result<(), AppError> {
+    apply_replacement(
+        file_state,
+        edit.offset,
+        edit.new_bytes.len(),
+        &edit.old_bytes,
+    )
+}
+
+fn reapply(file_state: &mut FileState, edit: &Edit) -> Result<(), AppError> {
+    apply_replacement(
+        file_state,
+        edit.offset,
+        edit.old_bytes.len(),
+        &edit.new_bytes,
+    )
+}
+
+/// The smallest region covering every edit of `group`.
+fn edit_range(group: &[Edit]) -> EditRange {
+    let start = group.iter().map(|edit| edit.offset).min().unwrap_or(0);
+    let end = group
+        .iter()
+        .map(|edit| edit.offset + std::cmp::max(edit.old_bytes.len(), edit.new_bytes.len()) as u64)
+        .max()
+        .unwrap_or(0);
+    EditRange {
+        offset: start,
+        length: end - start,
+    }
+}
+
+fn check_no_transaction(file_state: &FileState) -> Result<(), AppError> {
+    if file_state.journal.transactions > 0 {
+        return Err(AppError::InvalidArgument(
+            "Cannot undo or redo while a transaction is open".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reverts the most recent edit, or group of edits made in one transaction.
+/// Returns `None` when there is nothing to undo.
+#[tauri::command]
+pub fn undo(handle: u64, state: State<AppState>) -> Result<Option<EditRange>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    check_no_transaction(file_state)?;
+
+    let Some(group) = file_state.journal.undo.pop_back() else {
+        return Ok(None);
+    };
+    for (i, edit) in group.iter().enumerate().rev() {
+        if let Err(e) = revert(file_state, edit) {
+            // Puts back what was already reverted, so the group stays whole.
+            for edit in &group[i + 1..] {
+                let _ = reapply(file_state, edit);
+            }
+            file_state.journal.undo.push_back(group);
+            return Err(e);
+        }
+    }
+
+    let range = edit_range(&group);
+    file_state.journal.redo.push(group);
+    Ok(Some(range))
+}
+
+/// Re-applies the most recently undone edit or group. Returns `None` when
+/// there is nothing to redo.
+#[tauri::command]
+pub fn redo(handle: u64, state: State<AppState>) -> Result<Option<EditRange>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    check_no_transaction(file_state)?;
+
+    let Some(group) = file_state.journal.redo.pop() else {
+        return Ok(None);
+    };
+    for (i, edit) in group.iter().enumerate() {
+        if let Err(e) = reapply(file_state, edit) {
+            for edit in group[..i].iter().rev() {
+                let _ = revert(file_state, edit);
+            }
+            file_state.journal.redo.push(group);
+            return Err(e);
+        }
+    }
+
+    let range = edit_range(&group);
+    file_state.journal.push(group);
+    Ok(Some(range))
+}
+
+/// Starts grouping edits, so that one `undo` reverts everything done until
+/// the matching `commit_transaction`. Transactions begun inside one join it.
+#[tauri::command]
+pub fn begin_transaction(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    file_state.journal.transactions += 1;
+    Ok(())
+}
+
+/// Ends the transaction begun last. Ending the outermost one records its
+/// edits as a single undo entry, or nothing if one of them was too large to
+/// undo.
+#[tauri::command]
+pub fn commit_transaction(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let journal = &mut app_state.get_mut(handle)?.journal;
+    if journal.transactions == 0 {
+        return Err(AppError::InvalidArgument(
+            "No transaction is open".to_string(),
+        ));
+    }
+
+    journal.transactions -= 1;
+    if journal.transactions == 0 {
+        let group = std::mem::take(&mut journal.pending);
+        if !std::mem::take(&mut journal.poisoned) && !group.is_empty() {
+            journal.push(group);
+        }
+    }
+    Ok(())
+}
+
+/// Sets how many edits or groups are kept for undo, dropping the oldest
+/// beyond that.
+#[tauri::command]
+pub fn set_undo_depth(handle: u64, depth: usize, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    file_state.journal.depth = depth;
+    file_state.journal.trim();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::splice::{fill_range, insert_bytes, MAX_UNDOABLE_EDIT};
+    use crate::test_support::{app, open_temp};
+    use crate::{open_bytes, save_file_data};
+
+    #[test]
+    fn undo_restores_and_redo_reapplies() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+        let contents = || std::fs::read(file.path()).unwrap();
+
+        save_file_data(handle, 2, b"ab".to_vec(), None, None, app.state()).unwrap();
+        save_file_data(handle, 8, b"xyz".to_vec(), Some(true), None, app.state()).unwrap();
+        assert_eq!(contents(), b"01ab4567xyz");
+
+        undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"01ab456789");
+        undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"0123456789");
+        assert!(undo(handle, app.state()).unwrap().is_none());
+
+        redo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"01ab456789");
+        redo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"01ab4567xyz");
+        assert!(redo(handle, app.state()).unwrap().is_none());
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"abcd");
+
+        save_file_data(handle, 0, b"X".to_vec(), None, None, app.state()).unwrap();
+        undo(handle, app.state()).unwrap();
+        save_file_data(handle, 1, b"Y".to_vec(), None, None, app.state()).unwrap();
+        assert!(redo(handle, app.state()).unwrap().is_none());
+    }
+
+    #[test]
+    fn undo_depth_drops_oldest_edits() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"abcd");
+
+        set_undo_depth(handle, 2, app.state()).unwrap();
+        for (offset, byte) in [(0, b'1'), (1, b'2'), (2, b'3')] {
+            save_file_data(handle, offset, vec![byte], None, None, app.state()).unwrap();
+        }
+        while undo(handle, app.state()).unwrap().is_some() {}
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"1bcd");
+    }
+
+    #[test]
+    fn one_undo_reverts_a_transaction() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+        let contents = || std::fs::read(file.path()).unwrap();
+
+        save_file_data(handle, 0, b"A".to_vec(), None, None, app.state()).unwrap();
+        begin_transaction(handle, app.state()).unwrap();
+        insert_bytes(handle, 2, b"++".to_vec(), app.state()).unwrap();
+        begin_transaction(handle, app.state()).unwrap();
+        fill_range(handle, Some(6), Some(9), b"x".to_vec(), app.state()).unwrap();
+        commit_transaction(handle, app.state()).unwrap();
+        assert!(undo(handle, app.state()).is_err());
+        commit_transaction(handle, app.state()).unwrap();
+        assert_eq!(contents(), b"A1++23xxx789");
+
+        undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"A123456789");
+        redo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"A1++23xxx789");
+        undo(handle, app.state()).unwrap().unwrap();
+        undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(contents(), b"0123456789");
+        assert!(matches!(
+            commit_transaction(handle, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_edit_poisons_the_transaction() {
+        let app = app();
+        let size = MAX_UNDOABLE_EDIT as usize + 1;
+        let handle = open_bytes(vec![0; size], app.state()).unwrap().handle;
+
+        save_file_data(handle, 0, b"A".to_vec(), None, None, app.state()).unwrap();
+        begin_transaction(handle, app.state()).unwrap();
+        save_file_data(handle, 1, b"B".to_vec(), None, None, app.state()).unwrap();
+        fill_range(
+            handle,
+            Some(0),
+            Some(size as u64),
+            b"x".to_vec(),
+            app.state(),
+        )
+        .unwrap();
+        save_file_data(handle, 2, b"C".to_vec(), None, None, app.state()).unwrap();
+        commit_transaction(handle, app.state()).unwrap();
+        assert!(undo(handle, app.state()).unwrap().is_none());
+
+        // Later edits are recorded again.
+        save_file_data(handle, 3, b"D".to_vec(), None, None, app.state()).unwrap();
+        undo(handle, app.state()).unwrap().unwrap();
+        assert!(undo(handle, app.state()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use serde::Deserialize;
+use tauri::State;
+
+use crate::error::AppError;
+use crate::inspect::ValueKind;
+use crate::tags::{now_millis, Tag};
+use crate::{lock_state, AppState};
+
+/// One field of a struct layout: `count` consecutive values of `kind`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct StructField {
+    pub name: String,
+    pub kind: String,
+    #[serde(default = "default_count")]
+    pub count: u64,
+}
+
+fn default_count() -> u64 {
+    1
+}
+
+/// Lays `fields` out back to back from `offset` and returns one tag per field,
+/// named after its kind (e.g. `u32[4]`) and displayed with the field name.
+/// The tags are not added to the file; pass them to `add_tag` to keep them.
+#[tauri::command]
+pub fn apply_struct(
+    handle: u64,
+    offset: u64,
+    fields: Vec<StructField>,
+    state: State<AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let file_size = lock_state(&state).get(handle)?.file_size;
+
+    let mut tags = Vec::with_capacity(fields.len());
+    let mut start = offset;
+    for field in &fields {
+        let kind = ValueKind::parse(&field.kind)?;
+        if field.count == 0 {
+            return Err(AppError::InvalidArgument(format!(
+                "Field '{}' must have a count of at least 1",
+                field.name
+            )));
+        }
+
+        let end = (kind.size() as u64)
+            .checked_mul(field.count)
+            .and_then(|size| start.checked_add(size))
+            .ok_or(AppError::InvalidRange {
+                start,
+                end: u64::MAX,
+            })?;
+        if end > file_size {
+            return Err(AppError::OutOfBounds {
+                requested: end,
+                size: file_size,
+            });
+        }
+
+        let name = if field.count == 1 {
+            field.kind.clone()
+        } else {
+            format!("{}[{}]", field.kind, field.count)
+        };
+        tags.push(Tag::new(start, end, name, &field.name));
+        start = end;
+    }
+    Ok(tags)
+}
+
+/// Most elements `tag_array` tags at once.
+const MAX_ARRAY_TAGS: u64 = 100_000;
+
+/// Tags an array of `count` elements of `element_size` bytes from `offset`,
+/// one tag per element named `name[0]`, `name[1]`, ..., and returns the
+/// added tags. The whole array must fit in the file and, unless
+/// `allow_overlap` is set, must not overlap an existing tag.
+#[tauri::command]
+pub fn tag_array(
+    handle: u64,
+    offset: u64,
+    element_size: u64,
+    count: u64,
+    name: String,
+    allow_overlap: Option<bool>,
+    state: State<AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    if element_size == 0 || !(1..=MAX_ARRAY_TAGS).contains(&count) {
+        return Err(AppError::InvalidArgument(format!(
+            "Arrays need elements of at least one byte and between 1 and {} elements",
+            MAX_ARRAY_TAGS
+        )));
+    }
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let end = element_size
+        .checked_mul(count)
+        .and_then(|size| offset.checked_add(size))
+        .ok_or(AppError::InvalidRange {
+            start: offset,
+            end: u64::MAX,
+        })?;
+    if end > file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: end,
+            size: file_state.file_size,
+        });
+    }
+    let array = Tag::new(offset, end, name.clone(), &name);
+    if !allow_overlap.unwrap_or(false) {
+        if let Some(existing) = file_state.tags.iter().find(|tag| tag.overlaps(&array)) {
+            return Err(AppError::TagOverlap {
+                id: existing.id,
+                name: existing.name.clone(),
+            });
+        }
+    }
+
+    let created_at = now_millis();
+    let elements: Vec<Tag> = (0..count)
+        .map(|index| {
+            let start = offset + index * element_size;
+            let element_name = format!("{}[{}]", name, index);
+            let mut tag = Tag::new(
+                start,
+                start + element_size,
+                element_name.clone(),
+                &element_name,
+            );
+            tag.id = file_state.next_tag_id + index;
+            tag.created_at = created_at;
+            tag
+        })
+        .collect();
+    file_state.next_tag_id += count;
+    file_state.tags.extend(elements.iter().cloned());
+    // Stable, so the new tags land after existing ones with the same start,
+    // as `add_tag` would put them.
+    file_state.tags.sort_by_key(|tag| tag.start);
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::test_support::app;
+
+    fn field(name: &str, kind: &str, count: u64) -> StructField {
+        StructField {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn fields_are_contiguous_and_sized() {
+        let app = app();
+        let handle = open_bytes(vec![0; 32], app.state()).unwrap().handle;
+        let fields = vec![
+            field("magic", "u32", 1),
+            field("flags", "u16", 1),
+            field("entries", "u8", 6),
+        ];
+
+        let tags = apply_struct(handle, 4, fields, app.state()).unwrap();
+        let laid_out: Vec<(u64, u64, &str, &str)> = tags
+            .iter()
+            .map(|tag| {
+                (
+                    tag.start,
+                    tag.end,
+                    tag.name.as_str(),
+                    tag.display_name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            laid_out,
+            vec![
+                (4, 8, "u32", "magic"),
+                (8, 10, "u16", "flags"),
+                (10, 16, "u8[6]", "entries"),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_past_end_of_file_fails() {
+        let app = app();
+        let handle = open_bytes(vec![0; 8], app.state()).unwrap().handle;
+        let fields = vec![field("a", "u32", 1), field("b", "u64", 1)];
+        assert!(matches!(
+            apply_struct(handle, 0, fields, app.state()),
+            Err(AppError::OutOfBounds {
+                requested: 12,
+                size: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn tags_arrays_element_by_element() {
+        let app = app();
+        let handle = open_bytes(vec![0; 64], app.state()).unwrap().handle;
+        let tags = tag_array(handle, 8, 12, 3, "entry".to_string(), None, app.state()).unwrap();
+        let elements: Vec<_> = tags
+            .iter()
+            .map(|tag| (tag.start, tag.end, tag.name.as_str()))
+            .collect();
+        assert_eq!(
+            elements,
+            [
+                (8, 20, "entry[0]"),
+                (20, 32, "entry[1]"),
+                (32, 44, "entry[2]")
+            ]
+        );
+        assert_eq!(
+            crate::tags::get_all_tags(handle, app.state())
+                .unwrap()
+                .len(),
+            3
+        );
+        let ids: Vec<_> = tags.iter().map(|tag| tag.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+
+        assert!(matches!(
+            tag_array(handle, 44, 12, 2, "past".to_string(), None, app.state()),
+            Err(AppError::OutOfBounds { requested: 68, .. })
+        ));
+        assert!(matches!(
+            tag_array(handle, 40, 4, 2, "over".to_string(), None, app.state()),
+            Err(AppError::TagOverlap { .. })
+        ));
+        assert!(matches!(
+            tag_array(handle, 0, 0, 2, "empty".to_string(), None, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
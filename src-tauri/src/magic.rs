@@ -0,0 +1,173 @@
+use tauri::State;
+
+use crate::cancel::Operation;
+use crate::error::AppError;
+use crate::formats::{region_header, run_parser, ParserRegistry};
+use crate::search::scan_signatures;
+use crate::tags::Tag;
+use crate::{lock_state, run_blocking, AppState};
+
+/// A well-known file signature.
+struct Magic {
+    /// Short identifier used as the tag name.
+    name: &'static str,
+    /// Human-readable format name used as the display name.
+    format: &'static str,
+    bytes: &'static [u8],
+    /// The only offset the signature counts at, for formats that can only
+    /// start a file.
+    only_at: Option<u64>,
+}
+
+const MAGICS: &[Magic] = &[
+    Magic {
+        name: "png",
+        format: "PNG",
+        bytes: b"\x89PNG\r\n\x1a\n",
+        only_at: None,
+    },
+    Magic {
+        name: "jpeg",
+        format: "JPEG",
+        bytes: b"\xff\xd8\xff",
+        only_at: None,
+    },
+    Magic {
+        name: "gif",
+        format: "GIF",
+        bytes: b"GIF87a",
+        only_at: None,
+    },
+    Magic {
+        name: "gif",
+        format: "GIF",
+        bytes: b"GIF89a",
+        only_at: None,
+    },
+    Magic {
+        name: "zip",
+        format: "ZIP",
+        bytes: b"PK\x03\x04",
+        only_at: None,
+    },
+    Magic {
+        name: "gzip",
+        format: "GZIP",
+        bytes: b"\x1f\x8b\x08",
+        only_at: None,
+    },
+    Magic {
+        name: "7z",
+        format: "7-Zip",
+        bytes: b"7z\xbc\xaf\x27\x1c",
+        only_at: None,
+    },
+    Magic {
+        name: "rar",
+        format: "RAR",
+        bytes: b"Rar!\x1a\x07",
+        only_at: None,
+    },
+    Magic {
+        name: "pdf",
+        format: "PDF",
+        bytes: b"%PDF-",
+        only_at: None,
+    },
+    Magic {
+        name: "elf",
+        format: "ELF",
+        bytes: b"\x7fELF",
+        only_at: None,
+    },
+    // `MZ` is far too common to mean anything away from the start.
+    Magic {
+        name: "pe",
+        format: "PE/MZ",
+        bytes: b"MZ",
+        only_at: Some(0),
+    },
+];
+
+/// Scans the file for the signatures of common formats and returns a tag
+/// covering each one found, along with the tags of every registered format
+/// parser that recognizes the start of the file, ordered by offset. The tags
+/// are not added to the file. Can be stopped through `operation_id`, and gives
+/// up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn scan_magic(
+    handle: u64,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    parsers: State<'_, ParserRegistry>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let reader = lock_state(&state).get(handle)?.reader();
+    let parsers = parsers.parsers();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let patterns: Vec<&[u8]> = MAGICS.iter().map(|magic| magic.bytes).collect();
+        let found = scan_signatures(&reader, &patterns, &token)?;
+        let mut tags: Vec<Tag> = found
+            .into_iter()
+            .filter_map(|(index, offset)| {
+                let magic = &MAGICS[index];
+                if magic.only_at.is_some_and(|only_at| only_at != offset) {
+                    return None;
+                }
+                let end = offset + magic.bytes.len() as u64;
+                Some(Tag::new(offset, end, magic.name.to_string(), magic.format))
+            })
+            .collect();
+
+        let header = region_header(&reader, 0)?;
+        for parser in parsers.iter().filter(|parser| parser.detect(&header)) {
+            token.check()?;
+            tags.extend(run_parser(parser.as_ref(), &reader, 0));
+        }
+        tags.sort_by_key(|tag| tag.start);
+        Ok(tags)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::test_support::app;
+
+    #[test]
+    fn tags_embedded_png_and_elf_headers() {
+        let app = app();
+        let mut bytes = vec![0u8; 64];
+        bytes[8..16].copy_from_slice(b"\x89PNG\r\n\x1a\n");
+        bytes[40..44].copy_from_slice(b"\x7fELF");
+        // Only counts at offset 0.
+        bytes[50..52].copy_from_slice(b"MZ");
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+
+        let found: Vec<_> = tauri::async_runtime::block_on(scan_magic(
+            handle,
+            None,
+            None,
+            app.state(),
+            app.state(),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|tag| (tag.start, tag.end, tag.name, tag.display_name))
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                (8, 16, "png".to_string(), "PNG".to_string()),
+                (40, 44, "elf".to_string(), "ELF".to_string()),
+            ]
+        );
+    }
+}
@@ -1,137 +1,2180 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{self, Read, Seek, SeekFrom};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
-use tauri::State;
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct Tag {
-    start: u64,
-    end: u64,
-    name: String,
-    display_name: String,
-    color: Option<String>,
-}
+mod atomic;
+mod benchmark;
+mod bookmarks;
+mod cache;
+mod cancel;
+mod command_log;
+mod device;
+mod diff;
+mod disasm;
+mod encoding;
+mod error;
+mod export;
+mod formats;
+mod goto;
+mod gzip;
+mod hash;
+mod hexdump;
+mod inflate;
+mod inspect;
+mod journal;
+mod layout;
+mod magic;
+mod overview;
+mod palette;
+mod patch;
+mod reader;
+mod recent;
+mod search;
+mod selection;
+mod sidecar;
+mod snapshot;
+mod splice;
+mod stats;
+mod stream;
+mod strings;
+mod tags;
+mod template;
+#[cfg(test)]
+mod test_support;
+mod transform;
+mod watch;
+mod workspace;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use tauri::{AppHandle, Runtime, State};
+
+use atomic::SaveMode;
+use bookmarks::Bookmark;
+use cache::{BlockCache, ReadAhead};
+use error::AppError;
+use inspect::Endian;
+use journal::{Edit, EditJournal};
+use reader::{to_usize, Backing, FileReader, Segment};
+use tags::Tag;
+use transform::Transform;
 
 struct FileState {
+    /// Path of the file, or of the first part of a split file. `None` for
+    /// files opened from memory.
     file_path: Option<PathBuf>,
-    file: Option<File>,
+    /// The path as passed to `open_file`, before `file_path` was resolved
+    /// from it.
+    given_path: Option<PathBuf>,
+    backing: Backing,
+    /// Held for writing while the file's bytes change and for reading by
+    /// every [`FileReader`] read, so reads never see a half-applied edit.
+    io_lock: Arc<RwLock<()>>,
+    /// Read-only mapping of the file, used for reads when present.
+    #[cfg(feature = "mmap")]
+    mmap: Option<Arc<reader::Mapping>>,
     file_size: u64,
+    read_only: bool,
     tags: Vec<Tag>,
+    next_tag_id: u64,
+    /// Tag categories hidden with `set_category_visible`.
+    hidden_categories: HashSet<String>,
+    bookmarks: Vec<Bookmark>,
+    next_bookmark_id: u64,
+    /// Current `[start, end)` selections, ordered and never overlapping, used
+    /// when a range command is given no range.
+    selections: Vec<(u64, u64)>,
+    /// Locations visited with `push_location`, oldest first, and the index
+    /// of the current one for `nav_back` and `nav_forward`.
+    nav_history: Vec<u64>,
+    nav_index: usize,
+    journal: EditJournal,
+    /// Checkpoints taken with `create_snapshot`.
+    snapshots: snapshot::Snapshots,
+    /// Where the next chunk of each `save_file_data_chunk` session goes.
+    write_sessions: HashMap<u64, u64>,
+    next_write_session: u64,
+    /// Reads are widened to multiples of this many bytes. Always a power of
+    /// two; only multiples of the cache block size are cached.
+    alignment: u64,
+    /// Totals of `get_file_data` reads while enabled with `set_io_stats`.
+    io_stats: Option<IoStats>,
+    /// Longest read `get_file_data` serves, so a bad request fails instead
+    /// of allocating the whole range.
+    max_read_bytes: u64,
+    /// Used by the inspector when a call gives no endianness, set with
+    /// `set_default_endian`.
+    default_endian: Endian,
+    /// Decoding applied by `get_file_data`, set with `set_transform`.
+    transform: Option<Arc<Transform>>,
+    /// Recently read blocks as returned by `get_file_data`, kept in step with
+    /// every write.
+    cache: BlockCache,
+    /// Prefetching of the blocks after sequential reads into `cache`.
+    read_ahead: ReadAhead,
+    /// Templates re-run when an edit touches a field they depend on.
+    live_templates: Vec<template::LiveTemplate>,
+    next_live_template: u64,
+    /// Whether growth is reported as `file-grew`, for files opened with
+    /// `tail`.
+    tail: bool,
+    /// The last fingerprint taken and the cache generation it was taken at,
+    /// so an edit since makes it stale.
+    fingerprint: Option<(u64, hash::Fingerprint)>,
+    /// Reports external modifications; dropping it stops watching.
+    watcher: Option<notify::RecommendedWatcher>,
+    /// How the file looked right after the app's own last write, so the
+    /// watcher can tell the events it causes from external changes.
+    own_write: Option<watch::Stamp>,
 }
 
 impl FileState {
-    fn new() -> Self {
+    fn new(file_path: Option<PathBuf>, backing: Backing, file_size: u64, read_only: bool) -> Self {
         Self {
-            file_path: None,
-            file: None,
-            file_size: 0,
+            file_path,
+            given_path: None,
+            backing,
+            io_lock: Arc::default(),
+            #[cfg(feature = "mmap")]
+            mmap: None,
+            file_size,
+            read_only,
             tags: Vec::new(),
+            next_tag_id: 1,
+            hidden_categories: HashSet::new(),
+            bookmarks: Vec::new(),
+            next_bookmark_id: 1,
+            selections: Vec::new(),
+            nav_history: Vec::new(),
+            nav_index: 0,
+            journal: EditJournal::new(),
+            snapshots: snapshot::Snapshots::new(),
+            write_sessions: HashMap::new(),
+            next_write_session: 1,
+            alignment: cache::BLOCK_SIZE,
+            io_stats: None,
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+            default_endian: Endian::Little,
+            transform: None,
+            cache: BlockCache::new(),
+            read_ahead: ReadAhead::new(),
+            live_templates: Vec::new(),
+            next_live_template: 1,
+            tail: false,
+            fingerprint: None,
+            watcher: None,
+            own_write: None,
+        }
+    }
+
+    /// Snapshot of the file for reading outside the state lock.
+    fn reader(&self) -> FileReader {
+        FileReader {
+            backing: self.backing.clone(),
+            io_lock: Arc::clone(&self.io_lock),
+            #[cfg(feature = "mmap")]
+            mmap: self.mmap.clone(),
+            transform: None,
+            file_size: self.file_size,
+        }
+    }
+
+    /// Like `reader`, but reads through the file's transform.
+    fn view_reader(&self) -> FileReader {
+        FileReader {
+            transform: self.transform.clone(),
+            ..self.reader()
+        }
+    }
+
+    /// Resolves an optional range argument, defaulting to the selection.
+    /// A given range is taken relative to `anchor`, as in [`anchored_range`].
+    /// Commands that work on one range need the range spelled out while
+    /// several are selected.
+    fn range_or_selection(
+        &self,
+        start: Option<u64>,
+        end: Option<u64>,
+        anchor: Option<&str>,
+    ) -> Result<(u64, u64), AppError> {
+        match self.ranges_or_selections(start, end, anchor)?[..] {
+            [range] => Ok(range),
+            _ => Err(AppError::InvalidArgument(
+                "Several ranges are selected; give a range explicitly".to_string(),
+            )),
+        }
+    }
+
+    /// Like `range_or_selection`, defaulting to every selection in order.
+    fn ranges_or_selections(
+        &self,
+        start: Option<u64>,
+        end: Option<u64>,
+        anchor: Option<&str>,
+    ) -> Result<Vec<(u64, u64)>, AppError> {
+        match (start, end) {
+            (Some(start), Some(end)) => {
+                Ok(vec![anchored_range(self.file_size, start, end, anchor)?])
+            }
+            (None, None) if self.selections.is_empty() => Err(AppError::InvalidArgument(
+                "No range given and nothing is selected".to_string(),
+            )),
+            (None, None) => Ok(self.selections.clone()),
+            _ => Err(AppError::InvalidArgument(
+                "Range needs both a start and an end".to_string(),
+            )),
+        }
+    }
+
+    /// Fills `buf` from `offset`, failing if the file ends first.
+    fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), AppError> {
+        self.reader().read_exact_at(offset, buf)
+    }
+
+    /// Drops the mapping, also for readers that still hold it, before an edit
+    /// changes the layout of the file.
+    #[cfg(feature = "mmap")]
+    fn unmap(&mut self) {
+        if let Some(mapping) = self.mmap.take() {
+            let _guard = self.io_lock.write().unwrap_or_else(PoisonError::into_inner);
+            mapping.revoke();
+        }
+    }
+
+    /// Writes `bytes` at `offset` and flushes, growing `file_size` if the
+    /// write extends past the current end.
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) -> Result<(), AppError> {
+        snapshot::record_edit(self, offset, bytes.len() as u64, bytes.len() as u64)?;
+        self.cache
+            .invalidate(offset, offset.saturating_add(bytes.len() as u64));
+        match &mut self.backing {
+            Backing::File(file) => {
+                let _guard = self.io_lock.write().unwrap_or_else(PoisonError::into_inner);
+                reader::write_all_at(file, bytes, offset)?;
+                file.as_ref()
+                    .flush()
+                    .map_err(|e| AppError::Io(format!("Failed to flush file data: {}", e)))?;
+                self.own_write = watch::stamp(file);
+            }
+            Backing::Memory(buffer) => {
+                let buffer = Arc::make_mut(buffer);
+                let offset = to_usize(offset)?;
+                let end = offset + bytes.len();
+                if end > buffer.len() {
+                    buffer.resize(end, 0);
+                }
+                buffer[offset..end].copy_from_slice(bytes);
+            }
+            Backing::Split(_) | Backing::Gzip(_) => return Err(AppError::ReadOnly),
+        }
+
+        let end = offset + bytes.len() as u64;
+        if end > self.file_size {
+            self.file_size = end;
+        }
+        self.rerun_live_templates(offset, end);
+        Ok(())
+    }
+
+    /// Lets live templates re-parse after `[start, end)` changed, or after an
+    /// edit moved them.
+    pub(crate) fn rerun_live_templates(&mut self, start: u64, end: u64) {
+        if self.live_templates.is_empty() {
+            return;
+        }
+        let reader = self.reader();
+        for template in &mut self.live_templates {
+            template.edited(&reader, start, end);
+        }
+    }
+}
+
+/// Finishes opening file `handle` once it has a handle: remembers it as
+/// recent and starts watching it. Needs the app handle, which is why it is
+/// kept as a closure for deferred files.
+type OnOpen = Box<dyn Fn(u64, &mut FileState) + Send>;
+
+/// A handle from `open_file_deferred`, opened as `open_file` would on first
+/// use.
+struct Deferred {
+    given_path: PathBuf,
+    options: OpenArgs,
+    on_open: OnOpen,
+}
+
+/// Every open file, keyed by the handle returned from `open_file`.
+struct OpenFiles {
+    files: HashMap<u64, FileState>,
+    /// Handles from `open_file_deferred` that have not been used yet.
+    deferred: HashMap<u64, Deferred>,
+    next_handle: u64,
+    next_stream_id: u64,
+    /// Cancel flags of running operations, keyed by operation id.
+    operations: HashMap<u64, cancel::CancelToken>,
+}
+
+impl OpenFiles {
+    fn new() -> Self {
+        Self {
+            files: HashMap::new(),
+            deferred: HashMap::new(),
+            next_handle: 1,
+            next_stream_id: 1,
+            operations: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, handle: u64) -> Result<&FileState, AppError> {
+        self.get_mut(handle).map(|file_state| &*file_state)
+    }
+
+    /// Opening a handle from `open_file_deferred` happens here, on first use.
+    fn get_mut(&mut self, handle: u64) -> Result<&mut FileState, AppError> {
+        if let Some(deferred) = self.deferred.get(&handle) {
+            let options = &deferred.options;
+            let mut file_state = open_file_state(deferred.given_path.clone(), options)?;
+            (deferred.on_open)(handle, &mut file_state);
+            self.deferred.remove(&handle);
+            self.files.insert(handle, file_state);
+        }
+        self.files
+            .get_mut(&handle)
+            .ok_or(AppError::FileNotOpen(handle))
+    }
+}
+
+/// Shared state behind every command.
+///
+/// Commands hold the lock only for bookkeeping. Long reads such as
+/// `get_file_data`, `find_bytes` and `hash_range` take a [`FileReader`]
+/// snapshot, drop the lock and do their I/O on a blocking worker thread, so
+/// they never stall other commands or the UI. Edits hold the file's
+/// `io_lock` while its bytes change, so each read sees the file either
+/// before or after an edit; a read made of several chunks may still see an
+/// edit land between two of them.
+type AppState = Arc<Mutex<OpenFiles>>;
+
+/// Locks the shared state. A command that panics while holding the lock
+/// poisons it; the open files are still there, so later commands carry on
+/// with them instead of panicking in turn.
+fn lock_state(state: &AppState) -> MutexGuard<'_, OpenFiles> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `task` on the blocking thread pool and waits for its result.
+async fn run_blocking<T, F>(task: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, AppError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(task)
+        .await
+        .map_err(|e| AppError::Internal(format!("Background task failed: {}", e)))?
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct OpenedFile {
+    handle: u64,
+    file_size: u64,
+}
+
+/// Resolves `path` to an absolute path free of symlinks and `..`, checking
+/// that it names something other than a directory. Relative paths resolve
+/// against the working directory.
+fn resolve_path(path: &Path) -> Result<PathBuf, AppError> {
+    let resolved = std::fs::canonicalize(path)
+        .map_err(|e| AppError::Io(format!("Failed to open file {}: {}", path.display(), e)))?;
+    if resolved.is_dir() {
+        return Err(AppError::InvalidArgument(format!(
+            "{} is a directory, not a file",
+            path.display()
+        )));
+    }
+    Ok(resolved)
+}
+
+/// Opens `path` for reading and, where permitted, writing, and returns the
+/// file and whether it is read-only.
+fn open_path(path: &Path) -> Result<(File, bool), AppError> {
+    // Prefer read-write so edits can be saved, but still allow inspecting
+    // files we are not permitted to modify.
+    match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => Ok((file, false)),
+        Err(_) => {
+            let file = File::open(path).map_err(|e| {
+                AppError::Io(format!("Failed to open file {}: {}", path.display(), e))
+            })?;
+            Ok((file, true))
         }
     }
 }
 
-type AppState = Arc<Mutex<FileState>>;
+/// Opens `path`, read-only if it cannot be written. External changes are
+/// reported as `file-changed`; with `tail`, the file is followed as it grows,
+/// as a log being written, and each growth is also reported as `file-grew`.
+#[tauri::command]
+fn open_file<R: Runtime>(
+    path: String,
+    use_mmap: Option<bool>,
+    gzip: Option<bool>,
+    tail: Option<bool>,
+    app: AppHandle<R>,
+    state: State<AppState>,
+) -> Result<OpenedFile, AppError> {
+    open_given_path(PathBuf::from(path), use_mmap, gzip, tail, app, state)
+}
 
+/// Like `open_file`, for paths that are not valid UTF-8 and so cannot be
+/// passed as a string. On Unix `path_bytes` are the path's raw bytes, as
+/// returned in `file_info`'s `path_bytes`; elsewhere they must be UTF-8.
 #[tauri::command]
-fn open_file(path: String, state: State<AppState>) -> Result<u64, String> {
-    let path = PathBuf::from(path);
+fn open_file_os<R: Runtime>(
+    path_bytes: Vec<u8>,
+    use_mmap: Option<bool>,
+    gzip: Option<bool>,
+    tail: Option<bool>,
+    app: AppHandle<R>,
+    state: State<AppState>,
+) -> Result<OpenedFile, AppError> {
+    open_given_path(
+        path_from_bytes(path_bytes)?,
+        use_mmap,
+        gzip,
+        tail,
+        app,
+        state,
+    )
+}
 
-    let file = File::open(&path).map_err(|e| format!("Failed to open file: {}", e))?;
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf, AppError> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
 
-    let metadata = file
-        .metadata()
-        .map_err(|e| format!("Failed to read metadata: {}", e))?;
-    let file_size = metadata.len();
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf, AppError> {
+    String::from_utf8(bytes)
+        .map(PathBuf::from)
+        .map_err(|_| AppError::InvalidArgument("Path is not valid UTF-8".to_string()))
+}
 
-    let mut app_state = state.lock().unwrap();
-    app_state.file_path = Some(path);
-    app_state.file = Some(file);
-    app_state.file_size = file_size;
-    app_state.tags.clear();
-    Ok(file_size)
+/// The optional arguments of `open_file`.
+struct OpenArgs {
+    use_mmap: Option<bool>,
+    gzip: Option<bool>,
+    tail: Option<bool>,
 }
 
+fn open_given_path<R: Runtime>(
+    given_path: PathBuf,
+    use_mmap: Option<bool>,
+    gzip: Option<bool>,
+    tail: Option<bool>,
+    app: AppHandle<R>,
+    state: State<AppState>,
+) -> Result<OpenedFile, AppError> {
+    let options = OpenArgs {
+        use_mmap,
+        gzip,
+        tail,
+    };
+    let mut file_state = open_file_state(given_path, &options)?;
+    let file_size = file_state.file_size;
+
+    let mut app_state = lock_state(&state);
+    let handle = app_state.next_handle;
+    app_state.next_handle += 1;
+    finish_open(&app, state.inner(), handle, &mut file_state);
+    app_state.files.insert(handle, file_state);
+    Ok(OpenedFile { handle, file_size })
+}
+
+/// Opens the file at `given_path` for `open_file` or a deferred handle's
+/// first use, ready to be given a handle.
+fn open_file_state(given_path: PathBuf, options: &OpenArgs) -> Result<FileState, AppError> {
+    let path = resolve_path(&given_path)?;
+    let (file, read_only) = open_path(&path)?;
+    let file_size = device::file_size(&file)?;
+
+    let file = Arc::new(file);
+    let gzip = options.gzip.unwrap_or(false);
+    let (backing, file_size, read_only) = if gzip {
+        // Saving would mean recompressing the whole file, so gzip files are
+        // only inspected.
+        let index = gzip::GzipIndex::build(Arc::clone(&file), file_size)?;
+        let size = index.size();
+        (Backing::Gzip(Arc::new(index)), size, true)
+    } else {
+        (Backing::File(Arc::clone(&file)), file_size, read_only)
+    };
+    let mut file_state = FileState::new(Some(path), backing, file_size, read_only);
+    file_state.given_path = Some(given_path);
+    file_state.tail = options.tail.unwrap_or(false);
+    #[cfg(feature = "mmap")]
+    if options.use_mmap.unwrap_or(false) && !gzip && file_size > 0 {
+        // Mapping can fail (e.g. on some network filesystems); plain reads
+        // still work in that case.
+        // SAFETY: the mapping is only read through `mapped_slice`, which stays
+        // within its length, and is revoked before any edit shrinks the file.
+        // Truncation by another process is the usual mmap caveat and the
+        // reason mapping is opt-in.
+        file_state.mmap = unsafe { memmap2::Mmap::map(file.as_ref()) }
+            .ok()
+            .map(|mmap| Arc::new(reader::Mapping::new(mmap)));
+    }
+    #[cfg(not(feature = "mmap"))]
+    let _ = options.use_mmap;
+    Ok(file_state)
+}
+
+/// See [`OnOpen`].
+fn finish_open<R: Runtime>(
+    app: &AppHandle<R>,
+    state: &AppState,
+    handle: u64,
+    file_state: &mut FileState,
+) {
+    let Some(path) = file_state.file_path.clone() else {
+        return;
+    };
+    recent::remember(app, &path);
+    // Watching is best effort; without it external changes go unnoticed.
+    file_state.watcher = watch::watch_file(app.clone(), state, handle, &path).ok();
+}
+
+/// Returns a handle for `path` without opening it, for files another step
+/// has yet to write. The file is opened the first time the handle is used,
+/// as `open_file` would with the same arguments, and that use fails with an
+/// I/O error if it is still missing; later uses retry.
 #[tauri::command]
-fn get_file_data(start: u64, end: u64, state: State<AppState>) -> Result<Vec<u8>, String> {
-    let mut app_state = state.lock().unwrap();
+fn open_file_deferred<R: Runtime>(
+    path: String,
+    use_mmap: Option<bool>,
+    gzip: Option<bool>,
+    tail: Option<bool>,
+    app: AppHandle<R>,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    // A weak reference avoids a cycle through the deferred handle stored in
+    // the state.
+    let weak_state = Arc::downgrade(state.inner());
+    let on_open: OnOpen = Box::new(move |handle, file_state| {
+        if let Some(state) = weak_state.upgrade() {
+            finish_open(&app, &state, handle, file_state);
+        }
+    });
+    let deferred = Deferred {
+        given_path: PathBuf::from(path),
+        options: OpenArgs {
+            use_mmap,
+            gzip,
+            tail,
+        },
+        on_open,
+    };
+    let mut app_state = lock_state(&state);
+    let handle = app_state.next_handle;
+    app_state.next_handle += 1;
+    app_state.deferred.insert(handle, deferred);
+    Ok(handle)
+}
 
-    if end <= start {
-        return Err("End position must be greater than start position".to_string());
+/// Opens `paths` (e.g. `img.001`, `img.002`, ...) as one read-only file made of
+/// the parts laid end to end in the order given.
+#[tauri::command]
+fn open_split(paths: Vec<String>, state: State<AppState>) -> Result<OpenedFile, AppError> {
+    if paths.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Split file needs at least one part".to_string(),
+        ));
     }
-    if app_state.file.is_none() {
-        return Err("No file is currently open".to_string());
+
+    let mut segments = Vec::with_capacity(paths.len());
+    let mut file_size = 0u64;
+    for path in paths {
+        let path = resolve_path(Path::new(&path))?;
+        let file = File::open(&path)
+            .map_err(|e| AppError::Io(format!("Failed to open file {}: {}", path.display(), e)))?;
+        let size = device::file_size(&file)?;
+        segments.push(Segment {
+            path,
+            file: Arc::new(file),
+            start: file_size,
+            size,
+        });
+        file_size += size;
     }
-    let file_size = app_state.file_size;
-    if start >= file_size {
-        return Err(format!(
-            "Start position {} exceeds file size {}",
-            start, file_size
-        ));
+
+    let file_state = FileState::new(
+        Some(segments[0].path.clone()),
+        Backing::Split(Arc::new(segments)),
+        file_size,
+        true,
+    );
+
+    let mut app_state = lock_state(&state);
+    let handle = app_state.next_handle;
+    app_state.next_handle += 1;
+    app_state.files.insert(handle, file_state);
+    Ok(OpenedFile { handle, file_size })
+}
+
+/// Opens `data` as an in-memory file. Edits change the buffer only; there is
+/// nothing on disk to save to.
+#[tauri::command]
+fn open_bytes(data: Vec<u8>, state: State<AppState>) -> Result<OpenedFile, AppError> {
+    let file_size = data.len() as u64;
+    let file_state = FileState::new(None, Backing::Memory(Arc::new(data)), file_size, false);
+
+    let mut app_state = lock_state(&state);
+    let handle = app_state.next_handle;
+    app_state.next_handle += 1;
+    app_state.files.insert(handle, file_state);
+    Ok(OpenedFile { handle, file_size })
+}
+
+#[tauri::command]
+fn close_file(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let (file_state, deferred) = {
+        let mut app_state = lock_state(&state);
+        (
+            app_state.files.remove(&handle),
+            app_state.deferred.remove(&handle),
+        )
+    };
+    // Dropped outside the lock so the file's watcher can shut down cleanly.
+    match (file_state, deferred) {
+        (None, None) => Err(AppError::FileNotOpen(handle)),
+        _ => Ok(()),
     }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct FileInfo {
+    /// Absolute and with symlinks resolved; `None` for files opened from
+    /// memory.
+    path: Option<String>,
+    /// `path` as raw bytes, which unlike `path` keeps names that are not
+    /// valid UTF-8 intact. Can be passed to `open_file_os`.
+    path_bytes: Option<Vec<u8>>,
+    /// The path as given when opening, which may be relative or a symlink.
+    given_path: Option<String>,
+    size: u64,
+    read_only: bool,
+    tag_count: usize,
+}
 
-    let end = std::cmp::min(end, file_size);
-    const ALIGNMENT: u64 = 512;
+/// Describes an open file, so the frontend does not have to cache it.
+#[tauri::command]
+fn file_info(handle: u64, state: State<AppState>) -> Result<FileInfo, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    Ok(FileInfo {
+        path: file_state
+            .file_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned()),
+        path_bytes: file_state
+            .file_path
+            .as_ref()
+            .map(|path| path.as_os_str().as_encoded_bytes().to_vec()),
+        given_path: file_state
+            .given_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned()),
+        size: file_state.file_size,
+        read_only: file_state.read_only,
+        tag_count: file_state.tags.len(),
+    })
+}
 
-    let aligned_start = (start / ALIGNMENT) * ALIGNMENT;
+/// What this build of the backend can do, so the frontend can hide what is
+/// missing.
+#[derive(Serialize, Clone, Debug)]
+struct Capabilities {
+    version: String,
+    /// Memory-mapped reads through `open_file`'s `use_mmap`.
+    mmap: bool,
+    /// `disassemble`.
+    disasm: bool,
+    /// `render_overview`.
+    overview: bool,
+    /// Gzip files through `open_file`'s `gzip`, and `decompress_range`.
+    /// Always built in.
+    compression: bool,
+}
 
-    let aligned_end = if end % ALIGNMENT == 0 {
-        end
-    } else {
-        ((end / ALIGNMENT) + 1) * ALIGNMENT
+/// Reports the optional features compiled into the backend.
+#[tauri::command]
+fn capabilities() -> Result<Capabilities, AppError> {
+    Ok(Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        mmap: cfg!(feature = "mmap"),
+        disasm: cfg!(feature = "disasm"),
+        overview: cfg!(feature = "overview"),
+        compression: true,
+    })
+}
+
+/// Converts `[start, end)` to absolute offsets. Offsets count from the start
+/// of the file, or back from its end when `anchor` is `"end"`, so
+/// `(16, 0, "end")` is the last 16 bytes.
+fn anchored_range(
+    file_size: u64,
+    start: u64,
+    end: u64,
+    anchor: Option<&str>,
+) -> Result<(u64, u64), AppError> {
+    match anchor.unwrap_or("start") {
+        "start" => Ok((start, end)),
+        "end" => {
+            let from_end = |distance: u64| {
+                file_size
+                    .checked_sub(distance)
+                    .ok_or(AppError::OutOfBounds {
+                        requested: distance,
+                        size: file_size,
+                    })
+            };
+            Ok((from_end(start)?, from_end(end)?))
+        }
+        other => Err(AppError::InvalidArgument(format!(
+            "Unsupported anchor '{}', expected 'start' or 'end'",
+            other
+        ))),
+    }
+}
+
+/// Explains a read of file `handle` that ended early: if the file is now
+/// smaller than `expected_size`, its size is refreshed and `FileShrunk`
+/// returned, otherwise `UnexpectedEof`.
+fn shrunk_or_eof(state: &AppState, handle: u64, expected_size: u64) -> AppError {
+    match watch::refresh_size(state, handle) {
+        Some(new_size) if new_size < expected_size => AppError::FileShrunk { new_size },
+        _ => AppError::UnexpectedEof,
+    }
+}
+
+#[tauri::command]
+async fn get_file_data(
+    handle: u64,
+    start: u64,
+    end: u64,
+    anchor: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, AppError> {
+    let (reader, start, end, requested_length, alignment, generation, cached, prefetch) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get_mut(handle)?;
+        let reader = file_state.view_reader();
+        let (start, end) = anchored_range(reader.file_size, start, end, anchor.as_deref())?;
+        // Empty requests, including any request on an empty file, succeed
+        // with no bytes as long as they start within the file.
+        if start <= reader.file_size && (end == start || start == reader.file_size) {
+            return Ok(Vec::new());
+        }
+        let end = reader.validate_range(start, end)?;
+        if end - start > file_state.max_read_bytes {
+            return Err(AppError::ReadTooLarge {
+                requested: end - start,
+                limit: file_state.max_read_bytes,
+            });
+        }
+        let requested_length = to_usize(end - start)?;
+        let prefetch = file_state
+            .read_ahead
+            .record(start, end, reader.file_size)
+            .filter(|&(from, to)| {
+                !reader.is_mapped(from, (to - from) as usize)
+                    && !file_state.cache.is_cached(from, to)
+            });
+        // Mapped reads are already served from the page cache.
+        let cached = if reader.is_mapped(start, requested_length) {
+            None
+        } else {
+            file_state.cache.read(start, end)
+        };
+        (
+            reader,
+            start,
+            end,
+            requested_length,
+            file_state.alignment,
+            file_state.cache.generation(),
+            cached,
+            prefetch,
+        )
     };
-    let aligned_length = (aligned_end - aligned_start) as usize;
+    // Started without the lock held, which the prefetch takes to cache what
+    // it read.
+    if let Some((from, to)) = prefetch {
+        let state = state.inner().clone();
+        cache::prefetch(state, handle, reader.clone(), from, to, generation);
+    }
+    if let Some(cached) = cached {
+        record_io(&state, handle, requested_length, 0);
+        return Ok(cached);
+    }
 
-    let start_offset = (start - aligned_start) as usize;
-    let requested_length = (end - start) as usize;
+    let expected_size = reader.file_size;
+    let read = run_blocking(move || {
+        if reader.is_mapped(start, requested_length) {
+            let mut data = vec![0u8; requested_length];
+            reader.read_exact_at(start, &mut data)?;
+            return Ok((data, requested_length, None));
+        }
 
-    let file = app_state.file.as_mut().unwrap();
-    file.seek(SeekFrom::Start(aligned_start))
-        .map_err(|e| format!("Failed to seek to position {}: {}", aligned_start, e))?;
+        let aligned_start = (start / alignment) * alignment;
 
-    let mut aligned_buffer = vec![0u8; aligned_length];
-    file.read_exact(&mut aligned_buffer).map_err(|e| {
-        if e.kind() == io::ErrorKind::UnexpectedEof {
-            format!("Unexpected EOF when reading file data")
+        let aligned_end = if end % alignment == 0 {
+            end
         } else {
-            format!("Failed to read file data: {}", e)
+            ((end / alignment) + 1) * alignment
+        };
+        let aligned_length = to_usize(aligned_end - aligned_start)?;
+        // Below the alignment, which is at most 1 MiB.
+        let start_offset = (start - aligned_start) as usize;
+
+        // The aligned end can lie past the end of the file, so the last block
+        // is usually shorter than the buffer.
+        let mut aligned_buffer = vec![0u8; aligned_length];
+        let read = reader.read_at(aligned_start, &mut aligned_buffer)?;
+        if read < start_offset + requested_length {
+            return Err(AppError::UnexpectedEof);
+        }
+        aligned_buffer.truncate(read);
+
+        let data = aligned_buffer[start_offset..start_offset + requested_length].to_vec();
+        // Smaller alignments do not line up with the cache's blocks.
+        if alignment % cache::BLOCK_SIZE != 0 {
+            return Ok((data, read, None));
         }
-    })?;
+        Ok((data, read, Some((aligned_start, aligned_buffer))))
+    })
+    .await;
+    let (data, read, blocks) = match read {
+        Err(AppError::UnexpectedEof) => return Err(shrunk_or_eof(&state, handle, expected_size)),
+        read => read?,
+    };
+    record_io(&state, handle, requested_length, read);
 
-    Ok(aligned_buffer[start_offset..start_offset + requested_length].to_vec())
+    if let Some((aligned_start, blocks)) = blocks {
+        // Nothing is cached if the file was closed or edited meanwhile.
+        if let Ok(file_state) = lock_state(&state).get_mut(handle) {
+            file_state.cache.insert(aligned_start, &blocks, generation);
+        }
+    }
+    Ok(data)
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct FileDataEx {
+    bytes: Vec<u8>,
+    /// Whether the range was cut short by the end of the file.
+    truncated: bool,
+    /// Offset just past the last byte returned.
+    actual_end: u64,
 }
 
+/// Like `get_file_data`, but says when the range ran past the end of the
+/// file instead of silently clamping it.
 #[tauri::command]
-fn get_tags_in_range(start: u64, end: u64, state: State<AppState>) -> Result<Vec<Tag>, String> {
-    let app_state = state.lock().unwrap();
+async fn get_file_data_ex(
+    handle: u64,
+    start: u64,
+    end: u64,
+    anchor: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<FileDataEx, AppError> {
+    let file_size = lock_state(&state).get(handle)?.view_reader().file_size;
+    let (start, end) = anchored_range(file_size, start, end, anchor.as_deref())?;
+    let bytes = get_file_data(handle, start, end, None, state).await?;
+    let actual_end = start + bytes.len() as u64;
+    Ok(FileDataEx {
+        bytes,
+        truncated: actual_end < end,
+        actual_end,
+    })
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct RawRead {
+    bytes: Vec<u8>,
+    /// Whether the end of the file came before `length` bytes were read.
+    eof: bool,
+}
+
+/// Reads `length` bytes at `offset` exactly as asked, with none of the
+/// alignment, caching or transform `get_file_data` applies. Meant for
+/// checking the aligned path and for scripts. Fewer bytes come back only at
+/// the end of the file, with `eof` set.
+#[tauri::command]
+async fn read_raw(
+    handle: u64,
+    offset: u64,
+    length: usize,
+    state: State<'_, AppState>,
+) -> Result<RawRead, AppError> {
+    let (reader, max_read_bytes) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (file_state.reader(), file_state.max_read_bytes)
+    };
+    if offset > reader.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: reader.file_size,
+        });
+    }
+    if length as u64 > max_read_bytes {
+        return Err(AppError::ReadTooLarge {
+            requested: length as u64,
+            limit: max_read_bytes,
+        });
+    }
+
+    run_blocking(move || {
+        let mut bytes = vec![0u8; length];
+        let read = reader.read_at(offset, &mut bytes)?;
+        bytes.truncate(read);
+        Ok(RawRead {
+            bytes,
+            eof: read < length,
+        })
+    })
+    .await
+}
 
-    let tags_in_range: Vec<Tag> = app_state
-        .tags
-        .iter()
-        .filter(|tag| tag.end >= start && tag.start <= end)
-        .cloned()
-        .collect();
-    Ok(tags_in_range)
+/// Like `get_file_data`, but returns the bytes base64-encoded, which is far
+/// smaller over IPC than a JSON array of numbers.
+#[tauri::command]
+async fn get_file_data_b64(
+    handle: u64,
+    start: u64,
+    end: u64,
+    anchor: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let bytes = get_file_data(handle, start, end, anchor, state).await?;
+    Ok(BASE64_STANDARD.encode(bytes))
 }
 
+/// Ranges in a batch this close together are read in one go.
+const BATCH_MERGE_GAP: u64 = 4096;
+
+/// Longest read a batch merges ranges into.
+const MAX_BATCH_SPAN: u64 = 1024 * 1024;
+
+/// Reads several small ranges in one call, e.g. every field highlighted in
+/// the view. Ranges close together share one aligned read. Each range gets
+/// its own result, checked and clamped as by `get_file_data`, so a bad
+/// range does not fail the others.
 #[tauri::command]
-fn get_all_tags(state: State<AppState>) -> Result<Vec<Tag>, String> {
-    let app_state = state.lock().unwrap();
-    Ok(app_state.tags.clone())
+async fn get_file_data_batch(
+    handle: u64,
+    ranges: Vec<(u64, u64)>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Result<Vec<u8>, AppError>>, AppError> {
+    let (reader, alignment, max_read_bytes) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (
+            file_state.view_reader(),
+            file_state.alignment,
+            file_state.max_read_bytes,
+        )
+    };
+
+    let mut results: Vec<Option<Result<Vec<u8>, AppError>>> = vec![None; ranges.len()];
+    let mut reads = Vec::new();
+    for (i, &(start, end)) in ranges.iter().enumerate() {
+        if start <= reader.file_size && (end == start || start == reader.file_size) {
+            results[i] = Some(Ok(Vec::new()));
+            continue;
+        }
+        match reader.validate_range(start, end) {
+            Ok(end) if end - start > max_read_bytes => {
+                results[i] = Some(Err(AppError::ReadTooLarge {
+                    requested: end - start,
+                    limit: max_read_bytes,
+                }))
+            }
+            Ok(end) => reads.push((start, end, i)),
+            Err(e) => results[i] = Some(Err(e)),
+        }
+    }
+
+    run_blocking(move || {
+        reads.sort_unstable();
+        let mut reads = reads.into_iter().peekable();
+        while let Some(first) = reads.next() {
+            let span_start = first.0 - first.0 % alignment;
+            let mut span_end = first.1;
+            let mut members = vec![first];
+            while let Some(&(start, end, _)) = reads.peek() {
+                let merged_end = std::cmp::max(span_end, end);
+                if start > span_end.saturating_add(BATCH_MERGE_GAP)
+                    || merged_end - span_start > MAX_BATCH_SPAN
+                {
+                    break;
+                }
+                span_end = merged_end;
+                members.push(reads.next().unwrap());
+            }
+            let span_end =
+                std::cmp::min(span_end.div_ceil(alignment) * alignment, reader.file_size);
+
+            let mut buffer = vec![0u8; to_usize(span_end - span_start)?];
+            let read = reader.read_at(span_start, &mut buffer);
+            for (start, end, i) in members {
+                let from = to_usize(start - span_start)?;
+                let to = to_usize(end - span_start)?;
+                results[i] = Some(match read {
+                    Ok(read) if read < to => Err(AppError::UnexpectedEof),
+                    Ok(_) => Ok(buffer[from..to].to_vec()),
+                    Err(ref e) => Err(e.clone()),
+                });
+            }
+        }
+        Ok(results.into_iter().map(Option::unwrap).collect())
+    })
+    .await
+}
+
+/// Running totals of the bytes `get_file_data` was asked for and the bytes
+/// it read from the file to serve them, see `set_io_stats`.
+#[derive(Serialize, Clone, Debug, Default)]
+struct IoStats {
+    reads: u64,
+    requested_bytes: u64,
+    /// Including what alignment added. Reads served from the cache add
+    /// nothing.
+    read_bytes: u64,
+    /// `read_bytes` over `requested_bytes`; 0 before any read.
+    over_read_ratio: f64,
+}
+
+/// Adds one `get_file_data` read to the file's totals, if they are kept.
+/// Nothing is recorded if the file was closed meanwhile.
+fn record_io(state: &AppState, handle: u64, requested: usize, read: usize) {
+    if let Ok(FileState {
+        io_stats: Some(stats),
+        ..
+    }) = lock_state(state).get_mut(handle)
+    {
+        stats.reads += 1;
+        stats.requested_bytes += requested as u64;
+        stats.read_bytes += read as u64;
+    }
+}
+
+/// Starts or stops keeping totals of how much `get_file_data` reads beyond
+/// what it is asked for, which shows what the alignment costs. Stopping
+/// drops the totals.
+#[tauri::command]
+fn set_io_stats(handle: u64, enabled: bool, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if !enabled {
+        file_state.io_stats = None;
+    } else if file_state.io_stats.is_none() {
+        file_state.io_stats = Some(IoStats::default());
+    }
+    Ok(())
+}
+
+/// Returns the totals kept since `set_io_stats` or the last
+/// `reset_io_stats`, or `None` while they are not kept.
+#[tauri::command]
+fn io_stats(handle: u64, state: State<AppState>) -> Result<Option<IoStats>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(app_state.get(handle)?.io_stats.clone().map(|mut stats| {
+        if stats.requested_bytes > 0 {
+            stats.over_read_ratio = stats.read_bytes as f64 / stats.requested_bytes as f64;
+        }
+        stats
+    }))
+}
+
+/// Sets the totals kept by `set_io_stats` back to zero.
+#[tauri::command]
+fn reset_io_stats(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    if let Some(stats) = &mut app_state.get_mut(handle)?.io_stats {
+        *stats = IoStats::default();
+    }
+    Ok(())
+}
+
+/// Largest alignment accepted by `set_alignment`.
+const MAX_ALIGNMENT: u64 = 1024 * 1024;
+
+/// Sets the granularity `get_file_data` widens reads to, e.g. 4096 for
+/// drives with 4K sectors. Must be a power of two no larger than 1 MiB.
+#[tauri::command]
+fn set_alignment(handle: u64, bytes: u64, state: State<AppState>) -> Result<(), AppError> {
+    check_alignment(bytes)?;
+    lock_state(&state).get_mut(handle)?.alignment = bytes;
+    Ok(())
+}
+
+fn check_alignment(bytes: u64) -> Result<(), AppError> {
+    if !bytes.is_power_of_two() || bytes > MAX_ALIGNMENT {
+        return Err(AppError::InvalidArgument(format!(
+            "Alignment {} must be a power of two no larger than {}",
+            bytes, MAX_ALIGNMENT
+        )));
+    }
+    Ok(())
+}
+
+/// Default for the longest read `get_file_data` serves.
+const DEFAULT_MAX_READ_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Sets the longest read `get_file_data` serves for the file.
+#[tauri::command]
+fn set_max_read_bytes(handle: u64, bytes: u64, state: State<AppState>) -> Result<(), AppError> {
+    if bytes == 0 {
+        return Err(AppError::InvalidArgument(
+            "Read limit must be at least one byte".to_string(),
+        ));
+    }
+    lock_state(&state).get_mut(handle)?.max_read_bytes = bytes;
+    Ok(())
+}
+
+/// Returns where a write of `len` bytes at `start` ends, if it may happen:
+/// unless `allow_grow`, it must lie within the file.
+fn write_end(file_size: u64, start: u64, len: usize, allow_grow: bool) -> Result<u64, AppError> {
+    let end = start
+        .checked_add(len as u64)
+        .ok_or(AppError::InvalidRange {
+            start,
+            end: u64::MAX,
+        })?;
+    if start > file_size && !allow_grow {
+        return Err(AppError::OutOfBounds {
+            requested: start,
+            size: file_size,
+        });
+    }
+    if end > file_size && !allow_grow {
+        return Err(AppError::OutOfBounds {
+            requested: end,
+            size: file_size,
+        });
+    }
+    Ok(end)
+}
+
+/// Writes `bytes` at `start`, growing the file only if `allow_grow` is set.
+/// A write starting past the end zero-fills the gap before it; undoing it
+/// removes the written bytes but keeps the gap. With `save_mode` `atomic`
+/// the edit goes to a new copy of the file that is renamed over the
+/// original, so a crash mid-write cannot leave it half written; the default
+/// `in-place` writes straight into the file.
+#[tauri::command]
+fn save_file_data(
+    handle: u64,
+    start: u64,
+    bytes: Vec<u8>,
+    allow_grow: Option<bool>,
+    save_mode: Option<String>,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let save_mode = save_mode
+        .as_deref()
+        .map_or(Ok(SaveMode::InPlace), SaveMode::parse)?;
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+
+    let file_size = file_state.file_size;
+    let end = write_end(file_size, start, bytes.len(), allow_grow.unwrap_or(false))?;
+
+    let old_length = std::cmp::min(end, file_size).saturating_sub(start);
+    let mut old_bytes = vec![0u8; to_usize(old_length)?];
+    file_state.read_exact_at(start, &mut old_bytes)?;
+
+    let mut replaced = None;
+    match (save_mode, &file_state.backing, file_state.file_path.clone()) {
+        (SaveMode::Atomic, Backing::File(_), Some(path)) => {
+            snapshot::record_edit(file_state, start, bytes.len() as u64, bytes.len() as u64)?;
+            let file = atomic::replace_file(&file_state.reader(), &path, start, &bytes, || Ok(()))?;
+            // The mapping and the cache describe the replaced file.
+            #[cfg(feature = "mmap")]
+            file_state.unmap();
+            file_state.cache.clear();
+            file_state.backing = Backing::File(Arc::new(file));
+            file_state.file_size = std::cmp::max(end, file_size);
+            file_state.rerun_live_templates(start, end);
+            replaced = file_state.watcher.take().map(|watcher| (watcher, path));
+        }
+        // Files in memory have nothing on disk to corrupt.
+        _ => file_state.write_at(start, &bytes)?,
+    }
+    file_state.journal.record(Edit {
+        offset: start,
+        old_bytes,
+        new_bytes: bytes,
+    });
+    drop(app_state);
+    if let Some((watcher, path)) = replaced {
+        watch::rewatch(state.inner(), handle, watcher, &path);
+    }
+    Ok(())
+}
+
+/// Writes one chunk of an edit too large to send at once. The first chunk
+/// gives the `offset` the edit starts at and no `session_id`; the returned
+/// session id is passed with every later chunk, each written in place right
+/// after the one before. `is_final` ends the session. Chunks may grow the file
+/// only if `allow_grow` is set, and each is undone separately.
+#[tauri::command]
+fn save_file_data_chunk(
+    handle: u64,
+    session_id: Option<u64>,
+    offset: Option<u64>,
+    bytes: Vec<u8>,
+    is_final: bool,
+    allow_grow: Option<bool>,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+
+    let (session_id, start) = match session_id {
+        Some(session_id) => {
+            let start = *file_state.write_sessions.get(&session_id).ok_or_else(|| {
+                AppError::InvalidArgument(format!("No chunked save session {}", session_id))
+            })?;
+            if offset.is_some_and(|offset| offset != start) {
+                return Err(AppError::InvalidArgument(format!(
+                    "Chunk of session {} must be written at {}",
+                    session_id, start
+                )));
+            }
+            (session_id, start)
+        }
+        None => {
+            let start = offset.ok_or_else(|| {
+                AppError::InvalidArgument("The first chunk of a save needs an offset".to_string())
+            })?;
+            let session_id = file_state.next_write_session;
+            file_state.next_write_session += 1;
+            (session_id, start)
+        }
+    };
+
+    let file_size = file_state.file_size;
+    let end = write_end(file_size, start, bytes.len(), allow_grow.unwrap_or(false))?;
+    let mut old_bytes = vec![0u8; to_usize(std::cmp::min(end, file_size).saturating_sub(start))?];
+    file_state.read_exact_at(start, &mut old_bytes)?;
+    file_state.write_at(start, &bytes)?;
+    file_state.journal.record(Edit {
+        offset: start,
+        old_bytes,
+        new_bytes: bytes,
+    });
+    if is_final {
+        file_state.write_sessions.remove(&session_id);
+    } else {
+        file_state.write_sessions.insert(session_id, end);
+    }
+    Ok(session_id)
 }
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
-        .manage(Arc::new(Mutex::new(FileState::new())))
-        .invoke_handler(tauri::generate_handler![
+        .manage(Arc::new(Mutex::new(OpenFiles::new())))
+        .manage(formats::ParserRegistry::with_builtins())
+        .manage(command_log::CommandLog::new())
+        .setup(|app| {
+            recent::RecentFiles::manage(app);
+            Ok(())
+        })
+        .invoke_handler(command_log::logging(tauri::generate_handler![
+            capabilities,
             open_file,
+            open_file_os,
+            open_file_deferred,
+            open_split,
+            open_bytes,
+            close_file,
+            file_info,
             get_file_data,
-            get_tags_in_range,
-            get_all_tags,
-        ])
+            get_file_data_ex,
+            read_raw,
+            get_file_data_b64,
+            get_file_data_batch,
+            set_alignment,
+            set_io_stats,
+            io_stats,
+            reset_io_stats,
+            set_max_read_bytes,
+            save_file_data,
+            save_file_data_chunk,
+            benchmark::benchmark_read,
+            bookmarks::add_bookmark,
+            bookmarks::remove_bookmark,
+            bookmarks::list_bookmarks,
+            cache::clear_cache,
+            cache::cache_stats,
+            cache::set_cache_capacity,
+            cache::set_prefetch,
+            cancel::cancel_operation,
+            diff::diff_files,
+            diff::diff_against_baseline,
+            diff::similar_blocks,
+            diff::compare_offset,
+            disasm::disassemble,
+            encoding::detect_encoding,
+            export::export_range,
+            goto::align_offset,
+            goto::follow_pointer,
+            goto::push_location,
+            goto::nav_back,
+            goto::nav_forward,
+            goto::resolve_offset,
+            goto::offset_in_tag,
+            hash::hash_range,
+            hash::hash_file,
+            hash::file_fingerprint,
+            hash::verify_checksum,
+            hash::locate_checksum,
+            hexdump::format_hexdump,
+            hexdump::copy_range,
+            hexdump::format_annotated_dump,
+            inflate::decompress_range,
+            inspect::guess_types,
+            inspect::set_default_endian,
+            inspect::inspect_value,
+            inspect::write_value,
+            inspect::inspect_bits,
+            inspect::set_bit,
+            journal::undo,
+            journal::redo,
+            journal::set_undo_depth,
+            journal::begin_transaction,
+            journal::commit_transaction,
+            command_log::set_command_logging,
+            command_log::get_command_log,
+            command_log::dump_command_log,
+            snapshot::create_snapshot,
+            snapshot::restore_snapshot,
+            snapshot::delete_snapshot,
+            layout::apply_struct,
+            layout::tag_array,
+            magic::scan_magic,
+            recent::recent_files,
+            recent::clear_recent_files,
+            overview::render_overview,
+            patch::export_patch,
+            patch::apply_patch,
+            palette::recolor_tags,
+            palette::ensure_contrast,
+            search::find_bytes,
+            search::find_pattern,
+            search::find_bytes_backward,
+            search::find_bytes_in_tags,
+            search::find_with_context,
+            search::find_text,
+            search::find_value,
+            search::find_signatures,
+            selection::set_selection,
+            selection::get_selection,
+            selection::add_selection,
+            selection::list_selections,
+            selection::clear_selections,
+            splice::insert_bytes,
+            splice::delete_bytes,
+            splice::replace_all,
+            splice::fill_range,
+            splice::swap_endian_range,
+            splice::resize_file,
+            stats::byte_histogram,
+            stats::classify_regions,
+            stats::entropy_map,
+            stats::find_holes,
+            stats::find_runs,
+            stats::shannon_entropy,
+            stream::stream_range,
+            strings::find_strings,
+            tags::add_tag,
+            tags::dedup_tags,
+            tags::remove_tag,
+            tags::update_tag,
+            tags::update_note,
+            tags::merge_tags,
+            tags::split_tag,
+            tags::get_tags_in_range,
+            tags::tags_containing,
+            tags::tags_intersecting,
+            tags::color_map,
+            tags::set_category_visible,
+            tags::get_all_tags,
+            tags::get_tag_context,
+            tags::next_tag,
+            tags::prev_tag,
+            tags::search_tags,
+            tags::tag_stats,
+            tags::tag_tree,
+            tags::extract_fields,
+            template::apply_template,
+            template::stop_live_template,
+            transform::set_transform,
+            watch::stop_watching,
+            sidecar::save_tags,
+            sidecar::load_tags,
+            sidecar::import_tags_csv,
+            sidecar::import_tags_json,
+            sidecar::export_tags,
+            workspace::save_workspace,
+            workspace::load_workspace,
+        ]))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::test_support::{app, open, open_temp};
+
+    #[test]
+    fn save_file_data_patches_in_place() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"hello world");
+
+        save_file_data(handle, 6, b"WORLD".to_vec(), None, None, app.state()).unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"hello WORLD");
+
+        save_file_data(handle, 11, b"!".to_vec(), Some(true), None, app.state()).unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"hello WORLD!");
+    }
+
+    #[test]
+    fn save_file_data_rejects_writes_past_end() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"abc");
+
+        assert!(matches!(
+            save_file_data(handle, 2, b"xy".to_vec(), None, None, app.state()),
+            Err(AppError::OutOfBounds {
+                requested: 4,
+                size: 3
+            })
+        ));
+        assert!(matches!(
+            save_file_data(handle, 4, b"x".to_vec(), None, None, app.state()),
+            Err(AppError::OutOfBounds {
+                requested: 4,
+                size: 3
+            })
+        ));
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn handles_are_independent() {
+        let app = app();
+        let (_first, first) = open_temp(&app, b"first file");
+        let (_second, second) = open_temp(&app, b"SECOND FILE");
+        assert_ne!(first, second);
+
+        let read = |handle| {
+            tauri::async_runtime::block_on(get_file_data(handle, 0, 6, None, app.state())).unwrap()
+        };
+        assert_eq!(read(first), b"first ");
+        assert_eq!(read(second), b"SECOND");
+
+        let tag = Tag::new(0, 5, "magic".to_string(), "Magic");
+        tags::add_tag(first, tag, None, None, None, app.state()).unwrap();
+        assert_eq!(tags::get_all_tags(first, app.state()).unwrap().len(), 1);
+        assert!(tags::get_all_tags(second, app.state()).unwrap().is_empty());
+
+        close_file(first, app.state()).unwrap();
+        assert!(matches!(
+            file_info(first, app.state()),
+            Err(AppError::FileNotOpen(handle)) if handle == first
+        ));
+        assert_eq!(read(second), b"SECOND");
+    }
+
+    #[test]
+    fn reads_proceed_while_a_long_read_is_in_flight() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"concurrent");
+        // A long read holds its reader's I/O guard, but not the state lock.
+        let reader = app
+            .state::<AppState>()
+            .lock()
+            .unwrap()
+            .get(handle)
+            .unwrap()
+            .reader();
+        let in_flight = reader.io_lock.read().unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let app_handle = app.handle().clone();
+        std::thread::spawn(move || {
+            let info = file_info(handle, app_handle.state()).map(|info| info.size);
+            let data = tauri::async_runtime::block_on(get_file_data(
+                handle,
+                0,
+                4,
+                None,
+                app_handle.state(),
+            ));
+            sender.send((info, data)).unwrap();
+        });
+        let (info, data) = receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("commands blocked behind an in-flight read");
+        assert_eq!(info.unwrap(), 10);
+        assert_eq!(data.unwrap(), b"conc");
+        drop(in_flight);
+    }
+
+    #[test]
+    fn get_file_data_reads_the_unaligned_last_block() {
+        let app = app();
+        let bytes: Vec<u8> = (0..600u32).map(|i| i as u8).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+
+        for _ in 0..2 {
+            let data =
+                tauri::async_runtime::block_on(get_file_data(handle, 550, 600, None, app.state()));
+            assert_eq!(data.unwrap(), &bytes[550..600]);
+        }
+    }
+
+    #[test]
+    fn file_info_reports_size_and_tag_count() {
+        let app = app();
+        let (file, handle) = open_temp(&app, &[0; 48]);
+        for start in [0, 16] {
+            let tag = Tag::new(start, start + 8, "t".to_string(), "t");
+            tags::add_tag(handle, tag, None, None, None, app.state()).unwrap();
+        }
+
+        let info = file_info(handle, app.state()).unwrap();
+        assert_eq!((info.size, info.tag_count, info.read_only), (48, 2, false));
+        assert_eq!(
+            info.path.as_deref(),
+            Some(file.path().to_string_lossy().as_ref())
+        );
+    }
+
+    #[test]
+    fn empty_file_opens_and_reads_empty() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"");
+
+        assert_eq!(file_info(handle, app.state()).unwrap().size, 0);
+        let data = tauri::async_runtime::block_on(get_file_data(handle, 0, 0, None, app.state()));
+        assert_eq!(data.unwrap(), Vec::<u8>::new());
+        let past_end =
+            tauri::async_runtime::block_on(get_file_data(handle, 1, 2, None, app.state()));
+        assert!(matches!(past_end, Err(AppError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn reads_stay_correct_for_any_alignment() {
+        let app = app();
+        let bytes: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+
+        for alignment in [4096, 1] {
+            set_alignment(handle, alignment, app.state()).unwrap();
+            for (start, end) in [(0, 1), (4090, 4100), (8191, 10_000), (5, 9_999)] {
+                let data = tauri::async_runtime::block_on(get_file_data(
+                    handle,
+                    start,
+                    end,
+                    None,
+                    app.state(),
+                ));
+                assert_eq!(data.unwrap(), &bytes[start as usize..end as usize]);
+            }
+        }
+        for bad in [0, 3, 2 * MAX_ALIGNMENT] {
+            assert!(matches!(
+                set_alignment(handle, bad, app.state()),
+                Err(AppError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn open_bytes_reads_and_patches_in_memory() {
+        let app = app();
+        let handle = open_bytes(b"in memory".to_vec(), app.state())
+            .unwrap()
+            .handle;
+        let read = |start, end| {
+            tauri::async_runtime::block_on(get_file_data(handle, start, end, None, app.state()))
+                .unwrap()
+        };
+
+        let info = file_info(handle, app.state()).unwrap();
+        assert_eq!((info.path, info.size), (None, 9));
+        assert_eq!(read(3, 9), b"memory");
+        save_file_data(handle, 3, b"MEM".to_vec(), None, None, app.state()).unwrap();
+        assert_eq!(read(0, 9), b"in MEMory");
+        save_file_data(handle, 9, b"!".to_vec(), Some(true), None, app.state()).unwrap();
+        assert_eq!(read(0, 10), b"in MEMory!");
+    }
+
+    #[test]
+    fn get_file_data_ex_flags_reads_past_eof() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[7; 100]);
+
+        let data =
+            tauri::async_runtime::block_on(get_file_data_ex(handle, 90, 116, None, app.state()))
+                .unwrap();
+        assert_eq!(data.bytes, [7; 10]);
+        assert!(data.truncated);
+        assert_eq!(data.actual_end, 100);
+
+        let data =
+            tauri::async_runtime::block_on(get_file_data_ex(handle, 80, 90, None, app.state()))
+                .unwrap();
+        assert_eq!(
+            (data.bytes.len(), data.truncated, data.actual_end),
+            (10, false, 90)
+        );
+
+        let data =
+            tauri::async_runtime::block_on(get_file_data_ex(handle, 100, 104, None, app.state()))
+                .unwrap();
+        assert!(data.bytes.is_empty() && data.truncated);
+        assert_eq!(data.actual_end, 100);
+    }
+
+    #[test]
+    fn read_raw_matches_aligned_reads() {
+        let app = app();
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(5000).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+        set_alignment(handle, 512, app.state()).unwrap();
+
+        for (offset, length) in [(0, 1), (1, 511), (511, 2), (700, 1300), (4999, 1)] {
+            let raw = tauri::async_runtime::block_on(read_raw(handle, offset, length, app.state()))
+                .unwrap();
+            let end = offset + length as u64;
+            let aligned = tauri::async_runtime::block_on(get_file_data(
+                handle,
+                offset,
+                end,
+                None,
+                app.state(),
+            ))
+            .unwrap();
+            assert_eq!(raw.bytes, aligned, "{}+{}", offset, length);
+            assert!(!raw.eof);
+        }
+
+        let raw = tauri::async_runtime::block_on(read_raw(handle, 4990, 64, app.state())).unwrap();
+        assert_eq!((raw.bytes.as_slice(), raw.eof), (&bytes[4990..], true));
+        assert!(matches!(
+            tauri::async_runtime::block_on(read_raw(handle, 5001, 1, app.state())),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn get_file_data_b64_matches_get_file_data() {
+        let app = app();
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(10_000).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+        set_alignment(handle, 4096, app.state()).unwrap();
+
+        for (start, end) in [(0, 10_000), (3, 4099), (9_990, 10_100), (10_000, 10_000)] {
+            let plain = tauri::async_runtime::block_on(get_file_data(
+                handle,
+                start,
+                end,
+                None,
+                app.state(),
+            ))
+            .unwrap();
+            let encoded = tauri::async_runtime::block_on(get_file_data_b64(
+                handle,
+                start,
+                end,
+                None,
+                app.state(),
+            ))
+            .unwrap();
+            assert_eq!(BASE64_STANDARD.decode(encoded).unwrap(), plain);
+        }
+        assert!(matches!(
+            tauri::async_runtime::block_on(get_file_data_b64(
+                handle,
+                10_001,
+                10_002,
+                None,
+                app.state()
+            )),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn end_anchored_reads_count_back_from_eof() {
+        let app = app();
+        let bytes: Vec<u8> = (0..100u8).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+        let read = |start, end, anchor: &str| {
+            tauri::async_runtime::block_on(get_file_data(
+                handle,
+                start,
+                end,
+                Some(anchor.to_string()),
+                app.state(),
+            ))
+        };
+
+        assert_eq!(read(16, 0, "end").unwrap(), &bytes[84..]);
+        assert_eq!(read(10, 4, "end").unwrap(), &bytes[90..96]);
+        assert_eq!(read(100, 98, "end").unwrap(), &bytes[..2]);
+        assert_eq!(
+            read(101, 0, "end"),
+            Err(AppError::OutOfBounds {
+                requested: 101,
+                size: 100
+            })
+        );
+        assert!(matches!(
+            read(0, 16, "middle"),
+            Err(AppError::InvalidArgument(_))
+        ));
+
+        let data = tauri::async_runtime::block_on(get_file_data_ex(
+            handle,
+            8,
+            0,
+            Some("end".to_string()),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!((data.bytes.len(), data.truncated), (8, false));
+    }
+
+    #[test]
+    fn oversized_reads_fail_instead_of_allocating() {
+        let app = app();
+        // Sparse, so the file costs nothing on disk.
+        let file = NamedTempFile::new().unwrap();
+        file.as_file().set_len(DEFAULT_MAX_READ_BYTES * 2).unwrap();
+        let handle = open(&app, &file);
+        let read = |start, end| {
+            tauri::async_runtime::block_on(get_file_data(handle, start, end, None, app.state()))
+        };
+
+        assert_eq!(
+            read(0, u64::MAX),
+            Err(AppError::ReadTooLarge {
+                requested: DEFAULT_MAX_READ_BYTES * 2,
+                limit: DEFAULT_MAX_READ_BYTES
+            })
+        );
+
+        set_max_read_bytes(handle, 1024, app.state()).unwrap();
+        assert_eq!(read(0, 1024).unwrap().len(), 1024);
+        assert!(matches!(
+            read(0, 1025),
+            Err(AppError::ReadTooLarge { limit: 1024, .. })
+        ));
+    }
+
+    #[test]
+    fn commands_keep_working_after_a_panic_while_locked() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"still here");
+        let state = app.state::<AppState>().inner().clone();
+        let panicked = std::thread::spawn(move || {
+            let mut app_state = lock_state(&state);
+            app_state.get_mut(handle).unwrap().file_size = 5;
+            panic!("command failed while holding the lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(app.state::<AppState>().is_poisoned());
+
+        let info = file_info(handle, app.state()).unwrap();
+        assert_eq!(info.size, 5);
+        let read =
+            tauri::async_runtime::block_on(get_file_data(handle, 0, 5, None, app.state())).unwrap();
+        assert_eq!(read, b"still");
+        assert!(matches!(
+            file_info(handle + 1, app.state()),
+            Err(AppError::FileNotOpen(_))
+        ));
+    }
+
+    #[test]
+    fn edits_keep_working_after_a_panic_during_io() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"still here");
+        let reader = lock_state(app.state::<AppState>().inner())
+            .get(handle)
+            .unwrap()
+            .reader();
+        let panicked = std::thread::spawn(move || {
+            let _guard = reader.io_lock.write().unwrap();
+            panic!("write failed while holding the I/O lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+
+        save_file_data(handle, 0, b"STILL".to_vec(), None, None, app.state()).unwrap();
+        let read = tauri::async_runtime::block_on(get_file_data(handle, 0, 10, None, app.state()))
+            .unwrap();
+        assert_eq!(read, b"STILL here");
+    }
+
+    #[test]
+    fn io_stats_count_the_bytes_alignment_adds() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[1; 2000]);
+        set_alignment(handle, 512, app.state()).unwrap();
+        let read = |start, end| {
+            tauri::async_runtime::block_on(get_file_data(handle, start, end, None, app.state()))
+                .unwrap()
+        };
+        read(0, 10);
+        assert!(io_stats(handle, app.state()).unwrap().is_none());
+
+        set_io_stats(handle, true, app.state()).unwrap();
+        // Served from the block cached by the read above, then two blocks,
+        // then the short last block.
+        read(10, 20);
+        read(500, 600);
+        read(1990, 2000);
+        let stats = io_stats(handle, app.state()).unwrap().unwrap();
+        assert_eq!(
+            (stats.reads, stats.requested_bytes, stats.read_bytes),
+            (3, 120, 1024 + 464)
+        );
+        assert_eq!(stats.over_read_ratio, 1488.0 / 120.0);
+
+        reset_io_stats(handle, app.state()).unwrap();
+        read(1030, 1040);
+        let stats = io_stats(handle, app.state()).unwrap().unwrap();
+        assert_eq!(
+            (stats.reads, stats.requested_bytes, stats.read_bytes),
+            (1, 10, 512)
+        );
+        assert_eq!(stats.over_read_ratio, 51.2);
+        set_io_stats(handle, false, app.state()).unwrap();
+        assert!(io_stats(handle, app.state()).unwrap().is_none());
+    }
+
+    #[test]
+    fn get_file_data_batch_reads_each_range() {
+        let app = app();
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(64 * 1024).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+        set_alignment(handle, 512, app.state()).unwrap();
+        let ranges = vec![
+            (10, 14),
+            (5, 12),
+            (40_000, 40_003),
+            (20, 20),
+            (64 * 1024 + 1, 64 * 1024 + 8),
+            (30, 20),
+            (64 * 1024 - 2, 64 * 1024 + 10),
+            (100, 108),
+        ];
+
+        let results = tauri::async_runtime::block_on(get_file_data_batch(
+            handle,
+            ranges.clone(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(results.len(), ranges.len());
+        let expected = |start: usize, end: usize| Ok(bytes[start..end].to_vec());
+        assert_eq!(results[0], expected(10, 14));
+        assert_eq!(results[1], expected(5, 12));
+        assert_eq!(results[2], expected(40_000, 40_003));
+        assert_eq!(results[3], Ok(Vec::new()));
+        assert_eq!(
+            results[4],
+            Err(AppError::OutOfBounds {
+                requested: 64 * 1024 + 1,
+                size: 64 * 1024
+            })
+        );
+        assert_eq!(
+            results[5],
+            Err(AppError::InvalidRange { start: 30, end: 20 })
+        );
+        assert_eq!(results[6], expected(64 * 1024 - 2, 64 * 1024));
+        assert_eq!(results[7], expected(100, 108));
+
+        assert!(matches!(
+            tauri::async_runtime::block_on(get_file_data_batch(handle + 1, ranges, app.state())),
+            Err(AppError::FileNotOpen(_))
+        ));
+    }
+
+    #[test]
+    fn reads_report_files_truncated_while_open() {
+        let app = app();
+        let (file, handle) = open_temp(&app, &[3; 4096]);
+        // Without the watcher nothing else notices the truncation.
+        let watcher = lock_state(&app.state())
+            .get_mut(handle)
+            .unwrap()
+            .watcher
+            .take();
+        drop(watcher);
+        file.as_file().set_len(100).unwrap();
+
+        let read =
+            tauri::async_runtime::block_on(get_file_data(handle, 0, 2048, None, app.state()));
+        assert_eq!(read, Err(AppError::FileShrunk { new_size: 100 }));
+        assert_eq!(file_info(handle, app.state()).unwrap().size, 100);
+        let read =
+            tauri::async_runtime::block_on(get_file_data(handle, 0, 2048, None, app.state()));
+        assert_eq!(read.unwrap(), vec![3; 100]);
+    }
+
+    #[test]
+    fn deferred_files_open_on_first_use() {
+        let app = app();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("not-yet-written.bin");
+        let handle = open_file_deferred(
+            path.to_string_lossy().into_owned(),
+            None,
+            None,
+            None,
+            app.handle().clone(),
+            app.state(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            file_info(handle, app.state()),
+            Err(AppError::Io(message)) if message.contains("not-yet-written.bin")
+        ));
+
+        std::fs::write(&path, b"written later").unwrap();
+        let read = tauri::async_runtime::block_on(get_file_data(handle, 0, 13, None, app.state()))
+            .unwrap();
+        assert_eq!(read, b"written later");
+        assert_eq!(file_info(handle, app.state()).unwrap().size, 13);
+        // Opened like any other file, watcher included.
+        assert!(lock_state(&app.state())
+            .get(handle)
+            .unwrap()
+            .watcher
+            .is_some());
+
+        close_file(handle, app.state()).unwrap();
+        assert!(matches!(
+            file_info(handle, app.state()),
+            Err(AppError::FileNotOpen(_))
+        ));
+    }
+
+    #[test]
+    fn open_file_resolves_relative_paths_and_rejects_directories() {
+        let app = app();
+        let dir = tempfile::tempdir().unwrap();
+        let resolved = std::fs::canonicalize(dir.path()).unwrap().join("data.bin");
+        std::fs::write(&resolved, b"data").unwrap();
+        let open_path = |path: &Path| {
+            open_file(
+                path.to_string_lossy().into_owned(),
+                None,
+                None,
+                None,
+                app.handle().clone(),
+                app.state(),
+            )
+        };
+        let info = |handle| file_info(handle, app.state()).unwrap();
+
+        // The same file as reached from the working directory.
+        let cwd = std::env::current_dir().unwrap();
+        let mut relative = PathBuf::new();
+        for _ in cwd.components().skip(1) {
+            relative.push("..");
+        }
+        relative.push(resolved.strip_prefix("/").unwrap_or(&resolved));
+        let handle = open_path(&relative).unwrap().handle;
+        assert_eq!(
+            info(handle).path.as_deref(),
+            Some(resolved.to_str().unwrap())
+        );
+        assert_eq!(
+            info(handle).given_path.as_deref(),
+            Some(relative.to_str().unwrap())
+        );
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.bin");
+            std::os::unix::fs::symlink(&resolved, &link).unwrap();
+            let handle = open_path(&link).unwrap().handle;
+            assert_eq!(
+                info(handle).path.as_deref(),
+                Some(resolved.to_str().unwrap())
+            );
+            assert_eq!(
+                info(handle).given_path.as_deref(),
+                Some(link.to_str().unwrap())
+            );
+        }
+
+        assert!(matches!(
+            open_path(dir.path()),
+            Err(AppError::InvalidArgument(message)) if message.contains("is a directory")
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn opens_paths_that_are_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let app = app();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .canonicalize()
+            .unwrap()
+            .join(OsStr::from_bytes(b"caf\xe9.bin"));
+        std::fs::write(&path, b"latin-1 name").unwrap();
+        let path_bytes = path.as_os_str().as_bytes().to_vec();
+
+        let opened = open_file_os(
+            path_bytes.clone(),
+            None,
+            None,
+            None,
+            app.handle().clone(),
+            app.state(),
+        )
+        .unwrap();
+        let read = tauri::async_runtime::block_on(get_file_data(
+            opened.handle,
+            0,
+            opened.file_size,
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(read, b"latin-1 name");
+        let info = file_info(opened.handle, app.state()).unwrap();
+        assert_eq!(info.path_bytes, Some(path_bytes));
+        assert!(info.path.unwrap().ends_with("caf\u{fffd}.bin"));
+    }
+
+    #[test]
+    fn chunked_saves_write_each_chunk_after_the_last() {
+        let app = app();
+        let size = 3 * 1024 * 1024;
+        let (file, handle) = open_temp(&app, &vec![0u8; size]);
+        let chunks: Vec<Vec<u8>> = (1..=3u8).map(|n| vec![n; 1024 * 1024 - 100]).collect();
+        let chunk = |session_id, offset, index: usize, is_final| {
+            save_file_data_chunk(
+                handle,
+                session_id,
+                offset,
+                chunks[index].clone(),
+                is_final,
+                None,
+                app.state(),
+            )
+        };
+
+        let session = chunk(None, Some(100), 0, false).unwrap();
+        assert!(matches!(
+            chunk(Some(session), Some(100), 1, false),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert_eq!(chunk(Some(session), None, 1, false).unwrap(), session);
+        assert_eq!(chunk(Some(session), None, 2, true).unwrap(), session);
+        assert!(matches!(
+            chunk(Some(session), None, 0, false),
+            Err(AppError::InvalidArgument(_))
+        ));
+
+        let mut expected = vec![0u8; size];
+        let edit = chunks.concat();
+        expected[100..100 + edit.len()].copy_from_slice(&edit);
+        assert_eq!(std::fs::read(file.path()).unwrap(), expected);
+
+        // A chunk running past the end needs `allow_grow`.
+        assert!(matches!(
+            save_file_data_chunk(
+                handle,
+                None,
+                Some(size as u64 - 1),
+                b"xy".to_vec(),
+                true,
+                None,
+                app.state()
+            ),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn capabilities_reflect_the_build() {
+        let capabilities = capabilities().unwrap();
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(capabilities.mmap, cfg!(feature = "mmap"));
+        assert_eq!(capabilities.disasm, cfg!(feature = "disasm"));
+        assert_eq!(capabilities.overview, cfg!(feature = "overview"));
+        assert!(capabilities.compression);
+    }
+
+    #[test]
+    fn writes_past_the_end_zero_fill_the_gap() {
+        for save_mode in [None, Some("atomic".to_string())] {
+            let app = app();
+            let (file, handle) = open_temp(&app, b"0123456789");
+            save_file_data(
+                handle,
+                110,
+                b"ABCDEFGHIJ".to_vec(),
+                Some(true),
+                save_mode.clone(),
+                app.state(),
+            )
+            .unwrap();
+
+            assert_eq!(file_info(handle, app.state()).unwrap().size, 120);
+            let read =
+                tauri::async_runtime::block_on(get_file_data(handle, 0, 120, None, app.state()))
+                    .unwrap();
+            let mut expected = b"0123456789".to_vec();
+            expected.extend([0; 100]);
+            expected.extend(b"ABCDEFGHIJ");
+            assert_eq!(read, expected, "{:?}", save_mode);
+            assert_eq!(std::fs::read(file.path()).unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::reader::FileReader;
+use crate::tags::Tag;
+use crate::{lock_state, run_blocking, AppState};
+
+/// Largest width or height `render_overview` draws.
+const MAX_OVERVIEW_SIDE: u32 = 4096;
+
+const OVERVIEW_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Pixels past the end of the file, or not covered by any tag.
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+
+/// Tags without a color of their own.
+const UNCOLORED_TAG: [u8; 3] = [160, 160, 160];
+
+/// The class color of a byte: zeros, `0xff`, printable ASCII, other control
+/// characters and everything else each get their own.
+fn byte_color(byte: u8) -> [u8; 3] {
+    match byte {
+        0x00 => [0, 0, 0],
+        0xff => [255, 255, 255],
+        0x20..=0x7e => [55, 126, 184],
+        0x01..=0x1f | 0x7f => [77, 175, 74],
+        _ => [228, 26, 28],
+    }
+}
+
+/// The RGB part of a `#RRGGBB` or `#RRGGBBAA` tag color.
+fn tag_rgb(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?.get(..6)?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+/// Bytes each of `pixels` pixels stands for, so the file fits.
+fn span_per_pixel(file_size: u64, pixels: u64) -> u64 {
+    std::cmp::max(file_size.div_ceil(pixels), 1)
+}
+
+/// Colors each pixel with the average class color of the bytes it covers.
+fn byte_pixels(reader: &FileReader, pixels: usize) -> Result<Vec<u8>, AppError> {
+    let span = span_per_pixel(reader.file_size, pixels as u64);
+    // Red, green and blue sums and the byte count per pixel.
+    let mut sums = vec![[0u64; 4]; pixels];
+    reader.for_each_chunk(0, reader.file_size, OVERVIEW_CHUNK_SIZE, |offset, chunk| {
+        for (i, &byte) in chunk.iter().enumerate() {
+            let sum = &mut sums[((offset + i as u64) / span) as usize];
+            for (total, channel) in sum.iter_mut().zip(byte_color(byte)) {
+                *total += channel as u64;
+            }
+            sum[3] += 1;
+        }
+        Ok(())
+    })?;
+
+    Ok(sums
+        .into_iter()
+        .flat_map(|[r, g, b, count]| match count {
+            0 => BACKGROUND,
+            count => [(r / count) as u8, (g / count) as u8, (b / count) as u8],
+        })
+        .collect())
+}
+
+/// Colors each pixel with the color of the topmost tag covering any of its
+/// bytes.
+fn tag_pixels(tags: &[Tag], file_size: u64, pixels: usize) -> Vec<u8> {
+    let span = span_per_pixel(file_size, pixels as u64);
+    let mut rgb = BACKGROUND.repeat(pixels);
+    let mut layered: Vec<&Tag> = tags.iter().collect();
+    layered.sort_by_key(|tag| tag.layer);
+    for tag in layered {
+        let color = tag
+            .color
+            .as_deref()
+            .and_then(tag_rgb)
+            .unwrap_or(UNCOLORED_TAG);
+        let first = (tag.start / span) as usize;
+        let last = ((tag.end - 1) / span) as usize;
+        for pixel in rgb[first * 3..(last + 1) * 3].chunks_exact_mut(3) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+    rgb
+}
+
+#[cfg(feature = "overview")]
+fn write_png(out_path: &str, width: u32, height: u32, rgb: &[u8]) -> Result<(), AppError> {
+    image::save_buffer_with_format(
+        out_path,
+        rgb,
+        width,
+        height,
+        image::ExtendedColorType::Rgb8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| AppError::Io(format!("Failed to write {}: {}", out_path, e)))
+}
+
+#[cfg(not(feature = "overview"))]
+fn write_png(_out_path: &str, _width: u32, _height: u32, _rgb: &[u8]) -> Result<(), AppError> {
+    Err(AppError::InvalidArgument(
+        "Overview images need the app to be built with the `overview` feature".to_string(),
+    ))
+}
+
+/// Draws the whole file as a `width` x `height` PNG at `out_path`, row by row
+/// from the top left, each pixel standing for an equal span of bytes.
+/// `color_by` is `bytes` (the default), coloring zeros, `0xff`, printable
+/// ASCII, control characters and other bytes apart, or `tags`, using the
+/// colors of the tags covering each span.
+#[tauri::command]
+pub async fn render_overview(
+    handle: u64,
+    width: u32,
+    height: u32,
+    out_path: String,
+    color_by: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    if !(1..=MAX_OVERVIEW_SIDE).contains(&width) || !(1..=MAX_OVERVIEW_SIDE).contains(&height) {
+        return Err(AppError::InvalidArgument(format!(
+            "Overview must be between 1 and {} pixels on each side",
+            MAX_OVERVIEW_SIDE
+        )));
+    }
+    let by_tags = match color_by.as_deref().unwrap_or("bytes") {
+        "bytes" => false,
+        "tags" => true,
+        other => {
+            return Err(AppError::InvalidArgument(format!(
+                "Unsupported overview coloring '{}', expected 'bytes' or 'tags'",
+                other
+            )))
+        }
+    };
+    let (reader, tags) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        let tags = if by_tags {
+            file_state.tags.clone()
+        } else {
+            Vec::new()
+        };
+        (file_state.reader(), tags)
+    };
+
+    run_blocking(move || {
+        let pixels = width as usize * height as usize;
+        let rgb = if by_tags {
+            tag_pixels(&tags, reader.file_size, pixels)
+        } else {
+            byte_pixels(&reader, pixels)?
+        };
+        write_png(&out_path, width, height, &rgb)
+    })
+    .await
+}
+
+#[cfg(all(test, feature = "overview"))]
+mod tests {
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::tags::add_tag;
+    use crate::test_support::app;
+
+    #[test]
+    fn renders_at_the_requested_size() {
+        let app = app();
+        let mut bytes = vec![0u8; 16];
+        bytes.extend_from_slice(&[b'A'; 16]);
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let mut tag = Tag::new(8, 12, "t".to_string(), "t");
+        tag.color = Some("#ff8000".to_string());
+        add_tag(handle, tag, None, None, None, app.state()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let render = |color_by: &str| {
+            let path = dir.path().join(format!("{}.png", color_by));
+            let path = path.to_string_lossy().into_owned();
+            block_on(render_overview(
+                handle,
+                4,
+                2,
+                path.clone(),
+                Some(color_by.to_string()),
+                app.state(),
+            ))
+            .unwrap();
+            image::open(path).unwrap().to_rgb8()
+        };
+
+        let bytes = render("bytes");
+        assert_eq!(bytes.dimensions(), (4, 2));
+        assert_eq!(bytes.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(bytes.get_pixel(3, 1).0, byte_color(b'A'));
+
+        let tags = render("tags");
+        assert_eq!(tags.dimensions(), (4, 2));
+        assert_eq!(tags.get_pixel(2, 0).0, [255, 128, 0]);
+        assert_eq!(tags.get_pixel(0, 1).0, BACKGROUND);
+    }
+}
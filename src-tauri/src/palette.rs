@@ -0,0 +1,282 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::{lock_state, AppState};
+
+/// Visually distinct colors (Tableau 10), all valid `#RRGGBB`.
+const PALETTE: [&str; 10] = [
+    "#4E79A7", "#F28E2B", "#E15759", "#76B7B2", "#59A14F", "#EDC948", "#B07AA1", "#FF9DA7",
+    "#9C755F", "#BAB0AC",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scheme {
+    /// Cycles through the palette in tag order.
+    Categorical,
+    /// One color per power-of-two size class.
+    BySize,
+}
+
+impl Scheme {
+    fn parse(scheme: &str) -> Result<Self, AppError> {
+        match scheme {
+            "categorical" => Ok(Self::Categorical),
+            "by_size" => Ok(Self::BySize),
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported color scheme '{}', expected 'categorical' or 'by_size'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Colors tags from a fixed palette and returns how many were changed. Only
+/// uncolored tags are touched unless `recolor_all` is set. With the
+/// `categorical` scheme neighbouring tags always get different colors;
+/// `by_size` gives tags of similar length the same color.
+#[tauri::command]
+pub fn recolor_tags(
+    handle: u64,
+    scheme: String,
+    recolor_all: Option<bool>,
+    state: State<AppState>,
+) -> Result<usize, AppError> {
+    let scheme = Scheme::parse(&scheme)?;
+    let recolor_all = recolor_all.unwrap_or(false);
+
+    let mut app_state = lock_state(&state);
+    let tags = &mut app_state.get_mut(handle)?.tags;
+
+    let mut changed = 0;
+    let mut previous: Option<String> = None;
+    for (index, tag) in tags.iter_mut().enumerate() {
+        if recolor_all || tag.color.is_none() {
+            let mut choice = match scheme {
+                Scheme::Categorical => index,
+                Scheme::BySize => (63 - (tag.end - tag.start).leading_zeros()) as usize,
+            } % PALETTE.len();
+            if scheme == Scheme::Categorical && previous.as_deref() == Some(PALETTE[choice]) {
+                choice = (choice + 1) % PALETTE.len();
+            }
+            tag.color = Some(PALETTE[choice].to_string());
+            changed += 1;
+        }
+        previous = tag.color.clone();
+    }
+    Ok(changed)
+}
+
+/// Parses `#RRGGBB`, or `#RRGGBBAA` keeping the alpha part as it is.
+fn parse_rgb(color: &str) -> Option<([u8; 3], &str)> {
+    let hex = color.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let value = u32::from_str_radix(&hex[..6], 16).ok()?;
+    let alpha = &hex[6..];
+    if !alpha.is_empty() {
+        u8::from_str_radix(alpha, 16).ok()?;
+    }
+    Some((
+        [(value >> 16) as u8, (value >> 8) as u8, value as u8],
+        alpha,
+    ))
+}
+
+/// WCAG relative luminance of an sRGB color, from 0.0 (black) to 1.0.
+fn luminance(rgb: [u8; 3]) -> f64 {
+    let linear = |channel: u8| {
+        let c = channel as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(rgb[0]) + 0.7152 * linear(rgb[1]) + 0.0722 * linear(rgb[2])
+}
+
+/// WCAG contrast ratio of two colors, from 1.0 to 21.0.
+fn contrast(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn mix(color: [u8; 3], target: [u8; 3], amount: f64) -> [u8; 3] {
+    let channel = |c: u8, t: u8| {
+        (c as f64 + (t as f64 - c as f64) * amount)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    [
+        channel(color[0], target[0]),
+        channel(color[1], target[1]),
+        channel(color[2], target[2]),
+    ]
+}
+
+/// Lightens or darkens `color`, whichever takes less, just enough to reach
+/// `min_ratio` against `background`. Mixing toward white or black keeps the
+/// hue. When neither gets there, goes as far as the better one allows.
+fn adjust_contrast(color: [u8; 3], background: [u8; 3], min_ratio: f64) -> [u8; 3] {
+    // The smallest mix toward `target` that is enough, found by bisection.
+    let needed = |target: [u8; 3]| {
+        if contrast(mix(color, target, 1.0), background) < min_ratio {
+            return None;
+        }
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..20 {
+            let middle = (low + high) / 2.0;
+            if contrast(mix(color, target, middle), background) >= min_ratio {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+        Some((high, mix(color, target, high)))
+    };
+    let white = [255, 255, 255];
+    let black = [0, 0, 0];
+    match (needed(white), needed(black)) {
+        (Some((lighten, lighter)), Some((darken, darker))) => {
+            if lighten <= darken {
+                lighter
+            } else {
+                darker
+            }
+        }
+        (Some((_, lighter)), None) => lighter,
+        (None, Some((_, darker))) => darker,
+        (None, None) if contrast(white, background) >= contrast(black, background) => white,
+        (None, None) => black,
+    }
+}
+
+/// Changes tag colors that contrast with `background` (`#RRGGBB`) by less
+/// than `min_ratio`, the WCAG contrast ratio from 1 to 21 (4.5 for body
+/// text), and returns how many were changed. Colors are lightened or
+/// darkened, keeping their hue and alpha. Uncolored tags are left alone.
+#[tauri::command]
+pub fn ensure_contrast(
+    handle: u64,
+    background: String,
+    min_ratio: f64,
+    state: State<AppState>,
+) -> Result<usize, AppError> {
+    let background = match parse_rgb(&background) {
+        Some((rgb, "")) => rgb,
+        _ => {
+            return Err(AppError::InvalidArgument(format!(
+                "Invalid background color '{}', expected #RRGGBB",
+                background
+            )))
+        }
+    };
+    if !(1.0..=21.0).contains(&min_ratio) {
+        return Err(AppError::InvalidArgument(format!(
+            "Contrast ratio {} must be between 1 and 21",
+            min_ratio
+        )));
+    }
+
+    let mut app_state = lock_state(&state);
+    let tags = &mut app_state.get_mut(handle)?.tags;
+    let mut changed = 0;
+    for tag in tags.iter_mut() {
+        let Some((rgb, alpha)) = tag.color.as_deref().and_then(parse_rgb) else {
+            continue;
+        };
+        if contrast(rgb, background) >= min_ratio {
+            continue;
+        }
+        let [r, g, b] = adjust_contrast(rgb, background, min_ratio);
+        tag.color = Some(format!("#{:02X}{:02X}{:02X}{}", r, g, b, alpha));
+        changed += 1;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::tags::{add_tag, get_all_tags, Tag};
+    use crate::test_support::{app, open_temp};
+
+    fn parse_color(color: &str) -> Option<u32> {
+        let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+        u32::from_str_radix(hex, 16).ok()
+    }
+
+    #[test]
+    fn categorical_colors_are_valid_and_neighbours_differ() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for start in (0..48).step_by(4) {
+            let mut tag = Tag::new(start, start + 4, "t".to_string(), "t");
+            // Would collide with the color its successor is due.
+            if start == 4 {
+                tag.color = Some(PALETTE[2].to_string());
+            }
+            add_tag(handle, tag, None, None, None, app.state()).unwrap();
+        }
+
+        let changed = recolor_tags(handle, "categorical".to_string(), None, app.state()).unwrap();
+        assert_eq!(changed, 11);
+        let colors: Vec<String> = get_all_tags(handle, app.state())
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.color.unwrap())
+            .collect();
+        assert!(colors.iter().all(|color| parse_color(color).is_some()));
+        assert!(colors.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(colors[1], PALETTE[2]);
+    }
+
+    #[test]
+    fn rejects_unknown_scheme() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 4]);
+        assert!(matches!(
+            recolor_tags(handle, "rainbow".to_string(), None, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn ensure_contrast_fixes_only_unreadable_colors() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let colors = [Some("#202428"), Some("#FFD700"), None, Some("#1a1a2eCC")];
+        for (i, color) in colors.into_iter().enumerate() {
+            let start = i as u64 * 8;
+            let mut tag = Tag::new(start, start + 4, "t".to_string(), "t");
+            tag.color = color.map(str::to_string);
+            add_tag(handle, tag, None, None, None, app.state()).unwrap();
+        }
+        let background = [0x1e, 0x1e, 0x1e];
+
+        let changed = ensure_contrast(handle, "#1E1E1E".to_string(), 4.5, app.state()).unwrap();
+        assert_eq!(changed, 2);
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        let adjusted = parse_rgb(tags[0].color.as_deref().unwrap()).unwrap().0;
+        assert!(contrast(adjusted, background) >= 4.5);
+        // Lightened rather than turned white: still bluish grey.
+        assert!(adjusted[2] >= adjusted[0] && adjusted != [255, 255, 255]);
+        assert_eq!(tags[1].color.as_deref(), Some("#FFD700"));
+        assert_eq!(tags[2].color, None);
+        let (rgb, alpha) = parse_rgb(tags[3].color.as_deref().unwrap()).unwrap();
+        assert!(contrast(rgb, background) >= 4.5);
+        assert_eq!(alpha, "CC");
+
+        assert!(matches!(
+            ensure_contrast(handle, "#1E1E1E".to_string(), 30.0, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            ensure_contrast(handle, "dark".to_string(), 4.5, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
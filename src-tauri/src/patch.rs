@@ -0,0 +1,382 @@
+//! Binary patches turning one file into another.
+//!
+//! A patch is `BLPATCH1`, the target size as a little-endian `u64`, then a
+//! stream of opcodes:
+//!
+//! - `0x00 offset:u64 length:u64` copies bytes from the source file.
+//! - `0x01 length:u64 bytes...` inserts literal bytes.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::error::AppError;
+use crate::export::{is_source_file, source_paths};
+use crate::reader::{to_usize, FileReader};
+use crate::{lock_state, run_blocking, AppState};
+
+const PATCH_MAGIC: &[u8; 8] = b"BLPATCH1";
+const OP_COPY: u8 = 0;
+const OP_INSERT: u8 = 1;
+
+/// Length of the source blocks matched against the target. Shorter common
+/// runs are sent as literals.
+const MATCH_BLOCK: usize = 32;
+
+/// Largest file `export_patch` loads, on either side.
+const MAX_PATCH_INPUT: u64 = 256 * 1024 * 1024;
+
+const APPLY_CHUNK_SIZE: usize = 1024 * 1024;
+
+const HASH_BASE: u64 = 0x100000001b3;
+
+/// Polynomial hash of a `MATCH_BLOCK`-byte window.
+fn block_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |hash, &byte| {
+        hash.wrapping_mul(HASH_BASE).wrapping_add(byte as u64)
+    })
+}
+
+fn push_copy(patch: &mut Vec<u8>, offset: usize, length: usize) {
+    patch.push(OP_COPY);
+    patch.extend_from_slice(&(offset as u64).to_le_bytes());
+    patch.extend_from_slice(&(length as u64).to_le_bytes());
+}
+
+fn push_insert(patch: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    patch.push(OP_INSERT);
+    patch.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    patch.extend_from_slice(bytes);
+}
+
+/// Encodes a patch turning `source` into `target`. Runs of `target` found
+/// anywhere in `source` become copies; everything else is inserted.
+pub(crate) fn encode_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = PATCH_MAGIC.to_vec();
+    patch.extend_from_slice(&(target.len() as u64).to_le_bytes());
+
+    let mut index = HashMap::new();
+    for start in (0..source.len().saturating_sub(MATCH_BLOCK - 1)).step_by(MATCH_BLOCK) {
+        index
+            .entry(block_hash(&source[start..start + MATCH_BLOCK]))
+            .or_insert(start);
+    }
+    // Weight of the byte leaving the window when it rolls forward.
+    let leaving_weight = (1..MATCH_BLOCK).fold(1u64, |weight, _| weight.wrapping_mul(HASH_BASE));
+
+    let mut literal_start = 0;
+    let mut position = 0;
+    let mut hash = target.get(..MATCH_BLOCK).map(block_hash).unwrap_or(0);
+    while position + MATCH_BLOCK <= target.len() {
+        let found = index
+            .get(&hash)
+            .copied()
+            .filter(|&at| source[at..at + MATCH_BLOCK] == target[position..position + MATCH_BLOCK]);
+        if let Some(at) = found {
+            // Grow the match back into the pending literal, then forward.
+            let (mut from, mut to) = (at, position);
+            while from > 0 && to > literal_start && source[from - 1] == target[to - 1] {
+                from -= 1;
+                to -= 1;
+            }
+            let mut length = position + MATCH_BLOCK - to;
+            while from + length < source.len()
+                && to + length < target.len()
+                && source[from + length] == target[to + length]
+            {
+                length += 1;
+            }
+
+            push_insert(&mut patch, &target[literal_start..to]);
+            push_copy(&mut patch, from, length);
+            position = to + length;
+            literal_start = position;
+            if let Some(window) = target.get(position..position + MATCH_BLOCK) {
+                hash = block_hash(window);
+            }
+            continue;
+        }
+
+        if position + MATCH_BLOCK < target.len() {
+            hash = hash
+                .wrapping_sub((target[position] as u64).wrapping_mul(leaving_weight))
+                .wrapping_mul(HASH_BASE)
+                .wrapping_add(target[position + MATCH_BLOCK] as u64);
+        }
+        position += 1;
+    }
+    push_insert(&mut patch, &target[literal_start..]);
+    patch
+}
+
+fn read_u64(patch: &mut impl Read) -> Result<u64, AppError> {
+    let mut bytes = [0u8; 8];
+    patch
+        .read_exact(&mut bytes)
+        .map_err(|_| AppError::InvalidFormat("Patch is truncated".to_string()))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Applies `patch` to `source`, writing the target to `out`, and returns the
+/// target size.
+pub(crate) fn apply_patch_to(
+    source: &FileReader,
+    patch: &mut impl Read,
+    out: &mut impl Write,
+) -> Result<u64, AppError> {
+    let write_error =
+        |e: std::io::Error| AppError::Io(format!("Failed to write patched file: {}", e));
+
+    let mut magic = [0u8; 8];
+    if patch.read_exact(&mut magic).is_err() || &magic != PATCH_MAGIC {
+        return Err(AppError::InvalidFormat("Not a patch file".to_string()));
+    }
+    let target_size = read_u64(patch)?;
+
+    let mut written = 0u64;
+    let mut op = [0u8; 1];
+    let mut buf = vec![0u8; APPLY_CHUNK_SIZE];
+    loop {
+        match patch.read(&mut op) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => return Err(AppError::Io(format!("Failed to read patch: {}", e))),
+        }
+        match op[0] {
+            OP_COPY => {
+                let offset = read_u64(patch)?;
+                let length = read_u64(patch)?;
+                let end = offset
+                    .checked_add(length)
+                    .filter(|&end| end <= source.file_size);
+                let Some(end) = end else {
+                    return Err(AppError::InvalidFormat(format!(
+                        "Patch copies {}..{} past the end of the {}-byte source",
+                        offset,
+                        offset.saturating_add(length),
+                        source.file_size
+                    )));
+                };
+                source.for_each_chunk(offset, end, APPLY_CHUNK_SIZE, |_, chunk| {
+                    out.write_all(chunk).map_err(write_error)
+                })?;
+                written += length;
+            }
+            OP_INSERT => {
+                let mut remaining = read_u64(patch)?;
+                while remaining > 0 {
+                    let want = std::cmp::min(remaining, buf.len() as u64) as usize;
+                    patch
+                        .read_exact(&mut buf[..want])
+                        .map_err(|_| AppError::InvalidFormat("Patch is truncated".to_string()))?;
+                    out.write_all(&buf[..want]).map_err(write_error)?;
+                    remaining -= want as u64;
+                    written += want as u64;
+                }
+            }
+            other => {
+                return Err(AppError::InvalidFormat(format!(
+                    "Unknown patch opcode {:#04x}",
+                    other
+                )))
+            }
+        }
+    }
+    out.flush().map_err(write_error)?;
+
+    if written != target_size {
+        return Err(AppError::InvalidFormat(format!(
+            "Patch produced {} bytes but describes a {}-byte file",
+            written, target_size
+        )));
+    }
+    Ok(written)
+}
+
+fn read_all(reader: &FileReader) -> Result<Vec<u8>, AppError> {
+    if reader.file_size > MAX_PATCH_INPUT {
+        return Err(AppError::InvalidArgument(format!(
+            "Files over {} bytes are too large to diff into a patch",
+            MAX_PATCH_INPUT
+        )));
+    }
+    let mut bytes = vec![0u8; to_usize(reader.file_size)?];
+    reader.read_exact_at(0, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Opens `out_path` for writing, refusing to replace an existing file unless
+/// `overwrite` is set or to write into one of `readers`.
+fn create_output(
+    out_path: &str,
+    overwrite: Option<bool>,
+    readers: &[(&FileReader, &[PathBuf])],
+) -> Result<File, AppError> {
+    if readers
+        .iter()
+        .any(|(reader, paths)| is_source_file(reader, paths, Path::new(out_path)))
+    {
+        return Err(AppError::InvalidArgument(
+            "Cannot write a patch result into an open source file".to_string(),
+        ));
+    }
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if overwrite.unwrap_or(false) {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options
+        .open(out_path)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", out_path, e)))
+}
+
+/// Writes a patch to `out_path` that turns the file open as `handle_a` into
+/// the one open as `handle_b`, and returns the patch size.
+#[tauri::command]
+pub async fn export_patch(
+    handle_a: u64,
+    handle_b: u64,
+    out_path: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<u64, AppError> {
+    let ((reader_a, paths_a), (reader_b, paths_b)) = {
+        let mut app_state = lock_state(&state);
+        let a = app_state.get(handle_a)?;
+        let a = (a.reader(), source_paths(a));
+        let b = app_state.get(handle_b)?;
+        (a, (b.reader(), source_paths(b)))
+    };
+
+    run_blocking(move || {
+        let patch = encode_patch(&read_all(&reader_a)?, &read_all(&reader_b)?);
+        let mut out = create_output(
+            &out_path,
+            overwrite,
+            &[(&reader_a, &paths_a), (&reader_b, &paths_b)],
+        )?;
+        out.write_all(&patch)
+            .map_err(|e| AppError::Io(format!("Failed to write patch: {}", e)))?;
+        Ok(patch.len() as u64)
+    })
+    .await
+}
+
+/// Applies the patch at `patch_path` to the file open as `handle`, writes the
+/// result to `out_path` and returns its size. A failed patch leaves the
+/// partial result behind.
+#[tauri::command]
+pub async fn apply_patch(
+    handle: u64,
+    patch_path: String,
+    out_path: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<u64, AppError> {
+    let (reader, paths) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (file_state.reader(), source_paths(file_state))
+    };
+
+    run_blocking(move || {
+        let patch = File::open(&patch_path)
+            .map_err(|e| AppError::Io(format!("Failed to open patch {}: {}", patch_path, e)))?;
+        let out = create_output(&out_path, overwrite, &[(&reader, &paths)])?;
+        apply_patch_to(
+            &reader,
+            &mut BufReader::new(patch),
+            &mut BufWriter::new(out),
+        )
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tauri::async_runtime::block_on;
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp, reader_over};
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (x >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn patch_round_trips_between_files() {
+        let a = pseudo_random(20_000, 1);
+        let mut b = a[..5_000].to_vec();
+        b.extend_from_slice(b"inserted firmware bytes");
+        b.extend_from_slice(&a[5_000..9_000]);
+        b.extend_from_slice(&a[12_000..]);
+        b[15_000..15_004].copy_from_slice(b"XXXX");
+        b.extend_from_slice(&pseudo_random(100, 2));
+
+        let app = app();
+        let (_file_a, handle_a) = open_temp(&app, &a);
+        let (_file_b, handle_b) = open_temp(&app, &b);
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+
+        let patch_size = block_on(export_patch(
+            handle_a,
+            handle_b,
+            path("a-to-b.patch"),
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert!(patch_size < 1_000, "patch is {} bytes", patch_size);
+
+        let size = block_on(apply_patch(
+            handle_a,
+            path("a-to-b.patch"),
+            path("b.bin"),
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(size, b.len() as u64);
+        assert_eq!(fs::read(path("b.bin")).unwrap(), b);
+    }
+
+    #[test]
+    fn patches_between_tiny_files() {
+        for (a, b) in [(&b""[..], &b"new"[..]), (b"old", b""), (b"same", b"same")] {
+            let patch = encode_patch(a, b);
+            let mut out = Vec::new();
+            apply_patch_to(&reader_over(a), &mut patch.as_slice(), &mut out).unwrap();
+            assert_eq!(out, b);
+        }
+    }
+
+    #[test]
+    fn copy_past_the_source_is_rejected() {
+        let mut patch = PATCH_MAGIC.to_vec();
+        patch.extend_from_slice(&8u64.to_le_bytes());
+        push_copy(&mut patch, 4, 8);
+
+        let result = apply_patch_to(
+            &reader_over(b"short"),
+            &mut patch.as_slice(),
+            &mut Vec::new(),
+        );
+        assert!(matches!(result, Err(AppError::InvalidFormat(_))));
+    }
+}
@@ -0,0 +1,498 @@
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+#[cfg(feature = "mmap")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::error::AppError;
+use crate::gzip::GzipIndex;
+use crate::transform::Transform;
+
+#[cfg(unix)]
+pub(crate) fn read_file_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+pub(crate) fn read_file_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Converts an offset or length in a file to an index into memory, failing
+/// where `usize` cannot hold it rather than silently truncating it.
+pub(crate) fn to_usize(offset: u64) -> Result<usize, AppError> {
+    narrow(offset)
+}
+
+fn narrow<T: TryFrom<u64>>(offset: u64) -> Result<T, AppError> {
+    T::try_from(offset).map_err(|_| AppError::OffsetTooLarge { offset })
+}
+
+#[cfg(unix)]
+fn write_file_at(file: &File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_file_at(file: &File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_write(file, buf, offset)
+}
+
+/// Writes all of `bytes` at `offset` without touching the file cursor.
+pub(crate) fn write_all_at(file: &File, mut bytes: &[u8], mut offset: u64) -> Result<(), AppError> {
+    while !bytes.is_empty() {
+        match write_file_at(file, bytes, offset) {
+            Ok(0) => {
+                return Err(AppError::Io(
+                    "Failed to write file data: wrote zero bytes".to_string(),
+                ))
+            }
+            Ok(n) => {
+                bytes = &bytes[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(AppError::Io(format!("Failed to write file data: {}", e))),
+        }
+    }
+    Ok(())
+}
+
+/// One part of a file opened with `open_split`, covering
+/// `[start, start + size)` of the combined address space.
+pub(crate) struct Segment {
+    pub path: PathBuf,
+    pub file: Arc<File>,
+    pub start: u64,
+    pub size: u64,
+}
+
+/// Where the bytes of an open file live.
+#[derive(Clone)]
+pub(crate) enum Backing {
+    /// A regular file on disk.
+    File(Arc<File>),
+    /// Parts of a file opened with `open_split`, in address order.
+    Split(Arc<Vec<Segment>>),
+    /// A buffer opened with `open_bytes`. Writers copy it first if a reader
+    /// still holds the old contents.
+    Memory(Arc<Vec<u8>>),
+    /// A gzip file opened with `gzip`, read through its decompressed
+    /// contents.
+    Gzip(Arc<GzipIndex>),
+}
+
+/// Read-only mapping of a file, shared by the file's state and its readers.
+///
+/// Edits that shrink the file revoke the mapping while holding the I/O lock
+/// for writing, and readers only touch it while holding that lock for
+/// reading, so no read faults on pages an edit has cut off.
+#[cfg(feature = "mmap")]
+pub(crate) struct Mapping {
+    mmap: memmap2::Mmap,
+    revoked: AtomicBool,
+}
+
+#[cfg(feature = "mmap")]
+impl Mapping {
+    pub(crate) fn new(mmap: memmap2::Mmap) -> Self {
+        Self {
+            mmap,
+            revoked: AtomicBool::new(false),
+        }
+    }
+
+    /// Stops every reader from using the mapping. Must be called with the
+    /// I/O lock held for writing.
+    pub(crate) fn revoke(&self) {
+        self.revoked.store(true, Ordering::Release);
+    }
+
+    fn bytes(&self) -> Option<&[u8]> {
+        (!self.revoked.load(Ordering::Acquire)).then_some(&self.mmap[..])
+    }
+}
+
+/// Cursor-free read access to an open file.
+///
+/// Reads use positional I/O, so any number of readers can share the same
+/// `File` concurrently. Cloning is cheap, which lets commands take a reader
+/// out of the shared state and release the lock before doing any I/O.
+#[derive(Clone)]
+pub(crate) struct FileReader {
+    pub backing: Backing,
+    /// Shared with the file's state; held for reading during every read so
+    /// reads never see an edit half-applied.
+    pub io_lock: Arc<RwLock<()>>,
+    #[cfg(feature = "mmap")]
+    pub mmap: Option<Arc<Mapping>>,
+    /// Decoding applied to every read, for readers of the decoded view.
+    pub transform: Option<Arc<Transform>>,
+    /// Size of the file when the reader was taken.
+    pub file_size: u64,
+}
+
+impl FileReader {
+    /// Reads as many bytes as are available at `offset` into `buf`, returning
+    /// the number read. Short counts only happen at end of file.
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, AppError> {
+        let read = self.read_raw_at(offset, buf)?;
+        if let Some(transform) = &self.transform {
+            transform.apply(offset, &mut buf[..read]);
+        }
+        Ok(read)
+    }
+
+    fn read_raw_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, AppError> {
+        let _guard = self.io_lock.read().unwrap_or_else(PoisonError::into_inner);
+        if let Backing::Gzip(index) = &self.backing {
+            let end = std::cmp::min(offset.saturating_add(buf.len() as u64), self.file_size);
+            let len = end.saturating_sub(offset) as usize;
+            return index.read_at(offset, &mut buf[..len]);
+        }
+        if let Some(mapped) = self.mapped_slice(offset, buf.len()) {
+            buf[..mapped.len()].copy_from_slice(mapped);
+            return Ok(mapped.len());
+        }
+
+        let mut filled = 0;
+        while filled < buf.len() {
+            let Some((file, file_offset, available)) = self.locate(offset + filled as u64) else {
+                break;
+            };
+            let want = std::cmp::min((buf.len() - filled) as u64, available) as usize;
+            match read_file_at(file, &mut buf[filled..filled + want], file_offset) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(AppError::Io(format!("Failed to read file data: {}", e))),
+            }
+        }
+        Ok(filled)
+    }
+
+    /// Maps `position` to the file holding it, the offset within that file
+    /// and how many bytes it holds from there on.
+    fn locate(&self, position: u64) -> Option<(&File, u64, u64)> {
+        let segments = match &self.backing {
+            Backing::File(file) => return Some((file, position, u64::MAX)),
+            Backing::Split(segments) => segments,
+            // Served entirely by `mapped_slice` and the gzip index.
+            Backing::Memory(_) | Backing::Gzip(_) => return None,
+        };
+        let index = segments.partition_point(|segment| segment.start + segment.size <= position);
+        let segment = segments.get(index)?;
+        let within = position - segment.start;
+        Some((&segment.file, within, segment.size - within))
+    }
+
+    /// Whether reads of `offset..offset + len` are served straight from memory
+    /// rather than from the file.
+    pub(crate) fn is_mapped(&self, offset: u64, len: usize) -> bool {
+        let _guard = self.io_lock.read().unwrap_or_else(PoisonError::into_inner);
+        self.mapped_slice(offset, len).is_some()
+    }
+
+    /// Returns the bytes at `offset..offset + len` (clamped to the file size)
+    /// straight from memory, if the mapping or in-memory buffer covers all of
+    /// them. Writes that grew the file past the mapping fall back to regular
+    /// reads. The I/O lock must be held for reading while the slice is used.
+    fn mapped_slice(&self, offset: u64, len: usize) -> Option<&[u8]> {
+        let bytes = match &self.backing {
+            Backing::Memory(bytes) => bytes.as_slice(),
+            _ => self.mapping()?,
+        };
+        let end = std::cmp::min(offset.checked_add(len as u64)?, self.file_size);
+        if offset > end || end > bytes.len() as u64 {
+            return None;
+        }
+        Some(&bytes[offset as usize..end as usize])
+    }
+
+    #[cfg(feature = "mmap")]
+    fn mapping(&self) -> Option<&[u8]> {
+        self.mmap.as_ref().and_then(|mapping| mapping.bytes())
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn mapping(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Fills `buf` from `offset`, failing if the file ends first.
+    pub(crate) fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), AppError> {
+        if self.read_at(offset, buf)? < buf.len() {
+            return Err(AppError::UnexpectedEof);
+        }
+        Ok(())
+    }
+
+    /// Checks a `[start, end)` request against the file and returns `end`
+    /// clamped to the file size.
+    pub(crate) fn validate_range(&self, start: u64, end: u64) -> Result<u64, AppError> {
+        if end <= start {
+            return Err(AppError::InvalidRange { start, end });
+        }
+        if start >= self.file_size {
+            return Err(AppError::OutOfBounds {
+                requested: start,
+                size: self.file_size,
+            });
+        }
+        Ok(std::cmp::min(end, self.file_size))
+    }
+
+    /// Streams `[start, end)` through `f` in chunks of at most `chunk_size`
+    /// bytes, passing the absolute offset of each chunk.
+    pub(crate) fn for_each_chunk<F>(
+        &self,
+        start: u64,
+        end: u64,
+        chunk_size: usize,
+        mut f: F,
+    ) -> Result<(), AppError>
+    where
+        F: FnMut(u64, &[u8]) -> Result<(), AppError>,
+    {
+        let capacity = std::cmp::min(chunk_size as u64, end.saturating_sub(start)) as usize;
+        let mut buffer = vec![0u8; capacity];
+        let mut offset = start;
+        while offset < end {
+            let want = std::cmp::min(chunk_size as u64, end - offset) as usize;
+            let read = self.read_at(offset, &mut buffer[..want])?;
+            if read == 0 {
+                break;
+            }
+            f(offset, &buffer[..read])?;
+            offset += read as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open_temp, temp_file};
+    use crate::{file_info, get_file_data, open_split, save_file_data};
+
+    #[test]
+    fn split_read_straddles_the_part_boundary() {
+        let app = app();
+        let parts = [temp_file(b"first part|"), temp_file(b"second part")];
+        let paths = parts
+            .iter()
+            .map(|part| part.path().to_string_lossy().into_owned())
+            .collect();
+        let handle = open_split(paths, app.state()).unwrap().handle;
+        let read = |start, end| {
+            tauri::async_runtime::block_on(get_file_data(handle, start, end, None, app.state()))
+                .unwrap()
+        };
+
+        assert_eq!(file_info(handle, app.state()).unwrap().size, 22);
+        assert_eq!(read(6, 17), b"part|second");
+        assert_eq!(read(0, 22), b"first part|second part");
+        assert_eq!(read(11, 17), b"second");
+    }
+
+    #[test]
+    fn split_parts_are_resolved_like_open_file() {
+        let app = app();
+        let part = temp_file(b"part");
+        let dir = tempfile::tempdir().unwrap();
+        let paths = vec![
+            part.path().to_string_lossy().into_owned(),
+            dir.path().to_string_lossy().into_owned(),
+        ];
+        assert!(matches!(
+            open_split(paths, app.state()),
+            Err(AppError::InvalidArgument(message)) if message.contains("is a directory")
+        ));
+    }
+
+    #[test]
+    fn concurrent_reads_never_see_a_half_applied_edit() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &vec![b'a'; 256 * 1024]);
+
+        let readers: Vec<_> = (0..8u64)
+            .map(|thread| {
+                let app_handle = app.handle().clone();
+                std::thread::spawn(move || {
+                    for round in 0..200u64 {
+                        let start = (thread * 7919 + round * 104_729) % (192 * 1024);
+                        let data = tauri::async_runtime::block_on(get_file_data(
+                            handle,
+                            start,
+                            start + 64 * 1024,
+                            None,
+                            app_handle.state(),
+                        ))
+                        .unwrap();
+                        assert_eq!(data.len(), 64 * 1024);
+                        assert!(data.iter().all(|&byte| byte == data[0]), "torn read");
+                    }
+                })
+            })
+            .collect();
+        for round in 0..50 {
+            let fill = if round % 2 == 0 { b'b' } else { b'a' };
+            save_file_data(handle, 0, vec![fill; 256 * 1024], None, None, app.state()).unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn offsets_too_large_for_memory_are_rejected() {
+        // u32 and u16 stand in for the usize of 32- and 16-bit targets.
+        assert_eq!(narrow::<u32>(u32::MAX as u64), Ok(u32::MAX));
+        assert_eq!(
+            narrow::<u32>(u32::MAX as u64 + 1),
+            Err(AppError::OffsetTooLarge {
+                offset: u32::MAX as u64 + 1
+            })
+        );
+        assert!(matches!(
+            narrow::<u16>(70_000),
+            Err(AppError::OffsetTooLarge { offset: 70_000 })
+        ));
+        assert_eq!(to_usize(4096), Ok(4096));
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod mmap_tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, temp_file};
+    use crate::{lock_state, open_file, AppState};
+
+    /// Deterministic bytes that do not repeat with a short period.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn open_reader(path: &std::path::Path, use_mmap: bool) -> FileReader {
+        let app = app();
+        let path = path.to_string_lossy().into_owned();
+        let handle = open_file(
+            path,
+            Some(use_mmap),
+            None,
+            None,
+            app.handle().clone(),
+            app.state(),
+        )
+        .unwrap()
+        .handle;
+        let state = app.state::<AppState>();
+        let reader = lock_state(&state).get(handle).unwrap().reader();
+        assert_eq!(reader.mmap.is_some(), use_mmap);
+        reader
+    }
+
+    #[test]
+    fn mapped_and_plain_reads_agree() {
+        let bytes = noise(3 * 1024 * 1024 + 17);
+        let file = temp_file(&bytes);
+        let mapped = open_reader(file.path(), true);
+        let plain = open_reader(file.path(), false);
+
+        let len = bytes.len() as u64;
+        for (offset, size) in [(0, 1), (0, 4096), (4095, 2), (len - 5, 5), (len - 3, 10)] {
+            let mut from_map = vec![0u8; size];
+            let mut from_file = vec![0u8; size];
+            let read = mapped.read_at(offset, &mut from_map).unwrap();
+            assert_eq!(plain.read_at(offset, &mut from_file).unwrap(), read);
+            assert_eq!(
+                from_map[..read],
+                bytes[offset as usize..offset as usize + read]
+            );
+            assert_eq!(from_map, from_file);
+        }
+    }
+
+    /// Times the same scattered reads through both backends. Timings are
+    /// printed rather than asserted; run with `--nocapture` to see them.
+    #[test]
+    fn scattered_read_timing() {
+        let bytes = noise(8 * 1024 * 1024);
+        let file = temp_file(&bytes);
+        let offsets: Vec<u64> = noise(4 * 10_000)
+            .chunks(4)
+            .map(|chunk| {
+                u32::from_le_bytes(chunk.try_into().unwrap()) as u64 % (bytes.len() as u64 - 64)
+            })
+            .collect();
+
+        let mut digests = Vec::new();
+        for use_mmap in [false, true] {
+            let reader = open_reader(file.path(), use_mmap);
+            let started = std::time::Instant::now();
+            let mut digest = 0u64;
+            let mut buffer = [0u8; 64];
+            for &offset in &offsets {
+                reader.read_exact_at(offset, &mut buffer).unwrap();
+                digest = digest.wrapping_mul(31).wrapping_add(buffer[0] as u64);
+            }
+            eprintln!(
+                "{} reads with mmap={}: {:?}",
+                offsets.len(),
+                use_mmap,
+                started.elapsed()
+            );
+            digests.push(digest);
+        }
+        assert_eq!(digests[0], digests[1]);
+    }
+
+    #[test]
+    fn held_mapped_reader_survives_a_shrink() {
+        let bytes = noise(64 * 1024);
+        let file = temp_file(&bytes);
+        let app = app();
+        let path = file.path().to_string_lossy().into_owned();
+        let handle = open_file(
+            path,
+            Some(true),
+            None,
+            None,
+            app.handle().clone(),
+            app.state(),
+        )
+        .unwrap()
+        .handle;
+        let stale = app
+            .state::<AppState>()
+            .lock()
+            .unwrap()
+            .get(handle)
+            .unwrap()
+            .reader();
+        assert!(stale.is_mapped(0, bytes.len()));
+
+        crate::splice::resize_file(handle, 4096, app.state()).unwrap();
+        // Past the new end, the revoked mapping falls back to plain reads,
+        // which come up short instead of faulting.
+        assert!(!stale.is_mapped(0, 1));
+        let mut buffer = vec![0u8; 4096];
+        assert_eq!(stale.read_at(32 * 1024, &mut buffer).unwrap(), 0);
+        assert_eq!(stale.read_at(0, &mut buffer).unwrap(), 4096);
+        assert_eq!(buffer, bytes[..4096]);
+    }
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::error::AppError;
+
+/// Most paths kept in the recent files list.
+const MAX_RECENT_FILES: usize = 20;
+
+/// The persisted list of recently opened paths, most recent first.
+pub struct RecentFiles {
+    /// JSON file the list is stored in.
+    path: PathBuf,
+    /// Serializes updates so concurrent opens cannot lose entries.
+    lock: Mutex<()>,
+}
+
+impl RecentFiles {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Manages a list stored in the app's data directory.
+    pub fn manage<R: Runtime>(app: &tauri::App<R>) {
+        if let Ok(dir) = app.path().app_data_dir() {
+            app.manage(Self::new(dir.join("recent_files.json")));
+        }
+    }
+
+    fn load(&self) -> Vec<String> {
+        // A missing or unreadable list just starts over.
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn store(&self, paths: &[String]) -> Result<(), AppError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| AppError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+        }
+        let json = serde_json::to_string_pretty(paths).map_err(|e| {
+            AppError::InvalidFormat(format!("Failed to serialize recent files: {}", e))
+        })?;
+        fs::write(&self.path, json).map_err(|e| {
+            AppError::Io(format!(
+                "Failed to write recent files to {}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Moves `opened` to the front of the list, adding it if needed.
+    pub fn push(&self, opened: &Path) -> Result<(), AppError> {
+        let opened = opened
+            .canonicalize()
+            .unwrap_or_else(|_| opened.to_path_buf())
+            .to_string_lossy()
+            .into_owned();
+        let _guard = self.lock.lock().unwrap();
+        let mut paths = self.load();
+        paths.retain(|path| *path != opened);
+        paths.insert(0, opened);
+        paths.truncate(MAX_RECENT_FILES);
+        self.store(&paths)
+    }
+}
+
+/// Records `path` as just opened, if the app keeps a recent files list.
+/// Failures are ignored; the list is a convenience.
+pub(crate) fn remember<R: Runtime>(app: &AppHandle<R>, path: &Path) {
+    if let Some(recent) = app.try_state::<RecentFiles>() {
+        let _ = recent.push(path);
+    }
+}
+
+/// Recently opened paths, most recent first.
+#[tauri::command]
+pub fn recent_files(recent: State<RecentFiles>) -> Vec<String> {
+    let _guard = recent.lock.lock().unwrap();
+    recent.load()
+}
+
+#[tauri::command]
+pub fn clear_recent_files(recent: State<RecentFiles>) -> Result<(), AppError> {
+    let _guard = recent.lock.lock().unwrap();
+    recent.store(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::test_support::{app, open, temp_file};
+
+    #[test]
+    fn opening_files_moves_them_to_the_front() {
+        let app = app();
+        let data_dir = tempfile::tempdir().unwrap();
+        app.manage(RecentFiles::new(data_dir.path().join("recent_files.json")));
+        let first = temp_file(b"a");
+        let second = temp_file(b"b");
+        let name = |file: &tempfile::NamedTempFile| {
+            file.path()
+                .canonicalize()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        open(&app, &first);
+        open(&app, &second);
+        open(&app, &first);
+        assert_eq!(recent_files(app.state()), [name(&first), name(&second)]);
+
+        clear_recent_files(app.state()).unwrap();
+        assert!(recent_files(app.state()).is_empty());
+    }
+
+    #[test]
+    fn list_is_capped() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let recent = RecentFiles::new(data_dir.path().join("nested/recent_files.json"));
+        for i in 0..MAX_RECENT_FILES + 5 {
+            recent
+                .push(Path::new(&format!("/no/such/file{}", i)))
+                .unwrap();
+        }
+
+        let paths = recent.load();
+        assert_eq!(paths.len(), MAX_RECENT_FILES);
+        assert_eq!(paths[0], format!("/no/such/file{}", MAX_RECENT_FILES + 4));
+        assert_eq!(paths[MAX_RECENT_FILES - 1], "/no/such/file5");
+    }
+}
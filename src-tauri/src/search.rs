@@ -0,0 +1,1371 @@
+use aho_corasick::AhoCorasick;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::{AppHandle, Emitter, Runtime, State};
+
+use crate::cancel::{CancelToken, Operation};
+use crate::error::AppError;
+use crate::inspect::{Endian, ValueKind};
+use crate::reader::{to_usize, FileReader};
+use crate::{lock_state, run_blocking, AppState};
+
+/// Size of each read while scanning the file.
+const SCAN_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How far a search advances between `search-progress` events.
+const PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Searches covering at least this many bytes run on every core.
+const PARALLEL_SEARCH_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Bytes each thread of a parallel search scans at a time.
+const SEARCH_PARTITION_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Payload of a `search-progress` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchProgress {
+    handle: u64,
+    bytes_scanned: u64,
+    file_size: u64,
+}
+
+/// Payload of the `search-done` event that ends a search with progress.
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchDone {
+    handle: u64,
+    match_count: usize,
+}
+
+/// Scans `[start, end)` and returns the offsets of non-overlapping windows
+/// of `pattern_len` bytes for which `is_match` holds, calling `on_progress`
+/// with the offset scanned up to after every chunk. An error from
+/// `on_progress` stops the scan.
+///
+/// The last `pattern_len - 1` bytes of every chunk are carried over into the
+/// next one so matches straddling a chunk boundary are still found.
+fn scan_matches_with_progress<F, P>(
+    reader: &FileReader,
+    start: u64,
+    end: u64,
+    pattern_len: usize,
+    max_results: usize,
+    is_match: F,
+    mut on_progress: P,
+) -> Result<Vec<u64>, AppError>
+where
+    F: Fn(&[u8]) -> bool,
+    P: FnMut(u64) -> Result<(), AppError>,
+{
+    if start > reader.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: start,
+            size: reader.file_size,
+        });
+    }
+
+    let mut matches = Vec::new();
+    if max_results == 0 {
+        return Ok(matches);
+    }
+
+    let mut window: Vec<u8> = Vec::with_capacity(SCAN_CHUNK_SIZE + pattern_len);
+    let mut window_start = start;
+    let mut read_pos = start;
+    let mut chunk = vec![0u8; SCAN_CHUNK_SIZE];
+
+    while read_pos < end {
+        let want = std::cmp::min(SCAN_CHUNK_SIZE as u64, end - read_pos) as usize;
+        let read = reader.read_at(read_pos, &mut chunk[..want])?;
+        if read == 0 {
+            break;
+        }
+        window.extend_from_slice(&chunk[..read]);
+        read_pos += read as u64;
+
+        let mut i = 0;
+        while i + pattern_len <= window.len() {
+            if is_match(&window[i..i + pattern_len]) {
+                matches.push(window_start + i as u64);
+                if matches.len() == max_results {
+                    return Ok(matches);
+                }
+                i += pattern_len;
+            } else {
+                i += 1;
+            }
+        }
+
+        // Everything before `i` has been fully examined; what remains is
+        // shorter than the pattern and may complete in the next chunk.
+        window.drain(..i);
+        window_start += i as u64;
+        on_progress(read_pos)?;
+    }
+
+    Ok(matches)
+}
+
+/// Like `scan_matches_with_progress` for an exact `pattern`, splitting
+/// `[start, end)` into partitions of `partition_size` bytes that are scanned
+/// in parallel, a batch of one per thread at a time. The offsets are exactly
+/// those a sequential scan returns.
+///
+/// Each partition reports the matches starting inside it, reading
+/// `pattern.len() - 1` bytes into the next one, so none is found twice. Its
+/// non-overlapping matches are only those of the whole scan if the scan
+/// enters it in step: when a match running over from the partition before
+/// ends after the partition's own first match, the partition is scanned
+/// again from where that match ends.
+fn scan_pattern_parallel<P>(
+    reader: &FileReader,
+    pattern: &[u8],
+    start: u64,
+    end: u64,
+    max_results: usize,
+    partition_size: u64,
+    on_progress: P,
+) -> Result<Vec<u64>, AppError>
+where
+    P: Fn(u64) -> Result<(), AppError> + Sync,
+{
+    if start > reader.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: start,
+            size: reader.file_size,
+        });
+    }
+    let mut matches = Vec::new();
+    if max_results == 0 {
+        return Ok(matches);
+    }
+
+    let overlap = pattern.len() as u64 - 1;
+    let scanned = AtomicU64::new(0);
+    let scan = |from: u64, partition_end: u64| {
+        let mut last = from;
+        scan_matches_with_progress(
+            reader,
+            from,
+            std::cmp::min(partition_end.saturating_add(overlap), end),
+            pattern.len(),
+            max_results,
+            |window| window == pattern,
+            |read_pos| {
+                let total = scanned.fetch_add(read_pos - last, Ordering::Relaxed) + read_pos - last;
+                last = read_pos;
+                on_progress(std::cmp::min(start.saturating_add(total), end))
+            },
+        )
+    };
+
+    let partitions: Vec<(u64, u64)> = (start..end)
+        .step_by(partition_size as usize)
+        .map(|from| {
+            (
+                from,
+                std::cmp::min(from.saturating_add(partition_size), end),
+            )
+        })
+        .collect();
+    // The first offset the next match may start at.
+    let mut next = start;
+    for batch in partitions.chunks(rayon::current_num_threads().max(1)) {
+        let found: Vec<_> = batch
+            .par_iter()
+            .map(|&(from, partition_end)| scan(from, partition_end))
+            .collect();
+        for (&(_, partition_end), partition_matches) in batch.iter().zip(found) {
+            let mut partition_matches = partition_matches?;
+            if partition_matches.first().is_some_and(|&first| first < next) {
+                partition_matches = scan(next, partition_end)?;
+            }
+            for offset in partition_matches {
+                matches.push(offset);
+                if matches.len() == max_results {
+                    return Ok(matches);
+                }
+                next = offset + pattern.len() as u64;
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Finds the non-overlapping matches of `pattern` in `[start, end)`, in
+/// parallel when the range is large.
+pub(crate) fn find_exact<P>(
+    reader: &FileReader,
+    pattern: &[u8],
+    start: u64,
+    end: u64,
+    max_results: usize,
+    on_progress: P,
+) -> Result<Vec<u64>, AppError>
+where
+    P: Fn(u64) -> Result<(), AppError> + Sync,
+{
+    if end.saturating_sub(start) >= PARALLEL_SEARCH_THRESHOLD {
+        scan_pattern_parallel(
+            reader,
+            pattern,
+            start,
+            end,
+            max_results,
+            SEARCH_PARTITION_SIZE,
+            on_progress,
+        )
+    } else {
+        scan_matches_with_progress(
+            reader,
+            start,
+            end,
+            pattern.len(),
+            max_results,
+            |window| window == pattern,
+            on_progress,
+        )
+    }
+}
+
+/// Returns the offset of the last match of `pattern` starting before
+/// `before`, scanning backward a chunk at a time. Each chunk also reads the
+/// first `pattern.len() - 1` bytes of the chunk after it, so matches
+/// straddling a boundary are still found.
+fn find_last_match(
+    reader: &FileReader,
+    pattern: &[u8],
+    before: u64,
+    token: &CancelToken,
+) -> Result<Option<u64>, AppError> {
+    if before > reader.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: before,
+            size: reader.file_size,
+        });
+    }
+    let overlap = pattern.len() as u64 - 1;
+    // Matches may run past `before` as long as they start ahead of it.
+    let scan_end = std::cmp::min(before.saturating_add(overlap), reader.file_size);
+    let mut chunk = vec![0u8; SCAN_CHUNK_SIZE + overlap as usize];
+    let mut chunk_end = scan_end;
+    while chunk_end >= pattern.len() as u64 {
+        token.check()?;
+        let chunk_start = chunk_end.saturating_sub(SCAN_CHUNK_SIZE as u64 + overlap);
+        let window = &mut chunk[..(chunk_end - chunk_start) as usize];
+        reader.read_exact_at(chunk_start, window)?;
+        let last = window
+            .windows(pattern.len())
+            .enumerate()
+            .rev()
+            .map(|(i, candidate)| (chunk_start + i as u64, candidate))
+            .find(|&(offset, candidate)| offset < before && candidate == pattern);
+        if let Some((offset, _)) = last {
+            return Ok(Some(offset));
+        }
+        if chunk_start == 0 {
+            break;
+        }
+        chunk_end = chunk_start + overlap;
+    }
+    Ok(None)
+}
+
+/// The part of the file a search starting at `start` covers: all of it from
+/// `start` on, or only what lies inside `range`.
+fn search_bounds(
+    reader: &FileReader,
+    start: u64,
+    range: Option<(u64, u64)>,
+) -> Result<(u64, u64), AppError> {
+    match range {
+        Some((range_start, range_end)) => {
+            let range_end = reader.validate_range(range_start, range_end)?;
+            let start = std::cmp::max(start, range_start);
+            Ok((std::cmp::min(start, range_end), range_end))
+        }
+        None => Ok((start, reader.file_size)),
+    }
+}
+
+/// Parses a hex pattern such as `AA ?? B? 0C` into `(value, mask)` bytes,
+/// where each `?` is a wildcard nibble.
+fn parse_masked_pattern(pattern: &str) -> Result<(Vec<u8>, Vec<u8>), AppError> {
+    let mut value = Vec::new();
+    let mut mask = Vec::new();
+
+    for token in pattern.split_whitespace() {
+        let nibbles: Vec<char> = token.chars().collect();
+        if nibbles.len() % 2 != 0 {
+            return Err(AppError::InvalidArgument(format!(
+                "Pattern token '{}' has an odd number of nibbles",
+                token
+            )));
+        }
+        for pair in nibbles.chunks(2) {
+            let mut byte_value = 0u8;
+            let mut byte_mask = 0u8;
+            for &nibble in pair {
+                byte_value <<= 4;
+                byte_mask <<= 4;
+                if nibble == '?' {
+                    continue;
+                }
+                let digit = nibble.to_digit(16).ok_or_else(|| {
+                    AppError::InvalidArgument(format!(
+                        "Invalid character '{}' in pattern token '{}'",
+                        nibble, token
+                    ))
+                })?;
+                byte_value |= digit as u8;
+                byte_mask |= 0xF;
+            }
+            value.push(byte_value);
+            mask.push(byte_mask);
+        }
+    }
+
+    if value.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search pattern must not be empty".to_string(),
+        ));
+    }
+    Ok((value, mask))
+}
+
+/// Searches for `pattern` from `start`, within `range` if given. With
+/// `progress`, emits a `search-progress` event every 64 MiB scanned and
+/// a `search-done` event with the match count before returning. Can be
+/// stopped through `operation_id`, and gives up with `timed_out` after
+/// `timeout_ms`. With `decoded`, searches the bytes as seen through the
+/// file's transform.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_bytes<R: Runtime>(
+    handle: u64,
+    pattern: Vec<u8>,
+    start: u64,
+    max_results: usize,
+    range: Option<(u64, u64)>,
+    progress: Option<bool>,
+    operation_id: Option<u64>,
+    decoded: Option<bool>,
+    timeout_ms: Option<u64>,
+    app: AppHandle<R>,
+    state: State<'_, AppState>,
+) -> Result<Vec<u64>, AppError> {
+    if pattern.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search pattern must not be empty".to_string(),
+        ));
+    }
+    let reader = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        if decoded.unwrap_or(false) {
+            file_state.view_reader()
+        } else {
+            file_state.reader()
+        }
+    };
+    let (start, end) = search_bounds(&reader, start, range)?;
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    let progress = progress.unwrap_or(false);
+
+    run_blocking(move || {
+        let file_size = reader.file_size;
+        let last_reported = AtomicU64::new(start);
+        let matches = find_exact(
+            &reader,
+            &pattern,
+            start,
+            end,
+            max_results,
+            |bytes_scanned| {
+                token.check()?;
+                let last = last_reported.load(Ordering::Relaxed);
+                // Parallel partitions race to report; one of them wins.
+                if progress
+                    && bytes_scanned.saturating_sub(last) >= PROGRESS_INTERVAL
+                    && last_reported
+                        .compare_exchange(last, bytes_scanned, Ordering::Relaxed, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    let _ = app.emit(
+                        "search-progress",
+                        SearchProgress {
+                            handle,
+                            bytes_scanned,
+                            file_size,
+                        },
+                    );
+                }
+                Ok(())
+            },
+        )?;
+        if progress {
+            let _ = app.emit(
+                "search-done",
+                SearchDone {
+                    handle,
+                    match_count: matches.len(),
+                },
+            );
+        }
+        Ok(matches)
+    })
+    .await
+}
+
+/// Returns the offset of the nearest match of `pattern` that starts before
+/// `before`, for stepping back through matches. Can be stopped through
+/// `operation_id`, and gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn find_bytes_backward(
+    handle: u64,
+    pattern: Vec<u8>,
+    before: u64,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Option<u64>, AppError> {
+    if pattern.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search pattern must not be empty".to_string(),
+        ));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    run_blocking(move || find_last_match(&reader, &pattern, before, &token)).await
+}
+
+/// A match found by `find_bytes_in_tags`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct TagMatch {
+    pub tag_id: u64,
+    pub offset: u64,
+}
+
+/// Searches for `pattern` only inside tags, optionally just those whose display
+/// name is `display_name_filter`. Matches are ordered by tag start, and a match
+/// inside overlapping tags is reported once for each. Can be stopped through
+/// `operation_id`, and gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn find_bytes_in_tags(
+    handle: u64,
+    pattern: Vec<u8>,
+    display_name_filter: Option<String>,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<TagMatch>, AppError> {
+    if pattern.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search pattern must not be empty".to_string(),
+        ));
+    }
+    let (reader, spans) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        let spans: Vec<(u64, u64, u64)> = file_state
+            .tags
+            .iter()
+            .filter(|tag| {
+                display_name_filter
+                    .as_ref()
+                    .map_or(true, |filter| tag.display_name == *filter)
+            })
+            .map(|tag| (tag.id, tag.start, tag.end))
+            .collect();
+        (file_state.reader(), spans)
+    };
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let mut found = Vec::new();
+        for (tag_id, start, end) in spans {
+            let offsets = scan_matches_with_progress(
+                &reader,
+                start,
+                end,
+                pattern.len(),
+                usize::MAX,
+                |window| window == pattern,
+                |_| token.check(),
+            )?;
+            found.extend(
+                offsets
+                    .into_iter()
+                    .map(|offset| TagMatch { tag_id, offset }),
+            );
+        }
+        Ok(found)
+    })
+    .await
+}
+
+/// Longest context `find_with_context` returns on each side of a match.
+const MAX_MATCH_CONTEXT: u64 = 4096;
+
+/// A match found by `find_with_context`, with the bytes around it.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct MatchContext {
+    pub offset: u64,
+    /// Offset of the first byte of `bytes`, before the match unless the file
+    /// starts first.
+    pub context_start: u64,
+    /// Up to `context` bytes before the match, the match itself and up to
+    /// `context` bytes after it.
+    pub bytes: Vec<u8>,
+}
+
+/// Like `find_bytes` from the start of the file, returning each match with up
+/// to `context` bytes around it (at most 4 KiB) so hits can be previewed
+/// without further reads. Can be stopped through `operation_id`, and gives up
+/// with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn find_with_context(
+    handle: u64,
+    pattern: Vec<u8>,
+    context: u64,
+    max_results: usize,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<MatchContext>, AppError> {
+    if pattern.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search pattern must not be empty".to_string(),
+        ));
+    }
+    if context > MAX_MATCH_CONTEXT {
+        return Err(AppError::InvalidArgument(format!(
+            "Context must be at most {} bytes",
+            MAX_MATCH_CONTEXT
+        )));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let offsets = scan_matches_with_progress(
+            &reader,
+            0,
+            reader.file_size,
+            pattern.len(),
+            max_results,
+            |window| window == pattern,
+            |_| token.check(),
+        )?;
+        offsets
+            .into_iter()
+            .map(|offset| {
+                let context_start = offset.saturating_sub(context);
+                let context_end =
+                    std::cmp::min(offset + pattern.len() as u64 + context, reader.file_size);
+                let mut bytes = vec![0u8; to_usize(context_end - context_start)?];
+                reader.read_exact_at(context_start, &mut bytes)?;
+                Ok(MatchContext {
+                    offset,
+                    context_start,
+                    bytes,
+                })
+            })
+            .collect()
+    })
+    .await
+}
+
+/// Searches for a hex pattern with wildcard nibbles, e.g. `4D 5A ?? ?0`, within
+/// `range` if given. Can be stopped through `operation_id`, and gives up with
+/// `timed_out` after `timeout_ms`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_pattern(
+    handle: u64,
+    pattern: String,
+    start: u64,
+    max_results: usize,
+    range: Option<(u64, u64)>,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<u64>, AppError> {
+    let (value, mask) = parse_masked_pattern(&pattern)?;
+
+    let reader = lock_state(&state).get(handle)?.reader();
+    let (start, end) = search_bounds(&reader, start, range)?;
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    run_blocking(move || {
+        scan_matches_with_progress(
+            &reader,
+            start,
+            end,
+            value.len(),
+            max_results,
+            |window| {
+                window
+                    .iter()
+                    .zip(value.iter().zip(&mask))
+                    .all(|(byte, (value, mask))| byte & mask == *value)
+            },
+            |_| token.check(),
+        )
+    })
+    .await
+}
+
+/// Encodes `needle` as it would be stored in a file with `encoding`.
+fn encode_text(needle: &str, encoding: &str) -> Result<Vec<u8>, AppError> {
+    match encoding {
+        "ascii" => {
+            if !needle.is_ascii() {
+                return Err(AppError::InvalidArgument(
+                    "Search text contains non-ASCII characters".to_string(),
+                ));
+            }
+            Ok(needle.as_bytes().to_vec())
+        }
+        "utf8" => Ok(needle.as_bytes().to_vec()),
+        "utf16le" => Ok(needle.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        "utf16be" => Ok(needle.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        other => Err(AppError::InvalidArgument(format!(
+            "Unsupported text encoding '{}'",
+            other
+        ))),
+    }
+}
+
+/// Returns up to `max_results` offsets that are a multiple of
+/// `pattern.len()` and hold `pattern`. No match can straddle two chunks,
+/// since chunks start at such offsets too.
+fn scan_aligned_values(
+    reader: &FileReader,
+    pattern: &[u8],
+    max_results: usize,
+    token: &CancelToken,
+) -> Result<Vec<u64>, AppError> {
+    let mut matches = Vec::new();
+    if max_results == 0 {
+        return Ok(matches);
+    }
+    reader.for_each_chunk(0, reader.file_size, SCAN_CHUNK_SIZE, |offset, chunk| {
+        token.check()?;
+        for (i, value) in chunk.chunks_exact(pattern.len()).enumerate() {
+            if value == pattern && matches.len() < max_results {
+                matches.push(offset + (i * pattern.len()) as u64);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(matches)
+}
+
+/// Searches for `value` stored as `kind` (an inspector type such as `u32`) in
+/// `endian` byte order. With `aligned`, only offsets that are a multiple of the
+/// type's size match, as for fields of a packed array. Can be stopped through
+/// `operation_id`, and gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_value(
+    handle: u64,
+    kind: String,
+    endian: String,
+    value: String,
+    aligned: bool,
+    max_results: usize,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<u64>, AppError> {
+    let pattern = ValueKind::parse(&kind)?.encode(&value, Endian::parse(&endian)?)?;
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        if aligned {
+            return scan_aligned_values(&reader, &pattern, max_results, &token);
+        }
+        scan_matches_with_progress(
+            &reader,
+            0,
+            reader.file_size,
+            pattern.len(),
+            max_results,
+            |window| window == pattern,
+            |_| token.check(),
+        )
+    })
+    .await
+}
+
+/// Searches for `needle` encoded as `ascii`, `utf8`, `utf16le` or `utf16be`.
+/// With `case_insensitive`, ASCII letters match regardless of case. Only
+/// `range` is searched if given. Can be stopped through `operation_id`, and
+/// gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_text(
+    handle: u64,
+    needle: String,
+    encoding: String,
+    case_insensitive: bool,
+    start: u64,
+    max_results: usize,
+    range: Option<(u64, u64)>,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<u64>, AppError> {
+    let mut pattern = encode_text(&needle, &encoding)?;
+    if pattern.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search text must not be empty".to_string(),
+        ));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let (start, end) = search_bounds(&reader, start, range)?;
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        if case_insensitive {
+            pattern.make_ascii_lowercase();
+        }
+        scan_matches_with_progress(
+            &reader,
+            start,
+            end,
+            pattern.len(),
+            max_results,
+            |window| {
+                if case_insensitive {
+                    window
+                        .iter()
+                        .zip(&pattern)
+                        .all(|(byte, expected)| byte.to_ascii_lowercase() == *expected)
+                } else {
+                    window == pattern
+                }
+            },
+            |_| token.check(),
+        )
+    })
+    .await
+}
+
+/// Finds every occurrence of each of `patterns` in a single pass and returns
+/// `(pattern index, offset)` pairs ordered by offset. Overlapping matches are
+/// all reported. Patterns must not be empty. Stops with `Cancelled` once
+/// `token` is cancelled.
+pub(crate) fn scan_signatures(
+    reader: &FileReader,
+    patterns: &[&[u8]],
+    token: &CancelToken,
+) -> Result<Vec<(usize, u64)>, AppError> {
+    let Some(longest) = patterns.iter().map(|bytes| bytes.len()).max() else {
+        return Ok(Vec::new());
+    };
+    let automaton = AhoCorasick::new(patterns).map_err(|e| {
+        AppError::InvalidArgument(format!("Failed to build signature matcher: {}", e))
+    })?;
+
+    let mut found = Vec::new();
+    let mut window: Vec<u8> = Vec::with_capacity(SCAN_CHUNK_SIZE + longest);
+    let mut window_start = 0u64;
+    reader.for_each_chunk(0, reader.file_size, SCAN_CHUNK_SIZE, |_, chunk| {
+        token.check()?;
+        let carried = window.len();
+        window.extend_from_slice(chunk);
+        for found_match in automaton.find_overlapping_iter(&window) {
+            // Matches ending within the carried bytes were already
+            // reported with the previous chunk.
+            if found_match.end() > carried {
+                found.push((
+                    found_match.pattern().as_usize(),
+                    window_start + found_match.start() as u64,
+                ));
+            }
+        }
+
+        // Keep just enough bytes for a match straddling the boundary.
+        let examined = window.len() - std::cmp::min(longest - 1, window.len());
+        window.drain(..examined);
+        window_start += examined as u64;
+        Ok(())
+    })?;
+
+    found.sort_by_key(|(_, offset)| *offset);
+    Ok(found)
+}
+
+/// Finds every occurrence of each named byte pattern in a single pass and
+/// returns `(name, offset)` pairs ordered by offset. Overlapping matches,
+/// including matches of different patterns at the same offset, are all
+/// reported. Can be stopped through `operation_id`, and gives up with
+/// `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn find_signatures(
+    handle: u64,
+    patterns: Vec<(String, Vec<u8>)>,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<(String, u64)>, AppError> {
+    if let Some((name, _)) = patterns.iter().find(|(_, bytes)| bytes.is_empty()) {
+        return Err(AppError::InvalidArgument(format!(
+            "Signature '{}' must not be empty",
+            name
+        )));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let bytes: Vec<&[u8]> = patterns.iter().map(|(_, bytes)| bytes.as_slice()).collect();
+        let found = scan_signatures(&reader, &bytes, &token)?;
+        Ok(found
+            .into_iter()
+            .map(|(index, offset)| (patterns[index].0.clone(), offset))
+            .collect())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tauri::{Listener, Manager};
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::tags::{add_tag, Tag};
+    use crate::test_support::{app, open_temp, reader_over};
+
+    fn find_bytes_in_file(
+        reader: &FileReader,
+        pattern: &[u8],
+        start: u64,
+        max_results: usize,
+    ) -> Result<Vec<u64>, AppError> {
+        find_exact(
+            reader,
+            pattern,
+            start,
+            reader.file_size,
+            max_results,
+            |_| Ok(()),
+        )
+    }
+
+    #[test]
+    fn finds_match_straddling_chunk_boundary() {
+        let mut bytes = vec![0u8; 2 * SCAN_CHUNK_SIZE];
+        let boundary = SCAN_CHUNK_SIZE - 2;
+        bytes[boundary..boundary + 4].copy_from_slice(b"\xde\xad\xbe\xef");
+        bytes[10..14].copy_from_slice(b"\xde\xad\xbe\xef");
+        let reader = reader_over(&bytes);
+
+        let found = find_bytes_in_file(&reader, b"\xde\xad\xbe\xef", 0, usize::MAX).unwrap();
+        assert_eq!(found, vec![10, boundary as u64]);
+        assert_eq!(
+            find_bytes_in_file(&reader, b"\xde\xad\xbe\xef", 11, usize::MAX).unwrap(),
+            vec![boundary as u64]
+        );
+        assert_eq!(
+            find_bytes_in_file(&reader, b"\xde\xad\xbe\xef", 0, 1).unwrap(),
+            vec![10]
+        );
+    }
+
+    #[test]
+    fn matches_do_not_overlap() {
+        let reader = reader_over(b"aaaaa");
+        assert_eq!(
+            find_bytes_in_file(&reader, b"aa", 0, usize::MAX).unwrap(),
+            vec![0, 2]
+        );
+    }
+
+    #[test]
+    fn masked_pattern_mixes_byte_and_nibble_wildcards() {
+        let (value, mask) = parse_masked_pattern("4D ?? ?A B?").unwrap();
+        assert_eq!(value, vec![0x4d, 0x00, 0x0a, 0xb0]);
+        assert_eq!(mask, vec![0xff, 0x00, 0x0f, 0xf0]);
+
+        let app = app();
+        let bytes = b"\x4d\x01\x2a\xb3--\x4d\xff\xfa\xbf--\x4d\x00\x0b\xb0--\x4d\x00\x0a\xc0";
+        let handle = open_bytes(bytes.to_vec(), app.state()).unwrap().handle;
+        let found = tauri::async_runtime::block_on(find_pattern(
+            handle,
+            "4D ?? ?A B?".to_string(),
+            0,
+            usize::MAX,
+            None,
+            None,
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(found, vec![0, 6]);
+    }
+
+    #[test]
+    fn masked_pattern_rejects_bad_tokens() {
+        for pattern in ["", "4", "4D5", "GG", "4D ?"] {
+            assert!(
+                matches!(
+                    parse_masked_pattern(pattern),
+                    Err(AppError::InvalidArgument(_))
+                ),
+                "{:?} should be rejected",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn find_text_utf16le_and_case_insensitive() {
+        let app = app();
+        let mut bytes = b"..Hello..".to_vec();
+        bytes.extend("hElLo".encode_utf16().flat_map(u16::to_le_bytes));
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let find = |needle: &str, encoding: &str, case_insensitive: bool| {
+            tauri::async_runtime::block_on(find_text(
+                handle,
+                needle.to_string(),
+                encoding.to_string(),
+                case_insensitive,
+                0,
+                usize::MAX,
+                None,
+                None,
+                None,
+                app.state(),
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(find("hello", "utf16le", true), vec![9]);
+        assert!(find("hello", "utf16le", false).is_empty());
+        assert_eq!(find("HELLO", "ascii", true), vec![2]);
+        assert_eq!(find("Hello", "ascii", false), vec![2]);
+    }
+
+    #[test]
+    fn overlapping_signatures_are_all_reported() {
+        let mut bytes = vec![0u8; 2 * SCAN_CHUNK_SIZE];
+        let boundary = SCAN_CHUNK_SIZE - 2;
+        for at in [100, boundary] {
+            bytes[at..at + 5].copy_from_slice(b"MZ\x90\x00\x03");
+        }
+        let reader = reader_over(&bytes);
+
+        let patterns: [&[u8]; 3] = [b"MZ", b"MZ\x90\x00", b"\x90\x00\x03"];
+        let mut found = scan_signatures(&reader, &patterns, &CancelToken::default()).unwrap();
+        found.sort_by_key(|&(index, offset)| (offset, index));
+        let expected: Vec<(usize, u64)> = [100, boundary as u64]
+            .into_iter()
+            .flat_map(|at| [(0, at), (1, at), (2, at + 2)])
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn find_bytes_reports_progress_and_done() {
+        let app = app();
+        let size = PROGRESS_INTERVAL as usize + 2 * SCAN_CHUNK_SIZE;
+        let mut bytes = vec![0u8; size];
+        for at in [5, size / 2, size - 4] {
+            bytes[at..at + 4].copy_from_slice(b"FIND");
+        }
+        let (_file, handle) = open_temp(&app, &bytes);
+        drop(bytes);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        for event in ["search-progress", "search-done"] {
+            let events = Arc::clone(&events);
+            app.listen_any(event, move |e| {
+                let payload: serde_json::Value = serde_json::from_str(e.payload()).unwrap();
+                events.lock().unwrap().push((event, payload));
+            });
+        }
+
+        let found = tauri::async_runtime::block_on(find_bytes(
+            handle,
+            b"FIND".to_vec(),
+            0,
+            usize::MAX,
+            None,
+            Some(true),
+            None,
+            None,
+            None,
+            app.handle().clone(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(found.len(), 3);
+
+        let events = events.lock().unwrap();
+        let (last, progress) = events.split_last().unwrap();
+        assert!(!progress.is_empty());
+        for (event, payload) in progress {
+            assert_eq!(*event, "search-progress");
+            assert!(payload["bytes_scanned"].as_u64().unwrap() >= PROGRESS_INTERVAL);
+            assert_eq!(payload["file_size"], size as u64);
+        }
+        assert_eq!(last.0, "search-done");
+        assert_eq!(last.1["match_count"], 3);
+    }
+
+    #[test]
+    fn range_confines_the_search() {
+        let app = app();
+        let handle = open_bytes(b"ab--ab--ab--ab".to_vec(), app.state())
+            .unwrap()
+            .handle;
+        let find = |start, range| {
+            tauri::async_runtime::block_on(find_bytes(
+                handle,
+                b"ab".to_vec(),
+                start,
+                usize::MAX,
+                range,
+                None,
+                None,
+                None,
+                None,
+                app.handle().clone(),
+                app.state(),
+            ))
+        };
+
+        assert_eq!(find(0, None).unwrap(), vec![0, 4, 8, 12]);
+        // A match straddling the end of the range is outside it.
+        assert_eq!(find(0, Some((3, 9))).unwrap(), vec![4]);
+        assert_eq!(find(6, Some((3, 14))).unwrap(), vec![8, 12]);
+        assert!(matches!(
+            find(0, Some((20, 30))),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn find_bytes_in_tags_reports_the_containing_tag() {
+        let app = app();
+        let handle = open_bytes(b"ab--ab--ab--ab".to_vec(), app.state())
+            .unwrap()
+            .handle;
+        let mut ids = Vec::new();
+        for (start, end, display_name) in [(0, 3, "Header"), (4, 11, "Body"), (12, 14, "Header")] {
+            let tag = Tag::new(start, end, display_name.to_lowercase(), display_name);
+            ids.push(add_tag(handle, tag, None, None, None, app.state()).unwrap());
+        }
+        let find = |filter: Option<&str>| {
+            tauri::async_runtime::block_on(find_bytes_in_tags(
+                handle,
+                b"ab".to_vec(),
+                filter.map(str::to_string),
+                None,
+                None,
+                app.state(),
+            ))
+            .unwrap()
+        };
+
+        let all = find(None);
+        assert_eq!(
+            all.iter()
+                .map(|found| (found.tag_id, found.offset))
+                .collect::<Vec<_>>(),
+            [(ids[0], 0), (ids[1], 4), (ids[1], 8), (ids[2], 12)]
+        );
+        let headers = find(Some("Header"));
+        assert_eq!(
+            headers,
+            [
+                TagMatch {
+                    tag_id: ids[0],
+                    offset: 0
+                },
+                TagMatch {
+                    tag_id: ids[2],
+                    offset: 12
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn context_is_clamped_at_the_file_edges() {
+        let app = app();
+        let handle = open_bytes(b"ABcdefghijklmnopAB".to_vec(), app.state())
+            .unwrap()
+            .handle;
+
+        let found = tauri::async_runtime::block_on(find_with_context(
+            handle,
+            b"AB".to_vec(),
+            4,
+            10,
+            None,
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(
+            found,
+            [
+                MatchContext {
+                    offset: 0,
+                    context_start: 0,
+                    bytes: b"ABcdef".to_vec(),
+                },
+                MatchContext {
+                    offset: 16,
+                    context_start: 12,
+                    bytes: b"mnopAB".to_vec(),
+                },
+            ]
+        );
+        let first = &found[0];
+        let at = (first.offset - first.context_start) as usize;
+        assert_eq!(&first.bytes[at..at + 2], b"AB");
+
+        let wide = tauri::async_runtime::block_on(find_with_context(
+            handle,
+            b"AB".to_vec(),
+            MAX_MATCH_CONTEXT + 1,
+            10,
+            None,
+            None,
+            app.state(),
+        ));
+        assert!(matches!(wide, Err(AppError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn backward_search_finds_the_nearest_earlier_match() {
+        let mut bytes = vec![0u8; 2 * SCAN_CHUNK_SIZE];
+        // Straddles the start of the first chunk read back from the end.
+        let straddling = SCAN_CHUNK_SIZE as u64 - 5;
+        for offset in [10, 500, straddling] {
+            bytes[offset as usize..offset as usize + 4].copy_from_slice(b"\xde\xad\xbe\xef");
+        }
+        let app = app();
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let find = |before| {
+            tauri::async_runtime::block_on(find_bytes_backward(
+                handle,
+                b"\xde\xad\xbe\xef".to_vec(),
+                before,
+                None,
+                None,
+                app.state(),
+            ))
+        };
+
+        assert_eq!(find(2 * SCAN_CHUNK_SIZE as u64), Ok(Some(straddling)));
+        assert_eq!(find(straddling + 1), Ok(Some(straddling)));
+        assert_eq!(find(straddling), Ok(Some(500)));
+        assert_eq!(find(501), Ok(Some(500)));
+        assert_eq!(find(500), Ok(Some(10)));
+        assert_eq!(find(10), Ok(None));
+        assert!(matches!(
+            find(3 * SCAN_CHUNK_SIZE as u64),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn searches_give_up_after_their_timeout() {
+        let app = app();
+        let handle = open_bytes(vec![0; 64 * 1024 * 1024], app.state())
+            .unwrap()
+            .handle;
+        let find = |timeout_ms| {
+            tauri::async_runtime::block_on(find_bytes(
+                handle,
+                vec![0],
+                0,
+                usize::MAX,
+                None,
+                None,
+                None,
+                None,
+                timeout_ms,
+                app.handle().clone(),
+                app.state(),
+            ))
+        };
+
+        assert_eq!(find(Some(0)), Err(AppError::TimedOut { timeout_ms: 0 }));
+        let hashed = tauri::async_runtime::block_on(crate::hash::hash_range(
+            handle,
+            Some(0),
+            Some(64 * 1024 * 1024),
+            None,
+            "sha256".to_string(),
+            None,
+            Some(0),
+            app.state(),
+        ));
+        assert_eq!(hashed, Err(AppError::TimedOut { timeout_ms: 0 }));
+        let pattern = tauri::async_runtime::block_on(find_pattern(
+            handle,
+            "00 ?0".to_string(),
+            0,
+            usize::MAX,
+            None,
+            None,
+            Some(0),
+            app.state(),
+        ));
+        assert_eq!(pattern, Err(AppError::TimedOut { timeout_ms: 0 }));
+        let text = tauri::async_runtime::block_on(find_text(
+            handle,
+            "a".to_string(),
+            "utf8".to_string(),
+            false,
+            0,
+            usize::MAX,
+            None,
+            None,
+            Some(0),
+            app.state(),
+        ));
+        assert_eq!(text, Err(AppError::TimedOut { timeout_ms: 0 }));
+        // A generous timeout leaves the search alone.
+        let found = tauri::async_runtime::block_on(find_bytes(
+            handle,
+            vec![0],
+            0,
+            10,
+            None,
+            None,
+            None,
+            None,
+            Some(60_000),
+            app.handle().clone(),
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(found.len(), 10);
+    }
+
+    #[test]
+    fn finds_typed_values_aligned_or_anywhere() {
+        let app = app();
+        let mut bytes = vec![0u8; 32];
+        bytes[4..8].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        bytes[13..17].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        bytes[24..28].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let find = |endian: &str, aligned| {
+            tauri::async_runtime::block_on(find_value(
+                handle,
+                "u32".to_string(),
+                endian.to_string(),
+                "305419896".to_string(),
+                aligned,
+                usize::MAX,
+                None,
+                None,
+                app.state(),
+            ))
+        };
+
+        assert_eq!(find("little", false).unwrap(), [4, 13]);
+        assert_eq!(find("little", true).unwrap(), [4]);
+        assert_eq!(find("big", true).unwrap(), [24]);
+        assert!(matches!(
+            tauri::async_runtime::block_on(find_value(
+                handle,
+                "u24".to_string(),
+                "little".to_string(),
+                "1".to_string(),
+                false,
+                10,
+                None,
+                None,
+                app.state(),
+            )),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn parallel_search_matches_the_sequential_scan() {
+        // Runs of `a` and `b` give many overlapping candidates, some of them
+        // straddling partition boundaries.
+        let mut state = 0x853c_49e6_748f_ea9bu64;
+        let bytes: Vec<u8> = (0..20_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                if state % 3 == 0 {
+                    b'b'
+                } else {
+                    b'a'
+                }
+            })
+            .collect();
+        let reader = reader_over(&bytes);
+        let end = bytes.len() as u64;
+
+        for pattern in [&b"a"[..], b"aa", b"aba", b"aaaa", b"abaab"] {
+            let sequential = scan_matches_with_progress(
+                &reader,
+                0,
+                end,
+                pattern.len(),
+                usize::MAX,
+                |window| window == pattern,
+                |_| Ok(()),
+            )
+            .unwrap();
+            assert!(sequential.len() > 100, "{:?}", pattern);
+            for partition_size in [1, 2, 3, 7, 64, 4099, 1 << 20] {
+                for (start, max_results) in [(0, usize::MAX), (5, usize::MAX), (0, 50)] {
+                    let expected: Vec<u64> = scan_matches_with_progress(
+                        &reader,
+                        start,
+                        end,
+                        pattern.len(),
+                        max_results,
+                        |window| window == pattern,
+                        |_| Ok(()),
+                    )
+                    .unwrap();
+                    let parallel = scan_pattern_parallel(
+                        &reader,
+                        pattern,
+                        start,
+                        end,
+                        max_results,
+                        partition_size,
+                        |_| Ok(()),
+                    )
+                    .unwrap();
+                    assert_eq!(
+                        parallel,
+                        expected,
+                        "{:?} from {} in partitions of {}",
+                        String::from_utf8_lossy(pattern),
+                        start,
+                        partition_size
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::{lock_state, AppState, FileState};
+
+fn check_selection(file_state: &FileState, start: u64, end: u64) -> Result<(), AppError> {
+    if end <= start {
+        return Err(AppError::InvalidRange { start, end });
+    }
+    if end > file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: end,
+            size: file_state.file_size,
+        });
+    }
+    Ok(())
+}
+
+/// Selects `[start, end)` alone, replacing any other selections. Range
+/// commands fall back to the selection when called without a range.
+#[tauri::command]
+pub fn set_selection(
+    handle: u64,
+    start: u64,
+    end: u64,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    check_selection(file_state, start, end)?;
+    file_state.selections = vec![(start, end)];
+    Ok(())
+}
+
+/// Returns the first selection.
+#[tauri::command]
+pub fn get_selection(handle: u64, state: State<AppState>) -> Result<Option<(u64, u64)>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(app_state.get(handle)?.selections.first().copied())
+}
+
+/// Selects `[start, end)` as well as what is already selected, for commands
+/// such as `fill_range` and `hash_range` that work on every selection. The
+/// range must not overlap another selection.
+#[tauri::command]
+pub fn add_selection(
+    handle: u64,
+    start: u64,
+    end: u64,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    check_selection(file_state, start, end)?;
+    let index = file_state
+        .selections
+        .partition_point(|&(selected_start, _)| selected_start < start);
+    let overlaps_previous = index > 0 && file_state.selections[index - 1].1 > start;
+    let overlaps_next = file_state
+        .selections
+        .get(index)
+        .is_some_and(|&(next_start, _)| next_start < end);
+    if overlaps_previous || overlaps_next {
+        return Err(AppError::InvalidArgument(format!(
+            "Selection [{}, {}) overlaps another selection",
+            start, end
+        )));
+    }
+    file_state.selections.insert(index, (start, end));
+    Ok(())
+}
+
+/// Returns every selection, ordered by offset.
+#[tauri::command]
+pub fn list_selections(handle: u64, state: State<AppState>) -> Result<Vec<(u64, u64)>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(app_state.get(handle)?.selections.clone())
+}
+
+#[tauri::command]
+pub fn clear_selections(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    app_state.get_mut(handle)?.selections.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::hash::hash_range;
+    use crate::splice::{delete_bytes, fill_range, insert_bytes};
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn set_get_and_clear() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 16]);
+
+        assert_eq!(get_selection(handle, app.state()).unwrap(), None);
+        set_selection(handle, 2, 6, app.state()).unwrap();
+        assert_eq!(get_selection(handle, app.state()).unwrap(), Some((2, 6)));
+        assert_eq!(
+            set_selection(handle, 6, 6, app.state()),
+            Err(AppError::InvalidRange { start: 6, end: 6 })
+        );
+        assert!(matches!(
+            set_selection(handle, 0, 17, app.state()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+        clear_selections(handle, app.state()).unwrap();
+        assert_eq!(get_selection(handle, app.state()).unwrap(), None);
+    }
+
+    #[test]
+    fn range_commands_default_to_the_selection() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"..abc..");
+        let hash = |start, end| {
+            tauri::async_runtime::block_on(hash_range(
+                handle,
+                start,
+                end,
+                None,
+                "crc32".to_string(),
+                None,
+                None,
+                app.state(),
+            ))
+        };
+
+        assert!(matches!(
+            hash(None, None),
+            Err(AppError::InvalidArgument(_))
+        ));
+        set_selection(handle, 2, 5, app.state()).unwrap();
+        assert_eq!(hash(None, None).unwrap(), hash(Some(2), Some(5)).unwrap());
+    }
+
+    #[test]
+    fn selection_follows_edits() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 16]);
+        set_selection(handle, 4, 8, app.state()).unwrap();
+
+        insert_bytes(handle, 0, vec![1; 2], app.state()).unwrap();
+        assert_eq!(get_selection(handle, app.state()).unwrap(), Some((6, 10)));
+        delete_bytes(handle, 8, 4, app.state()).unwrap();
+        assert_eq!(get_selection(handle, app.state()).unwrap(), Some((6, 8)));
+        delete_bytes(handle, 5, 4, app.state()).unwrap();
+        assert_eq!(get_selection(handle, app.state()).unwrap(), None);
+    }
+
+    #[test]
+    fn adds_disjoint_selections_in_order() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 32]);
+
+        set_selection(handle, 10, 12, app.state()).unwrap();
+        add_selection(handle, 20, 24, app.state()).unwrap();
+        add_selection(handle, 2, 4, app.state()).unwrap();
+        // Touching is not overlapping.
+        add_selection(handle, 12, 14, app.state()).unwrap();
+        assert_eq!(
+            list_selections(handle, app.state()).unwrap(),
+            [(2, 4), (10, 12), (12, 14), (20, 24)]
+        );
+        for (start, end) in [(3, 5), (11, 13), (0, 32), (23, 25)] {
+            assert!(matches!(
+                add_selection(handle, start, end, app.state()),
+                Err(AppError::InvalidArgument(_))
+            ));
+        }
+        assert!(matches!(
+            add_selection(handle, 30, 33, app.state()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+
+        set_selection(handle, 0, 1, app.state()).unwrap();
+        assert_eq!(list_selections(handle, app.state()).unwrap(), [(0, 1)]);
+        clear_selections(handle, app.state()).unwrap();
+        assert!(list_selections(handle, app.state()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fills_and_hashes_every_selection() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789abcdef");
+        set_selection(handle, 1, 3, app.state()).unwrap();
+        add_selection(handle, 8, 11, app.state()).unwrap();
+        let hash = |start, end| {
+            tauri::async_runtime::block_on(hash_range(
+                handle,
+                start,
+                end,
+                None,
+                "sha256".to_string(),
+                None,
+                None,
+                app.state(),
+            ))
+        };
+        let selected = hash(None, None).unwrap();
+
+        fill_range(handle, None, None, b"xy".to_vec(), app.state()).unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"0xy34567xyxbcdef");
+
+        // The selected bytes hashed together, in order.
+        let (_joined, joined) = open_temp(&app, b"1289a");
+        let expected = tauri::async_runtime::block_on(hash_range(
+            joined,
+            Some(0),
+            Some(5),
+            None,
+            "sha256".to_string(),
+            None,
+            None,
+            app.state(),
+        ))
+        .unwrap();
+        assert_eq!(selected, expected);
+        assert!(matches!(
+            tauri::async_runtime::block_on(crate::stats::shannon_entropy(
+                handle,
+                None,
+                None,
+                None,
+                app.state()
+            )),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,917 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::bookmarks::{validate_bookmark, Bookmark};
+use crate::error::AppError;
+use crate::hash::{fingerprint_of, Fingerprint};
+use crate::tags::{insert_sorted, validate_tag, Tag};
+use crate::{lock_state, AppState, FileState};
+
+/// Returns `path` if given, otherwise `<file_path>.bltags` next to the file.
+fn sidecar_path(file_state: &FileState, path: Option<String>) -> Result<PathBuf, AppError> {
+    if let Some(path) = path {
+        return Ok(PathBuf::from(path));
+    }
+    let file_path = file_state.file_path.as_ref().ok_or_else(|| {
+        AppError::InvalidArgument("Files opened from memory need an explicit tags path".to_string())
+    })?;
+    let mut sidecar = file_path.clone().into_os_string();
+    sidecar.push(".bltags");
+    Ok(PathBuf::from(sidecar))
+}
+
+/// Version written by `save_tags`. Bump it whenever a field changes meaning,
+/// and teach `parse_sidecar` to migrate the old one. Sidecars written before
+/// versioning have no version at all.
+const SIDECAR_SCHEMA_VERSION: u32 = 2;
+
+/// Contents of a sidecar file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Sidecar {
+    schema_version: u32,
+    tags: Vec<Tag>,
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+    /// The file's contents when saved, for finding the sidecar again after
+    /// the file is renamed.
+    #[serde(default)]
+    fingerprint: Option<Fingerprint>,
+}
+
+/// Sidecars from before versioning: a bare tag list in the oldest, then an
+/// object with tags and bookmarks.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LegacySidecar {
+    WithBookmarks {
+        tags: Vec<Tag>,
+        #[serde(default)]
+        bookmarks: Vec<Bookmark>,
+    },
+    TagsOnly(Vec<Tag>),
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LoadTagsReport {
+    loaded: usize,
+    /// Tags from the sidecar that did not fit the current file.
+    dropped: Vec<Tag>,
+    loaded_bookmarks: usize,
+    /// Bookmarks from the sidecar that did not fit the current file.
+    dropped_bookmarks: Vec<Bookmark>,
+}
+
+/// Writes the file's tags and bookmarks as JSON and returns the path written
+/// to.
+#[tauri::command]
+pub fn save_tags(
+    handle: u64,
+    path: Option<String>,
+    state: State<AppState>,
+) -> Result<String, AppError> {
+    // Without one the sidecar still loads from next to the file.
+    let fingerprint = fingerprint_of(&state, handle).ok();
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let path = sidecar_path(file_state, path)?;
+
+    let sidecar = Sidecar {
+        schema_version: SIDECAR_SCHEMA_VERSION,
+        tags: file_state.tags.clone(),
+        bookmarks: file_state.bookmarks.clone(),
+        fingerprint,
+    };
+    let json = serde_json::to_string_pretty(&sidecar)
+        .map_err(|e| AppError::InvalidFormat(format!("Failed to serialize tags: {}", e)))?;
+    fs::write(&path, json)
+        .map_err(|e| AppError::Io(format!("Failed to write tags to {}: {}", path.display(), e)))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Replaces the file's tags and bookmarks with those stored at `path`. Entries
+/// that are not valid for the current file size are dropped and reported.
+/// Without a `path` the sidecar next to the file is read, or if there is none,
+/// a sidecar in the same directory saved for the same contents, so tags
+/// follow a renamed file.
+#[tauri::command]
+pub fn load_tags(
+    handle: u64,
+    path: Option<String>,
+    state: State<AppState>,
+) -> Result<LoadTagsReport, AppError> {
+    let explicit = path.is_some();
+    let mut path = sidecar_path(lock_state(&state).get(handle)?, path)?;
+    if !explicit && !path.exists() {
+        if let Some(renamed) = find_sidecar(&path, &fingerprint_of(&state, handle)?) {
+            path = renamed;
+        }
+    }
+
+    let json = fs::read_to_string(&path).map_err(|e| {
+        AppError::Io(format!(
+            "Failed to read tags from {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let (tags, bookmarks) = parse_sidecar(&json)?;
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    Ok(restore_annotations(file_state, tags, bookmarks))
+}
+
+/// Looks next to `missing` for a sidecar saved for contents matching
+/// `fingerprint`. Sidecars that cannot be read are skipped.
+fn find_sidecar(missing: &Path, fingerprint: &Fingerprint) -> Option<PathBuf> {
+    let directory = match missing.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "bltags")
+        })
+        .find(|path| {
+            let Ok(json) = fs::read_to_string(path) else {
+                return false;
+            };
+            serde_json::from_str::<serde_json::Value>(&json)
+                .ok()
+                .and_then(|value| serde_json::from_value(value.get("fingerprint")?.clone()).ok())
+                .is_some_and(|stored: Fingerprint| stored == *fingerprint)
+        })
+}
+
+/// Reads the tags and bookmarks from a sidecar's JSON, in any of its formats.
+/// Sidecars from a newer version are refused.
+pub(crate) fn parse_sidecar(json: &str) -> Result<(Vec<Tag>, Vec<Bookmark>), AppError> {
+    let parse_error =
+        |e: serde_json::Error| AppError::InvalidFormat(format!("Failed to parse tags: {}", e));
+    let value: serde_json::Value = serde_json::from_str(json).map_err(parse_error)?;
+    let version = match value.get("schema_version") {
+        None => {
+            let (mut tags, bookmarks) = match serde_json::from_value(value).map_err(parse_error)? {
+                LegacySidecar::WithBookmarks { tags, bookmarks } => (tags, bookmarks),
+                LegacySidecar::TagsOnly(tags) => (tags, Vec::new()),
+            };
+            // Display names were optional before versioning.
+            for tag in &mut tags {
+                if tag.display_name.is_empty() {
+                    tag.display_name = tag.name.clone();
+                }
+            }
+            return Ok((tags, bookmarks));
+        }
+        Some(version) => version.as_u64().ok_or_else(|| {
+            AppError::InvalidFormat(format!("Invalid tags schema version {}", version))
+        })?,
+    };
+    if version > SIDECAR_SCHEMA_VERSION as u64 {
+        return Err(AppError::InvalidFormat(format!(
+            "Tags schema version {} is newer than this version of the app supports ({})",
+            version, SIDECAR_SCHEMA_VERSION
+        )));
+    }
+    let sidecar: Sidecar = serde_json::from_value(value).map_err(parse_error)?;
+    Ok((sidecar.tags, sidecar.bookmarks))
+}
+
+/// Replaces the file's tags and bookmarks with stored ones, dropping and
+/// reporting those that are not valid for the current file size.
+pub(crate) fn restore_annotations(
+    file_state: &mut FileState,
+    tags: Vec<Tag>,
+    bookmarks: Vec<Bookmark>,
+) -> LoadTagsReport {
+    let (mut tags, dropped): (Vec<Tag>, Vec<Tag>) = tags
+        .into_iter()
+        .partition(|tag| validate_tag(tag, file_state.file_size).is_ok());
+    tags.sort_by_key(|tag| tag.start);
+
+    // Stored ids are kept so they stay stable across sessions. Tags from
+    // sidecars written before ids existed all read as 0, and hand-edited
+    // files may repeat an id; those get fresh ones.
+    let mut next_tag_id = tags.iter().map(|tag| tag.id + 1).max().unwrap_or(1);
+    let mut seen = HashSet::new();
+    for tag in &mut tags {
+        if tag.id == 0 || !seen.insert(tag.id) {
+            tag.id = next_tag_id;
+            next_tag_id += 1;
+        }
+    }
+
+    let (mut bookmarks, dropped_bookmarks): (Vec<Bookmark>, Vec<Bookmark>) = bookmarks
+        .into_iter()
+        .partition(|bookmark| validate_bookmark(bookmark, file_state.file_size).is_ok());
+    bookmarks.sort_by_key(|bookmark| bookmark.offset);
+
+    let loaded = tags.len();
+    let loaded_bookmarks = bookmarks.len();
+    file_state.tags = tags;
+    file_state.next_tag_id = next_tag_id;
+    file_state.next_bookmark_id = bookmarks
+        .iter()
+        .map(|bookmark| bookmark.id + 1)
+        .max()
+        .unwrap_or(1);
+    file_state.bookmarks = bookmarks;
+    LoadTagsReport {
+        loaded,
+        dropped,
+        loaded_bookmarks,
+        dropped_bookmarks,
+    }
+}
+
+/// A CSV row or JSON entry that could not be imported.
+#[derive(Serialize, Clone, Debug)]
+pub struct ImportRowError {
+    /// 1-based line number in the CSV file, or position in the JSON array.
+    line: usize,
+    message: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ImportTagsReport {
+    imported: usize,
+    errors: Vec<ImportRowError>,
+}
+
+/// Adds the tags parsed from an import, numbered from 1 as `"{row} {n}"` in
+/// errors, optionally replacing the existing ones. Invalid rows are reported;
+/// with `strict`, the first one aborts the import and no tags are changed.
+fn add_imported_tags(
+    file_state: &mut FileState,
+    rows: impl Iterator<Item = (usize, Result<Tag, String>)>,
+    row: &str,
+    clear_existing: bool,
+    strict: Option<bool>,
+) -> Result<ImportTagsReport, AppError> {
+    let mut tags = Vec::new();
+    let mut errors = Vec::new();
+    for (line, parsed) in rows {
+        match parsed {
+            Ok(tag) => tags.push(tag),
+            Err(message) if strict.unwrap_or(false) => {
+                return Err(AppError::InvalidFormat(format!(
+                    "{} {}: {}",
+                    row, line, message
+                )));
+            }
+            Err(message) => errors.push(ImportRowError { line, message }),
+        }
+    }
+
+    if clear_existing {
+        file_state.tags.clear();
+    }
+    let imported = tags.len();
+    for mut tag in tags {
+        tag.id = file_state.next_tag_id;
+        file_state.next_tag_id += 1;
+        insert_sorted(&mut file_state.tags, tag);
+    }
+    Ok(ImportTagsReport { imported, errors })
+}
+
+/// Splits one CSV line into fields. Fields may be wrapped in double quotes,
+/// with `""` standing for a literal quote.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal offset.
+fn parse_csv_offset(field: &str, column: &str) -> Result<u64, String> {
+    let field = field.trim();
+    let parsed = match field
+        .strip_prefix("0x")
+        .or_else(|| field.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => field.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid {} offset '{}'", column, field))
+}
+
+/// Parses a `start,end,name,color` row into a tag; `color` may be empty or
+/// left out.
+fn parse_csv_tag(line: &str, file_size: u64) -> Result<Tag, String> {
+    let fields = split_csv_line(line)?;
+    if fields.len() < 3 || fields.len() > 4 {
+        return Err(format!(
+            "Expected 3 or 4 fields (start,end,name,color), found {}",
+            fields.len()
+        ));
+    }
+
+    let start = parse_csv_offset(&fields[0], "start")?;
+    let end = parse_csv_offset(&fields[1], "end")?;
+    let name = fields[2].trim();
+    let mut tag = Tag::new(start, end, name.to_string(), name);
+    tag.color = fields
+        .get(3)
+        .map(|color| color.trim())
+        .filter(|color| !color.is_empty())
+        .map(str::to_string);
+
+    validate_tag(&tag, file_size).map_err(|e| e.to_string())?;
+    Ok(tag)
+}
+
+/// Adds tags from a `start,end,name,color` CSV file, optionally replacing the
+/// existing ones. Blank lines and a leading header row are skipped. Invalid
+/// rows are reported by line number; with `strict`, the first one aborts the
+/// import and no tags are changed.
+#[tauri::command]
+pub fn import_tags_csv(
+    handle: u64,
+    path: String,
+    clear_existing: bool,
+    strict: Option<bool>,
+    state: State<AppState>,
+) -> Result<ImportTagsReport, AppError> {
+    let csv = fs::read_to_string(&path)
+        .map_err(|e| AppError::Io(format!("Failed to read tags from {}: {}", path, e)))?;
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let file_size = file_state.file_size;
+    let is_header = |index: usize, line: &str| {
+        index == 0 && line.trim_start().to_ascii_lowercase().starts_with("start")
+    };
+    let rows = csv
+        .lines()
+        .enumerate()
+        .filter(|&(index, line)| !line.trim().is_empty() && !is_header(index, line))
+        .map(|(index, line)| (index + 1, parse_csv_tag(line, file_size)));
+    add_imported_tags(file_state, rows, "Line", clear_existing, strict)
+}
+
+/// Which keys of the objects in a JSON tag export hold each tag field, see
+/// `import_tags_json`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct JsonTagMapping {
+    start: String,
+    /// Key of the end offset, or of the length with `end_is_length`.
+    end: String,
+    #[serde(default)]
+    end_is_length: bool,
+    name: String,
+    #[serde(default)]
+    color: Option<String>,
+}
+
+/// Reads an offset given as a number or as a decimal or `0x` string.
+fn json_offset(entry: &serde_json::Value, key: &str) -> Result<u64, String> {
+    match entry.get(key) {
+        Some(serde_json::Value::Number(number)) => number
+            .as_u64()
+            .ok_or_else(|| format!("Invalid {} '{}'", key, number)),
+        Some(serde_json::Value::String(text)) => parse_csv_offset(text, key),
+        Some(other) => Err(format!("Invalid {} '{}'", key, other)),
+        None => Err(format!("Missing '{}'", key)),
+    }
+}
+
+fn parse_json_tag(
+    entry: &serde_json::Value,
+    mapping: &JsonTagMapping,
+    file_size: u64,
+) -> Result<Tag, String> {
+    if !entry.is_object() {
+        return Err("Expected an object".to_string());
+    }
+    let start = json_offset(entry, &mapping.start)?;
+    let end = json_offset(entry, &mapping.end)?;
+    let end = if mapping.end_is_length {
+        start
+            .checked_add(end)
+            .ok_or_else(|| format!("Length {} is too large", end))?
+    } else {
+        end
+    };
+    let name = match entry.get(&mapping.name) {
+        Some(serde_json::Value::String(name)) => name.trim(),
+        Some(_) => return Err(format!("'{}' is not a string", mapping.name)),
+        None => return Err(format!("Missing '{}'", mapping.name)),
+    };
+    let mut tag = Tag::new(start, end, name.to_string(), name);
+    tag.color = mapping
+        .color
+        .as_ref()
+        .and_then(|key| entry.get(key))
+        .and_then(|color| color.as_str())
+        .map(str::trim)
+        .filter(|color| !color.is_empty())
+        .map(str::to_string);
+
+    validate_tag(&tag, file_size).map_err(|e| e.to_string())?;
+    Ok(tag)
+}
+
+/// Adds tags from a JSON array of objects exported by another tool, e.g.
+/// `[{"offset": 16, "size": 4, "label": "magic"}]`. `mapping` names the keys
+/// holding each field and whether the end key is a length. Offsets may be
+/// numbers or decimal or `0x` strings. Invalid entries are reported by their
+/// 1-based position; otherwise this works like `import_tags_csv`.
+#[tauri::command]
+pub fn import_tags_json(
+    handle: u64,
+    path: String,
+    mapping: JsonTagMapping,
+    clear_existing: bool,
+    strict: Option<bool>,
+    state: State<AppState>,
+) -> Result<ImportTagsReport, AppError> {
+    let json = fs::read_to_string(&path)
+        .map_err(|e| AppError::Io(format!("Failed to read tags from {}: {}", path, e)))?;
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&json).map_err(|e| {
+        AppError::InvalidFormat(format!("Expected a JSON array of tags in {}: {}", path, e))
+    })?;
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let file_size = file_state.file_size;
+    let rows = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (index + 1, parse_json_tag(entry, &mapping, file_size)));
+    add_imported_tags(file_state, rows, "Entry", clear_existing, strict)
+}
+
+/// Turns `name` into an identifier other tools accept as a label.
+fn label_name(name: &str) -> String {
+    let mut label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        label.insert(0, '_');
+    }
+    label
+}
+
+/// The comment written for a tag: its display name and any note.
+fn tag_comment(tag: &Tag) -> String {
+    match &tag.note {
+        Some(note) => format!("{}: {}", tag.display_name, note),
+        None => tag.display_name.clone(),
+    }
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes `text` for a double-quoted IDC string.
+fn idc_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders tags as `Address,Label,Comment,Length` rows for a Ghidra import
+/// script.
+fn ghidra_csv(tags: &[Tag]) -> String {
+    let mut csv = String::from("Address,Label,Comment,Length\n");
+    for tag in tags {
+        csv.push_str(&format!(
+            "0x{:x},{},{},{}\n",
+            tag.start,
+            label_name(&tag.name),
+            csv_field(&tag_comment(tag)),
+            tag.end - tag.start
+        ));
+    }
+    csv
+}
+
+/// Renders tags as an IDC script naming and commenting each tag's start.
+fn ida_idc(tags: &[Tag]) -> String {
+    let mut idc = String::from("#include <idc.idc>\n\nstatic main()\n{\n");
+    for tag in tags {
+        idc.push_str(&format!(
+            "    set_name(0x{:x}, \"{}\", SN_NOWARN);\n    set_cmt(0x{:x}, \"{}\", 0);\n",
+            tag.start,
+            label_name(&tag.name),
+            tag.start,
+            idc_string(&tag_comment(tag))
+        ));
+    }
+    idc.push_str("}\n");
+    idc
+}
+
+/// Writes the file's tags to `out_path` as labels and comments at each tag's
+/// start offset, for another analysis tool. `format` is `ghidra-csv` or
+/// `ida-idc`.
+#[tauri::command]
+pub fn export_tags(
+    handle: u64,
+    format: String,
+    out_path: String,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let contents = {
+        let mut app_state = lock_state(&state);
+        let tags = &app_state.get(handle)?.tags;
+        match format.as_str() {
+            "ghidra-csv" => ghidra_csv(tags),
+            "ida-idc" => ida_idc(tags),
+            other => {
+                return Err(AppError::InvalidArgument(format!(
+                    "Unsupported tag export format '{}', expected 'ghidra-csv' or 'ida-idc'",
+                    other
+                )))
+            }
+        }
+    };
+    fs::write(&out_path, contents)
+        .map_err(|e| AppError::Io(format!("Failed to write tags to {}: {}", out_path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::bookmarks::{add_bookmark, list_bookmarks};
+    use crate::tags::{add_tag, get_all_tags};
+    use crate::test_support::{app, open_temp, temp_file};
+
+    fn summary(tags: &[Tag]) -> Vec<(u64, u64, u64, String)> {
+        tags.iter()
+            .map(|tag| (tag.id, tag.start, tag.end, tag.name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 32]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tags.json").to_string_lossy().into_owned();
+
+        let mut header = Tag::new(0, 4, "header".to_string(), "Header");
+        header.color = Some("#ff0000".to_string());
+        add_tag(handle, header, None, None, None, app.state()).unwrap();
+        add_tag(
+            handle,
+            Tag::new(8, 16, "body".to_string(), "Body"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        add_bookmark(handle, 20, "here".to_string(), app.state()).unwrap();
+        let saved_tags = get_all_tags(handle, app.state()).unwrap();
+        save_tags(handle, Some(path.clone()), app.state()).unwrap();
+
+        {
+            let state = app.state::<AppState>();
+            let mut app_state = lock_state(&state);
+            let file_state = app_state.get_mut(handle).unwrap();
+            file_state.tags.clear();
+            file_state.bookmarks.clear();
+        }
+
+        let report = load_tags(handle, Some(path), app.state()).unwrap();
+        assert_eq!((report.loaded, report.loaded_bookmarks), (2, 1));
+        let loaded = get_all_tags(handle, app.state()).unwrap();
+        assert_eq!(summary(&loaded), summary(&saved_tags));
+        assert_eq!(loaded[0].color.as_deref(), Some("#ff0000"));
+        assert_eq!(list_bookmarks(handle, app.state()).unwrap()[0].offset, 20);
+
+        // New tags must not reuse a loaded id.
+        let id = add_tag(
+            handle,
+            Tag::new(20, 24, "new".to_string(), "New"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        assert!(loaded.iter().all(|tag| tag.id != id));
+    }
+
+    #[test]
+    fn tags_follow_a_renamed_file() {
+        let app = app();
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("original.bin");
+        fs::write(&original, [7; 64]).unwrap();
+        fs::write(dir.path().join("other.bin"), [8; 64]).unwrap();
+        let open = |path: &Path| {
+            let path = path.to_string_lossy().into_owned();
+            crate::open_file(path, None, None, None, app.handle().clone(), app.state())
+                .unwrap()
+                .handle
+        };
+
+        let handle = open(&original);
+        add_tag(
+            handle,
+            Tag::new(0, 4, "magic".to_string(), "Magic"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        save_tags(handle, None, app.state()).unwrap();
+        crate::close_file(handle, app.state()).unwrap();
+
+        let renamed = dir.path().join("renamed.bin");
+        fs::rename(&original, &renamed).unwrap();
+        let handle = open(&renamed);
+        let report = load_tags(handle, None, app.state()).unwrap();
+        assert_eq!(report.loaded, 1);
+        assert_eq!(get_all_tags(handle, app.state()).unwrap()[0].name, "magic");
+
+        let other = open(&dir.path().join("other.bin"));
+        assert!(matches!(
+            load_tags(other, None, app.state()),
+            Err(AppError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn load_renumbers_old_and_duplicate_ids() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 32]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.json");
+        // A tag-only sidecar from before ids, plus a repeated id.
+        fs::write(
+            &path,
+            r#"[
+                {"start": 0, "end": 2, "name": "a", "display_name": "a", "color": null},
+                {"start": 2, "end": 4, "name": "b", "display_name": "b", "color": null},
+                {"id": 7, "start": 4, "end": 6, "name": "c", "display_name": "c", "color": null},
+                {"id": 7, "start": 6, "end": 8, "name": "d", "display_name": "d", "color": null}
+            ]"#,
+        )
+        .unwrap();
+
+        let path = path.to_string_lossy().into_owned();
+        load_tags(handle, Some(path), app.state()).unwrap();
+        let mut ids: Vec<u64> = get_all_tags(handle, app.state())
+            .unwrap()
+            .iter()
+            .map(|tag| tag.id)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+        assert!(!ids.contains(&0));
+
+        let id = add_tag(
+            handle,
+            Tag::new(10, 12, "e".to_string(), "e"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        assert!(!ids.contains(&id));
+    }
+
+    #[test]
+    fn csv_import_lenient_and_strict() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 32]);
+        let csv = temp_file(
+            b"start,end,name,color\n\
+             0,4,magic,#ff0000\n\
+             0x8,0x10,\"body, quoted\",\n\
+             \n\
+             20,40,too long,\n\
+             24,28,trailer\n",
+        );
+        let path = csv.path().to_string_lossy().into_owned();
+
+        let strict = import_tags_csv(handle, path.clone(), false, Some(true), app.state());
+        assert!(
+            matches!(strict, Err(AppError::InvalidFormat(message)) if message.starts_with("Line 5:"))
+        );
+        assert!(get_all_tags(handle, app.state()).unwrap().is_empty());
+
+        let report = import_tags_csv(handle, path, false, None, app.state()).unwrap();
+        assert_eq!(report.imported, 3);
+        let lines: Vec<usize> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![5]);
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        let names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, vec!["magic", "body, quoted", "trailer"]);
+        assert_eq!(tags[0].color.as_deref(), Some("#ff0000"));
+        assert_eq!(tags[1].color, None);
+    }
+
+    #[test]
+    fn json_import_maps_keys_and_lengths() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let json = temp_file(
+            br##"[
+                {"offset": 16, "size": 4, "label": "magic", "type": "u32", "rgb": "#00ff00"},
+                {"offset": "0x20", "size": 8, "label": "table"},
+                {"offset": 60, "size": 8, "label": "past the end"},
+                {"offset": 0, "label": "no size"}
+            ]"##,
+        );
+        let path = json.path().to_string_lossy().into_owned();
+        let mapping = |end_is_length| JsonTagMapping {
+            start: "offset".to_string(),
+            end: "size".to_string(),
+            end_is_length,
+            name: "label".to_string(),
+            color: Some("rgb".to_string()),
+        };
+
+        let report = import_tags_json(
+            handle,
+            path.clone(),
+            mapping(true),
+            false,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(report.imported, 2);
+        let lines: Vec<usize> = report.errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        let spans: Vec<(u64, u64, &str)> = tags
+            .iter()
+            .map(|tag| (tag.start, tag.end, tag.name.as_str()))
+            .collect();
+        assert_eq!(spans, [(16, 20, "magic"), (0x20, 0x28, "table")]);
+        assert_eq!(tags[0].color.as_deref(), Some("#00ff00"));
+
+        // Read as end offsets, the sizes come before the starts.
+        let strict = import_tags_json(handle, path, mapping(false), true, Some(true), app.state());
+        assert!(
+            matches!(strict, Err(AppError::InvalidFormat(message)) if message.starts_with("Entry 1:"))
+        );
+        assert_eq!(get_all_tags(handle, app.state()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn exports_tags_for_ghidra_and_ida() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        add_tag(
+            handle,
+            Tag::new(0x10, 0x14, "magic".to_string(), "Magic"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        let mut table = Tag::new(0x20, 0x40, "2nd table".to_string(), "Table, \"v2\"");
+        table.note = Some("see spec".to_string());
+        add_tag(handle, table, None, None, None, app.state()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let export = |format: &str| {
+            let path = dir.path().join(format).to_string_lossy().into_owned();
+            export_tags(handle, format.to_string(), path.clone(), app.state()).unwrap();
+            fs::read_to_string(path).unwrap()
+        };
+
+        let csv = export("ghidra-csv");
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            [
+                "Address,Label,Comment,Length",
+                "0x10,magic,Magic,4",
+                "0x20,_2nd_table,\"Table, \"\"v2\"\": see spec\",32",
+            ]
+        );
+
+        let idc = export("ida-idc");
+        assert!(idc.starts_with("#include <idc.idc>"));
+        assert!(idc.contains("    set_name(0x10, \"magic\", SN_NOWARN);\n"));
+        assert!(idc.contains("    set_cmt(0x20, \"Table, \\\"v2\\\": see spec\", 0);\n"));
+
+        assert!(matches!(
+            export_tags(handle, "r2".to_string(), String::new(), app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn tags_added_after_loading_get_fresh_ids() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tags.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"id": 42, "start": 0, "end": 4, "name": "a", "display_name": "A"},
+                {"id": 7, "start": 8, "end": 12, "name": "b", "display_name": "B"}
+            ]"#,
+        )
+        .unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        load_tags(handle, Some(path), app.state()).unwrap();
+        let id = add_tag(
+            handle,
+            Tag::new(16, 20, "c".to_string(), "C"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(id, 43);
+        let ids: Vec<u64> = get_all_tags(handle, app.state())
+            .unwrap()
+            .iter()
+            .map(|tag| tag.id)
+            .collect();
+        assert_eq!(ids, [42, 7, 43]);
+    }
+
+    #[test]
+    fn legacy_tags_get_defaults_and_newer_schemas_are_refused() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 32]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("legacy.bltags");
+        fs::write(&path, r#"[{"start": 4, "end": 8, "name": "magic"}]"#).unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        load_tags(handle, Some(path.clone()), app.state()).unwrap();
+        let tag = &get_all_tags(handle, app.state()).unwrap()[0];
+        assert_eq!((tag.start, tag.end, tag.name.as_str()), (4, 8, "magic"));
+        assert_eq!(tag.display_name, "magic");
+        assert_ne!(tag.id, 0);
+        assert_eq!((tag.color.as_ref(), tag.layer), (None, 0));
+        assert_eq!((tag.note.as_ref(), tag.category.as_ref()), (None, None));
+        assert_eq!(tag.created_at, 0);
+
+        save_tags(handle, Some(path.clone()), app.state()).unwrap();
+        let mut saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["schema_version"], SIDECAR_SCHEMA_VERSION);
+
+        saved["schema_version"] = serde_json::json!(SIDECAR_SCHEMA_VERSION + 1);
+        fs::write(&path, saved.to_string()).unwrap();
+        assert!(matches!(
+            load_tags(handle, Some(path), app.state()),
+            Err(AppError::InvalidFormat(_))
+        ));
+        assert_eq!(get_all_tags(handle, app.state()).unwrap().len(), 1);
+    }
+}
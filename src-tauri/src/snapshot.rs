@@ -0,0 +1,259 @@
+use tauri::State;
+
+use crate::bookmarks::Bookmark;
+use crate::error::AppError;
+use crate::reader::to_usize;
+use crate::splice::{splice_bytes, MAX_UNDOABLE_EDIT};
+use crate::tags::Tag;
+use crate::{lock_state, AppState, FileState};
+
+/// How to take back one edit: the `new_len` bytes now at `offset` replaced
+/// `old_bytes`.
+struct Reversal {
+    offset: u64,
+    old_bytes: Vec<u8>,
+    new_len: u64,
+}
+
+struct Snapshot {
+    id: u64,
+    tags: Vec<Tag>,
+    bookmarks: Vec<Bookmark>,
+    selections: Vec<(u64, u64)>,
+    /// How many entries `Snapshots::log` held when the snapshot was taken.
+    log_len: usize,
+}
+
+/// Checkpoints of one file. Rather than copying the file, each checkpoint
+/// keeps the bytes that later edits overwrite: every edit made while a
+/// snapshot exists adds a reversal to the log, and restoring one takes back
+/// the edits logged after it, latest first.
+pub(crate) struct Snapshots {
+    taken: Vec<Snapshot>,
+    log: Vec<Reversal>,
+    next_id: u64,
+}
+
+impl Snapshots {
+    pub(crate) fn new() -> Self {
+        Self {
+            taken: Vec::new(),
+            log: Vec::new(),
+            next_id: 1,
+        }
+    }
+}
+
+/// Logs the bytes about to change while a snapshot exists: the `removed`
+/// bytes at `offset` are replaced by `inserted` ones. Either may reach past
+/// the end of the file, and an `offset` past the end counts the zeros filling
+/// the gap as inserted. Edits removing more than `MAX_UNDOABLE_EDIT` bytes are
+/// refused rather than held in memory.
+pub(crate) fn record_edit(
+    file_state: &mut FileState,
+    offset: u64,
+    removed: u64,
+    inserted: u64,
+) -> Result<(), AppError> {
+    if file_state.snapshots.taken.is_empty() {
+        return Ok(());
+    }
+    let file_size = file_state.file_size;
+    let start = std::cmp::min(offset, file_size);
+    let removed = std::cmp::min(removed, file_size - start);
+    if removed > MAX_UNDOABLE_EDIT {
+        return Err(AppError::InvalidArgument(format!(
+            "Edit overwrites {} bytes, more than snapshots can keep ({})",
+            removed, MAX_UNDOABLE_EDIT
+        )));
+    }
+    let mut old_bytes = vec![0u8; to_usize(removed)?];
+    file_state.read_exact_at(start, &mut old_bytes)?;
+    file_state.snapshots.log.push(Reversal {
+        offset: start,
+        old_bytes,
+        new_len: inserted + (offset - start),
+    });
+    Ok(())
+}
+
+/// Takes a checkpoint of the file's bytes, tags, bookmarks and selections and
+/// returns its id, for `restore_snapshot`. Later edits keep the bytes they
+/// overwrite for as long as the snapshot exists, so snapshots no longer
+/// needed should be deleted; an edit overwriting more than 16 MiB at once is
+/// refused while any exist.
+#[tauri::command]
+pub fn create_snapshot(handle: u64, state: State<AppState>) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    let snapshots = &mut file_state.snapshots;
+
+    let id = snapshots.next_id;
+    snapshots.next_id += 1;
+    snapshots.taken.push(Snapshot {
+        id,
+        tags: file_state.tags.clone(),
+        bookmarks: file_state.bookmarks.clone(),
+        selections: file_state.selections.clone(),
+        log_len: snapshots.log.len(),
+    });
+    Ok(id)
+}
+
+/// Reverts the file's bytes, tags, bookmarks and selections to snapshot `id`.
+/// Snapshots taken after it are deleted, while `id` itself stays and can be
+/// restored again. The undo history is cleared, as its edits no longer apply.
+#[tauri::command]
+pub fn restore_snapshot(handle: u64, id: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    let index = snapshot_index(file_state, id)?;
+
+    // Taken out so the edits made while restoring are not logged.
+    let mut snapshots = std::mem::replace(&mut file_state.snapshots, Snapshots::new());
+    snapshots.taken.truncate(index + 1);
+    let log_len = snapshots.taken[index].log_len;
+    let mut result = Ok(());
+    while snapshots.log.len() > log_len {
+        let reversal = snapshots.log.last().unwrap();
+        result = splice_bytes(
+            file_state,
+            reversal.offset,
+            reversal.new_len,
+            &reversal.old_bytes,
+        );
+        if result.is_err() {
+            break;
+        }
+        snapshots.log.pop();
+    }
+    if result.is_ok() {
+        let snapshot = &snapshots.taken[index];
+        file_state.tags = snapshot.tags.clone();
+        file_state.bookmarks = snapshot.bookmarks.clone();
+        file_state.selections = snapshot.selections.clone();
+    }
+    file_state.snapshots = snapshots;
+    file_state.journal.clear();
+    result
+}
+
+/// Deletes snapshot `id`, freeing the bytes kept only for it.
+#[tauri::command]
+pub fn delete_snapshot(handle: u64, id: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    let index = snapshot_index(file_state, id)?;
+
+    let snapshots = &mut file_state.snapshots;
+    snapshots.taken.remove(index);
+    // Entries logged before the oldest remaining snapshot are never replayed.
+    let unused = snapshots
+        .taken
+        .first()
+        .map_or(snapshots.log.len(), |snapshot| snapshot.log_len);
+    snapshots.log.drain(..unused);
+    for snapshot in &mut snapshots.taken {
+        snapshot.log_len -= unused;
+    }
+    Ok(())
+}
+
+fn snapshot_index(file_state: &FileState, id: u64) -> Result<usize, AppError> {
+    file_state
+        .snapshots
+        .taken
+        .iter()
+        .position(|snapshot| snapshot.id == id)
+        .ok_or_else(|| AppError::InvalidArgument(format!("No snapshot {}", id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::bookmarks::{add_bookmark, list_bookmarks};
+    use crate::save_file_data;
+    use crate::selection::{list_selections, set_selection};
+    use crate::splice::{delete_bytes, insert_bytes, resize_file};
+    use crate::tags::{add_tag, get_all_tags};
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn restore_reverts_bytes_and_tags() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+        let contents = || std::fs::read(file.path()).unwrap();
+        let tag = |start, end| Tag::new(start, end, "field".to_string(), "Field");
+        let tags = || -> Vec<(u64, u64, u64)> {
+            get_all_tags(handle, app.state())
+                .unwrap()
+                .iter()
+                .map(|tag| (tag.id, tag.start, tag.end))
+                .collect()
+        };
+        add_tag(handle, tag(2, 6), None, None, None, app.state()).unwrap();
+        add_bookmark(handle, 3, "kept".to_string(), app.state()).unwrap();
+        set_selection(handle, 1, 4, app.state()).unwrap();
+
+        let snapshot = create_snapshot(handle, app.state()).unwrap();
+        let snapshot_tags = tags();
+        save_file_data(handle, 1, b"ab".to_vec(), None, None, app.state()).unwrap();
+        insert_bytes(handle, 0, b"XYZ".to_vec(), app.state()).unwrap();
+        delete_bytes(handle, 8, 3, app.state()).unwrap();
+        save_file_data(handle, 12, b"far".to_vec(), Some(true), None, app.state()).unwrap();
+        add_tag(handle, tag(0, 1), None, None, None, app.state()).unwrap();
+        add_bookmark(handle, 9, "later".to_string(), app.state()).unwrap();
+        set_selection(handle, 5, 8, app.state()).unwrap();
+
+        let later = create_snapshot(handle, app.state()).unwrap();
+        let later_contents = contents();
+        resize_file(handle, 4, app.state()).unwrap();
+
+        restore_snapshot(handle, snapshot, app.state()).unwrap();
+        assert_eq!(contents(), b"0123456789");
+        assert_eq!(tags(), snapshot_tags);
+        let bookmarks = list_bookmarks(handle, app.state()).unwrap();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].offset, 3);
+        assert_eq!(list_selections(handle, app.state()).unwrap(), vec![(1, 4)]);
+        assert_ne!(later_contents, contents());
+        assert!(matches!(
+            restore_snapshot(handle, later, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+
+        // The snapshot survives its own restore.
+        save_file_data(handle, 0, b"!".to_vec(), None, None, app.state()).unwrap();
+        restore_snapshot(handle, snapshot, app.state()).unwrap();
+        assert_eq!(contents(), b"0123456789");
+
+        delete_snapshot(handle, snapshot, app.state()).unwrap();
+        assert!(matches!(
+            restore_snapshot(handle, snapshot, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_edits_too_large_to_keep() {
+        let app = app();
+        let size = MAX_UNDOABLE_EDIT + 1;
+        let (file, handle) = open_temp(&app, &vec![1; size as usize]);
+        let snapshot = create_snapshot(handle, app.state()).unwrap();
+
+        assert!(matches!(
+            resize_file(handle, 0, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+        assert_eq!(std::fs::metadata(file.path()).unwrap().len(), size);
+
+        delete_snapshot(handle, snapshot, app.state()).unwrap();
+        resize_file(handle, 0, app.state()).unwrap();
+        assert_eq!(std::fs::metadata(file.path()).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,1100 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, PoisonError};
+use tauri::State;
+
+use crate::bookmarks::shift_bookmarks;
+use crate::cancel::{CancelToken, Operation};
+use crate::error::AppError;
+use crate::journal::Edit;
+use crate::reader::{self, to_usize, Backing, FileReader};
+use crate::search::find_exact;
+use crate::snapshot;
+use crate::tags::{shift_range, shift_tags};
+use crate::watch;
+use crate::{lock_state, run_blocking, AppState, FileState};
+
+const SPLICE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Replaces the `removed` bytes at `offset` with `inserted`, shifting the rest
+/// of the file and the tags after it. The caller checks that the removed
+/// range lies within the file.
+pub(crate) fn splice(
+    file_state: &mut FileState,
+    offset: u64,
+    removed: u64,
+    inserted: &[u8],
+) -> Result<(), AppError> {
+    splice_bytes(file_state, offset, removed, inserted)?;
+    shift_for_replacement(file_state, offset, removed, inserted.len() as u64);
+    file_state.rerun_live_templates(offset, offset + inserted.len() as u64);
+    Ok(())
+}
+
+/// Moves annotations for a replacement of `removed` bytes by `inserted` ones.
+/// Bytes common to both sides are overwritten in place; only the difference
+/// is inserted or removed.
+fn shift_for_replacement(file_state: &mut FileState, offset: u64, removed: u64, inserted: u64) {
+    let common = std::cmp::min(removed, inserted);
+    shift_annotations(
+        file_state,
+        offset + common,
+        inserted as i64 - removed as i64,
+    );
+}
+
+/// Moves tags, bookmarks, live templates and the selections to follow `delta`
+/// bytes being inserted at `edit_point` (when positive) or removed from it
+/// (when negative). Every edit that changes the file's length goes through
+/// here, so annotations cannot drift from the bytes they describe. Selections
+/// left empty are dropped; moved live templates re-run on the caller's next
+/// `rerun_live_templates`.
+fn shift_annotations(file_state: &mut FileState, edit_point: u64, delta: i64) {
+    if delta == 0 {
+        return;
+    }
+    let (removed, inserted) = if delta < 0 {
+        (delta.unsigned_abs(), 0)
+    } else {
+        (0, delta as u64)
+    };
+    shift_tags(&mut file_state.tags, edit_point, removed, inserted);
+    shift_bookmarks(&mut file_state.bookmarks, edit_point, removed, inserted);
+    for template in &mut file_state.live_templates {
+        template.shift(edit_point, removed, inserted);
+    }
+    file_state.selections = file_state
+        .selections
+        .iter()
+        .filter_map(|&selection| shift_range(selection, edit_point, removed, inserted))
+        .collect();
+}
+
+/// Replaces file contents like `splice` without touching tags.
+///
+/// When the length changes, the tail is staged in a temporary file and copied
+/// back in place, so the file keeps its identity (permissions, links, open
+/// handles) instead of being replaced by a new one.
+pub(crate) fn splice_bytes(
+    file_state: &mut FileState,
+    offset: u64,
+    removed: u64,
+    inserted: &[u8],
+) -> Result<(), AppError> {
+    let inserted_len = inserted.len() as u64;
+    if removed == inserted_len {
+        return file_state.write_at(offset, inserted);
+    }
+
+    snapshot::record_edit(file_state, offset, removed, inserted_len)?;
+    let tail_start = offset + removed;
+    let new_size = file_state.file_size - removed + inserted_len;
+
+    let file = match &mut file_state.backing {
+        Backing::File(file) => Arc::clone(file),
+        Backing::Memory(buffer) => {
+            Arc::make_mut(buffer).splice(
+                to_usize(offset)?..to_usize(tail_start)?,
+                inserted.iter().copied(),
+            );
+            file_state.cache.clear();
+            file_state.file_size = new_size;
+            return Ok(());
+        }
+        Backing::Split(_) | Backing::Gzip(_) => return Err(AppError::ReadOnly),
+    };
+
+    // The old mapping no longer matches the file layout, and some platforms
+    // refuse to resize a mapped file.
+    #[cfg(feature = "mmap")]
+    file_state.unmap();
+    file_state.cache.clear();
+
+    let mut tail = tempfile::tempfile()
+        .map_err(|e| AppError::Io(format!("Failed to create temporary file: {}", e)))?;
+    file_state.reader().for_each_chunk(
+        tail_start,
+        file_state.file_size,
+        SPLICE_CHUNK_SIZE,
+        |_, chunk| {
+            tail.write_all(chunk)
+                .map_err(|e| AppError::Io(format!("Failed to stage file data: {}", e)))
+        },
+    )?;
+
+    tail.seek(SeekFrom::Start(0))
+        .map_err(|e| AppError::Io(format!("Failed to rewind staged data: {}", e)))?;
+
+    // Readers must not see the file between writing the new bytes and
+    // restoring the tail.
+    let _guard = file_state
+        .io_lock
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    reader::write_all_at(&file, inserted, offset)?;
+    copy_back(&file, &mut tail, offset + inserted_len)?;
+
+    if new_size < file_state.file_size {
+        file.set_len(new_size)
+            .map_err(|e| AppError::Io(format!("Failed to truncate file: {}", e)))?;
+    }
+    file.as_ref()
+        .flush()
+        .map_err(|e| AppError::Io(format!("Failed to flush file data: {}", e)))?;
+    file_state.own_write = watch::stamp(&file);
+    file_state.file_size = new_size;
+    Ok(())
+}
+
+/// Writes the rest of `staged` to `file` from `position` on.
+fn copy_back(file: &File, staged: &mut File, mut position: u64) -> Result<(), AppError> {
+    let mut buffer = vec![0u8; SPLICE_CHUNK_SIZE];
+    loop {
+        let read = staged
+            .read(&mut buffer)
+            .map_err(|e| AppError::Io(format!("Failed to read staged data: {}", e)))?;
+        if read == 0 {
+            return Ok(());
+        }
+        reader::write_all_at(file, &buffer[..read], position)?;
+        position += read as u64;
+    }
+}
+
+/// Inserts `bytes` at `offset`, shifting everything after it.
+#[tauri::command]
+pub fn insert_bytes(
+    handle: u64,
+    offset: u64,
+    bytes: Vec<u8>,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    if offset > file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: offset,
+            size: file_state.file_size,
+        });
+    }
+    if bytes.is_empty() {
+        return Ok(());
+    }
+
+    splice(file_state, offset, 0, &bytes)?;
+    file_state.journal.record(Edit {
+        offset,
+        old_bytes: Vec::new(),
+        new_bytes: bytes,
+    });
+    Ok(())
+}
+
+/// Removes `length` bytes at `offset`, shifting everything after it back.
+#[tauri::command]
+pub fn delete_bytes(
+    handle: u64,
+    offset: u64,
+    length: u64,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    let end = offset.checked_add(length).ok_or(AppError::InvalidRange {
+        start: offset,
+        end: u64::MAX,
+    })?;
+    if end > file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: end,
+            size: file_state.file_size,
+        });
+    }
+    if length == 0 {
+        return Ok(());
+    }
+
+    let mut old_bytes = vec![0u8; to_usize(length)?];
+    file_state.read_exact_at(offset, &mut old_bytes)?;
+
+    splice(file_state, offset, length, &[])?;
+    file_state.journal.record(Edit {
+        offset,
+        old_bytes,
+        new_bytes: Vec::new(),
+    });
+    Ok(())
+}
+
+/// Streams `[from, to)` of the file through `out` with each of `matches`, which
+/// must be sorted, non-overlapping and inside the range, replaced by
+/// `replace`.
+fn for_each_replaced<F>(
+    reader: &FileReader,
+    (from, to): (u64, u64),
+    matches: &[u64],
+    find_len: u64,
+    replace: &[u8],
+    mut out: F,
+) -> Result<(), AppError>
+where
+    F: FnMut(&[u8]) -> Result<(), AppError>,
+{
+    let mut position = from;
+    for &offset in matches {
+        if offset > position {
+            reader.for_each_chunk(position, offset, SPLICE_CHUNK_SIZE, |_, chunk| out(chunk))?;
+        }
+        out(replace)?;
+        position = offset + find_len;
+    }
+    if to > position {
+        reader.for_each_chunk(position, to, SPLICE_CHUNK_SIZE, |_, chunk| out(chunk))?;
+    }
+    Ok(())
+}
+
+/// Finds the non-overlapping matches of `find` starting in the chunk at
+/// `position`, and returns them with where the next chunk starts: the end of
+/// the chunk, or of its last match if that runs past it.
+fn matches_in_chunk(
+    reader: &FileReader,
+    find: &[u8],
+    position: u64,
+) -> Result<(Vec<u64>, u64), AppError> {
+    let find_len = find.len() as u64;
+    let chunk_end = std::cmp::min(position + SPLICE_CHUNK_SIZE as u64, reader.file_size);
+    let scan_end = std::cmp::min(chunk_end + find_len - 1, reader.file_size);
+    let matches = find_exact(reader, find, position, scan_end, usize::MAX, |_| Ok(()))?;
+    let next = match matches.last() {
+        Some(&last) => std::cmp::max(chunk_end, last + find_len),
+        None => chunk_end,
+    };
+    Ok((matches, next))
+}
+
+/// Fails if the file was edited since `generation` was taken from its cache.
+fn check_unedited(file_state: &FileState, generation: u64) -> Result<(), AppError> {
+    if file_state.cache.generation() != generation {
+        return Err(AppError::InvalidArgument(
+            "File was edited while replace_all was running".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Records the undo step of a `replace_all` as it goes, as one entry while
+/// the matched and replacement bytes stay within `MAX_UNDOABLE_EDIT`.
+struct ReplaceEdits {
+    recorded: bool,
+    changed_bytes: u64,
+}
+
+impl ReplaceEdits {
+    fn new() -> Self {
+        Self {
+            recorded: false,
+            changed_bytes: 0,
+        }
+    }
+
+    /// Adds replacements at `offsets`, in the coordinates of the file with
+    /// every earlier match already replaced.
+    fn record(&mut self, file_state: &mut FileState, offsets: &[u64], find: &[u8], replace: &[u8]) {
+        if offsets.is_empty() || self.changed_bytes > MAX_UNDOABLE_EDIT {
+            return;
+        }
+        self.changed_bytes += offsets.len() as u64 * (find.len() + replace.len()) as u64;
+        if self.changed_bytes > MAX_UNDOABLE_EDIT {
+            file_state.journal.clear();
+            return;
+        }
+        let edits = offsets
+            .iter()
+            .map(|&offset| Edit {
+                offset,
+                old_bytes: find.to_vec(),
+                new_bytes: replace.to_vec(),
+            })
+            .collect();
+        if self.recorded {
+            file_state.journal.extend_last(edits);
+        } else {
+            file_state.journal.record_group(edits);
+            self.recorded = true;
+        }
+    }
+}
+
+/// `replace_all` for `replace` as long as `find`: each chunk's matches are
+/// patched into a copy of the span they cover, which is written back in one
+/// go. The state is locked only for each write, and each write is recorded
+/// as part of the same undo step, so a stopped run leaves the chunks
+/// replaced so far behind, undone together.
+fn replace_in_place(
+    state: &AppState,
+    handle: u64,
+    find: &[u8],
+    replace: &[u8],
+    token: &CancelToken,
+) -> Result<usize, AppError> {
+    let find_len = find.len() as u64;
+    let mut edits = ReplaceEdits::new();
+    let mut generation = None;
+    let mut position = 0;
+    let mut count = 0;
+    loop {
+        token.check()?;
+        // Taken afresh for every chunk, since a memory-backed file is
+        // copied on write while a reader still shares its buffer.
+        let reader = {
+            let mut app_state = lock_state(state);
+            let file_state = app_state.get_mut(handle)?;
+            check_unedited(
+                file_state,
+                *generation.get_or_insert(file_state.cache.generation()),
+            )?;
+            file_state.reader()
+        };
+        if position + find_len > reader.file_size {
+            return Ok(count);
+        }
+        let (matches, next) = matches_in_chunk(&reader, find, position)?;
+        position = next;
+        let (Some(&first), Some(&last)) = (matches.first(), matches.last()) else {
+            continue;
+        };
+        let mut span = vec![0u8; to_usize(last + find_len - first)?];
+        reader.read_exact_at(first, &mut span)?;
+        drop(reader);
+        for &offset in &matches {
+            let at = (offset - first) as usize;
+            span[at..at + find.len()].copy_from_slice(replace);
+        }
+
+        let mut app_state = lock_state(state);
+        let file_state = app_state.get_mut(handle)?;
+        check_unedited(file_state, generation.unwrap_or_default())?;
+        file_state.write_at(first, &span)?;
+        generation = Some(file_state.cache.generation());
+        edits.record(file_state, &matches, find, replace);
+        count += matches.len();
+    }
+}
+
+/// Where `replace_in_copy` builds the file from its first match on.
+enum Staged {
+    File(BufWriter<File>),
+    Memory(Vec<u8>),
+}
+
+impl Staged {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), AppError> {
+        match self {
+            Self::File(file) => file
+                .write_all(bytes)
+                .map_err(|e| AppError::Io(format!("Failed to stage file data: {}", e))),
+            Self::Memory(buffer) => {
+                buffer.extend_from_slice(bytes);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// `replace_all` when the lengths differ. The file is read a chunk at a time
+/// and rewritten from the first match on into a temporary file, so memory
+/// use does not grow with the file; only the match offsets are kept, to move
+/// annotations. The state is locked only to move the result into place, and
+/// a stopped run leaves the file untouched.
+fn replace_in_copy(
+    state: &AppState,
+    handle: u64,
+    find: &[u8],
+    replace: &[u8],
+    token: &CancelToken,
+) -> Result<usize, AppError> {
+    let (reader, generation) = {
+        let mut app_state = lock_state(state);
+        let file_state = app_state.get_mut(handle)?;
+        (file_state.reader(), file_state.cache.generation())
+    };
+    let mut staged = match &reader.backing {
+        Backing::File(_) => Staged::File(BufWriter::with_capacity(
+            SPLICE_CHUNK_SIZE,
+            tempfile::tempfile()
+                .map_err(|e| AppError::Io(format!("Failed to create temporary file: {}", e)))?,
+        )),
+        Backing::Memory(_) => Staged::Memory(Vec::new()),
+        Backing::Split(_) | Backing::Gzip(_) => return Err(AppError::ReadOnly),
+    };
+
+    let find_len = find.len() as u64;
+    let old_size = reader.file_size;
+    let mut matches = Vec::new();
+    let mut staged_to = None;
+    let mut position = 0;
+    while position + find_len <= old_size {
+        token.check()?;
+        let (found, next) = matches_in_chunk(&reader, find, position)?;
+        position = next;
+        let (Some(&first), Some(&last)) = (found.first(), found.last()) else {
+            continue;
+        };
+        let from = staged_to.unwrap_or(first);
+        for_each_replaced(
+            &reader,
+            (from, last + find_len),
+            &found,
+            find_len,
+            replace,
+            |bytes| staged.write(bytes),
+        )?;
+        staged_to = Some(last + find_len);
+        matches.extend_from_slice(&found);
+    }
+    let (Some(&first), Some(staged_to)) = (matches.first(), staged_to) else {
+        return Ok(0);
+    };
+    for_each_replaced(
+        &reader,
+        (staged_to, old_size),
+        &[],
+        find_len,
+        replace,
+        |bytes| staged.write(bytes),
+    )?;
+    token.check()?;
+    drop(reader);
+
+    let count = matches.len() as u64;
+    let new_size = old_size - count * find_len + count * replace.len() as u64;
+    let last = matches[matches.len() - 1];
+    let span_len = last + find_len - first;
+    let new_span_len = span_len - count * find_len + count * replace.len() as u64;
+
+    let mut app_state = lock_state(state);
+    let file_state = app_state.get_mut(handle)?;
+    check_unedited(file_state, generation)?;
+    snapshot::record_edit(file_state, first, span_len, new_span_len)?;
+    match (&mut file_state.backing, staged) {
+        (Backing::File(file), Staged::File(staged)) => {
+            let file = Arc::clone(file);
+            let mut staged = staged
+                .into_inner()
+                .map_err(|e| AppError::Io(format!("Failed to stage file data: {}", e.error())))?;
+            staged
+                .seek(SeekFrom::Start(0))
+                .map_err(|e| AppError::Io(format!("Failed to rewind staged data: {}", e)))?;
+
+            // See `splice_bytes`.
+            #[cfg(feature = "mmap")]
+            file_state.unmap();
+            file_state.cache.clear();
+
+            let _guard = file_state
+                .io_lock
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            copy_back(&file, &mut staged, first)?;
+            if new_size < old_size {
+                file.set_len(new_size)
+                    .map_err(|e| AppError::Io(format!("Failed to truncate file: {}", e)))?;
+            }
+            file.as_ref()
+                .flush()
+                .map_err(|e| AppError::Io(format!("Failed to flush file data: {}", e)))?;
+            file_state.own_write = watch::stamp(&file);
+        }
+        (Backing::Memory(buffer), Staged::Memory(tail)) => {
+            let buffer = Arc::make_mut(buffer);
+            buffer.truncate(to_usize(first)?);
+            buffer.extend_from_slice(&tail);
+            file_state.cache.clear();
+        }
+        _ => unreachable!("the backing does not change while a file is open"),
+    }
+    file_state.file_size = new_size;
+
+    // One edit per match, each at its offset once the earlier matches have
+    // been replaced, so undo moves annotations match by match.
+    let growth = replace.len() as i64 - find_len as i64;
+    let offsets: Vec<u64> = (0..)
+        .zip(&matches)
+        .map(|(i, &offset)| offset.saturating_add_signed(i * growth))
+        .collect();
+    // Later matches first, so earlier offsets are still in the original
+    // coordinates when their turn comes.
+    for &offset in matches.iter().rev() {
+        shift_for_replacement(file_state, offset, find_len, replace.len() as u64);
+    }
+    file_state.rerun_live_templates(first, first + new_span_len);
+    ReplaceEdits::new().record(file_state, &offsets, find, replace);
+    Ok(matches.len())
+}
+
+/// Replaces every non-overlapping occurrence of `find` with `replace` and
+/// returns how many were replaced. All replacements form a single undo step;
+/// if the matched and replacement bytes together exceed 16 MiB, the undo
+/// history is cleared instead.
+///
+/// The file is scanned a chunk at a time without holding the state lock, and
+/// an edit made meanwhile fails the run. Can be stopped through
+/// `operation_id`, and gives up with `timed_out` after `timeout_ms`; when
+/// the lengths are equal, the matches replaced until then stay replaced.
+#[tauri::command]
+pub async fn replace_all(
+    handle: u64,
+    find: Vec<u8>,
+    replace: Vec<u8>,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<usize, AppError> {
+    if find.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Search pattern must not be empty".to_string(),
+        ));
+    }
+    if lock_state(&state).get(handle)?.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    let state = Arc::clone(state.inner());
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    run_blocking(move || {
+        if find.len() == replace.len() {
+            replace_in_place(&state, handle, &find, &replace, &token)
+        } else {
+            replace_in_copy(&state, handle, &find, &replace, &token)
+        }
+    })
+    .await
+}
+
+/// Largest edit whose changed bytes are kept for undo.
+pub(crate) const MAX_UNDOABLE_EDIT: u64 = 16 * 1024 * 1024;
+
+/// Overwrites `[start, end)` with `pattern` repeated, the last repetition
+/// cut short at `end`. Fills larger than 16 MiB cannot be undone and clear
+/// the undo history instead.
+fn fill(file_state: &mut FileState, start: u64, end: u64, pattern: &[u8]) -> Result<(), AppError> {
+    let length = end - start;
+    let old_bytes = if length <= MAX_UNDOABLE_EDIT {
+        let mut old_bytes = vec![0u8; to_usize(length)?];
+        file_state.read_exact_at(start, &mut old_bytes)?;
+        Some(old_bytes)
+    } else {
+        None
+    };
+
+    // A whole number of repetitions per chunk keeps the pattern in phase.
+    let repetitions = std::cmp::max(1, SPLICE_CHUNK_SIZE / pattern.len());
+    let chunk = pattern.repeat(repetitions);
+    let mut offset = start;
+    while offset < end {
+        let count = std::cmp::min(chunk.len() as u64, end - offset) as usize;
+        file_state.write_at(offset, &chunk[..count])?;
+        offset += count as u64;
+    }
+
+    match old_bytes {
+        Some(old_bytes) => {
+            let new_bytes = pattern
+                .iter()
+                .copied()
+                .cycle()
+                .take(old_bytes.len())
+                .collect();
+            file_state.journal.record(Edit {
+                offset: start,
+                old_bytes,
+                new_bytes,
+            });
+        }
+        None => file_state.journal.clear(),
+    }
+    Ok(())
+}
+
+/// Fills `[start, end)`, or every selection, with `pattern` repeated from the
+/// start of each range. Each range is undone on its own.
+#[tauri::command]
+pub fn fill_range(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    pattern: Vec<u8>,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    if pattern.is_empty() {
+        return Err(AppError::InvalidArgument(
+            "Fill pattern must not be empty".to_string(),
+        ));
+    }
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    let ranges = file_state.ranges_or_selections(start, end, None)?;
+    for &(start, end) in &ranges {
+        if end <= start {
+            return Err(AppError::InvalidRange { start, end });
+        }
+        if end > file_state.file_size {
+            return Err(AppError::OutOfBounds {
+                requested: end,
+                size: file_state.file_size,
+            });
+        }
+    }
+
+    for (start, end) in ranges {
+        fill(file_state, start, end, &pattern)?;
+    }
+    Ok(())
+}
+
+/// Reverses the byte order of each `word_size`-byte word in `[start, end)`,
+/// for data written with the wrong endianness. The range must hold a whole
+/// number of words. Swaps larger than 16 MiB cannot be undone and clear the
+/// undo history instead.
+#[tauri::command]
+pub fn swap_endian_range(
+    handle: u64,
+    start: u64,
+    end: u64,
+    word_size: u64,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    if word_size == 0 || word_size > SPLICE_CHUNK_SIZE as u64 {
+        return Err(AppError::InvalidArgument(format!(
+            "Word size must be between 1 and {} bytes",
+            SPLICE_CHUNK_SIZE
+        )));
+    }
+    if end <= start {
+        return Err(AppError::InvalidRange { start, end });
+    }
+    if (end - start) % word_size != 0 {
+        return Err(AppError::InvalidArgument(format!(
+            "Range of {} bytes is not a multiple of the {}-byte word size",
+            end - start,
+            word_size
+        )));
+    }
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+    if end > file_state.file_size {
+        return Err(AppError::OutOfBounds {
+            requested: end,
+            size: file_state.file_size,
+        });
+    }
+
+    let undoable = end - start <= MAX_UNDOABLE_EDIT;
+    let mut old_bytes = Vec::new();
+    let mut new_bytes = Vec::new();
+    // Whole words per chunk, so none is split between two reads.
+    let word_size = word_size as usize;
+    let mut chunk = vec![0u8; SPLICE_CHUNK_SIZE / word_size * word_size];
+    let mut offset = start;
+    while offset < end {
+        let count = std::cmp::min(chunk.len() as u64, end - offset) as usize;
+        let chunk = &mut chunk[..count];
+        file_state.read_exact_at(offset, chunk)?;
+        if undoable {
+            old_bytes.extend_from_slice(chunk);
+        }
+        chunk
+            .chunks_exact_mut(word_size)
+            .for_each(|word| word.reverse());
+        file_state.write_at(offset, chunk)?;
+        if undoable {
+            new_bytes.extend_from_slice(chunk);
+        }
+        offset += count as u64;
+    }
+
+    if undoable {
+        file_state.journal.record(Edit {
+            offset: start,
+            old_bytes,
+            new_bytes,
+        });
+    } else {
+        file_state.journal.clear();
+    }
+    Ok(())
+}
+
+/// Truncates or zero-extends the file to `new_size` bytes. Tags and bookmarks
+/// past the new end are dropped, and tags crossing it are cut short. Returns
+/// how many tags were dropped or cut. Resizes by more than 16 MiB cannot be
+/// undone and clear the undo history instead.
+#[tauri::command]
+pub fn resize_file(handle: u64, new_size: u64, state: State<AppState>) -> Result<usize, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    if file_state.read_only {
+        return Err(AppError::ReadOnly);
+    }
+
+    let old_size = file_state.file_size;
+    let edit = if old_size.abs_diff(new_size) > MAX_UNDOABLE_EDIT {
+        None
+    } else if new_size < old_size {
+        let mut old_bytes = vec![0u8; to_usize(old_size - new_size)?];
+        file_state.read_exact_at(new_size, &mut old_bytes)?;
+        Some(Edit {
+            offset: new_size,
+            old_bytes,
+            new_bytes: Vec::new(),
+        })
+    } else {
+        Some(Edit {
+            offset: old_size,
+            old_bytes: Vec::new(),
+            new_bytes: vec![0u8; to_usize(new_size - old_size)?],
+        })
+    };
+
+    snapshot::record_edit(
+        file_state,
+        std::cmp::min(old_size, new_size),
+        old_size.saturating_sub(new_size),
+        new_size.saturating_sub(old_size),
+    )?;
+    #[cfg(feature = "mmap")]
+    file_state.unmap();
+    file_state.cache.clear();
+    match &mut file_state.backing {
+        Backing::File(file) => {
+            let _guard = file_state
+                .io_lock
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            file.set_len(new_size)
+                .map_err(|e| AppError::Io(format!("Failed to resize file: {}", e)))?;
+            file_state.own_write = watch::stamp(file);
+        }
+        Backing::Memory(buffer) => Arc::make_mut(buffer).resize(to_usize(new_size)?, 0),
+        Backing::Split(_) | Backing::Gzip(_) => return Err(AppError::ReadOnly),
+    }
+    file_state.file_size = new_size;
+
+    let mut affected = 0;
+    if new_size < old_size {
+        affected = file_state
+            .tags
+            .iter()
+            .filter(|tag| tag.end > new_size)
+            .count();
+        shift_annotations(file_state, new_size, -((old_size - new_size) as i64));
+        file_state.rerun_live_templates(new_size, new_size);
+    }
+    match edit {
+        Some(edit) => file_state.journal.record(edit),
+        None => file_state.journal.clear(),
+    }
+    Ok(affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::test::MockRuntime;
+    use tauri::{App, Manager};
+
+    use super::*;
+    use crate::tags::{add_tag, get_all_tags, Tag};
+    use crate::test_support::{app, open_temp};
+
+    fn add(app: &App<MockRuntime>, handle: u64, start: u64, end: u64) {
+        let tag = Tag::new(start, end, "t".to_string(), "t");
+        add_tag(handle, tag, None, None, None, app.state()).unwrap();
+    }
+
+    fn spans(app: &App<MockRuntime>, handle: u64) -> Vec<(u64, u64)> {
+        get_all_tags(handle, app.state())
+            .unwrap()
+            .iter()
+            .map(|tag| (tag.start, tag.end))
+            .collect()
+    }
+
+    #[test]
+    fn insert_shifts_contents_and_tags() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+        add(&app, handle, 0, 2);
+        add(&app, handle, 3, 6);
+        add(&app, handle, 6, 8);
+
+        insert_bytes(handle, 4, b"abc".to_vec(), app.state()).unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"0123abc456789");
+        // Before, spanning and after the insertion point.
+        assert_eq!(spans(&app, handle), vec![(0, 2), (3, 9), (9, 11)]);
+    }
+
+    #[test]
+    fn delete_shifts_contents_and_drops_emptied_tags() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+        add(&app, handle, 0, 3);
+        add(&app, handle, 3, 5);
+        add(&app, handle, 5, 8);
+        add(&app, handle, 8, 10);
+
+        delete_bytes(handle, 2, 4, app.state()).unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"016789");
+        assert_eq!(spans(&app, handle), vec![(0, 2), (2, 4), (4, 6)]);
+        assert!(matches!(
+            delete_bytes(handle, 4, 3, app.state()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn replace_all_equal_length_patches_in_place() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"ab--ab--ab");
+        add(&app, handle, 2, 4);
+
+        let replaced = tauri::async_runtime::block_on(replace_all(
+            handle,
+            b"ab".to_vec(),
+            b"XY".to_vec(),
+            None,
+            None,
+            app.state(),
+        ));
+        assert_eq!(replaced.unwrap(), 3);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"XY--XY--XY");
+        assert_eq!(spans(&app, handle), vec![(2, 4)]);
+
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"ab--ab--ab");
+    }
+
+    #[test]
+    fn replace_all_length_change_shifts_tags() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"a-a--a---");
+        add(&app, handle, 1, 2);
+        add(&app, handle, 3, 5);
+        add(&app, handle, 6, 9);
+
+        let replaced = tauri::async_runtime::block_on(replace_all(
+            handle,
+            b"a".to_vec(),
+            b"bbb".to_vec(),
+            None,
+            None,
+            app.state(),
+        ));
+        assert_eq!(replaced.unwrap(), 3);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"bbb-bbb--bbb---");
+        assert_eq!(spans(&app, handle), vec![(3, 4), (7, 9), (12, 15)]);
+
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"a-a--a---");
+        assert_eq!(spans(&app, handle), vec![(1, 2), (3, 5), (6, 9)]);
+        crate::journal::redo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"bbb-bbb--bbb---");
+        assert_eq!(spans(&app, handle), vec![(3, 4), (7, 9), (12, 15)]);
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+
+        let shrunk = tauri::async_runtime::block_on(replace_all(
+            handle,
+            b"--".to_vec(),
+            Vec::new(),
+            None,
+            None,
+            app.state(),
+        ));
+        assert_eq!(shrunk.unwrap(), 2);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"a-aa-");
+    }
+
+    #[test]
+    fn replace_all_finds_matches_across_chunks() {
+        let app = app();
+        let boundary = SPLICE_CHUNK_SIZE;
+        let mut bytes = vec![b'-'; 2 * SPLICE_CHUNK_SIZE + 10];
+        for offset in [3, boundary - 2, boundary + 5, 2 * boundary - 1] {
+            bytes[offset..offset + 4].copy_from_slice(b"find");
+        }
+        let (file, handle) = open_temp(&app, &bytes);
+        let replace = |find: &[u8], replace: &[u8]| {
+            tauri::async_runtime::block_on(replace_all(
+                handle,
+                find.to_vec(),
+                replace.to_vec(),
+                None,
+                None,
+                app.state(),
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(replace(b"find", b"FIND"), 4);
+        let expected: Vec<u8> = bytes
+            .iter()
+            .map(|&byte| {
+                if byte == b'-' {
+                    byte
+                } else {
+                    byte.to_ascii_uppercase()
+                }
+            })
+            .collect();
+        assert_eq!(std::fs::read(file.path()).unwrap(), expected);
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), bytes);
+
+        assert_eq!(replace(b"find", b"f"), 4);
+        let text = String::from_utf8(bytes.clone()).unwrap();
+        assert_eq!(
+            std::fs::read(file.path()).unwrap(),
+            text.replace("find", "f").into_bytes()
+        );
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn replace_all_gives_up_after_timeout() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"a-a-a");
+        for replace in [b"b".to_vec(), b"bb".to_vec()] {
+            let replaced = tauri::async_runtime::block_on(replace_all(
+                handle,
+                b"a".to_vec(),
+                replace,
+                None,
+                Some(0),
+                app.state(),
+            ));
+            assert_eq!(replaced, Err(AppError::TimedOut { timeout_ms: 0 }));
+            assert_eq!(std::fs::read(file.path()).unwrap(), b"a-a-a");
+        }
+    }
+
+    #[test]
+    fn fill_cuts_the_last_repetition_short() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+
+        fill_range(handle, Some(1), Some(9), b"abc".to_vec(), app.state()).unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"0abcabcab9");
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"0123456789");
+    }
+
+    #[test]
+    fn fill_keeps_pattern_in_phase_across_chunks() {
+        let app = app();
+        let size = 2 * SPLICE_CHUNK_SIZE + 5;
+        let (file, handle) = open_temp(&app, &vec![0; size]);
+
+        fill_range(
+            handle,
+            Some(0),
+            Some(size as u64),
+            b"xyz".to_vec(),
+            app.state(),
+        )
+        .unwrap();
+        let contents = std::fs::read(file.path()).unwrap();
+        assert!(contents
+            .iter()
+            .enumerate()
+            .all(|(i, &byte)| byte == b"xyz"[i % 3]));
+    }
+
+    #[test]
+    fn swap_endian_reverses_each_word() {
+        let app = app();
+        let words: Vec<u8> = [0x0102_0304u32, 0xa1b2_c3d4]
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        let mut bytes = b"hd".to_vec();
+        bytes.extend_from_slice(&words);
+        let (file, handle) = open_temp(&app, &bytes);
+
+        swap_endian_range(handle, 2, 10, 4, app.state()).unwrap();
+        let read =
+            tauri::async_runtime::block_on(crate::get_file_data(handle, 2, 10, None, app.state()))
+                .unwrap();
+        let swapped: Vec<u8> = [0x0102_0304u32, 0xa1b2_c3d4]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        assert_eq!(read, swapped);
+        assert_eq!(&std::fs::read(file.path()).unwrap()[..2], b"hd");
+
+        assert!(matches!(
+            swap_endian_range(handle, 2, 9, 4, app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+        crate::journal::undo(handle, app.state()).unwrap().unwrap();
+        assert_eq!(std::fs::read(file.path()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn shrink_prunes_and_cuts_tags() {
+        let app = app();
+        let (file, handle) = open_temp(&app, b"0123456789");
+        add(&app, handle, 0, 4);
+        add(&app, handle, 4, 8);
+        add(&app, handle, 8, 10);
+
+        assert_eq!(resize_file(handle, 6, app.state()).unwrap(), 2);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"012345");
+        assert_eq!(crate::file_info(handle, app.state()).unwrap().size, 6);
+        assert_eq!(spans(&app, handle), vec![(0, 4), (4, 6)]);
+
+        assert_eq!(resize_file(handle, 8, app.state()).unwrap(), 0);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"012345\0\0");
+    }
+
+    #[test]
+    fn undoing_an_insert_restores_annotations_exactly() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for (start, end) in [(0, 10), (10, 20), (5, 30), (40, 48)] {
+            let tag = Tag::new(start, end, "t".to_string(), "t");
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+        crate::bookmarks::add_bookmark(handle, 12, "b".to_string(), app.state()).unwrap();
+        crate::selection::set_selection(handle, 8, 16, app.state()).unwrap();
+        let original = spans(&app, handle);
+
+        insert_bytes(handle, 10, vec![0xaa; 4], app.state()).unwrap();
+        assert_eq!(spans(&app, handle), [(0, 10), (5, 34), (14, 24), (44, 52)]);
+        crate::journal::undo(handle, app.state()).unwrap();
+
+        assert_eq!(spans(&app, handle), original);
+        let bookmarks = crate::bookmarks::list_bookmarks(handle, app.state()).unwrap();
+        assert_eq!(bookmarks[0].offset, 12);
+        assert_eq!(
+            crate::selection::get_selection(handle, app.state()).unwrap(),
+            Some((8, 16))
+        );
+    }
+}
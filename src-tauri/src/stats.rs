@@ -0,0 +1,580 @@
+use std::fs::File;
+use tauri::State;
+
+use crate::cancel::Operation;
+use crate::error::AppError;
+use crate::reader::{Backing, FileReader};
+use crate::tags::Tag;
+use crate::{lock_state, run_blocking, AppState};
+
+const STATS_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Window sizes accepted by `classify_regions`. Smaller windows cannot reach
+/// the entropy of random data.
+const MIN_CLASSIFY_WINDOW: u64 = 256;
+const MAX_CLASSIFY_WINDOW: u64 = 16 * 1024 * 1024;
+
+/// Most buckets `entropy_map` splits a file into.
+const MAX_ENTROPY_BUCKETS: usize = 65536;
+
+/// Counts each byte value in `[start, end)`.
+fn histogram(reader: &FileReader, start: u64, end: u64) -> Result<Vec<u64>, AppError> {
+    let end = reader.validate_range(start, end)?;
+    let mut counts = vec![0u64; 256];
+    reader.for_each_chunk(start, end, STATS_CHUNK_SIZE, |_, chunk| {
+        for &byte in chunk {
+            counts[byte as usize] += 1;
+        }
+        Ok(())
+    })?;
+    Ok(counts)
+}
+
+/// Shannon entropy of a byte distribution, in bits per byte.
+fn entropy(counts: &[u64]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Returns the number of occurrences of each byte value (indexed 0..=255) in
+/// `[start, end)`, or in the selection when no range is given.
+#[tauri::command]
+pub async fn byte_histogram(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    anchor: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<u64>, AppError> {
+    let (reader, (start, end)) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (
+            file_state.reader(),
+            file_state.range_or_selection(start, end, anchor.as_deref())?,
+        )
+    };
+    run_blocking(move || histogram(&reader, start, end)).await
+}
+
+/// Returns the Shannon entropy of `[start, end)` (default: the selection) in
+/// bits per byte, from 0.0 to 8.0.
+#[tauri::command]
+pub async fn shannon_entropy(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    anchor: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<f64, AppError> {
+    let (reader, (start, end)) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        (
+            file_state.reader(),
+            file_state.range_or_selection(start, end, anchor.as_deref())?,
+        )
+    };
+    run_blocking(move || Ok(entropy(&histogram(&reader, start, end)?))).await
+}
+
+/// Splits the file into `buckets` equal parts, the last also taking the
+/// remainder, and returns the Shannon entropy of each, e.g. for a strip along
+/// the scrollbar. The file must have at least one byte per bucket. Can be
+/// stopped through `operation_id`, and gives up with `timed_out` after
+/// `timeout_ms`.
+#[tauri::command]
+pub async fn entropy_map(
+    handle: u64,
+    buckets: usize,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<f64>, AppError> {
+    if !(1..=MAX_ENTROPY_BUCKETS).contains(&buckets) {
+        return Err(AppError::InvalidArgument(format!(
+            "Bucket count must be between 1 and {}",
+            MAX_ENTROPY_BUCKETS
+        )));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    if reader.file_size < buckets as u64 {
+        return Err(AppError::InvalidArgument(format!(
+            "Cannot split {} bytes into {} buckets",
+            reader.file_size, buckets
+        )));
+    }
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let bucket_size = reader.file_size / buckets as u64;
+        (0..buckets as u64)
+            .map(|bucket| {
+                token.check()?;
+                let start = bucket * bucket_size;
+                let end = if bucket + 1 == buckets as u64 {
+                    reader.file_size
+                } else {
+                    start + bucket_size
+                };
+                Ok(entropy(&histogram(&reader, start, end)?))
+            })
+            .collect()
+    })
+    .await
+}
+
+/// The kind of content a block most likely holds, as `(name, display name)`.
+fn classify_block(block: &[u8]) -> Option<(&'static str, &'static str)> {
+    let mut counts = [0u64; 256];
+    for &byte in block {
+        counts[byte as usize] += 1;
+    }
+    let total = block.len() as u64;
+    let printable: u64 = (0x20..0x7f)
+        .chain([b'\t', b'\n', b'\r'])
+        .map(|byte| counts[byte as usize])
+        .sum();
+
+    let bits = entropy(&counts);
+    if counts[0] == total {
+        Some(("zeros", "Zeros"))
+    } else if printable * 10 >= total * 9 {
+        Some(("text", "Text"))
+    } else if bits >= 7.0 {
+        Some(("high-entropy", "High entropy"))
+    } else if bits >= 5.0 {
+        // Machine code sits between text and compressed data.
+        Some(("code", "Code"))
+    } else {
+        None
+    }
+}
+
+/// Classifies each `window`-byte block of the file as `zeros`, `text`,
+/// `high-entropy` (likely compressed or encrypted) or `code`, and returns a tag
+/// per run of same-class blocks. Blocks matching no class are left out. The
+/// tags are not added to the file. Can be stopped through `operation_id`, and
+/// gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn classify_regions(
+    handle: u64,
+    window: u64,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    if !(MIN_CLASSIFY_WINDOW..=MAX_CLASSIFY_WINDOW).contains(&window) {
+        return Err(AppError::InvalidArgument(format!(
+            "Window must be between {} and {} bytes",
+            MIN_CLASSIFY_WINDOW, MAX_CLASSIFY_WINDOW
+        )));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let mut tags: Vec<Tag> = Vec::new();
+        reader.for_each_chunk(0, reader.file_size, window as usize, |offset, block| {
+            token.check()?;
+            let Some((name, display_name)) = classify_block(block) else {
+                return Ok(());
+            };
+            let end = offset + block.len() as u64;
+            match tags.last_mut() {
+                Some(last) if last.name == name && last.end == offset => last.end = end,
+                _ => tags.push(Tag::new(offset, end, name.to_string(), display_name)),
+            }
+            Ok(())
+        })?;
+        Ok(tags)
+    })
+    .await
+}
+
+/// Collects runs of zero bytes at least `min_run` long as `zero-run` tags.
+struct ZeroRuns {
+    min_run: u64,
+    run_start: Option<u64>,
+    tags: Vec<Tag>,
+}
+
+impl ZeroRuns {
+    fn end_run(&mut self, end: u64) {
+        if let Some(start) = self.run_start.take() {
+            if end - start >= self.min_run {
+                self.tags
+                    .push(Tag::new(start, end, "zero-run".to_string(), "Zero run"));
+            }
+        }
+    }
+
+    /// Takes in a region from `start` on that is known to hold only zeros
+    /// without reading it; the next `scan` or `end_run` says where it ends.
+    fn zeros(&mut self, start: u64) {
+        self.run_start.get_or_insert(start);
+    }
+
+    fn scan(&mut self, offset: u64, chunk: &[u8]) {
+        let mut i = 0;
+        while i < chunk.len() {
+            let rest = &chunk[i..];
+            if self.run_start.is_some() {
+                let Some(n) = rest.iter().position(|&byte| byte != 0) else {
+                    return;
+                };
+                self.end_run(offset + (i + n) as u64);
+                i += n + 1;
+            } else {
+                let Some(n) = rest.iter().position(|&byte| byte == 0) else {
+                    return;
+                };
+                self.run_start = Some(offset + (i + n) as u64);
+                i += n + 1;
+            }
+        }
+    }
+}
+
+/// The parts of `file` below `size` that hold data, as reported by
+/// `SEEK_DATA` and `SEEK_HOLE`, or `None` if they cannot be asked for.
+/// Filesystems without sparse files report everything as data.
+#[cfg(target_os = "linux")]
+fn data_extents(file: &File, size: u64) -> Option<Vec<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut extents = Vec::new();
+    let mut position = 0u64;
+    while position < size {
+        // SAFETY: lseek only moves the file offset, which no read relies on
+        // as they are all positional.
+        let data = unsafe { libc::lseek(fd, position as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            // ENXIO means the rest of the file is a hole.
+            let hole_to_end = std::io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO);
+            return hole_to_end.then_some(extents);
+        }
+        // SAFETY: as above.
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return None;
+        }
+        let (data, hole) = (data as u64, std::cmp::min(hole as u64, size));
+        if data >= size {
+            break;
+        }
+        extents.push((data, hole));
+        position = hole;
+    }
+    Some(extents)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn data_extents(_file: &File, _size: u64) -> Option<Vec<(u64, u64)>> {
+    None
+}
+
+/// Returns a `zero-run` tag for each run of at least `min_run` zero bytes, such
+/// as the unused space of a disk image. Holes in sparse files are found through
+/// the filesystem rather than read, so only the parts holding data are scanned.
+/// The tags are not added to the file. Can be stopped through `operation_id`,
+/// and gives up with `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn find_holes(
+    handle: u64,
+    min_run: u64,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    if min_run == 0 {
+        return Err(AppError::InvalidArgument(
+            "Zero runs must be at least one byte long".to_string(),
+        ));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let size = reader.file_size;
+        let extents = match &reader.backing {
+            Backing::File(file) => data_extents(file, size),
+            _ => None,
+        };
+        let extents = extents.unwrap_or_else(|| vec![(0, size)]);
+
+        let mut runs = ZeroRuns {
+            min_run,
+            run_start: None,
+            tags: Vec::new(),
+        };
+        let mut position = 0;
+        for (start, end) in extents {
+            token.check()?;
+            if start > position {
+                runs.zeros(position);
+            }
+            reader.for_each_chunk(start, end, STATS_CHUNK_SIZE, |offset, chunk| {
+                token.check()?;
+                runs.scan(offset, chunk);
+                Ok(())
+            })?;
+            position = end;
+        }
+        if size > position {
+            runs.zeros(position);
+        }
+        runs.end_run(size);
+        Ok(runs.tags)
+    })
+    .await
+}
+
+/// Collects runs of any one byte value at least `min_length` long.
+struct ByteRuns {
+    min_length: u64,
+    /// The value and start of the run reaching the end of the last chunk.
+    run: Option<(u8, u64)>,
+    tags: Vec<Tag>,
+}
+
+impl ByteRuns {
+    fn end_run(&mut self, end: u64) {
+        if let Some((value, start)) = self.run.take() {
+            if end - start >= self.min_length {
+                let name = format!("run:0x{:02x}", value);
+                let display_name = format!("Run of 0x{:02x}", value);
+                self.tags.push(Tag::new(start, end, name, &display_name));
+            }
+        }
+    }
+
+    fn scan(&mut self, offset: u64, chunk: &[u8]) {
+        let mut i = 0;
+        while i < chunk.len() {
+            let value = match self.run {
+                Some((value, _)) => value,
+                None => {
+                    self.run = Some((chunk[i], offset + i as u64));
+                    chunk[i]
+                }
+            };
+            let Some(n) = chunk[i..].iter().position(|&byte| byte != value) else {
+                return;
+            };
+            i += n;
+            self.end_run(offset + i as u64);
+        }
+    }
+}
+
+/// Returns a `run:0xNN` tag for each run of at least `min_length` copies of one
+/// byte value, such as padding or fill, merged across chunks. Unlike
+/// `find_holes` the whole file is read. The tags are not added to the file. Can
+/// be stopped through `operation_id`, and gives up with `timed_out` after
+/// `timeout_ms`.
+#[tauri::command]
+pub async fn find_runs(
+    handle: u64,
+    min_length: u64,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    if min_length == 0 {
+        return Err(AppError::InvalidArgument(
+            "Runs must be at least one byte long".to_string(),
+        ));
+    }
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+
+    run_blocking(move || {
+        let mut runs = ByteRuns {
+            min_length,
+            run: None,
+            tags: Vec::new(),
+        };
+        reader.for_each_chunk(0, reader.file_size, STATS_CHUNK_SIZE, |offset, chunk| {
+            token.check()?;
+            runs.scan(offset, chunk);
+            Ok(())
+        })?;
+        runs.end_run(reader.file_size);
+        Ok(runs.tags)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::open_bytes;
+    use crate::test_support::{app, open, reader_over, temp_file};
+
+    fn entropy_of(bytes: &[u8]) -> f64 {
+        let reader = reader_over(bytes);
+        entropy(&histogram(&reader, 0, bytes.len() as u64).unwrap())
+    }
+
+    #[test]
+    fn all_zero_buffer_has_no_entropy() {
+        assert_eq!(entropy_of(&[0; 4096]), 0.0);
+    }
+
+    #[test]
+    fn uniform_buffer_approaches_eight_bits() {
+        // Every byte value equally often, in a scrambled order.
+        let bytes: Vec<u8> = (0..64 * 256u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        let entropy = entropy_of(&bytes);
+        assert!(entropy > 7.9 && entropy <= 8.0, "entropy was {}", entropy);
+    }
+
+    #[test]
+    fn histogram_counts_only_the_range() {
+        let reader = reader_over(b"aabbbc");
+        let counts = histogram(&reader, 1, 5).unwrap();
+        assert_eq!((counts[b'a' as usize], counts[b'b' as usize]), (1, 3));
+        assert_eq!(counts.iter().sum::<u64>(), 4);
+        assert!(matches!(
+            histogram(&reader, 6, 8),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn classifies_zero_text_and_random_regions() {
+        let mut bytes = vec![0u8; 4096];
+        let text = b"The quick brown fox jumps over the lazy dog.\n".repeat(100);
+        bytes.extend_from_slice(&text[..4096]);
+        let mut x = 1u32;
+        bytes.extend((0..4096).map(|_| {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (x >> 16) as u8
+        }));
+        let app = app();
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+
+        let classified: Vec<_> =
+            tauri::async_runtime::block_on(classify_regions(handle, 1024, None, None, app.state()))
+                .unwrap()
+                .into_iter()
+                .map(|tag| (tag.start, tag.end, tag.name))
+                .collect();
+        assert_eq!(
+            classified,
+            [
+                (0, 4096, "zeros".to_string()),
+                (4096, 8192, "text".to_string()),
+                (8192, 12288, "high-entropy".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_zero_runs_of_the_minimum_length() {
+        let mut bytes = vec![1u8; 100];
+        bytes.extend([0; 300]);
+        bytes.extend([2; 10]);
+        bytes.extend([0; 5]);
+        bytes.push(3);
+        bytes.extend([0; 200]);
+        let app = app();
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let runs = |min_run| -> Vec<(u64, u64)> {
+            tauri::async_runtime::block_on(find_holes(handle, min_run, None, None, app.state()))
+                .unwrap()
+                .into_iter()
+                .map(|tag| (tag.start, tag.end))
+                .collect()
+        };
+
+        assert_eq!(runs(64), [(100, 400), (416, 616)]);
+        assert_eq!(runs(5), [(100, 400), (410, 415), (416, 616)]);
+        assert!(runs(1000).is_empty());
+    }
+
+    #[test]
+    fn finds_the_holes_of_sparse_files() {
+        let file = temp_file(b"");
+        file.as_file().set_len(4 * 1024 * 1024).unwrap();
+        crate::reader::write_all_at(file.as_file(), b"data", 1024 * 1024).unwrap();
+        let app = app();
+        let handle = open(&app, &file);
+
+        let tags =
+            tauri::async_runtime::block_on(find_holes(handle, 4096, None, None, app.state()))
+                .unwrap();
+        let runs: Vec<_> = tags.iter().map(|tag| (tag.start, tag.end)).collect();
+        assert_eq!(runs, [(0, 1024 * 1024), (1024 * 1024 + 4, 4 * 1024 * 1024)]);
+        assert_eq!(tags[0].name, "zero-run");
+    }
+
+    #[test]
+    fn finds_runs_of_any_byte_value() {
+        let mut bytes = b"head".to_vec();
+        bytes.extend([0xff; 40]);
+        bytes.extend([0xaa; 3]);
+        bytes.extend(vec![0xaa; STATS_CHUNK_SIZE]);
+        bytes.extend(b"tail");
+        let app = app();
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+
+        let runs: Vec<_> =
+            tauri::async_runtime::block_on(find_runs(handle, 16, None, None, app.state()))
+                .unwrap()
+                .into_iter()
+                .map(|tag| (tag.name, tag.start, tag.end - tag.start))
+                .collect();
+        assert_eq!(
+            runs,
+            [
+                ("run:0xff".to_string(), 4, 40),
+                ("run:0xaa".to_string(), 44, STATS_CHUNK_SIZE as u64 + 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn entropy_map_tells_zeros_from_random_data() {
+        let mut bytes = vec![0u8; 8192];
+        let mut x = 7u32;
+        bytes.extend((0..8192 + 3).map(|_| {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (x >> 16) as u8
+        }));
+        let app = app();
+        let handle = open_bytes(bytes, app.state()).unwrap().handle;
+        let map = |buckets| {
+            tauri::async_runtime::block_on(entropy_map(handle, buckets, None, None, app.state()))
+        };
+
+        let strip = map(4).unwrap();
+        assert_eq!(strip.len(), 4);
+        assert_eq!(&strip[..2], [0.0, 0.0]);
+        assert!(strip[2..].iter().all(|&bits| bits > 7.5), "{:?}", strip);
+
+        assert!(matches!(map(0), Err(AppError::InvalidArgument(_))));
+        assert!(matches!(map(20_000), Err(AppError::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,134 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime, State};
+
+use crate::error::AppError;
+use crate::{lock_state, AppState};
+
+/// Largest chunk sent in one event; larger requested sizes are clamped.
+const MAX_STREAM_CHUNK: usize = 16 * 1024 * 1024;
+
+/// Payload of a `range-chunk` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct RangeChunk {
+    stream_id: u64,
+    handle: u64,
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+/// Payload of the `range-done` event that ends every stream.
+#[derive(Serialize, Clone, Debug)]
+pub struct RangeDone {
+    stream_id: u64,
+    handle: u64,
+    /// Set when the stream stopped early because a read failed.
+    error: Option<AppError>,
+}
+
+/// Starts streaming `[start, end)` (default: the selection) as `range-chunk`
+/// events of at most `chunk_size` bytes (capped at 16 MiB), followed by a
+/// `range-done` event.
+/// Returns the stream id carried by those events as soon as the range has
+/// been validated.
+#[tauri::command]
+pub fn stream_range<R: Runtime>(
+    handle: u64,
+    start: Option<u64>,
+    end: Option<u64>,
+    anchor: Option<String>,
+    chunk_size: usize,
+    app: AppHandle<R>,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    if chunk_size == 0 {
+        return Err(AppError::InvalidArgument(
+            "Chunk size must be greater than zero".to_string(),
+        ));
+    }
+    let chunk_size = std::cmp::min(chunk_size, MAX_STREAM_CHUNK);
+
+    let (stream_id, reader, (start, end)) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        let reader = file_state.reader();
+        let range = file_state.range_or_selection(start, end, anchor.as_deref())?;
+        let stream_id = app_state.next_stream_id;
+        app_state.next_stream_id += 1;
+        (stream_id, reader, range)
+    };
+    let end = reader.validate_range(start, end)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = reader.for_each_chunk(start, end, chunk_size, |offset, chunk| {
+            app.emit(
+                "range-chunk",
+                RangeChunk {
+                    stream_id,
+                    handle,
+                    offset,
+                    bytes: chunk.to_vec(),
+                },
+            )
+            .map_err(|e| AppError::Internal(format!("Failed to emit range chunk: {}", e)))
+        });
+        let _ = app.emit(
+            "range-done",
+            RangeDone {
+                stream_id,
+                handle,
+                error: result.err(),
+            },
+        );
+    });
+    Ok(stream_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tauri::{Listener, Manager};
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn chunks_reassemble_the_range() {
+        let app = app();
+        let bytes: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+
+        let (sender, receiver) = mpsc::channel();
+        let chunk_sender = sender.clone();
+        app.listen_any("range-chunk", move |event| {
+            let chunk: serde_json::Value = serde_json::from_str(event.payload()).unwrap();
+            let _ = chunk_sender.send(Some(chunk));
+        });
+        app.listen_any("range-done", move |event| {
+            let done: serde_json::Value = serde_json::from_str(event.payload()).unwrap();
+            assert!(done["error"].is_null());
+            let _ = sender.send(None);
+        });
+
+        let stream_id = stream_range(
+            handle,
+            Some(100),
+            Some(900),
+            None,
+            64,
+            app.handle().clone(),
+            app.state(),
+        )
+        .unwrap();
+
+        let mut reassembled = Vec::new();
+        while let Some(chunk) = receiver.recv_timeout(Duration::from_secs(10)).unwrap() {
+            assert_eq!(chunk["stream_id"], stream_id);
+            assert_eq!(chunk["offset"], 100 + reassembled.len() as u64);
+            let chunk_bytes: Vec<u8> = serde_json::from_value(chunk["bytes"].clone()).unwrap();
+            assert!(chunk_bytes.len() <= 64);
+            reassembled.extend(chunk_bytes);
+        }
+        assert_eq!(reassembled, &bytes[100..900]);
+    }
+}
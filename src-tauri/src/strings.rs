@@ -0,0 +1,195 @@
+use tauri::State;
+
+use crate::cancel::{CancelToken, Operation};
+use crate::error::AppError;
+use crate::reader::FileReader;
+use crate::tags::Tag;
+use crate::{lock_state, run_blocking, AppState};
+
+const STRINGS_CHUNK_SIZE: usize = 1024 * 1024;
+
+fn is_printable_ascii(byte: u8) -> bool {
+    (0x20..=0x7E).contains(&byte) || byte == b'\t'
+}
+
+/// A run of printable characters that is still being accumulated.
+struct Run {
+    start: u64,
+    text: String,
+}
+
+/// Collects printable runs of at least `min_length` characters.
+struct RunCollector {
+    min_length: usize,
+    found: Vec<Tag>,
+}
+
+impl RunCollector {
+    fn push(&mut self, run: &mut Option<Run>, offset: u64, ch: char) {
+        run.get_or_insert_with(|| Run {
+            start: offset,
+            text: String::new(),
+        })
+        .text
+        .push(ch);
+    }
+
+    /// Ends the current run at `end`, keeping it if it is long enough.
+    fn finish(&mut self, run: &mut Option<Run>, end: u64) {
+        if let Some(run) = run.take() {
+            if run.text.chars().count() >= self.min_length {
+                self.found
+                    .push(Tag::new(run.start, end, run.text, "string"));
+            }
+        }
+    }
+}
+
+/// Returns runs of printable characters like `strings(1)`. Supported
+/// encodings are `ascii` and `utf16le`; UTF-16 runs are found at both even
+/// and odd offsets. Can be stopped through `operation_id`, and gives up with
+/// `timed_out` after `timeout_ms`.
+#[tauri::command]
+pub async fn find_strings(
+    handle: u64,
+    min_length: usize,
+    encoding: String,
+    operation_id: Option<u64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let utf16 = match encoding.as_str() {
+        "ascii" => false,
+        "utf16le" => true,
+        other => {
+            return Err(AppError::InvalidArgument(format!(
+                "Unsupported string encoding '{}'",
+                other
+            )))
+        }
+    };
+    if min_length == 0 {
+        return Err(AppError::InvalidArgument(
+            "Minimum string length must be at least 1".to_string(),
+        ));
+    }
+
+    let reader = lock_state(&state).get(handle)?.reader();
+    let operation = Operation::register(&state, operation_id)?.with_timeout(timeout_ms);
+    let token = operation.token.clone();
+    run_blocking(move || scan_strings(&reader, min_length, utf16, &token)).await
+}
+
+fn scan_strings(
+    reader: &FileReader,
+    min_length: usize,
+    utf16: bool,
+    token: &CancelToken,
+) -> Result<Vec<Tag>, AppError> {
+    let file_size = reader.file_size;
+    let mut collector = RunCollector {
+        min_length,
+        found: Vec::new(),
+    };
+    // For UTF-16 one run is tracked per byte parity.
+    let mut runs: [Option<Run>; 2] = [None, None];
+    let mut previous: Option<u8> = None;
+
+    let mut chunk = vec![0u8; STRINGS_CHUNK_SIZE];
+    let mut offset = 0u64;
+    while offset < file_size {
+        token.check()?;
+        let read = reader.read_at(offset, &mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        for (i, &byte) in chunk[..read].iter().enumerate() {
+            let position = offset + i as u64;
+            if !utf16 {
+                if is_printable_ascii(byte) {
+                    collector.push(&mut runs[0], position, byte as char);
+                } else {
+                    collector.finish(&mut runs[0], position);
+                }
+                continue;
+            }
+
+            // Each byte completes the code unit that started one byte earlier.
+            if let Some(low) = previous {
+                let unit_start = position - 1;
+                let run = &mut runs[(unit_start % 2) as usize];
+                if byte == 0 && is_printable_ascii(low) {
+                    collector.push(run, unit_start, low as char);
+                } else {
+                    collector.finish(run, unit_start);
+                }
+            }
+            previous = Some(byte);
+        }
+        offset += read as u64;
+    }
+
+    // Close any runs that extend to the end of the file. For UTF-16 the
+    // run whose last code unit ends on the final byte closes at `end`, the
+    // other one a byte earlier.
+    let end = offset;
+    if utf16 {
+        let tail_parity = (end % 2) as usize;
+        collector.finish(&mut runs[tail_parity], end);
+        collector.finish(&mut runs[1 - tail_parity], end.saturating_sub(1));
+    } else {
+        collector.finish(&mut runs[0], end);
+    }
+
+    let mut found = collector.found;
+    found.sort_by_key(|tag| tag.start);
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::reader_over;
+
+    fn found(tags: &[Tag]) -> Vec<(u64, u64, &str)> {
+        tags.iter()
+            .map(|tag| (tag.start, tag.end, tag.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_planted_ascii_string() {
+        let mut bytes = vec![0u8; 64];
+        bytes[20..31].copy_from_slice(b"hello world");
+        bytes[40..42].copy_from_slice(b"hi");
+        let reader = reader_over(&bytes);
+
+        let tags = scan_strings(&reader, 4, false, &CancelToken::default()).unwrap();
+        assert_eq!(found(&tags), vec![(20, 31, "hello world")]);
+    }
+
+    #[test]
+    fn finds_string_straddling_chunk_boundary() {
+        let mut bytes = vec![0u8; STRINGS_CHUNK_SIZE + 16];
+        let start = STRINGS_CHUNK_SIZE - 3;
+        bytes[start..start + 6].copy_from_slice(b"BORDER");
+        let reader = reader_over(&bytes);
+
+        let tags = scan_strings(&reader, 4, false, &CancelToken::default()).unwrap();
+        assert_eq!(
+            found(&tags),
+            vec![(start as u64, start as u64 + 6, "BORDER")]
+        );
+    }
+
+    #[test]
+    fn finds_utf16_string_at_odd_offset() {
+        let mut bytes = vec![0u8; 32];
+        let text: Vec<u8> = "Name".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        bytes[5..13].copy_from_slice(&text);
+        let reader = reader_over(&bytes);
+
+        let tags = scan_strings(&reader, 4, true, &CancelToken::default()).unwrap();
+        assert_eq!(found(&tags), vec![(5, 13, "Name")]);
+    }
+}
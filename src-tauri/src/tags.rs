@@ -0,0 +1,1507 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::State;
+
+use crate::error::AppError;
+use crate::inspect::{Endian, ValueKind};
+use crate::reader::{to_usize, FileReader};
+use crate::{lock_state, run_blocking, AppState};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Tag {
+    /// Assigned by the backend when the tag is added; ignored on input.
+    #[serde(default)]
+    pub id: u64,
+    pub start: u64,
+    pub end: u64,
+    pub name: String,
+    /// Shown instead of `name`; sidecars from before versioning could leave
+    /// it out.
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Stacking order for overlapping tags; higher layers draw on top.
+    #[serde(default)]
+    pub layer: i32,
+    /// Inspector kind, e.g. `u32`, to decode the tag's first bytes as.
+    #[serde(default)]
+    pub value_type: Option<String>,
+    /// `little` or `big` for `value_type`; little endian when unset.
+    #[serde(default)]
+    pub endian: Option<String>,
+    /// Decoded value, filled in by `get_tags_in_range`; ignored on input.
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Free-form notes, set with `update_note`.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// When the tag was added, in Unix milliseconds; ignored on input. 0 for
+    /// tags saved before this was recorded.
+    #[serde(default)]
+    pub created_at: u64,
+    /// Group the tag belongs to, which `set_category_visible` can hide.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Id of the live template that produced the tag, see `apply_template`.
+    #[serde(default)]
+    pub depends_on: Option<u64>,
+}
+
+impl Tag {
+    /// Builds an uncolored tag that has not been assigned an id yet.
+    pub fn new(start: u64, end: u64, name: String, display_name: &str) -> Self {
+        Self {
+            id: 0,
+            start,
+            end,
+            name,
+            display_name: display_name.to_string(),
+            color: None,
+            layer: 0,
+            value_type: None,
+            endian: None,
+            value: None,
+            note: None,
+            created_at: 0,
+            category: None,
+            depends_on: None,
+        }
+    }
+
+    /// The decoding requested by `value_type` and `endian`, if any.
+    fn value_format(&self) -> Result<Option<(ValueKind, Endian)>, AppError> {
+        let Some(value_type) = &self.value_type else {
+            return Ok(None);
+        };
+        let endian = match &self.endian {
+            Some(endian) => Endian::parse(endian)?,
+            None => Endian::Little,
+        };
+        Ok(Some((ValueKind::parse(value_type)?, endian)))
+    }
+
+    pub(crate) fn overlaps(&self, other: &Tag) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    fn contains(&self, other: &Tag) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    /// Whether both mark the same bytes as the same thing.
+    fn duplicates(&self, other: &Tag) -> bool {
+        (self.start, self.end) == (other.start, other.end)
+            && self.display_name == other.display_name
+    }
+}
+
+/// Accepts `#RRGGBB` and `#RRGGBBAA`.
+fn is_valid_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => {
+            (hex.len() == 6 || hex.len() == 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => false,
+    }
+}
+
+pub(crate) fn validate_tag(tag: &Tag, file_size: u64) -> Result<(), AppError> {
+    if tag.start >= tag.end {
+        return Err(AppError::InvalidRange {
+            start: tag.start,
+            end: tag.end,
+        });
+    }
+    if tag.end > file_size {
+        return Err(AppError::OutOfBounds {
+            requested: tag.end,
+            size: file_size,
+        });
+    }
+    if let Some(color) = &tag.color {
+        if !is_valid_color(color) {
+            return Err(AppError::InvalidArgument(format!(
+                "Invalid tag color '{}', expected #RRGGBB or #RRGGBBAA",
+                color
+            )));
+        }
+    }
+    if let Some((kind, _)) = tag.value_format()? {
+        if tag.end - tag.start < kind.size() as u64 {
+            return Err(AppError::InvalidArgument(format!(
+                "Tag of {} bytes is too short for a {}",
+                tag.end - tag.start,
+                tag.value_type.as_deref().unwrap_or_default()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the value of a tag with a `value_type` from the file's bytes.
+fn decode_tag_value(reader: &FileReader, tag: &Tag) -> Result<Option<String>, AppError> {
+    let Some((kind, endian)) = tag.value_format()? else {
+        return Ok(None);
+    };
+    let mut bytes = vec![0u8; kind.size()];
+    reader.read_exact_at(tag.start, &mut bytes)?;
+    Ok(Some(kind.decode(&bytes, endian)))
+}
+
+/// Inserts `tag` keeping `tags` sorted by start offset.
+pub(crate) fn insert_sorted(tags: &mut Vec<Tag>, tag: Tag) {
+    let index = tags.partition_point(|existing| existing.start <= tag.start);
+    tags.insert(index, tag);
+}
+
+/// Maps `[start, end)` across `removed` bytes at `at` being replaced by
+/// `inserted` new ones, the way `shift_tags` moves a tag. Returns `None` when
+/// the range is left empty.
+pub(crate) fn shift_range(
+    (start, end): (u64, u64),
+    at: u64,
+    removed: u64,
+    inserted: u64,
+) -> Option<(u64, u64)> {
+    let removed_end = at + removed;
+    let start = if start < at {
+        start
+    } else if start >= removed_end {
+        start - removed + inserted
+    } else {
+        at + inserted
+    };
+    let end = if end <= at {
+        end
+    } else if end >= removed_end {
+        end - removed + inserted
+    } else {
+        at
+    };
+    (start < end).then_some((start, end))
+}
+
+/// Moves tags to follow `removed` bytes at `at` being replaced by `inserted`
+/// new ones. Tags starting at or after the edit shift with it, tags spanning
+/// it grow or shrink, and tags left empty by a deletion are dropped. Tags
+/// shrunk below the size of their `value_type` lose it.
+pub(crate) fn shift_tags(tags: &mut Vec<Tag>, at: u64, removed: u64, inserted: u64) {
+    // The mapping is monotonic, so the tags stay sorted by start.
+    tags.retain_mut(
+        |tag| match shift_range((tag.start, tag.end), at, removed, inserted) {
+            Some((start, end)) => {
+                tag.start = start;
+                tag.end = end;
+                if let Ok(Some((kind, _))) = tag.value_format() {
+                    if end - start < kind.size() as u64 {
+                        tag.value_type = None;
+                    }
+                }
+                true
+            }
+            None => false,
+        },
+    );
+}
+
+/// The current time in Unix milliseconds.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn tag_index(tags: &[Tag], id: u64) -> Result<usize, AppError> {
+    tags.iter()
+        .position(|tag| tag.id == id)
+        .ok_or(AppError::TagNotFound(id))
+}
+
+/// Returns the id assigned to the new tag. With `auto_layer`, the tag's layer
+/// is set to the number of existing tags that fully contain it, so nested tags
+/// stack above their parents (nesting also needs `allow_overlap`). With
+/// `dedup`, a tag that duplicates an existing one (see `dedup_tags`) is not
+/// added and the existing tag's id is returned.
+#[tauri::command]
+pub fn add_tag(
+    handle: u64,
+    mut tag: Tag,
+    allow_overlap: Option<bool>,
+    auto_layer: Option<bool>,
+    dedup: Option<bool>,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    validate_tag(&tag, file_state.file_size)?;
+
+    if dedup.unwrap_or(false) {
+        if let Some(existing) = file_state
+            .tags
+            .iter()
+            .find(|existing| existing.duplicates(&tag))
+        {
+            return Ok(existing.id);
+        }
+    }
+
+    if !allow_overlap.unwrap_or(false) {
+        if let Some(existing) = file_state
+            .tags
+            .iter()
+            .find(|existing| existing.overlaps(&tag))
+        {
+            return Err(AppError::TagOverlap {
+                id: existing.id,
+                name: existing.name.clone(),
+            });
+        }
+    }
+
+    if auto_layer.unwrap_or(false) {
+        let containing = file_state
+            .tags
+            .iter()
+            .filter(|existing| existing.contains(&tag))
+            .count();
+        tag.layer = i32::try_from(containing).unwrap_or(i32::MAX);
+    }
+
+    let id = file_state.next_tag_id;
+    file_state.next_tag_id += 1;
+    tag.id = id;
+    tag.value = None;
+    tag.created_at = now_millis();
+    insert_sorted(&mut file_state.tags, tag);
+    Ok(id)
+}
+
+/// Removes tags with the same range and display name as an earlier tag,
+/// keeping the one added first, and returns how many were removed.
+#[tauri::command]
+pub fn dedup_tags(handle: u64, state: State<AppState>) -> Result<usize, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let mut first_ids = HashMap::new();
+    for tag in &file_state.tags {
+        let id = first_ids
+            .entry((tag.start, tag.end, tag.display_name.as_str()))
+            .or_insert(tag.id);
+        *id = std::cmp::min(*id, tag.id);
+    }
+    let keep: HashSet<u64> = first_ids.into_values().collect();
+    let before = file_state.tags.len();
+    file_state.tags.retain(|tag| keep.contains(&tag.id));
+    Ok(before - file_state.tags.len())
+}
+
+/// Sets or, with `None`, clears the note of tag `id`.
+#[tauri::command]
+pub fn update_note(
+    handle: u64,
+    id: u64,
+    note: Option<String>,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let index = tag_index(&file_state.tags, id)?;
+    file_state.tags[index].note = note;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_tag(handle: u64, id: u64, state: State<AppState>) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let index = tag_index(&file_state.tags, id)?;
+    file_state.tags.remove(index);
+    Ok(())
+}
+
+/// Replaces the range, names and color of tag `id`, keeping its id, note
+/// and creation time.
+#[tauri::command]
+pub fn update_tag(
+    handle: u64,
+    id: u64,
+    mut tag: Tag,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let index = tag_index(&file_state.tags, id)?;
+    validate_tag(&tag, file_state.file_size)?;
+
+    tag.id = id;
+    tag.value = None;
+    let existing = file_state.tags.remove(index);
+    tag.note = existing.note;
+    tag.created_at = existing.created_at;
+    insert_sorted(&mut file_state.tags, tag);
+    Ok(())
+}
+
+/// Replaces tags `ids` by one tag named `name` spanning all of them, which
+/// takes its display name, color and layer from the first. With
+/// `require_contiguous`, the tags must overlap or touch with no gaps between
+/// them. Returns the id of the merged tag.
+#[tauri::command]
+pub fn merge_tags(
+    handle: u64,
+    mut ids: Vec<u64>,
+    name: String,
+    require_contiguous: Option<bool>,
+    state: State<AppState>,
+) -> Result<u64, AppError> {
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.len() < 2 {
+        return Err(AppError::InvalidArgument(
+            "Merging needs at least two tags".to_string(),
+        ));
+    }
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    for &id in &ids {
+        tag_index(&file_state.tags, id)?;
+    }
+
+    // `tags` is sorted by start, so the selected tags are too.
+    let selected: Vec<&Tag> = file_state
+        .tags
+        .iter()
+        .filter(|tag| ids.binary_search(&tag.id).is_ok())
+        .collect();
+    let mut end = selected[0].end;
+    for tag in &selected[1..] {
+        if require_contiguous.unwrap_or(false) && tag.start > end {
+            return Err(AppError::InvalidArgument(format!(
+                "Tags are not contiguous: gap between {} and {}",
+                end, tag.start
+            )));
+        }
+        end = std::cmp::max(end, tag.end);
+    }
+
+    let first = selected[0];
+    let mut merged = Tag::new(first.start, end, name, &first.display_name);
+    merged.color = first.color.clone();
+    merged.layer = first.layer;
+    merged.note = first.note.clone();
+    merged.created_at = now_millis();
+    merged.id = file_state.next_tag_id;
+    file_state.next_tag_id += 1;
+
+    let id = merged.id;
+    file_state
+        .tags
+        .retain(|tag| ids.binary_search(&tag.id).is_err());
+    insert_sorted(&mut file_state.tags, merged);
+    Ok(id)
+}
+
+/// Splits tag `id` at `at`, which must lie strictly inside it. The tag keeps
+/// its id and becomes the first half; the id of the second half is returned.
+#[tauri::command]
+pub fn split_tag(handle: u64, id: u64, at: u64, state: State<AppState>) -> Result<u64, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    let index = tag_index(&file_state.tags, id)?;
+    let tag = &file_state.tags[index];
+    if at <= tag.start || at >= tag.end {
+        return Err(AppError::InvalidArgument(format!(
+            "Split offset {} is not inside tag {}..{}",
+            at, tag.start, tag.end
+        )));
+    }
+
+    let mut first = tag.clone();
+    first.end = at;
+    let mut second = tag.clone();
+    second.start = at;
+    validate_tag(&first, file_state.file_size)?;
+    validate_tag(&second, file_state.file_size)?;
+
+    second.id = file_state.next_tag_id;
+    file_state.next_tag_id += 1;
+    let second_id = second.id;
+    file_state.tags[index] = first;
+    insert_sorted(&mut file_state.tags, second);
+    Ok(second_id)
+}
+
+/// Returns the tags sharing at least one byte with `[start, end)`. Like
+/// every range here the query is half-open, so a tag ending at `start` or
+/// starting at `end` is left out, and so are tags in hidden categories
+/// unless `include_hidden` is set. Tags with a `value_type` carry their
+/// decoded `value`, or no value if it cannot be read.
+#[tauri::command]
+pub fn get_tags_in_range(
+    handle: u64,
+    start: u64,
+    end: u64,
+    include_hidden: Option<bool>,
+    state: State<AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+
+    let include_hidden = include_hidden.unwrap_or(false);
+    let mut tags_in_range: Vec<Tag> = file_state
+        .tags
+        .iter()
+        .filter(|tag| tag.start < end && tag.end > start)
+        .filter(|tag| {
+            include_hidden
+                || tag.category.as_ref().map_or(true, |category| {
+                    !file_state.hidden_categories.contains(category)
+                })
+        })
+        .cloned()
+        .collect();
+    // One unreadable tag must not hide the others.
+    let reader = file_state.reader();
+    for tag in &mut tags_in_range {
+        tag.value = decode_tag_value(&reader, tag).ok().flatten();
+    }
+    // Lower layers first so the frontend can draw nested tags on top.
+    tags_in_range.sort_by_key(|tag| (tag.layer, tag.start));
+    Ok(tags_in_range)
+}
+
+/// Returns the color to draw each byte of `[start, end)` with: that of the
+/// topmost tag covering it, meaning the highest layer and then the shortest
+/// tag. Uncolored tags and tags in hidden categories leave the bytes to the
+/// tags below them. The range is clamped to the file and limited to its read
+/// limit, as set with `set_max_read_bytes`.
+#[tauri::command]
+pub fn color_map(
+    handle: u64,
+    start: u64,
+    end: u64,
+    state: State<AppState>,
+) -> Result<Vec<Option<String>>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let end = file_state.reader().validate_range(start, end)?;
+    if end - start > file_state.max_read_bytes {
+        return Err(AppError::ReadTooLarge {
+            requested: end - start,
+            limit: file_state.max_read_bytes,
+        });
+    }
+
+    let mut painters: Vec<&Tag> = file_state
+        .tags
+        .iter()
+        .filter(|tag| tag.start < end && tag.end > start && tag.color.is_some())
+        .filter(|tag| {
+            tag.category.as_ref().map_or(true, |category| {
+                !file_state.hidden_categories.contains(category)
+            })
+        })
+        .collect();
+    // Painted bottom to top, so the topmost tag is the last to touch a byte.
+    painters.sort_by_key(|tag| (tag.layer, std::cmp::Reverse(tag.end - tag.start)));
+    let mut colors = vec![None; to_usize(end - start)?];
+    for tag in painters {
+        let from = to_usize(tag.start.max(start) - start)?;
+        let to = to_usize(tag.end.min(end) - start)?;
+        colors[from..to].fill(tag.color.clone());
+    }
+    Ok(colors)
+}
+
+/// Returns every tag whose range contains `offset`, innermost (shortest)
+/// first, for showing what is under the cursor. Tags in hidden categories
+/// are included.
+#[tauri::command]
+pub fn tags_containing(
+    handle: u64,
+    offset: u64,
+    state: State<AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    let mut containing: Vec<Tag> = app_state
+        .get(handle)?
+        .tags
+        .iter()
+        .filter(|tag| tag.start <= offset && offset < tag.end)
+        .cloned()
+        .collect();
+    // Stable, so tags of the same size keep their start order.
+    containing.sort_by_key(|tag| tag.end - tag.start);
+    Ok(containing)
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TagIntersection {
+    pub tag: Tag,
+    /// Bytes the tag shares with the queried range.
+    pub intersection_len: u64,
+}
+
+/// Returns the tags sharing at least one byte with `[start, end)`, in start
+/// order, each with how many bytes it shares. Tags in hidden categories are
+/// included.
+#[tauri::command]
+pub fn tags_intersecting(
+    handle: u64,
+    start: u64,
+    end: u64,
+    state: State<AppState>,
+) -> Result<Vec<TagIntersection>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(app_state
+        .get(handle)?
+        .tags
+        .iter()
+        .filter(|tag| tag.start < end && tag.end > start)
+        .map(|tag| TagIntersection {
+            tag: tag.clone(),
+            intersection_len: tag.end.min(end) - tag.start.max(start),
+        })
+        .collect())
+}
+
+/// Shows or hides the tags in `category` in `get_tags_in_range`. Categories
+/// need not have any tags yet.
+#[tauri::command]
+pub fn set_category_visible(
+    handle: u64,
+    category: String,
+    visible: bool,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+
+    if visible {
+        file_state.hidden_categories.remove(&category);
+    } else {
+        file_state.hidden_categories.insert(category);
+    }
+    Ok(())
+}
+
+/// Largest window `get_tag_context` will return.
+const MAX_CONTEXT_WINDOW: u64 = 16 * 1024 * 1024;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TagContext {
+    pub tag: Tag,
+    /// Offset of the first byte in `bytes`.
+    pub window_start: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Returns tag `id` with the bytes from `context` bytes before it to `context`
+/// bytes after it, clamped to the file, so the frontend can reveal it in one
+/// call. Windows larger than 16 MiB are rejected.
+#[tauri::command]
+pub async fn get_tag_context(
+    handle: u64,
+    id: u64,
+    context: u64,
+    state: State<'_, AppState>,
+) -> Result<TagContext, AppError> {
+    let (tag, reader) = {
+        let mut app_state = lock_state(&state);
+        let file_state = app_state.get(handle)?;
+        let tag = file_state.tags[tag_index(&file_state.tags, id)?].clone();
+        (tag, file_state.reader())
+    };
+    let window_start = tag.start.saturating_sub(context);
+    let window_end = std::cmp::min(tag.end.saturating_add(context), reader.file_size);
+    if window_end - window_start > MAX_CONTEXT_WINDOW {
+        return Err(AppError::InvalidArgument(format!(
+            "Context window of {} bytes exceeds the {} byte limit",
+            window_end - window_start,
+            MAX_CONTEXT_WINDOW
+        )));
+    }
+
+    run_blocking(move || {
+        let mut tag = tag;
+        tag.value = decode_tag_value(&reader, &tag).ok().flatten();
+        let mut bytes = vec![0u8; to_usize(window_end - window_start)?];
+        reader.read_exact_at(window_start, &mut bytes)?;
+        Ok(TagContext {
+            tag,
+            window_start,
+            bytes,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_all_tags(handle: u64, state: State<AppState>) -> Result<Vec<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(app_state.get(handle)?.tags.clone())
+}
+
+/// Returns the tag with the smallest start strictly after `offset`, if any.
+#[tauri::command]
+pub fn next_tag(handle: u64, offset: u64, state: State<AppState>) -> Result<Option<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    let tags = &app_state.get(handle)?.tags;
+
+    let index = tags.partition_point(|tag| tag.start <= offset);
+    Ok(tags.get(index).cloned())
+}
+
+/// Returns the tag with the largest end at or before `offset`, if any.
+#[tauri::command]
+pub fn prev_tag(handle: u64, offset: u64, state: State<AppState>) -> Result<Option<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    let tags = &app_state.get(handle)?.tags;
+
+    // Only tags starting before `offset` can end at or before it.
+    let candidates = &tags[..tags.partition_point(|tag| tag.start < offset)];
+    Ok(candidates
+        .iter()
+        .filter(|tag| tag.end <= offset)
+        .max_by_key(|tag| tag.end)
+        .cloned())
+}
+
+/// Returns the tags whose name, display name or note contains `query`, sorted
+/// by start. With `case_insensitive`, letters match regardless of case.
+#[tauri::command]
+pub fn search_tags(
+    handle: u64,
+    query: String,
+    case_insensitive: bool,
+    state: State<AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    let tags = &app_state.get(handle)?.tags;
+
+    let lowered = query.to_lowercase();
+    let matches = |text: &str| {
+        if case_insensitive {
+            text.to_lowercase().contains(&lowered)
+        } else {
+            text.contains(&query)
+        }
+    };
+    // `tags` is kept sorted by start, so the matches are too.
+    Ok(tags
+        .iter()
+        .filter(|tag| {
+            matches(&tag.name)
+                || matches(&tag.display_name)
+                || tag.note.as_deref().is_some_and(matches)
+        })
+        .cloned()
+        .collect())
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TagStats {
+    pub count: usize,
+    /// Bytes covered by at least one tag; overlapping tags count once.
+    pub covered_bytes: u64,
+    /// `covered_bytes` as a percentage of the file size.
+    pub covered_percent: f64,
+    pub count_by_display_name: BTreeMap<String, usize>,
+}
+
+/// Returns the total length of the union of `tags`, which must be sorted by
+/// start.
+fn merged_coverage(tags: &[Tag]) -> u64 {
+    let mut covered = 0;
+    let mut current: Option<(u64, u64)> = None;
+    for tag in tags {
+        match current {
+            Some((start, end)) if tag.start <= end => {
+                current = Some((start, std::cmp::max(end, tag.end)));
+            }
+            _ => {
+                if let Some((start, end)) = current {
+                    covered += end - start;
+                }
+                current = Some((tag.start, tag.end));
+            }
+        }
+    }
+    if let Some((start, end)) = current {
+        covered += end - start;
+    }
+    covered
+}
+
+/// Summarizes the file's tags for an overview panel.
+#[tauri::command]
+pub fn tag_stats(handle: u64, state: State<AppState>) -> Result<TagStats, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let tags = &file_state.tags;
+
+    let covered_bytes = merged_coverage(tags);
+    let covered_percent = if file_state.file_size == 0 {
+        0.0
+    } else {
+        covered_bytes as f64 / file_state.file_size as f64 * 100.0
+    };
+    let mut count_by_display_name = BTreeMap::new();
+    for tag in tags {
+        *count_by_display_name
+            .entry(tag.display_name.clone())
+            .or_insert(0) += 1;
+    }
+    Ok(TagStats {
+        count: tags.len(),
+        covered_bytes,
+        covered_percent,
+        count_by_display_name,
+    })
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TagNode {
+    pub tag: Tag,
+    pub children: Vec<TagNode>,
+}
+
+/// Nests `tags` by containment, each under the smallest tag that contains
+/// it. Of tags with the same span, the first is the parent of the others.
+fn build_tag_tree(tags: &[Tag]) -> Vec<TagNode> {
+    // Containers sort before the tags they contain.
+    let mut order: Vec<&Tag> = tags.iter().collect();
+    order.sort_by_key(|tag| (tag.start, std::cmp::Reverse(tag.end)));
+
+    // Tags not yet ended at the current start, the only possible parents.
+    let mut open: Vec<usize> = Vec::new();
+    let mut parents = Vec::with_capacity(order.len());
+    for (i, tag) in order.iter().enumerate() {
+        open.retain(|&j| order[j].end > tag.start);
+        let parent = open
+            .iter()
+            .rev()
+            .filter(|&&j| order[j].contains(tag))
+            .min_by_key(|&&j| order[j].end - order[j].start)
+            .copied();
+        parents.push(parent);
+        open.push(i);
+    }
+
+    // Children come after their parent, so attach them back to front.
+    let mut nodes: Vec<Option<TagNode>> = order
+        .iter()
+        .map(|&tag| {
+            Some(TagNode {
+                tag: tag.clone(),
+                children: Vec::new(),
+            })
+        })
+        .collect();
+    let mut roots = Vec::new();
+    for i in (0..nodes.len()).rev() {
+        let mut node = nodes[i].take().unwrap();
+        node.children.reverse();
+        match parents[i] {
+            Some(parent) => nodes[parent].as_mut().unwrap().children.push(node),
+            None => roots.push(node),
+        }
+    }
+    roots.reverse();
+    roots
+}
+
+/// Returns the file's tags as a tree for a nested sidebar: each tag is a
+/// child of the smallest tag fully containing it, and tags that only overlap
+/// stay siblings. Siblings are ordered by start.
+#[tauri::command]
+pub fn tag_tree(handle: u64, state: State<AppState>) -> Result<Vec<TagNode>, AppError> {
+    let mut app_state = lock_state(&state);
+    Ok(build_tag_tree(&app_state.get(handle)?.tags))
+}
+
+/// The decoded value of a typed tag as JSON: numbers where they fit one,
+/// strings for infinities and NaN.
+fn json_value(reader: &FileReader, tag: &Tag) -> Result<Option<serde_json::Value>, AppError> {
+    let Some((kind, endian)) = tag.value_format()? else {
+        return Ok(None);
+    };
+    let mut bytes = vec![0u8; kind.size()];
+    reader.read_exact_at(tag.start, &mut bytes)?;
+    let decoded = kind.decode(&bytes, endian);
+    let number = match kind {
+        ValueKind::F32 | ValueKind::F64 => {
+            decoded.parse().ok().and_then(serde_json::Number::from_f64)
+        }
+        _ => decoded.parse().ok(),
+    };
+    Ok(Some(number.map_or(
+        serde_json::Value::String(decoded),
+        serde_json::Value::Number,
+    )))
+}
+
+/// Builds the JSON fields of `nodes`, each keyed by tag name. Tags with typed
+/// tags inside become objects of those and typed tags without become their
+/// value; tags with neither are left out. Siblings sharing a name are
+/// gathered into an array, in order.
+fn json_fields(
+    reader: &FileReader,
+    nodes: &[TagNode],
+) -> Result<serde_json::Map<String, serde_json::Value>, AppError> {
+    let mut fields = serde_json::Map::new();
+    for node in nodes {
+        let children = json_fields(reader, &node.children)?;
+        let value = if children.is_empty() {
+            match json_value(reader, &node.tag)? {
+                Some(value) => value,
+                None => continue,
+            }
+        } else {
+            serde_json::Value::Object(children)
+        };
+        match fields.get_mut(&node.tag.name) {
+            Some(serde_json::Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = serde_json::Value::Array(vec![existing.take(), value]),
+            None => {
+                fields.insert(node.tag.name.clone(), value);
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Decodes every tag with a `value_type` into one JSON object keyed by tag
+/// name, nested as in `tag_tree`: a tag containing typed tags becomes an
+/// object of their fields. Siblings sharing a name, as in arrays laid out by
+/// templates, become a JSON array.
+#[tauri::command]
+pub fn extract_fields(handle: u64, state: State<AppState>) -> Result<serde_json::Value, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let fields = json_fields(&file_state.reader(), &build_tag_tree(&file_state.tags))?;
+    Ok(serde_json::Value::Object(fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::test::MockRuntime;
+    use tauri::{App, Manager};
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    fn tag(start: u64, end: u64, name: &str) -> Tag {
+        Tag::new(start, end, name.to_string(), name)
+    }
+
+    fn spans(tags: &[Tag]) -> Vec<(u64, u64)> {
+        tags.iter().map(|tag| (tag.start, tag.end)).collect()
+    }
+
+    fn add(app: &App<MockRuntime>, handle: u64, tag: Tag) -> Result<u64, AppError> {
+        add_tag(handle, tag, None, None, None, app.state())
+    }
+
+    #[test]
+    fn add_tag_keeps_tags_sorted() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+
+        add(&app, handle, tag(40, 50, "c")).unwrap();
+        add(&app, handle, tag(0, 10, "a")).unwrap();
+        add(&app, handle, tag(20, 30, "b")).unwrap();
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        assert_eq!(spans(&tags), vec![(0, 10), (20, 30), (40, 50)]);
+    }
+
+    #[test]
+    fn add_tag_rejects_overlap() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+
+        let id = add(&app, handle, tag(10, 20, "first")).unwrap();
+        assert!(matches!(
+            add(&app, handle, tag(15, 25, "second")),
+            Err(AppError::TagOverlap { id: existing, .. }) if existing == id
+        ));
+        // Touching is not overlapping.
+        add(&app, handle, tag(20, 25, "adjacent")).unwrap();
+        add_tag(
+            handle,
+            tag(12, 18, "nested"),
+            Some(true),
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(get_all_tags(handle, app.state()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn remove_tag_by_id_leaves_others() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+
+        let a = add(&app, handle, tag(0, 10, "a")).unwrap();
+        let b = add(&app, handle, tag(10, 20, "b")).unwrap();
+        let c = add(&app, handle, tag(20, 30, "c")).unwrap();
+        remove_tag(handle, b, app.state()).unwrap();
+
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        let ids: Vec<u64> = tags.iter().map(|tag| tag.id).collect();
+        assert_eq!(ids, vec![a, c]);
+        assert_eq!(spans(&tags), vec![(0, 10), (20, 30)]);
+        assert!(matches!(
+            remove_tag(handle, b, app.state()),
+            Err(AppError::TagNotFound(id)) if id == b
+        ));
+    }
+
+    #[test]
+    fn update_tag_keeps_id_and_resorts() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+
+        let a = add(&app, handle, tag(0, 10, "a")).unwrap();
+        let b = add(&app, handle, tag(10, 20, "b")).unwrap();
+        update_tag(handle, a, tag(30, 40, "moved"), app.state()).unwrap();
+
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        assert_eq!(tags[0].id, b);
+        assert_eq!(
+            (tags[1].id, tags[1].start, tags[1].name.as_str()),
+            (a, 30, "moved")
+        );
+    }
+
+    #[test]
+    fn next_and_prev_tag_do_not_wrap() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for (start, end) in [(4, 8), (10, 20), (30, 40)] {
+            add(&app, handle, tag(start, end, "t")).unwrap();
+        }
+        let next = |offset| {
+            next_tag(handle, offset, app.state())
+                .unwrap()
+                .map(|t| t.start)
+        };
+        let prev = |offset| {
+            prev_tag(handle, offset, app.state())
+                .unwrap()
+                .map(|t| t.start)
+        };
+
+        assert_eq!(next(0), Some(4));
+        assert_eq!(next(4), Some(10));
+        assert_eq!(next(15), Some(30));
+        assert_eq!(next(30), None);
+
+        assert_eq!(prev(63), Some(30));
+        assert_eq!(prev(35), Some(10));
+        assert_eq!(prev(20), Some(10));
+        assert_eq!(prev(8), Some(4));
+        assert_eq!(prev(7), None);
+    }
+
+    #[test]
+    fn auto_layer_counts_containing_tags() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for (start, end, name) in [(0, 64, "outer"), (8, 32, "middle"), (12, 16, "inner")] {
+            add_tag(
+                handle,
+                tag(start, end, name),
+                Some(true),
+                Some(true),
+                None,
+                app.state(),
+            )
+            .unwrap();
+        }
+
+        let layered: Vec<_> = get_tags_in_range(handle, 0, 64, None, app.state())
+            .unwrap()
+            .into_iter()
+            .map(|tag| (tag.name, tag.layer))
+            .collect();
+        assert_eq!(
+            layered,
+            vec![
+                ("outer".to_string(), 0),
+                ("middle".to_string(), 1),
+                ("inner".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn search_tags_matches_name_or_display_name() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for (start, name, display_name) in [
+            (40, "crc", "Header CRC"),
+            (0, "header", "File header"),
+            (8, "body", "Body"),
+            (20, "HEADER_EXT", "Extension"),
+        ] {
+            add(
+                &app,
+                handle,
+                Tag::new(start, start + 4, name.to_string(), display_name),
+            )
+            .unwrap();
+        }
+        let search = |query: &str, case_insensitive| {
+            search_tags(handle, query.to_string(), case_insensitive, app.state())
+                .unwrap()
+                .into_iter()
+                .map(|tag| tag.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search("header", false), vec!["header"]);
+        assert_eq!(search("header", true), vec!["header", "HEADER_EXT", "crc"]);
+        assert_eq!(search("Body", false), vec!["body"]);
+        assert!(search("missing", true).is_empty());
+    }
+
+    #[test]
+    fn decodes_u32_tags_independently() {
+        let app = app();
+        let mut bytes = 0xdeadbeefu32.to_le_bytes().to_vec();
+        bytes.extend(0x0102_0304u32.to_be_bytes());
+        let (_file, handle) = open_temp(&app, &bytes);
+        let mut little = tag(0, 4, "little");
+        little.value_type = Some("u32".to_string());
+        add(&app, handle, little).unwrap();
+        let mut big = tag(4, 8, "big");
+        big.value_type = Some("u32".to_string());
+        big.endian = Some("big".to_string());
+        add(&app, handle, big).unwrap();
+        let values = || {
+            get_tags_in_range(handle, 0, 8, None, app.state())
+                .unwrap()
+                .into_iter()
+                .map(|tag| tag.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values(),
+            vec![Some("3735928559".to_string()), Some("16909060".to_string())]
+        );
+
+        // A value that cannot be decoded leaves the other tags intact.
+        app.state::<AppState>()
+            .lock()
+            .unwrap()
+            .get_mut(handle)
+            .unwrap()
+            .tags[1]
+            .endian = Some("middle".to_string());
+        assert_eq!(values(), vec![Some("3735928559".to_string()), None]);
+    }
+
+    #[test]
+    fn shrinking_a_tag_below_its_value_type_drops_it() {
+        let mut typed = tag(0, 8, "typed");
+        typed.value_type = Some("u64".to_string());
+        let mut tags = vec![typed];
+
+        shift_tags(&mut tags, 10, 0, 4);
+        assert_eq!(tags[0].value_type.as_deref(), Some("u64"));
+        shift_tags(&mut tags, 2, 1, 0);
+        assert_eq!(spans(&tags), vec![(0, 7)]);
+        assert_eq!(tags[0].value_type, None);
+    }
+
+    #[test]
+    fn tag_context_clamps_near_the_start() {
+        let app = app();
+        let bytes: Vec<u8> = (0..32).collect();
+        let (_file, handle) = open_temp(&app, &bytes);
+        let id = add(&app, handle, tag(2, 4, "early")).unwrap();
+        let context = |context| {
+            tauri::async_runtime::block_on(get_tag_context(handle, id, context, app.state()))
+        };
+
+        let window = context(5).unwrap();
+        assert_eq!((window.tag.id, window.window_start), (id, 0));
+        assert_eq!(window.bytes, &bytes[..9]);
+        let whole = context(u64::MAX).unwrap();
+        assert_eq!(whole.bytes, bytes);
+        assert_eq!(
+            tauri::async_runtime::block_on(get_tag_context(handle, id + 1, 0, app.state()))
+                .unwrap_err(),
+            AppError::TagNotFound(id + 1)
+        );
+    }
+
+    #[test]
+    fn merged_coverage_counts_overlaps_once() {
+        let tags = [
+            tag(0, 10, "a"),
+            tag(4, 12, "b"),
+            tag(5, 6, "c"),
+            tag(12, 14, "d"),
+            tag(20, 25, "e"),
+        ];
+        assert_eq!(merged_coverage(&tags), 19);
+        assert_eq!(merged_coverage(&[]), 0);
+    }
+
+    #[test]
+    fn tag_stats_summarizes_overlapping_tags() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 50]);
+        for (start, end, display_name) in [(0, 10, "Header"), (5, 15, "Field"), (30, 40, "Field")] {
+            let tag = Tag::new(start, end, display_name.to_lowercase(), display_name);
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+
+        let stats = tag_stats(handle, app.state()).unwrap();
+        assert_eq!((stats.count, stats.covered_bytes), (3, 25));
+        assert_eq!(stats.covered_percent, 50.0);
+        assert_eq!(
+            stats.count_by_display_name.into_iter().collect::<Vec<_>>(),
+            vec![("Field".to_string(), 2), ("Header".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn merge_replaces_tags_with_one_spanning_them() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let a = add(&app, handle, tag(0, 4, "a")).unwrap();
+        let b = add(&app, handle, tag(4, 8, "b")).unwrap();
+        let c = add(&app, handle, tag(12, 16, "c")).unwrap();
+        add(&app, handle, tag(20, 24, "other")).unwrap();
+
+        assert!(matches!(
+            merge_tags(
+                handle,
+                vec![c, a, b],
+                "abc".to_string(),
+                Some(true),
+                app.state()
+            ),
+            Err(AppError::InvalidArgument(_))
+        ));
+        let merged =
+            merge_tags(handle, vec![c, a, b], "abc".to_string(), None, app.state()).unwrap();
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        assert_eq!(spans(&tags), vec![(0, 16), (20, 24)]);
+        assert_eq!((tags[0].id, tags[0].name.as_str()), (merged, "abc"));
+        assert!(![a, b, c].contains(&merged));
+    }
+
+    #[test]
+    fn split_keeps_id_for_the_first_half() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let id = add(&app, handle, tag(10, 20, "whole")).unwrap();
+
+        let second = split_tag(handle, id, 15, app.state()).unwrap();
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        assert_eq!(spans(&tags), vec![(10, 15), (15, 20)]);
+        assert_eq!((tags[0].id, tags[1].id), (id, second));
+        for at in [10, 15, 20] {
+            assert!(matches!(
+                split_tag(handle, id, at, app.state()),
+                Err(AppError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn notes_update_and_survive_a_sidecar_round_trip() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 32]);
+        let id = add(&app, handle, tag(4, 8, "magic")).unwrap();
+        let created_at = get_tags_in_range(handle, 0, 32, None, app.state()).unwrap()[0].created_at;
+        assert!(created_at > 0);
+
+        update_note(handle, id, Some("first draft".to_string()), app.state()).unwrap();
+        update_note(
+            handle,
+            id,
+            Some("Checked against spec".to_string()),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(
+            update_note(handle, 99, None, app.state()),
+            Err(AppError::TagNotFound(99))
+        );
+        let found = search_tags(handle, "spec".to_string(), false, app.state()).unwrap();
+        assert_eq!(found.len(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tags.json").to_string_lossy().into_owned();
+        crate::sidecar::save_tags(handle, Some(path.clone()), app.state()).unwrap();
+        remove_tag(handle, id, app.state()).unwrap();
+        crate::sidecar::load_tags(handle, Some(path), app.state()).unwrap();
+
+        let reloaded = &get_tags_in_range(handle, 0, 32, None, app.state()).unwrap()[0];
+        assert_eq!(reloaded.note.as_deref(), Some("Checked against spec"));
+        assert_eq!(reloaded.created_at, created_at);
+    }
+
+    #[test]
+    fn tag_tree_nests_fields_inside_their_struct() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for tag in [
+            tag(4, 8, "magic"),
+            tag(0, 16, "header"),
+            tag(8, 12, "version"),
+            tag(12, 16, "flags"),
+            tag(0, 4, "length"),
+            // Overlaps `header` without fitting inside it.
+            tag(14, 20, "straddle"),
+        ] {
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+
+        let tree = tag_tree(handle, app.state()).unwrap();
+        let names = |nodes: &[TagNode]| -> Vec<String> {
+            nodes.iter().map(|node| node.tag.name.clone()).collect()
+        };
+        assert_eq!(names(&tree), ["header", "straddle"]);
+        assert_eq!(
+            names(&tree[0].children),
+            ["length", "magic", "version", "flags"]
+        );
+        assert!(tree[0].children.iter().all(|node| node.children.is_empty()));
+        assert!(tree[1].children.is_empty());
+    }
+
+    #[test]
+    fn tag_tree_picks_the_smallest_container() {
+        let tags = [
+            tag(0, 100, "file"),
+            tag(10, 60, "outer"),
+            tag(40, 80, "overlapping"),
+            tag(45, 50, "field"),
+        ];
+        let tree = build_tag_tree(&tags);
+        assert_eq!(tree.len(), 1);
+        let file = &tree[0];
+        assert_eq!(file.children.len(), 2);
+        // `overlapping` (40 bytes) is smaller than `outer` (50 bytes).
+        assert!(file.children[0].children.is_empty());
+        assert_eq!(file.children[1].children[0].tag.name, "field");
+    }
+
+    #[test]
+    fn get_tags_in_range_is_half_open() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for tag in [
+            tag(0, 8, "before"),
+            tag(8, 16, "inside"),
+            tag(16, 24, "after"),
+        ] {
+            add(&app, handle, tag).unwrap();
+        }
+        let names = |start, end| -> Vec<String> {
+            get_tags_in_range(handle, start, end, None, app.state())
+                .unwrap()
+                .into_iter()
+                .map(|tag| tag.name)
+                .collect()
+        };
+
+        assert_eq!(names(8, 16), ["inside"]);
+        assert_eq!(names(7, 16), ["before", "inside"]);
+        assert_eq!(names(8, 17), ["inside", "after"]);
+        assert_eq!(names(15, 16), ["inside"]);
+        assert_eq!(names(16, 17), ["after"]);
+        assert_eq!(names(24, 64), Vec::<String>::new());
+        assert_eq!(names(8, 8), Vec::<String>::new());
+    }
+
+    #[test]
+    fn hidden_categories_are_left_out_of_range_queries() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let mut header = tag(0, 8, "header");
+        header.category = Some("structure".to_string());
+        let mut checksum = tag(8, 12, "checksum");
+        checksum.category = Some("integrity".to_string());
+        for tag in [header, checksum, tag(12, 16, "plain")] {
+            add(&app, handle, tag).unwrap();
+        }
+        let names = |include_hidden| -> Vec<String> {
+            get_tags_in_range(handle, 0, 64, include_hidden, app.state())
+                .unwrap()
+                .into_iter()
+                .map(|tag| tag.name)
+                .collect()
+        };
+
+        set_category_visible(handle, "structure".to_string(), false, app.state()).unwrap();
+        assert_eq!(names(None), ["checksum", "plain"]);
+        assert_eq!(names(Some(true)), ["header", "checksum", "plain"]);
+
+        set_category_visible(handle, "integrity".to_string(), false, app.state()).unwrap();
+        set_category_visible(handle, "structure".to_string(), true, app.state()).unwrap();
+        assert_eq!(names(None), ["header", "plain"]);
+    }
+
+    #[test]
+    fn duplicate_tags_are_kept_once() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let first = add(&app, handle, tag(0, 8, "magic")).unwrap();
+        let again = add_tag(
+            handle,
+            tag(0, 8, "magic"),
+            None,
+            None,
+            Some(true),
+            app.state(),
+        );
+        assert_eq!(again.unwrap(), first);
+        assert_eq!(get_all_tags(handle, app.state()).unwrap().len(), 1);
+
+        // Imports that allow overlap can still pile up duplicates.
+        for tag in [tag(0, 8, "magic"), tag(0, 8, "other"), tag(0, 8, "magic")] {
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+        assert_eq!(dedup_tags(handle, app.state()).unwrap(), 2);
+        let tags = get_all_tags(handle, app.state()).unwrap();
+        let ids: Vec<_> = tags.iter().map(|tag| tag.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&first));
+        assert_eq!(dedup_tags(handle, app.state()).unwrap(), 0);
+    }
+
+    #[test]
+    fn containment_and_intersection_queries() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        for tag in [
+            tag(0, 64, "file"),
+            tag(8, 32, "header"),
+            tag(10, 14, "field"),
+            tag(40, 48, "trailer"),
+        ] {
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+
+        let under = |offset| -> Vec<String> {
+            tags_containing(handle, offset, app.state())
+                .unwrap()
+                .into_iter()
+                .map(|tag| tag.name)
+                .collect()
+        };
+        assert_eq!(under(12), ["field", "header", "file"]);
+        assert_eq!(under(14), ["header", "file"]);
+        assert_eq!(under(48), ["file"]);
+        assert_eq!(under(64), Vec::<String>::new());
+
+        let spanning: Vec<(String, u64)> = tags_intersecting(handle, 12, 44, app.state())
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.tag.name, hit.intersection_len))
+            .collect();
+        assert_eq!(
+            spanning,
+            [
+                ("file".to_string(), 32),
+                ("header".to_string(), 20),
+                ("field".to_string(), 2),
+                ("trailer".to_string(), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn color_map_takes_the_topmost_covering_color() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let colored = |start, end, color: Option<&str>, layer| {
+            let mut tag = tag(start, end, "tag");
+            tag.color = color.map(str::to_string);
+            tag.layer = layer;
+            tag
+        };
+        for tag in [
+            colored(0, 32, Some("#ff0000"), 0),
+            colored(4, 12, Some("#00ff00"), 0),
+            colored(8, 24, Some("#0000ff"), 1),
+            colored(20, 28, None, 2),
+        ] {
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+
+        let colors = color_map(handle, 0, 40, app.state()).unwrap();
+        assert_eq!(colors.len(), 40);
+        let at = |offset: usize| colors[offset].as_deref();
+        assert_eq!(at(0), Some("#ff0000"));
+        // The shorter tag wins within a layer.
+        assert_eq!(at(5), Some("#00ff00"));
+        // A higher layer wins over a shorter tag.
+        assert_eq!(at(10), Some("#0000ff"));
+        // An uncolored tag on top leaves the color below it.
+        assert_eq!(at(22), Some("#0000ff"));
+        assert_eq!(at(26), Some("#ff0000"));
+        assert_eq!(at(32), None);
+
+        let window = color_map(handle, 10, 14, app.state()).unwrap();
+        assert_eq!(window, vec![Some("#0000ff".to_string()); 4]);
+    }
+
+    #[test]
+    fn extract_fields_nests_typed_tags() {
+        let app = app();
+        let mut bytes = vec![0u8; 16];
+        bytes[..2].copy_from_slice(&0xcafeu16.to_be_bytes());
+        bytes[2..6].copy_from_slice(&7u32.to_le_bytes());
+        bytes[8..12].copy_from_slice(&1.5f32.to_le_bytes());
+        bytes[12] = 0xff;
+        bytes[13] = 2;
+        let (_file, handle) = open_temp(&app, &bytes);
+        let typed = |start, end, name, value_type: &str| {
+            let mut tag = tag(start, end, name);
+            tag.value_type = Some(value_type.to_string());
+            tag
+        };
+        let mut magic = typed(0, 2, "magic", "u16");
+        magic.endian = Some("big".to_string());
+        for tag in [
+            tag(0, 8, "header"),
+            magic,
+            typed(2, 6, "count", "u32"),
+            // Without a type and nothing typed inside, left out.
+            tag(6, 8, "padding"),
+            typed(8, 12, "scale", "f32"),
+            typed(12, 13, "item", "i8"),
+            typed(13, 14, "item", "i8"),
+        ] {
+            add_tag(handle, tag, Some(true), None, None, app.state()).unwrap();
+        }
+
+        assert_eq!(
+            extract_fields(handle, app.state()).unwrap(),
+            serde_json::json!({
+                "header": { "magic": 0xcafe, "count": 7 },
+                "scale": 1.5,
+                "item": [-1, 2],
+            })
+        );
+    }
+}
@@ -0,0 +1,867 @@
+//! A small language for describing binary headers.
+//!
+//! A template is a sequence of field declarations, read back to back:
+//!
+//! ```text
+//! u32be magic;
+//! u16 count;
+//! u8 entries[count];      // arrays may be sized by an earlier field
+//! if (count > 0) {
+//!     u32 first;
+//! } else {
+//!     u8 pad[4];
+//! }
+//! ```
+//!
+//! Types are the inspector kinds (`u8` to `f64`), little endian unless
+//! suffixed with `be`. Conditions compare an earlier integer field with a
+//! number, or test it against zero when written alone. `format png;` hands
+//! the rest of the file to a registered format parser, and reading carries
+//! on after the last tag it produced.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::error::AppError;
+use crate::formats::{region_header, run_parser, ParserRegistry};
+use crate::inspect::{Endian, ValueKind};
+use crate::reader::FileReader;
+use crate::tags::{shift_range, Tag};
+use crate::{lock_state, AppState};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "<", ">", ";", "[", "]", "(", ")", "{", "}",
+];
+
+fn template_error(line: usize, message: impl std::fmt::Display) -> AppError {
+    AppError::InvalidFormat(format!("Template line {}: {}", line, message))
+}
+
+/// Splits `source` into tokens tagged with their line number.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, AppError> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut rest = line.split("//").next().unwrap_or_default().trim_start();
+        while !rest.is_empty() {
+            if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                tokens.push((line_number, Token::Symbol(symbol)));
+                rest = &rest[symbol.len()..];
+            } else {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(template_error(
+                        line_number,
+                        format!("unexpected character '{}'", rest.chars().next().unwrap()),
+                    ));
+                }
+                let word = &rest[..end];
+                let token = if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    let number = match word.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16),
+                        None => word.parse(),
+                    };
+                    Token::Number(number.map_err(|_| {
+                        template_error(line_number, format!("invalid number '{}'", word))
+                    })?)
+                } else {
+                    Token::Ident(word.to_string())
+                };
+                tokens.push((line_number, token));
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Count {
+    Fixed(u64),
+    Field(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Field {
+        line: usize,
+        type_name: String,
+        kind: ValueKind,
+        endian: Endian,
+        name: String,
+        count: Option<Count>,
+    },
+    If {
+        line: usize,
+        field: String,
+        comparison: Comparison,
+        value: u64,
+        then: Vec<Statement>,
+        otherwise: Vec<Statement>,
+    },
+    Format {
+        line: usize,
+        parser: String,
+    },
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(line, _)| *line)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, AppError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(_, token)| token.clone())
+            .ok_or_else(|| template_error(self.line(), "unexpected end of template"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), AppError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Symbol(found) if found == symbol => Ok(()),
+            other => Err(template_error(
+                line,
+                format!("expected '{}', found {:?}", symbol, other),
+            )),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, AppError> {
+        let line = self.line();
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            other => Err(template_error(
+                line,
+                format!("expected a name, found {:?}", other),
+            )),
+        }
+    }
+
+    /// Parses statements until the end of the template or a closing brace.
+    fn statements(&mut self) -> Result<Vec<Statement>, AppError> {
+        let mut statements = Vec::new();
+        while let Some(token) = self.peek() {
+            if *token == Token::Symbol("}") {
+                break;
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, AppError> {
+        self.expect("{")?;
+        let statements = self.statements()?;
+        self.expect("}")?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, AppError> {
+        let line = self.line();
+        let word = self.ident()?;
+        if word == "if" {
+            return self.if_statement(line);
+        }
+        if word == "format" {
+            let parser = self.ident()?;
+            self.expect(";")?;
+            return Ok(Statement::Format { line, parser });
+        }
+
+        let (base, endian) = match word.strip_suffix("be") {
+            Some(base) => (base, Endian::Big),
+            None => (word.strip_suffix("le").unwrap_or(&word), Endian::Little),
+        };
+        let kind = ValueKind::parse(base).map_err(|e| template_error(line, e))?;
+        let name = self.ident()?;
+        let count = if self.peek() == Some(&Token::Symbol("[")) {
+            self.next()?;
+            let count_line = self.line();
+            let count = match self.next()? {
+                Token::Number(count) => Count::Fixed(count),
+                Token::Ident(field) => Count::Field(field),
+                other => {
+                    return Err(template_error(
+                        count_line,
+                        format!("expected an array size, found {:?}", other),
+                    ))
+                }
+            };
+            self.expect("]")?;
+            Some(count)
+        } else {
+            None
+        };
+        self.expect(";")?;
+        Ok(Statement::Field {
+            line,
+            type_name: word,
+            kind,
+            endian,
+            name,
+            count,
+        })
+    }
+
+    fn if_statement(&mut self, line: usize) -> Result<Statement, AppError> {
+        self.expect("(")?;
+        let field = self.ident()?;
+        let (comparison, value) = match self.peek() {
+            Some(Token::Symbol(")")) => (Comparison::Ne, 0),
+            _ => {
+                let comparison = match self.next()? {
+                    Token::Symbol("==") => Comparison::Eq,
+                    Token::Symbol("!=") => Comparison::Ne,
+                    Token::Symbol("<") => Comparison::Lt,
+                    Token::Symbol("<=") => Comparison::Le,
+                    Token::Symbol(">") => Comparison::Gt,
+                    Token::Symbol(">=") => Comparison::Ge,
+                    other => {
+                        return Err(template_error(
+                            line,
+                            format!("expected a comparison, found {:?}", other),
+                        ))
+                    }
+                };
+                match self.next()? {
+                    Token::Number(value) => (comparison, value),
+                    other => {
+                        return Err(template_error(
+                            line,
+                            format!("expected a number, found {:?}", other),
+                        ))
+                    }
+                }
+            }
+        };
+        self.expect(")")?;
+        let then = self.block()?;
+        let otherwise = if self.peek() == Some(&Token::Ident("else".to_string())) {
+            self.next()?;
+            self.block()?
+        } else {
+            Vec::new()
+        };
+        Ok(Statement::If {
+            line,
+            field,
+            comparison,
+            value,
+            then,
+            otherwise,
+        })
+    }
+}
+
+fn parse_template(source: &str) -> Result<Vec<Statement>, AppError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    let statements = parser.statements()?;
+    if parser.position < parser.tokens.len() {
+        return Err(template_error(parser.line(), "unmatched '}'"));
+    }
+    Ok(statements)
+}
+
+/// Walks parsed statements over the file, collecting a tag per field.
+struct Evaluator<'a> {
+    reader: &'a FileReader,
+    parsers: &'a ParserRegistry,
+    position: u64,
+    /// Integer fields read so far, for array sizes and conditions, with the
+    /// range each was read from.
+    values: HashMap<String, (i128, (u64, u64))>,
+    /// Ranges of the fields that sized an array or decided a condition.
+    sources: Vec<(u64, u64)>,
+    tags: Vec<Tag>,
+}
+
+impl Evaluator<'_> {
+    fn field_value(&mut self, line: usize, field: &str) -> Result<i128, AppError> {
+        let (value, range) = self.values.get(field).copied().ok_or_else(|| {
+            template_error(line, format!("'{}' is not an earlier integer field", field))
+        })?;
+        self.sources.push(range);
+        Ok(value)
+    }
+
+    fn run(&mut self, statements: &[Statement]) -> Result<(), AppError> {
+        for statement in statements {
+            match statement {
+                Statement::Field {
+                    line,
+                    type_name,
+                    kind,
+                    endian,
+                    name,
+                    count,
+                } => self.field(*line, type_name, *kind, *endian, name, count.as_ref())?,
+                Statement::If {
+                    line,
+                    field,
+                    comparison,
+                    value,
+                    then,
+                    otherwise,
+                } => {
+                    let actual = self.field_value(*line, field)?;
+                    let value = *value as i128;
+                    let holds = match comparison {
+                        Comparison::Eq => actual == value,
+                        Comparison::Ne => actual != value,
+                        Comparison::Lt => actual < value,
+                        Comparison::Le => actual <= value,
+                        Comparison::Gt => actual > value,
+                        Comparison::Ge => actual >= value,
+                    };
+                    self.run(if holds { then } else { otherwise })?;
+                }
+                Statement::Format { line, parser } => self.format(*line, parser)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn format(&mut self, line: usize, name: &str) -> Result<(), AppError> {
+        let parser = self
+            .parsers
+            .find(name)
+            .ok_or_else(|| template_error(line, format!("unknown format '{}'", name)))?;
+        if self.position >= self.reader.file_size {
+            return Err(AppError::OutOfBounds {
+                requested: self.position,
+                size: self.reader.file_size,
+            });
+        }
+        if !parser.detect(&region_header(self.reader, self.position)?) {
+            return Err(template_error(
+                line,
+                format!("no {} data at offset {}", name, self.position),
+            ));
+        }
+        let tags = run_parser(parser.as_ref(), self.reader, self.position);
+        if let Some(end) = tags.iter().map(|tag| tag.end).max() {
+            self.position = end;
+        }
+        self.tags.extend(tags);
+        Ok(())
+    }
+
+    fn field(
+        &mut self,
+        line: usize,
+        type_name: &str,
+        kind: ValueKind,
+        endian: Endian,
+        name: &str,
+        count: Option<&Count>,
+    ) -> Result<(), AppError> {
+        let count = match count {
+            None => None,
+            Some(Count::Fixed(count)) => Some(*count),
+            Some(Count::Field(field)) => {
+                let count = self.field_value(line, field)?;
+                Some(u64::try_from(count).map_err(|_| {
+                    template_error(line, format!("array size {} is negative", count))
+                })?)
+            }
+        };
+        if count == Some(0) {
+            // Empty arrays take no space and get no tag.
+            return Ok(());
+        }
+
+        let start = self.position;
+        let end = (kind.size() as u64)
+            .checked_mul(count.unwrap_or(1))
+            .and_then(|size| start.checked_add(size))
+            .ok_or(AppError::InvalidRange {
+                start,
+                end: u64::MAX,
+            })?;
+        if end > self.reader.file_size {
+            return Err(AppError::OutOfBounds {
+                requested: end,
+                size: self.reader.file_size,
+            });
+        }
+
+        let tag = match count {
+            Some(count) => Tag::new(start, end, format!("{}[{}]", type_name, count), name),
+            None => {
+                let mut bytes = vec![0u8; kind.size()];
+                self.reader.read_exact_at(start, &mut bytes)?;
+                let value = kind.decode(&bytes, endian);
+                if let Ok(integer) = value.parse::<i128>() {
+                    self.values
+                        .insert(name.to_string(), (integer, (start, end)));
+                }
+
+                let mut tag = Tag::new(start, end, type_name.to_string(), name);
+                tag.value_type = Some(format!("{:?}", kind).to_lowercase());
+                tag.endian = Some(
+                    match endian {
+                        Endian::Little => "little",
+                        Endian::Big => "big",
+                    }
+                    .to_string(),
+                );
+                tag.value = Some(value);
+                tag
+            }
+        };
+        self.tags.push(tag);
+        self.position = end;
+        Ok(())
+    }
+}
+
+/// What running a template over the file found.
+struct TemplateRun {
+    /// A tag per field.
+    tags: Vec<Tag>,
+    /// Ranges of the fields the layout depended on.
+    sources: Vec<(u64, u64)>,
+}
+
+/// Parses the file from `offset` with `template`.
+fn run_template(
+    reader: &FileReader,
+    template: &str,
+    offset: u64,
+    parsers: &ParserRegistry,
+) -> Result<TemplateRun, AppError> {
+    let statements = parse_template(template)?;
+    let mut evaluator = Evaluator {
+        reader,
+        parsers,
+        position: offset,
+        values: HashMap::new(),
+        sources: Vec::new(),
+        tags: Vec::new(),
+    };
+    evaluator.run(&statements)?;
+    Ok(TemplateRun {
+        tags: evaluator.tags,
+        sources: evaluator.sources,
+    })
+}
+
+/// Payload of the `template-updated` event sent when a live template is
+/// re-run after an edit.
+#[derive(Serialize, Clone, Debug)]
+pub struct TemplateUpdated {
+    handle: u64,
+    template_id: u64,
+    /// Empty if the template no longer fits the file.
+    tags: Vec<Tag>,
+    error: Option<AppError>,
+}
+
+/// Re-runs the template from an offset over the edited file, reports the new
+/// tags and returns the fields they depend on and where the last field ends,
+/// or `None` if the template failed.
+type Rerun = Box<dyn Fn(&FileReader, u64) -> Option<(Vec<(u64, u64)>, u64)> + Send>;
+
+/// A template applied with `live`, kept with the file so edits to the fields
+/// its layout depends on, such as an array's length, re-run it.
+pub(crate) struct LiveTemplate {
+    id: u64,
+    offset: u64,
+    /// Where the last field ends.
+    end: u64,
+    sources: Vec<(u64, u64)>,
+    /// Whether an edit moved bytes within or before the template since it
+    /// last ran.
+    moved: bool,
+    rerun: Rerun,
+}
+
+impl LiveTemplate {
+    /// Follows `removed` bytes at `at` being replaced by `inserted` new ones,
+    /// like `shift_tags`. Edits reaching into or before the template's fields
+    /// make the next `edited` call re-run it.
+    pub(crate) fn shift(&mut self, at: u64, removed: u64, inserted: u64) {
+        if at >= self.end {
+            return;
+        }
+        let removed_end = at + removed;
+        let shift = |point: u64| {
+            if point < at {
+                point
+            } else if point >= removed_end {
+                point - removed + inserted
+            } else {
+                at + inserted
+            }
+        };
+        self.offset = shift(self.offset);
+        self.end = shift(self.end);
+        self.sources = self
+            .sources
+            .iter()
+            .filter_map(|&source| shift_range(source, at, removed, inserted))
+            .collect();
+        self.moved = true;
+    }
+
+    /// Re-runs the template if `[start, end)` touches one of its sources, or
+    /// if an edit has moved it.
+    pub(crate) fn edited(&mut self, reader: &FileReader, start: u64, end: u64) {
+        let touched = self
+            .sources
+            .iter()
+            .any(|&(from, to)| from < end && start < to);
+        if touched || self.moved {
+            self.moved = false;
+            if let Some((sources, end)) = (self.rerun)(reader, self.offset) {
+                self.sources = sources;
+                self.end = end;
+            }
+        }
+    }
+}
+
+/// Where the last of `tags` ends, or `offset` if there are none.
+fn fields_end(tags: &[Tag], offset: u64) -> u64 {
+    tags.iter().map(|tag| tag.end).max().unwrap_or(offset)
+}
+
+/// Parses the file from `offset` with `template` and returns a tag per field,
+/// like `apply_struct`. Scalar fields come back typed and decoded. The tags
+/// are not added to the file.
+///
+/// With `live`, the template stays attached to the file: the returned tags
+/// carry its id in `depends_on`, and any later write to a field that sized an
+/// array or decided a condition, or edit that moves bytes within or before
+/// its fields, re-runs it and sends the new tags in a `template-updated`
+/// event, until `stop_live_template` is called.
+#[tauri::command]
+pub fn apply_template<R: Runtime>(
+    handle: u64,
+    template: String,
+    offset: u64,
+    live: Option<bool>,
+    app: AppHandle<R>,
+    parsers: State<ParserRegistry>,
+    state: State<AppState>,
+) -> Result<Vec<Tag>, AppError> {
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    let TemplateRun { mut tags, sources } =
+        run_template(&file_state.reader(), &template, offset, &parsers)?;
+    if !live.unwrap_or(false) {
+        return Ok(tags);
+    }
+
+    let id = file_state.next_live_template;
+    file_state.next_live_template += 1;
+    let mark = move |tags: &mut Vec<Tag>| {
+        for tag in tags {
+            tag.depends_on = Some(id);
+        }
+    };
+    mark(&mut tags);
+    let rerun = move |reader: &FileReader, offset: u64| {
+        let parsers = app.state::<ParserRegistry>();
+        let (tags, layout, error) = match run_template(reader, &template, offset, &parsers) {
+            Ok(TemplateRun { mut tags, sources }) => {
+                mark(&mut tags);
+                let end = fields_end(&tags, offset);
+                (tags, Some((sources, end)), None)
+            }
+            Err(e) => (Vec::new(), None, Some(e)),
+        };
+        let _ = app.emit(
+            "template-updated",
+            TemplateUpdated {
+                handle,
+                template_id: id,
+                tags,
+                error,
+            },
+        );
+        layout
+    };
+    file_state.live_templates.push(LiveTemplate {
+        id,
+        offset,
+        end: fields_end(&tags, offset),
+        sources,
+        moved: false,
+        rerun: Box::new(rerun),
+    });
+    Ok(tags)
+}
+
+/// Detaches live template `template_id` from the file. Returns false if no
+/// such template is attached.
+#[tauri::command]
+pub fn stop_live_template(
+    handle: u64,
+    template_id: u64,
+    state: State<AppState>,
+) -> Result<bool, AppError> {
+    let mut app_state = lock_state(&state);
+    let live_templates = &mut app_state.get_mut(handle)?.live_templates;
+    let before = live_templates.len();
+    live_templates.retain(|template| template.id != template_id);
+    Ok(live_templates.len() < before)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tauri::Listener;
+
+    use super::*;
+    use crate::inspect::write_value;
+    use crate::test_support::{app, open_temp, reader_over};
+
+    fn template_tags(
+        reader: &FileReader,
+        template: &str,
+        offset: u64,
+        parsers: &ParserRegistry,
+    ) -> Result<Vec<Tag>, AppError> {
+        run_template(reader, template, offset, parsers).map(|run| run.tags)
+    }
+
+    fn spans(tags: &[Tag]) -> Vec<(u64, u64, &str, &str)> {
+        tags.iter()
+            .map(|tag| {
+                (
+                    tag.start,
+                    tag.end,
+                    tag.name.as_str(),
+                    tag.display_name.as_str(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn length_prefixed_array() {
+        let reader = reader_over(&[3, 0, 0xaa, 0xbb, 0xcc, 0x34, 0x12]);
+        let tags = template_tags(
+            &reader,
+            "u16 len;\nu8 data[len];\nu16 tail;",
+            0,
+            &ParserRegistry::with_builtins(),
+        )
+        .unwrap();
+        assert_eq!(
+            spans(&tags),
+            vec![
+                (0, 2, "u16", "len"),
+                (2, 5, "u8[3]", "data"),
+                (5, 7, "u16", "tail"),
+            ]
+        );
+        assert_eq!(tags[0].value.as_deref(), Some("3"));
+        assert_eq!(tags[2].value.as_deref(), Some("4660"));
+    }
+
+    #[test]
+    fn conditional_picks_branch() {
+        let template = "u8 flag; // only one branch is read\n\
+                        if (flag == 1) { u32be big; } else { u8 small[2]; }";
+        let reader = reader_over(&[1, 0, 0, 1, 0]);
+        let tags = template_tags(&reader, template, 0, &ParserRegistry::with_builtins()).unwrap();
+        assert_eq!(spans(&tags)[1], (1, 5, "u32be", "big"));
+        assert_eq!(tags[1].value.as_deref(), Some("256"));
+
+        let reader = reader_over(&[0, 9, 9]);
+        let tags = template_tags(&reader, template, 0, &ParserRegistry::with_builtins()).unwrap();
+        assert_eq!(spans(&tags)[1], (1, 3, "u8[2]", "small"));
+    }
+
+    #[test]
+    fn bare_condition_tests_non_zero() {
+        let reader = reader_over(&[0, 0, 7]);
+        let tags = template_tags(
+            &reader,
+            "u16 n; if (n) { u8 x; }",
+            0,
+            &ParserRegistry::with_builtins(),
+        )
+        .unwrap();
+        assert_eq!(tags.len(), 1);
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let reader = reader_over(&[1, 2, 3, 4]);
+        for template in [
+            "u8 data[len];",
+            "u24 x;",
+            "u8 x",
+            "if (x) { u8 y; }",
+            "u8 a; }",
+            "u8 a; if (a > b) { }",
+        ] {
+            assert!(
+                matches!(
+                    template_tags(&reader, template, 0, &ParserRegistry::with_builtins()),
+                    Err(AppError::InvalidFormat(_))
+                ),
+                "{:?} should not parse",
+                template
+            );
+        }
+        assert!(matches!(
+            template_tags(&reader, "u32 a; u8 b;", 0, &ParserRegistry::with_builtins()),
+            Err(AppError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn live_templates_rerun_when_a_length_changes() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[3, 0, 0xaa, 0xbb, 0xcc, 0xdd, 0xee]);
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        {
+            let updates = Arc::clone(&updates);
+            app.listen_any("template-updated", move |e| {
+                let payload: serde_json::Value = serde_json::from_str(e.payload()).unwrap();
+                updates.lock().unwrap().push(payload);
+            });
+        }
+        let write = |offset, kind: &str, value: &str| {
+            write_value(
+                handle,
+                offset,
+                kind.to_string(),
+                None,
+                value.to_string(),
+                app.state(),
+            )
+            .unwrap()
+        };
+
+        let tags = apply_template(
+            handle,
+            "u16 len;\nu8 data[len];".to_string(),
+            0,
+            Some(true),
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(tags[1].name, "u8[3]");
+        let template_id = tags[0].depends_on.unwrap();
+        assert!(tags.iter().all(|tag| tag.depends_on == Some(template_id)));
+
+        // Editing the array's contents leaves its layout alone.
+        write(3, "u8", "1");
+        assert!(updates.lock().unwrap().is_empty());
+
+        write(0, "u16", "5");
+        {
+            let updates = updates.lock().unwrap();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0]["template_id"], template_id);
+            assert_eq!(updates[0]["tags"][1]["name"], "u8[5]");
+            assert_eq!(updates[0]["tags"][0]["value"], "5");
+        }
+
+        // A length past the end of the file is reported, not applied.
+        write(0, "u16", "9");
+        assert_eq!(updates.lock().unwrap()[1]["error"]["code"], "out_of_bounds");
+
+        assert!(stop_live_template(handle, template_id, app.state()).unwrap());
+        write(0, "u16", "2");
+        assert_eq!(updates.lock().unwrap().len(), 2);
+        assert!(!stop_live_template(handle, template_id, app.state()).unwrap());
+    }
+
+    #[test]
+    fn live_templates_follow_insertions_before_them() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0xff, 2, 0, 0xaa, 0xbb, 0xcc]);
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        {
+            let updates = Arc::clone(&updates);
+            app.listen_any("template-updated", move |e| {
+                let payload: serde_json::Value = serde_json::from_str(e.payload()).unwrap();
+                updates.lock().unwrap().push(payload);
+            });
+        }
+        apply_template(
+            handle,
+            "u16 len;\nu8 data[len];".to_string(),
+            1,
+            Some(true),
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+        )
+        .unwrap();
+
+        crate::splice::insert_bytes(handle, 0, vec![0; 2], app.state()).unwrap();
+        {
+            let updates = updates.lock().unwrap();
+            assert_eq!(updates.len(), 1);
+            assert_eq!(updates[0]["tags"][0]["start"], 3);
+            assert_eq!(updates[0]["tags"][1]["start"], 5);
+        }
+        // Edits after the template leave it alone.
+        crate::splice::insert_bytes(handle, 8, vec![0], app.state()).unwrap();
+        assert_eq!(updates.lock().unwrap().len(), 1);
+
+        // The length field was found at its new offset.
+        write_value(
+            handle,
+            3,
+            "u16".to_string(),
+            None,
+            "3".to_string(),
+            app.state(),
+        )
+        .unwrap();
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1]["tags"][1]["name"], "u8[3]");
+    }
+}
@@ -0,0 +1,55 @@
+//! Fixtures shared by the unit tests.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tauri::test::{mock_app, MockRuntime};
+use tauri::{App, Manager};
+use tempfile::NamedTempFile;
+
+use crate::formats::ParserRegistry;
+use crate::reader::{Backing, FileReader};
+use crate::{open_file, FileState, OpenFiles};
+
+/// A headless app managing an empty `AppState` and the built-in format
+/// parsers.
+pub(crate) fn app() -> App<MockRuntime> {
+    let app = mock_app();
+    app.manage(Arc::new(Mutex::new(OpenFiles::new())));
+    app.manage(ParserRegistry::with_builtins());
+    app
+}
+
+/// A temporary file holding `bytes`, deleted when dropped.
+pub(crate) fn temp_file(bytes: &[u8]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(bytes).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+/// Opens `file` in `app` and returns its handle.
+pub(crate) fn open(app: &App<MockRuntime>, file: &NamedTempFile) -> u64 {
+    let path = file.path().to_string_lossy().into_owned();
+    open_file(path, None, None, None, app.handle().clone(), app.state())
+        .unwrap()
+        .handle
+}
+
+/// Writes `bytes` to a new temporary file and opens it in `app`.
+pub(crate) fn open_temp(app: &App<MockRuntime>, bytes: &[u8]) -> (NamedTempFile, u64) {
+    let file = temp_file(bytes);
+    let handle = open(app, &file);
+    (file, handle)
+}
+
+/// A reader over an in-memory copy of `bytes`.
+pub(crate) fn reader_over(bytes: &[u8]) -> FileReader {
+    let file_size = bytes.len() as u64;
+    FileState::new(
+        None,
+        Backing::Memory(Arc::new(bytes.to_vec())),
+        file_size,
+        false,
+    )
+    .reader()
+}
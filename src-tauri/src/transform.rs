@@ -0,0 +1,160 @@
+use tauri::State;
+
+use crate::error::AppError;
+use crate::{lock_state, AppState};
+
+/// A decoding applied to the bytes shown to the user. The file itself is
+/// never changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Transform {
+    /// XOR with a repeating key.
+    Xor(Vec<u8>),
+    /// Wrapping addition of a repeating key.
+    Add(Vec<u8>),
+    /// Rotates each byte left by the given number of bits.
+    Rotate(u32),
+}
+
+impl Transform {
+    pub(crate) fn parse(kind: &str, key: Vec<u8>) -> Result<Option<Self>, AppError> {
+        let needs_key =
+            || AppError::InvalidArgument(format!("Transform '{}' needs a non-empty key", kind));
+        match kind {
+            "none" => Ok(None),
+            "xor" if key.is_empty() => Err(needs_key()),
+            "xor" => Ok(Some(Self::Xor(key))),
+            "add" if key.is_empty() => Err(needs_key()),
+            "add" => Ok(Some(Self::Add(key))),
+            "rotate" => match key.as_slice() {
+                [bits] if *bits < 8 => Ok(Some(Self::Rotate(*bits as u32))),
+                _ => Err(AppError::InvalidArgument(
+                    "Transform 'rotate' needs a single key byte of 0 to 7 bits".to_string(),
+                )),
+            },
+            other => Err(AppError::InvalidArgument(format!(
+                "Unsupported transform '{}'",
+                other
+            ))),
+        }
+    }
+
+    /// The `kind` and `key` that `parse` turns back into this transform.
+    pub(crate) fn describe(&self) -> (&'static str, Vec<u8>) {
+        match self {
+            Self::Xor(key) => ("xor", key.clone()),
+            Self::Add(key) => ("add", key.clone()),
+            Self::Rotate(bits) => ("rotate", vec![*bits as u8]),
+        }
+    }
+
+    /// Decodes `bytes` read from `offset` in place. Keys repeat from the
+    /// start of the file, so any range decodes the same way.
+    pub(crate) fn apply(&self, offset: u64, bytes: &mut [u8]) {
+        let keyed = |key: &[u8], op: fn(u8, u8) -> u8, bytes: &mut [u8]| {
+            let phase = (offset % key.len() as u64) as usize;
+            for (byte, k) in bytes.iter_mut().zip(key.iter().cycle().skip(phase)) {
+                *byte = op(*byte, *k);
+            }
+        };
+        match self {
+            Self::Xor(key) => keyed(key, |byte, k| byte ^ k, bytes),
+            Self::Add(key) => keyed(key, u8::wrapping_add, bytes),
+            Self::Rotate(bits) => bytes
+                .iter_mut()
+                .for_each(|byte| *byte = byte.rotate_left(*bits)),
+        }
+    }
+}
+
+/// Sets how `get_file_data` decodes the file: `xor` or `add` with a repeating
+/// `key`, `rotate` by `key[0]` bits, or `none`. Searches see decoded bytes
+/// only when asked to.
+#[tauri::command]
+pub fn set_transform(
+    handle: u64,
+    kind: String,
+    key: Vec<u8>,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let transform = Transform::parse(&kind, key)?;
+
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    file_state.transform = transform.map(std::sync::Arc::new);
+    // Cached blocks hold the bytes as seen through the old transform.
+    file_state.cache.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::get_file_data;
+    use crate::search::find_bytes;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn keys_repeat_from_the_start_of_the_file() {
+        let xor = Transform::Xor(vec![0x0f, 0xf0, 0xff]);
+        let mut whole = [0u8; 7];
+        xor.apply(0, &mut whole);
+        assert_eq!(whole, [0x0f, 0xf0, 0xff, 0x0f, 0xf0, 0xff, 0x0f]);
+        let mut tail = [0u8; 3];
+        xor.apply(4, &mut tail);
+        assert_eq!(tail, whole[4..]);
+
+        let mut bytes = [0xff, 0x81];
+        Transform::Add(vec![2]).apply(0, &mut bytes);
+        assert_eq!(bytes, [0x01, 0x83]);
+        Transform::Rotate(1).apply(0, &mut bytes);
+        assert_eq!(bytes, [0x02, 0x07]);
+    }
+
+    #[test]
+    fn xor_view_decodes_reads_and_optional_searches() {
+        let app = app();
+        let key = b"k3y";
+        let plain = b"secret message, secret";
+        let encoded: Vec<u8> = plain
+            .iter()
+            .zip(key.iter().cycle())
+            .map(|(byte, k)| byte ^ k)
+            .collect();
+        let (file, handle) = open_temp(&app, &encoded);
+        let read = || {
+            tauri::async_runtime::block_on(get_file_data(handle, 0, 22, None, app.state())).unwrap()
+        };
+        let find = |decoded| {
+            tauri::async_runtime::block_on(find_bytes(
+                handle,
+                b"secret".to_vec(),
+                0,
+                usize::MAX,
+                None,
+                None,
+                None,
+                decoded,
+                None,
+                app.handle().clone(),
+                app.state(),
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(read(), encoded);
+        set_transform(handle, "xor".to_string(), key.to_vec(), app.state()).unwrap();
+        assert_eq!(read(), plain);
+        assert_eq!(find(Some(true)), vec![0, 16]);
+        assert!(find(None).is_empty());
+        assert_eq!(std::fs::read(file.path()).unwrap(), encoded);
+
+        set_transform(handle, "none".to_string(), Vec::new(), app.state()).unwrap();
+        assert_eq!(read(), encoded);
+        assert!(matches!(
+            set_transform(handle, "xor".to_string(), Vec::new(), app.state()),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+}
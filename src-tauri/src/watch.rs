@@ -0,0 +1,273 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tauri::{AppHandle, Emitter, Runtime, State};
+
+use crate::device;
+use crate::error::AppError;
+use crate::reader::Backing;
+use crate::{lock_state, AppState, FileState};
+
+/// Payload of the `file-changed` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct FileChanged {
+    handle: u64,
+    file_size: u64,
+}
+
+/// Payload of the `file-grew` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct FileGrew {
+    handle: u64,
+    previous_size: u64,
+    file_size: u64,
+}
+
+/// Modification time and size of a file.
+pub(crate) type Stamp = (SystemTime, u64);
+
+/// Takes the stamp of `file`, after one of the app's own writes to it.
+pub(crate) fn stamp(file: &File) -> Option<Stamp> {
+    let metadata = file.metadata().ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Whether the file still looks exactly as the app's own last write left it,
+/// so a change event is only the echo of that write.
+fn unchanged_since_own_write(file_state: &FileState) -> bool {
+    let Backing::File(file) = &file_state.backing else {
+        return false;
+    };
+    file_state.own_write.is_some() && stamp(file) == file_state.own_write
+}
+
+/// Re-reads the size of file `handle` after an external change. Returns
+/// `None` once the file has been closed.
+pub(crate) fn refresh_size(state: &AppState, handle: u64) -> Option<u64> {
+    let mut app_state = lock_state(state);
+    let file_state = app_state.files.get_mut(&handle)?;
+    // The contents may have changed even if the size did not.
+    file_state.cache.clear();
+    let Backing::File(file) = &file_state.backing else {
+        return Some(file_state.file_size);
+    };
+    let file_size = device::file_size(file).ok()?;
+    if file_size != file_state.file_size {
+        file_state.file_size = file_size;
+        // A mapping of a resized file is unsafe to keep reading.
+        #[cfg(feature = "mmap")]
+        file_state.unmap();
+    }
+    Some(file_size)
+}
+
+/// Refreshes file `handle` after its contents changed on disk and emits
+/// `file-changed` with the new size, and `file-grew` as well if the file was
+/// opened with `tail` and got longer. Bytes appended are readable as soon as
+/// this returns. Changes that are only the app's own writes are ignored.
+pub(crate) fn report_change<R: Runtime>(app: &AppHandle<R>, state: &AppState, handle: u64) {
+    let Some((previous_size, tail)) = lock_state(state)
+        .files
+        .get(&handle)
+        .filter(|file_state| !unchanged_since_own_write(file_state))
+        .map(|file_state| (file_state.file_size, file_state.tail))
+    else {
+        return;
+    };
+    let Some(file_size) = refresh_size(state, handle) else {
+        return;
+    };
+    let _ = app.emit("file-changed", FileChanged { handle, file_size });
+    if tail && file_size > previous_size {
+        let grew = FileGrew {
+            handle,
+            previous_size,
+            file_size,
+        };
+        let _ = app.emit("file-grew", grew);
+    }
+}
+
+/// Watches `path` and calls `report_change` whenever its contents change.
+/// Watching stops when the returned watcher is dropped.
+pub(crate) fn watch_file<R: Runtime>(
+    app: AppHandle<R>,
+    state: &AppState,
+    handle: u64,
+    path: &Path,
+) -> Result<RecommendedWatcher, AppError> {
+    // A weak reference avoids a cycle through the watcher stored in the state.
+    let state = Arc::downgrade(state);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !(event.kind.is_modify() || event.kind.is_create()) {
+            return;
+        }
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        report_change(&app, &state, handle);
+    })
+    .map_err(|e| AppError::Io(format!("Failed to watch file: {}", e)))?;
+
+    watcher
+        .watch(path, RecursiveMode::NonRecursive)
+        .map_err(|e| AppError::Io(format!("Failed to watch file: {}", e)))?;
+    Ok(watcher)
+}
+
+/// Points `watcher` at whatever file now lives at `path`, after the one it
+/// watched was replaced by a rename, and hands it back to file `handle`.
+/// Must be called without the state lock held, for the same reason as in
+/// `stop_watching`. Best effort, like watching itself.
+pub(crate) fn rewatch(state: &AppState, handle: u64, mut watcher: RecommendedWatcher, path: &Path) {
+    let _ = watcher.unwatch(path);
+    let _ = watcher.watch(path, RecursiveMode::NonRecursive);
+    let mut app_state = lock_state(state);
+    match app_state.get_mut(handle) {
+        Ok(file_state) => file_state.watcher = Some(watcher),
+        // Closed meanwhile.
+        Err(_) => {
+            drop(app_state);
+            drop(watcher);
+        }
+    }
+}
+
+/// Stops reporting external changes to file `handle`.
+#[tauri::command]
+pub fn stop_watching(handle: u64, state: State<AppState>) -> Result<(), AppError> {
+    let watcher = lock_state(&state).get_mut(handle)?.watcher.take();
+    // Dropped only after the lock is released, since shutting the watcher
+    // down may wait for a callback that is itself waiting for the lock.
+    drop(watcher);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, Write};
+    use std::sync::Mutex;
+    use tauri::{Listener, Manager};
+
+    use super::*;
+    use crate::test_support::{app, open_temp};
+
+    #[test]
+    fn refresh_picks_up_external_size_change() {
+        let app = app();
+        let (mut file, handle) = open_temp(&app, b"1234");
+        file.write_all(b"5678").unwrap();
+        file.flush().unwrap();
+
+        let state = app.state::<AppState>();
+        assert_eq!(refresh_size(state.inner(), handle), Some(8));
+        assert_eq!(lock_state(&state).get(handle).unwrap().file_size, 8);
+    }
+
+    #[test]
+    fn tailed_files_report_growth() {
+        let app = app();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"first line\n").unwrap();
+        let path = file.path().to_string_lossy().into_owned();
+        let tail = |tail| {
+            crate::open_file(
+                path.clone(),
+                None,
+                None,
+                tail,
+                app.handle().clone(),
+                app.state(),
+            )
+            .unwrap()
+            .handle
+        };
+        let (tailed, plain) = (tail(Some(true)), tail(None));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        {
+            let events = Arc::clone(&events);
+            app.listen_any("file-grew", move |e| {
+                let payload: serde_json::Value = serde_json::from_str(e.payload()).unwrap();
+                events.lock().unwrap().push(payload);
+            });
+        }
+
+        file.write_all(b"second line\n").unwrap();
+        file.flush().unwrap();
+        let state = app.state::<AppState>();
+        for handle in [tailed, plain] {
+            report_change(app.handle(), state.inner(), handle);
+        }
+        assert_eq!(
+            *events.lock().unwrap(),
+            [serde_json::json!({"handle": tailed, "previous_size": 11, "file_size": 23})]
+        );
+        let appended =
+            tauri::async_runtime::block_on(crate::get_file_data(tailed, 11, 23, None, app.state()))
+                .unwrap();
+        assert_eq!(appended, b"second line\n");
+
+        // Nothing grew this time.
+        report_change(app.handle(), state.inner(), tailed);
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn own_writes_are_not_reported() {
+        let app = app();
+        let (mut file, handle) = open_temp(&app, b"1234");
+        // Only the changes reported below count.
+        stop_watching(handle, app.state()).unwrap();
+        let changes = Arc::new(Mutex::new(0));
+        {
+            let changes = Arc::clone(&changes);
+            app.listen_any("file-changed", move |_| *changes.lock().unwrap() += 1);
+        }
+        let state = app.state::<AppState>();
+        let generation = || lock_state(&state).get(handle).unwrap().cache.generation();
+
+        crate::splice::insert_bytes(handle, 4, b"56".to_vec(), app.state()).unwrap();
+        let written = generation();
+        report_change(app.handle(), state.inner(), handle);
+        assert_eq!(*changes.lock().unwrap(), 0);
+        assert_eq!(generation(), written);
+
+        file.seek(std::io::SeekFrom::End(0)).unwrap();
+        file.write_all(b"78").unwrap();
+        file.flush().unwrap();
+        report_change(app.handle(), state.inner(), handle);
+        assert_eq!(*changes.lock().unwrap(), 1);
+        assert_eq!(lock_state(&state).get(handle).unwrap().file_size, 8);
+    }
+
+    #[test]
+    fn stop_watching_drops_the_watcher() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, b"1234");
+        assert!(app
+            .state::<AppState>()
+            .lock()
+            .unwrap()
+            .get(handle)
+            .unwrap()
+            .watcher
+            .is_some());
+
+        stop_watching(handle, app.state()).unwrap();
+        assert!(app
+            .state::<AppState>()
+            .lock()
+            .unwrap()
+            .get(handle)
+            .unwrap()
+            .watcher
+            .is_none());
+        assert!(refresh_size(app.state::<AppState>().inner(), handle + 1).is_none());
+    }
+}
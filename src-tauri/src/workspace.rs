@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::bookmarks::Bookmark;
+use crate::error::AppError;
+use crate::hash::{fingerprint_of, Fingerprint};
+use crate::sidecar::{parse_sidecar, restore_annotations, LoadTagsReport};
+use crate::tags::Tag;
+use crate::transform::Transform;
+use crate::{check_alignment, lock_state, AppState};
+
+/// Version written by `save_workspace`. Bump it whenever a field changes
+/// meaning, and teach `load_workspace` to read the old one.
+const WORKSPACE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StoredTransform {
+    kind: String,
+    key: Vec<u8>,
+}
+
+/// Contents of a workspace file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Workspace {
+    version: u32,
+    tags: Vec<Tag>,
+    bookmarks: Vec<Bookmark>,
+    selections: Vec<(u64, u64)>,
+    cursor: u64,
+    alignment: u64,
+    transform: Option<StoredTransform>,
+    /// The file's contents when saved.
+    #[serde(default)]
+    fingerprint: Option<Fingerprint>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LoadWorkspaceReport {
+    #[serde(flatten)]
+    annotations: LoadTagsReport,
+    /// Where the view was when the workspace was saved, clamped to the file.
+    cursor: u64,
+    /// Settings that could not be restored and why.
+    warnings: Vec<String>,
+}
+
+/// Writes the file's tags, bookmarks, selections, alignment and transform,
+/// along with the view's `cursor`, to `path` as a versioned JSON document.
+#[tauri::command]
+pub fn save_workspace(
+    handle: u64,
+    path: String,
+    cursor: u64,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let fingerprint = fingerprint_of(&state, handle).ok();
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get(handle)?;
+    let workspace = Workspace {
+        version: WORKSPACE_VERSION,
+        tags: file_state.tags.clone(),
+        bookmarks: file_state.bookmarks.clone(),
+        selections: file_state.selections.clone(),
+        cursor,
+        alignment: file_state.alignment,
+        transform: file_state.transform.as_deref().map(|transform| {
+            let (kind, key) = transform.describe();
+            StoredTransform {
+                kind: kind.to_string(),
+                key,
+            }
+        }),
+        fingerprint,
+    };
+    let json = serde_json::to_string_pretty(&workspace)
+        .map_err(|e| AppError::InvalidFormat(format!("Failed to serialize workspace: {}", e)))?;
+    fs::write(&path, json)
+        .map_err(|e| AppError::Io(format!("Failed to write workspace to {}: {}", path, e)))
+}
+
+/// Restores a workspace saved with `save_workspace`. Tags and bookmarks are
+/// replaced as by `load_tags`; settings that no longer fit the file are left
+/// as they are and reported as warnings, as is a workspace saved for
+/// different contents. A plain tags sidecar loads as a workspace holding
+/// only tags and bookmarks. Workspaces from a newer version are refused
+/// without touching the file's state.
+#[tauri::command]
+pub fn load_workspace(
+    handle: u64,
+    path: String,
+    state: State<AppState>,
+) -> Result<LoadWorkspaceReport, AppError> {
+    let json = fs::read_to_string(&path)
+        .map_err(|e| AppError::Io(format!("Failed to read workspace from {}: {}", path, e)))?;
+    let value: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| AppError::InvalidFormat(format!("Failed to parse workspace: {}", e)))?;
+
+    let fingerprint = fingerprint_of(&state, handle).ok();
+    let mut app_state = lock_state(&state);
+    let file_state = app_state.get_mut(handle)?;
+    let version = match value.get("version") {
+        None => {
+            // Sidecars from `save_tags` predate workspaces.
+            let (tags, bookmarks) = parse_sidecar(&json)?;
+            return Ok(LoadWorkspaceReport {
+                annotations: restore_annotations(file_state, tags, bookmarks),
+                cursor: 0,
+                warnings: vec![format!(
+                    "{} is a tags file, so only tags and bookmarks were restored",
+                    path
+                )],
+            });
+        }
+        Some(version) => version.as_u64().ok_or_else(|| {
+            AppError::InvalidFormat(format!("Invalid workspace version {}", version))
+        })?,
+    };
+    if version > WORKSPACE_VERSION as u64 {
+        return Err(AppError::InvalidFormat(format!(
+            "Workspace version {} is newer than this version of the app supports ({})",
+            version, WORKSPACE_VERSION
+        )));
+    }
+    let mut value = value;
+    if version < 2 {
+        // Version 1 held at most one selection.
+        if let Some(object) = value.as_object_mut() {
+            let selection = object.remove("selection").unwrap_or_default();
+            let selections = match selection {
+                serde_json::Value::Null => serde_json::json!([]),
+                selection => serde_json::json!([selection]),
+            };
+            object.insert("selections".to_string(), selections);
+        }
+    }
+    let workspace: Workspace = serde_json::from_value(value)
+        .map_err(|e| AppError::InvalidFormat(format!("Failed to parse workspace: {}", e)))?;
+
+    let mut warnings = Vec::new();
+    if let (Some(saved), Some(current)) = (&workspace.fingerprint, &fingerprint) {
+        if saved != current {
+            warnings.push(format!(
+                "{} was saved for different contents than the file now has",
+                path
+            ));
+        }
+    }
+    let file_size = file_state.file_size;
+    let mut selections = Vec::new();
+    for (start, end) in workspace.selections {
+        let overlaps = selections
+            .last()
+            .is_some_and(|&(_, previous_end)| start < previous_end);
+        if start >= end || end > file_size || overlaps {
+            warnings.push(format!(
+                "Selection [{}, {}) does not fit the file and was not restored",
+                start, end
+            ));
+        } else {
+            selections.push((start, end));
+        }
+    }
+    file_state.selections = selections;
+    match check_alignment(workspace.alignment) {
+        Ok(()) => file_state.alignment = workspace.alignment,
+        Err(e) => warnings.push(e.to_string()),
+    }
+    let transform = match workspace.transform {
+        None => Ok(None),
+        Some(stored) => Transform::parse(&stored.kind, stored.key),
+    };
+    match transform {
+        Ok(transform) => {
+            file_state.transform = transform.map(Arc::new);
+            // Cached blocks hold the bytes as seen through the old transform.
+            file_state.cache.clear();
+        }
+        Err(e) => warnings.push(e.to_string()),
+    }
+    if workspace.cursor > file_size {
+        warnings.push(format!(
+            "Cursor {} is past the end of the file and was moved to {}",
+            workspace.cursor, file_size
+        ));
+    }
+
+    Ok(LoadWorkspaceReport {
+        annotations: restore_annotations(file_state, workspace.tags, workspace.bookmarks),
+        cursor: std::cmp::min(workspace.cursor, file_size),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri::Manager;
+
+    use super::*;
+    use crate::bookmarks::add_bookmark;
+    use crate::get_file_data;
+    use crate::selection::{add_selection, list_selections, set_selection};
+    use crate::tags::add_tag;
+    use crate::test_support::{app, open, open_temp};
+    use crate::transform::set_transform;
+    use crate::{close_file, set_alignment};
+
+    #[test]
+    fn round_trips_the_whole_session() {
+        let app = app();
+        let (file, handle) = open_temp(&app, &[0x55; 8192]);
+        add_tag(
+            handle,
+            Tag::new(16, 32, "header".to_string(), "Header"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        add_bookmark(handle, 100, "here".to_string(), app.state()).unwrap();
+        set_selection(handle, 10, 20, app.state()).unwrap();
+        add_selection(handle, 30, 40, app.state()).unwrap();
+        set_alignment(handle, 4096, app.state()).unwrap();
+        set_transform(handle, "xor".to_string(), vec![0x0f], app.state()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.blworkspace");
+        let path = path.to_string_lossy().into_owned();
+        save_workspace(handle, path.clone(), 1234, app.state()).unwrap();
+        close_file(handle, app.state()).unwrap();
+
+        let handle = open(&app, &file);
+        let report = load_workspace(handle, path, app.state()).unwrap();
+        assert_eq!(report.cursor, 1234);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+
+        let app_state = app.state::<AppState>();
+        let mut restored = lock_state(&app_state);
+        let file_state = restored.get(handle).unwrap();
+        assert_eq!(file_state.tags.len(), 1);
+        assert_eq!((file_state.tags[0].start, file_state.tags[0].end), (16, 32));
+        assert_eq!(file_state.bookmarks[0].offset, 100);
+        assert_eq!(file_state.alignment, 4096);
+        drop(restored);
+        assert_eq!(
+            list_selections(handle, app.state()).unwrap(),
+            [(10, 20), (30, 40)]
+        );
+        let decoded =
+            tauri::async_runtime::block_on(get_file_data(handle, 0, 2, None, app.state())).unwrap();
+        assert_eq!(decoded, [0x5a, 0x5a]);
+    }
+
+    #[test]
+    fn refuses_newer_versions_and_reads_plain_sidecars() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        add_tag(
+            handle,
+            Tag::new(0, 4, "kept".to_string(), "Kept"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+
+        let newer = path("newer.blworkspace");
+        let mut document = serde_json::json!({
+            "version": WORKSPACE_VERSION + 1,
+            "tags": [],
+            "bookmarks": [],
+            "selections": [],
+            "cursor": 0,
+            "alignment": 1,
+            "transform": null,
+            "layout": "something new",
+        });
+        fs::write(&newer, document.to_string()).unwrap();
+        assert!(matches!(
+            load_workspace(handle, newer.clone(), app.state()),
+            Err(AppError::InvalidFormat(_))
+        ));
+        document["version"] = serde_json::json!("one");
+        fs::write(&newer, document.to_string()).unwrap();
+        assert!(matches!(
+            load_workspace(handle, newer, app.state()),
+            Err(AppError::InvalidFormat(_))
+        ));
+        let app_state = app.state::<AppState>();
+        assert_eq!(lock_state(&app_state).get(handle).unwrap().tags.len(), 1);
+
+        let sidecar = path("plain.bltags");
+        fs::write(
+            &sidecar,
+            r#"[{"start": 8, "end": 12, "name": "old", "display_name": "Old"}]"#,
+        )
+        .unwrap();
+        let report = load_workspace(handle, sidecar, app.state()).unwrap();
+        assert_eq!(report.warnings.len(), 1);
+        let mut loaded = lock_state(&app_state);
+        let tags = &loaded.get(handle).unwrap().tags;
+        assert_eq!(tags.len(), 1);
+        assert_eq!((tags[0].start, tags[0].name.as_str()), (8, "old"));
+    }
+
+    #[test]
+    fn tags_added_after_loading_a_workspace_get_fresh_ids() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ids.blworkspace");
+        let document = serde_json::json!({
+            "version": WORKSPACE_VERSION,
+            "tags": [{"id": 42, "start": 0, "end": 4, "name": "a", "display_name": "A"}],
+            "bookmarks": [],
+            "selections": [],
+            "cursor": 0,
+            "alignment": 1,
+            "transform": null,
+        });
+        fs::write(&path, document.to_string()).unwrap();
+
+        load_workspace(handle, path.to_string_lossy().into_owned(), app.state()).unwrap();
+        let id = add_tag(
+            handle,
+            Tag::new(8, 12, "b".to_string(), "B"),
+            None,
+            None,
+            None,
+            app.state(),
+        )
+        .unwrap();
+        assert_eq!(id, 43);
+    }
+
+    #[test]
+    fn reads_the_single_selection_of_version_one() {
+        let app = app();
+        let (_file, handle) = open_temp(&app, &[0; 64]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("v1.blworkspace");
+        let path = path.to_string_lossy().into_owned();
+        let mut document = serde_json::json!({
+            "version": 1,
+            "tags": [],
+            "bookmarks": [],
+            "selection": [4, 8],
+            "cursor": 0,
+            "alignment": 1,
+            "transform": null,
+        });
+        fs::write(&path, document.to_string()).unwrap();
+        let report = load_workspace(handle, path.clone(), app.state()).unwrap();
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(list_selections(handle, app.state()).unwrap(), [(4, 8)]);
+
+        document["selection"] = serde_json::Value::Null;
+        fs::write(&path, document.to_string()).unwrap();
+        load_workspace(handle, path, app.state()).unwrap();
+        assert!(list_selections(handle, app.state()).unwrap().is_empty());
+    }
+}
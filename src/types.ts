@@ -1,9 +1,56 @@
 export interface Tag {
+  id: number;
   start: number;
   end: number;
   name: string;
   displayName: string;
   color?: string;
+  layer?: number;
+  value_type?: string;
+  endian?: 'little' | 'big';
+  value?: string;
+  note?: string | null;
+  created_at?: number;
+}
+
+export interface TagContext {
+  tag: Tag;
+  window_start: number;
+  bytes: number[];
+}
+
+export interface Bookmark {
+  id: number;
+  offset: number;
+  label: string;
+}
+
+export interface OpenedFile {
+  handle: number;
+  file_size: number;
+}
+
+export interface FileInfo {
+  path: string | null;
+  size: number;
+  read_only: boolean;
+  tag_count: number;
+}
+
+export interface FileChanged {
+  handle: number;
+  file_size: number;
+}
+
+export interface OffsetBytes {
+  handle: number;
+  bytes: number[] | null;
+}
+
+export interface AppError {
+  code: string;
+  message: string;
+  [detail: string]: unknown;
 }
 
 export interface Selection {